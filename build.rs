@@ -0,0 +1,12 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // The gRPC stubs are generated from proto/agent.proto; a vendored protoc is used unless
+    // PROTOC points at another one
+    #[cfg(feature = "grpc")]
+    {
+        if std::env::var_os("PROTOC").is_none() {
+            std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path()?);
+        }
+        tonic_build::compile_protos("proto/agent.proto")?;
+    }
+    Ok(())
+}
@@ -0,0 +1,175 @@
+use super::{step, Agent, AgentStep, DESCRIBE_TOOL};
+use crate::error::{AgentError, Result};
+use crate::mcp_client::ToolCall;
+use crate::model_client::repair::repair_json;
+use crate::model_client::{CompletionRequest, ModelResponse};
+use crate::schema;
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use rmcp::model;
+use serde_json::Value;
+
+impl Agent {
+    // Asks for options.samples completions at once and takes the first valid one to come back,
+    // dropping the rest. When none is valid, the first reply is used and fails the turn as usual
+    pub(super) async fn complete_best_of(
+        &mut self,
+        format: Option<Value>,
+    ) -> Result<ModelResponse> {
+        let request = self.completion_request(format);
+        let requests: Vec<CompletionRequest> = (0..self.options.samples)
+            .map(|index| {
+                let mut sample = request.clone();
+                let params = &mut sample.params;
+                params.temperature = Some(
+                    params
+                        .temperature
+                        .unwrap_or_default()
+                        .max(self.options.sample_temperature),
+                );
+                // A seed of its own for every sample, or they come out the same and a cache in
+                // front of the backend answers them all with one reply
+                params.seed = Some(params.seed.unwrap_or_default().wrapping_add(index as u64));
+                sample
+            })
+            .collect();
+
+        let mut chosen = None;
+        let mut invalid = Vec::new();
+        let mut error = None;
+        let mut pending: FuturesUnordered<_> = requests
+            .iter()
+            .map(|request| self.timed_completion(request))
+            .collect();
+        while let Some(response) = pending.next().await {
+            match response {
+                Ok(response) if self.acceptable(&response.response) => {
+                    chosen = Some(response);
+                    break;
+                }
+                Ok(response) => invalid.push(response),
+                Err(err) => {
+                    error.get_or_insert(err);
+                }
+            }
+        }
+        drop(pending);
+
+        if !invalid.is_empty() {
+            tracing::debug!(
+                invalid = invalid.len(),
+                samples = self.options.samples,
+                "Discarded invalid samples"
+            );
+        }
+        let response = chosen
+            .or_else(|| invalid.into_iter().next())
+            .ok_or_else(|| {
+                error.unwrap_or_else(|| AgentError::ModelError("No samples requested".to_string()))
+            })?;
+        self.record_response(&response);
+        Ok(response)
+    }
+
+    // Whether the turn could go ahead with this reply: an answer that passes the validators and
+    // output schema, a question, or a call to a permitted tool with arguments that fit its schema
+    fn acceptable(&self, reply: &str) -> bool {
+        let repair = repair_json(reply);
+        let reply = repair.as_ref().map_or(reply, |repair| repair.json.as_str());
+        match step::parse_reply(reply) {
+            Some(AgentStep::FinalAnswer(answer)) => {
+                self.validators
+                    .iter()
+                    .all(|validator| validator.validate(&answer).is_empty())
+                    && self
+                        .progress
+                        .task
+                        .output_schema
+                        .as_ref()
+                        .is_none_or(|schema| step::structured_answer(schema, &answer).is_ok())
+            }
+            Some(_) => true,
+            None => match serde_json::from_str::<model::JsonRpcRequest>(reply) {
+                Ok(request) => ToolCall::from_request(&request).is_none_or(|call| {
+                    (self.tool_permitted(&call.name) || call.name == DESCRIBE_TOOL)
+                        && self.tool_schema(&call.name).is_none_or(|schema| {
+                            schema::validate(&schema, &call.arguments).is_empty()
+                        })
+                }),
+                Err(_) => false,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mcp_client::MCPClient;
+    use crate::model_client::{ChatMessage, GenerationParams, ModelClient};
+    use crate::task::{Artifact, Task};
+    use async_trait::async_trait;
+    use serde_json::json;
+    use std::sync::{Arc, Mutex};
+
+    // Hands out its replies in order and keeps the parameters each request asked for
+    struct Samples {
+        replies: Mutex<Vec<String>>,
+        params: Arc<Mutex<Vec<GenerationParams>>>,
+    }
+
+    #[async_trait]
+    impl ModelClient for Samples {
+        async fn complete(&self, request: &CompletionRequest) -> Result<ModelResponse> {
+            self.params.lock().unwrap().push(request.params.clone());
+            Ok(ModelResponse {
+                response: self.replies.lock().unwrap().remove(0),
+                reasoning: None,
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_best_of_n_takes_the_valid_sample() -> Result<()> {
+        let params = Arc::new(Mutex::new(Vec::new()));
+        let model = Samples {
+            replies: Mutex::new(vec![
+                "Here is a haiku".to_string(),
+                json!({"final_answer": {"season": 7}}).to_string(),
+                json!({"final_answer": {"season": "autumn"}}).to_string(),
+            ]),
+            params: params.clone(),
+        };
+        let mut agent = Agent::new(
+            Box::new(model),
+            MCPClient::new(),
+            vec![ChatMessage::system("You are a poet")],
+        );
+        agent.set_options(crate::agent::RunOptions {
+            samples: 3,
+            ..Default::default()
+        });
+        let task = Task::new("Name a season").with_output_schema(json!({
+            "type": "object",
+            "properties": {"season": {"type": "string"}},
+            "required": ["season"]
+        }));
+
+        let result = agent.run_task(&task).await?;
+
+        assert!(result.success);
+        assert_eq!(result.turns, 1);
+        assert_eq!(
+            result.artifacts,
+            [Artifact::Text {
+                text: json!({"season": "autumn"}).to_string()
+            }]
+        );
+        let params = params.lock().unwrap();
+        let temperatures: Vec<Option<f32>> =
+            params.iter().map(|params| params.temperature).collect();
+        let seeds: Vec<Option<u64>> = params.iter().map(|params| params.seed).collect();
+        assert_eq!(temperatures, [Some(0.7); 3]);
+        assert_eq!(seeds, [Some(0), Some(1), Some(2)]);
+        Ok(())
+    }
+}
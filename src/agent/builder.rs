@@ -0,0 +1,477 @@
+use super::{Agent, PermissionProfile, RunOptions, StopCondition, SystemPrompt, ToolSelector};
+use crate::artifacts::ArtifactStore;
+use crate::audit::AuditLog;
+use crate::clock::Clock;
+use crate::error::{AgentError, Result};
+use crate::events::AgentObserver;
+use crate::guardrails::InjectionGuard;
+use crate::ids::IdGenerator;
+use crate::knowledge::KnowledgeBase;
+use crate::mcp_client::{MCPClient, ServerConfig};
+use crate::memory::MemoryStore;
+use crate::model_client::{ChatMessage, GenerationParams, ModelClient};
+use crate::native_tools::{Clarifier, CommandApproval, NativeTool, NativeTools, Scratchpad};
+use crate::redaction::Redactor;
+use crate::storage::RunStore;
+use crate::validation::Validator;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+// Fluent configuration for an Agent; build() checks it hangs together before anything runs
+#[derive(Default)]
+pub struct AgentBuilder {
+    model: Option<Box<dyn ModelClient>>,
+    server: Option<ServerConfig>,
+    mcp_client: Option<MCPClient>,
+    system_prompt: Option<String>,
+    generated_prompt: Option<SystemPrompt>,
+    messages: Vec<ChatMessage>,
+    allowed_tools: Option<Vec<String>>,
+    denied_tools: Vec<String>,
+    approval_required: Vec<String>,
+    approval: Option<CommandApproval>,
+    native_tools: NativeTools,
+    observers: Vec<Arc<dyn AgentObserver>>,
+    options: RunOptions,
+    params: GenerationParams,
+    redactor: Option<Redactor>,
+    injection_guard: Option<InjectionGuard>,
+    audit_log: Option<AuditLog>,
+    memory: Option<MemoryStore>,
+    knowledge: Option<KnowledgeBase>,
+    scratchpad: Option<Scratchpad>,
+    clarifier: Option<Arc<dyn Clarifier>>,
+    file_editing: bool,
+    compact_tool_catalog: Option<usize>,
+    tool_selector: Option<ToolSelector>,
+    run_store: Option<RunStore>,
+    workspace: Option<PathBuf>,
+    artifact_store: Option<ArtifactStore>,
+    clock: Option<Arc<dyn Clock>>,
+    ids: Option<Arc<dyn IdGenerator>>,
+    checkpoint_file: Option<PathBuf>,
+    stop_conditions: Vec<StopCondition>,
+    validators: Vec<Box<dyn Validator>>,
+}
+
+impl AgentBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn model(mut self, model: Box<dyn ModelClient>) -> Self {
+        self.model = Some(model);
+        self
+    }
+
+    // Start this MCP server when the agent is built
+    pub fn server(mut self, server: ServerConfig) -> Self {
+        self.server = Some(server);
+        self
+    }
+
+    // Use a client set up elsewhere, e.g. a replaying one; it is not initialized again
+    pub fn mcp_client(mut self, mcp_client: MCPClient) -> Self {
+        self.mcp_client = Some(mcp_client);
+        self
+    }
+
+    pub fn system_prompt(mut self, prompt: impl Into<String>) -> Self {
+        self.system_prompt = Some(prompt.into());
+        self
+    }
+
+    // Rendered at build time with the tools the agent may call, instead of a fixed system prompt
+    pub fn generated_system_prompt(mut self, prompt: SystemPrompt) -> Self {
+        self.generated_prompt = Some(prompt);
+        self
+    }
+
+    // Added to the conversation every task starts from, after the system prompt
+    pub fn message(mut self, message: ChatMessage) -> Self {
+        self.messages.push(message);
+        self
+    }
+
+    pub fn allow_tools<I, S>(mut self, tools: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.allowed_tools = Some(tools.into_iter().map(Into::into).collect());
+        self
+    }
+
+    pub fn deny_tools<I, S>(mut self, tools: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.denied_tools.extend(tools.into_iter().map(Into::into));
+        self
+    }
+
+    // Narrows the allowlist to the profile's and adds its denials and approvals
+    pub fn permission_profile(mut self, profile: PermissionProfile) -> Self {
+        self.allowed_tools = match (self.allowed_tools, profile.allowed_tools) {
+            (Some(allowed), Some(profile)) => Some(
+                allowed
+                    .into_iter()
+                    .filter(|tool| profile.contains(tool))
+                    .collect(),
+            ),
+            (allowed, profile) => allowed.or(profile),
+        };
+        self.denied_tools.extend(profile.denied_tools);
+        self.approval_required.extend(profile.approval_required);
+        self.approval = Some(profile.approval);
+        self
+    }
+
+    pub fn native_tools(mut self, tools: NativeTools) -> Self {
+        self.native_tools = tools;
+        self
+    }
+
+    pub fn native_tool(mut self, tool: Box<dyn NativeTool>) -> Self {
+        self.native_tools.register(tool);
+        self
+    }
+
+    pub fn observer(mut self, observer: Arc<dyn AgentObserver>) -> Self {
+        self.observers.push(observer);
+        self
+    }
+
+    pub fn options(mut self, options: RunOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn max_turns(mut self, max_turns: usize) -> Self {
+        self.options.max_turns = max_turns;
+        self
+    }
+
+    pub fn model_timeout(mut self, timeout: Duration) -> Self {
+        self.options.model_timeout = Some(timeout);
+        self
+    }
+
+    pub fn turn_deadline(mut self, deadline: Duration) -> Self {
+        self.options.turn_deadline = Some(deadline);
+        self
+    }
+
+    pub fn tool_timeout(mut self, tool: &str, timeout: Duration) -> Self {
+        self.options = self.options.with_tool_timeout(tool, timeout);
+        self
+    }
+
+    pub fn generation_params(mut self, params: GenerationParams) -> Self {
+        self.params = params;
+        self
+    }
+
+    pub fn redactor(mut self, redactor: Redactor) -> Self {
+        self.redactor = Some(redactor);
+        self
+    }
+
+    // List only tool names and one-line descriptions when there are more tools than this
+    pub fn compact_tool_catalog(mut self, above: usize) -> Self {
+        self.compact_tool_catalog = Some(above);
+        self
+    }
+
+    // Describe only the tools most relevant to each turn in the generated prompt
+    pub fn tool_selector(mut self, selector: ToolSelector) -> Self {
+        self.tool_selector = Some(selector);
+        self
+    }
+
+    pub fn injection_guard(mut self, guard: InjectionGuard) -> Self {
+        self.injection_guard = Some(guard);
+        self
+    }
+
+    pub fn audit_log(mut self, audit_log: AuditLog) -> Self {
+        self.audit_log = Some(audit_log);
+        self
+    }
+
+    pub fn memory(mut self, memory: MemoryStore) -> Self {
+        self.memory = Some(memory);
+        self
+    }
+
+    pub fn knowledge(mut self, knowledge: KnowledgeBase) -> Self {
+        self.knowledge = Some(knowledge);
+        self
+    }
+
+    pub fn scratchpad(mut self, scratchpad: Scratchpad) -> Self {
+        self.scratchpad = Some(scratchpad);
+        self
+    }
+
+    // Let the model ask the user about the task instead of guessing
+    pub fn clarifier(mut self, clarifier: Arc<dyn Clarifier>) -> Self {
+        self.clarifier = Some(clarifier);
+        self
+    }
+
+    // edit_file over the server's read_file and write_file, taking diffs or search/replace blocks
+    pub fn file_editing(mut self) -> Self {
+        self.file_editing = true;
+        self
+    }
+
+    pub fn run_store(mut self, run_store: RunStore) -> Self {
+        self.run_store = Some(run_store);
+        self
+    }
+
+    pub fn workspace(mut self, workspace: PathBuf) -> Self {
+        self.workspace = Some(workspace);
+        self
+    }
+
+    pub fn artifact_store(mut self, store: ArtifactStore) -> Self {
+        self.artifact_store = Some(store);
+        self
+    }
+
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
+    pub fn ids(mut self, ids: Arc<dyn IdGenerator>) -> Self {
+        self.ids = Some(ids);
+        self
+    }
+
+    pub fn checkpoint_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.checkpoint_file = Some(path.into());
+        self
+    }
+
+    pub fn stop_condition(mut self, condition: StopCondition) -> Self {
+        self.stop_conditions.push(condition);
+        self
+    }
+
+    pub fn validator(mut self, validator: Box<dyn Validator>) -> Self {
+        self.validators.push(validator);
+        self
+    }
+
+    fn validate(&self) -> Result<()> {
+        let mut problems = Vec::new();
+        if self.model.is_none() {
+            problems.push("a model is required".to_string());
+        }
+        if self.server.is_some() && self.mcp_client.is_some() {
+            problems.push("set either a server or an MCP client, not both".to_string());
+        }
+        if self
+            .system_prompt
+            .as_ref()
+            .is_some_and(|prompt| prompt.trim().is_empty())
+        {
+            problems.push("the system prompt is empty".to_string());
+        }
+        if let Some(allowed) = &self.allowed_tools {
+            let both: Vec<&str> = allowed
+                .iter()
+                .filter(|tool| self.denied_tools.contains(tool))
+                .map(String::as_str)
+                .collect();
+            if !both.is_empty() {
+                problems.push(format!(
+                    "tools both allowed and denied: {}",
+                    both.join(", ")
+                ));
+            }
+        }
+        if self.system_prompt.is_some() && self.generated_prompt.is_some() {
+            problems.push("set either a system prompt or a generated one, not both".to_string());
+        }
+        if self.options.max_turns == 0 {
+            problems.push("max_turns must be at least 1".to_string());
+        }
+        if self.options.samples == 0 {
+            problems.push("samples must be at least 1".to_string());
+        }
+        if self.options.max_repeated_calls == Some(0) {
+            problems.push("max_repeated_calls must be at least 1".to_string());
+        }
+        if self.options.budget.is_some() && self.options.pricing.is_none() {
+            problems.push("a budget needs a price per token".to_string());
+        }
+        let zero_timeouts = [self.options.model_timeout, self.options.turn_deadline]
+            .into_iter()
+            .flatten()
+            .chain([self.options.default_tool_timeout])
+            .chain(self.options.tool_timeouts.values().copied())
+            .any(|timeout| timeout.is_zero());
+        if zero_timeouts {
+            problems.push("timeouts must be longer than zero".to_string());
+        }
+        if let Some(workspace) = &self.workspace {
+            if !workspace.is_dir() {
+                problems.push(format!(
+                    "workspace {} is not a directory",
+                    workspace.display()
+                ));
+            }
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(AgentError::ConfigError(problems.join("; ")))
+        }
+    }
+
+    pub async fn build(self) -> Result<Agent> {
+        self.validate()?;
+        let mcp_client = match (self.mcp_client, self.server) {
+            (Some(mcp_client), _) => mcp_client,
+            (None, server) => {
+                let mut mcp_client =
+                    MCPClient::with_server(server.unwrap_or_else(|| ServerConfig::filesystem(".")));
+                mcp_client.init().await?;
+                mcp_client
+            }
+        };
+        let mut messages: Vec<ChatMessage> = self
+            .system_prompt
+            .into_iter()
+            .map(ChatMessage::system)
+            .collect();
+        messages.extend(self.messages);
+
+        let model = self
+            .model
+            .ok_or_else(|| AgentError::ConfigError("a model is required".to_string()))?;
+        let mut agent = Agent::new(model, mcp_client, messages);
+        agent.set_options(self.options);
+        agent.set_generation_params(self.params);
+        agent.set_native_tools(self.native_tools);
+        if let Some(tools) = self.allowed_tools {
+            agent.set_allowed_tools(tools);
+        }
+        agent.set_denied_tools(self.denied_tools);
+        agent.set_approval_required(
+            self.approval_required,
+            self.approval.unwrap_or(CommandApproval::Ask),
+        );
+        for observer in self.observers {
+            agent.add_observer(observer);
+        }
+        if let Some(redactor) = self.redactor {
+            agent.set_redactor(redactor);
+        }
+        if let Some(guard) = self.injection_guard {
+            agent.set_injection_guard(guard);
+        }
+        if let Some(audit_log) = self.audit_log {
+            agent.set_audit_log(audit_log);
+        }
+        if let Some(memory) = self.memory {
+            agent.set_memory(memory);
+        }
+        if let Some(knowledge) = self.knowledge {
+            agent.set_knowledge(knowledge);
+        }
+        if let Some(scratchpad) = self.scratchpad {
+            agent.set_scratchpad(scratchpad);
+        }
+        if let Some(clarifier) = self.clarifier {
+            agent.set_clarifier(clarifier);
+        }
+        if self.file_editing {
+            agent.enable_file_editing();
+        }
+        if let Some(above) = self.compact_tool_catalog {
+            agent.set_compact_tool_catalog(above);
+        }
+        if let Some(selector) = self.tool_selector {
+            agent.set_tool_selector(selector);
+        }
+        if let Some(run_store) = self.run_store {
+            agent.set_run_store(run_store);
+        }
+        if let Some(workspace) = self.workspace {
+            agent.set_workspace(workspace);
+        }
+        if let Some(store) = self.artifact_store {
+            agent.set_artifact_store(store);
+        }
+        if let Some(clock) = self.clock {
+            agent.set_clock(clock);
+        }
+        if let Some(ids) = self.ids {
+            agent.set_ids(ids);
+        }
+        if let Some(path) = self.checkpoint_file {
+            agent.set_checkpoint_file(path);
+        }
+        for condition in self.stop_conditions {
+            agent.add_stop_condition(condition);
+        }
+        for validator in self.validators {
+            agent.add_validator(validator);
+        }
+        if let Some(prompt) = self.generated_prompt {
+            agent.set_generated_prompt(prompt).await?;
+        }
+        Ok(agent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replay::{Fixture, ReplayModelClient};
+
+    #[tokio::test]
+    async fn test_build_reports_every_configuration_problem() {
+        let err = AgentBuilder::new()
+            .system_prompt(" ")
+            .max_turns(0)
+            .allow_tools(["read_file", "write_file"])
+            .deny_tools(["write_file"])
+            .build()
+            .await
+            .err()
+            .map(|err| err.to_string())
+            .unwrap_or_default();
+
+        assert!(err.contains("a model is required"));
+        assert!(err.contains("the system prompt is empty"));
+        assert!(err.contains("max_turns must be at least 1"));
+        assert!(err.contains("tools both allowed and denied: write_file"));
+    }
+
+    #[tokio::test]
+    async fn test_build_applies_the_configuration() -> Result<()> {
+        let fixture = Fixture::default();
+        let agent = Agent::builder()
+            .model(Box::new(ReplayModelClient::new(&fixture)))
+            .mcp_client(MCPClient::replaying(&fixture))
+            .system_prompt("You are a poet")
+            .message(ChatMessage::user("Write about ponds"))
+            .scratchpad(Scratchpad::new())
+            .build()
+            .await?;
+
+        let history = agent.history();
+        assert_eq!(history[0].content, "You are a poet");
+        assert!(history[1].content.contains("scratchpad"));
+        assert_eq!(history[2].content, "Write about ponds");
+        Ok(())
+    }
+}
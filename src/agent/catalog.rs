@@ -0,0 +1,132 @@
+use super::prompt::{describe_tool, example_call};
+use super::Agent;
+use crate::error::{AgentError, Result};
+use crate::mcp_client::ToolCall;
+use rmcp::model::{CallToolResult, Content, Tool};
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+pub const DESCRIBE_TOOL: &str = "describe_tool";
+
+// Longest one-line description a compacted catalog keeps of a tool
+const MAX_SUMMARY_CHARS: usize = 100;
+
+// Stands in for the tools' schemas when the catalog lists only their names
+pub fn describe_tool_tool() -> Tool {
+    let schema = json!({
+        "type": "object",
+        "properties": {"name": {"type": "string"}},
+        "required": ["name"]
+    });
+    Tool::new(
+        DESCRIBE_TOOL,
+        "Shows a tool's full description and arguments. Call it before a tool you haven't used yet",
+        Arc::new(schema.as_object().cloned().unwrap_or_default()),
+    )
+}
+
+// The first sentence of the description, on one line
+pub(super) fn summary(tool: &Tool) -> String {
+    let description = tool.description.trim();
+    let line = description.lines().next().unwrap_or_default();
+    let sentence = line.find(". ").map_or(line, |end| &line[..=end]);
+    match sentence.char_indices().nth(MAX_SUMMARY_CHARS) {
+        Some((end, _)) => format!("{}...", sentence[..end].trim_end()),
+        None => sentence.to_string(),
+    }
+}
+
+impl Agent {
+    // With more tools than this, the system prompt lists only their names and one-line
+    // descriptions, and the model asks describe_tool for the arguments of the one it needs
+    pub fn set_compact_tool_catalog(&mut self, above: usize) {
+        self.compact_catalog_above = Some(above);
+    }
+
+    // Whether these tools are too many to describe in full
+    pub(super) fn compacts(&self, tools: &[Tool]) -> bool {
+        self.compact_catalog_above
+            .is_some_and(|above| tools.len() > above)
+    }
+
+    // The full description of the tool, which the model reads as the call's result
+    pub(super) async fn show_tool(&self, call: &ToolCall) -> Result<Value> {
+        let name = call.arguments["name"]
+            .as_str()
+            .ok_or_else(|| AgentError::ToolError("Missing string argument 'name'".to_string()))?;
+        let tool = self
+            .tool_catalog
+            .iter()
+            .find(|tool| tool.name == name)
+            .cloned()
+            .ok_or_else(|| {
+                AgentError::ToolError(format!(
+                    "Unknown tool '{}'. The tools are listed in the system prompt",
+                    name
+                ))
+            })?;
+        let shown = format!(
+            "{}\nInput schema: {}\nCall it like this:\n{}",
+            describe_tool(&tool),
+            tool.schema_as_json_value(),
+            example_call(&tool)
+        );
+        Ok(json!(CallToolResult::success(vec![Content::text(shown)])))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::{AgentStep, SystemPrompt};
+    use crate::mcp_client::MCPClient;
+    use crate::native_tools::{CurrentTimeTool, MathEvalTool, NativeTools};
+    use crate::replay::{Fixture, Interaction, McpExchange, ReplayModelClient};
+    use crate::task::Task;
+
+    #[tokio::test]
+    async fn test_compact_catalog_lists_names_and_describes_on_demand() -> Result<()> {
+        let describe = json!({
+            "jsonrpc": "2.0",
+            "method": "tools/call",
+            "params": {"name": DESCRIBE_TOOL, "arguments": {"name": "math_eval"}},
+            "id": 1
+        });
+        let fixture = Fixture {
+            interactions: vec![
+                Interaction::Mcp(McpExchange::new(
+                    &json!({"method": "tools/list"}),
+                    &Ok(json!({"tools": []})),
+                )),
+                Interaction::model_reply(&describe),
+            ],
+        };
+        let mut tools = NativeTools::new();
+        tools.register(Box::new(MathEvalTool));
+        tools.register(Box::new(CurrentTimeTool::new()));
+        let mut agent = Agent::builder()
+            .model(Box::new(ReplayModelClient::new(&fixture)))
+            .mcp_client(MCPClient::replaying(&fixture))
+            .native_tools(tools)
+            .compact_tool_catalog(1)
+            .generated_system_prompt(SystemPrompt::new("You count syllables"))
+            .build()
+            .await?;
+
+        let prompt = agent.history()[0].content.clone();
+        agent.start_task(&Task::new("Count 5 + 7 + 5")).await?;
+        let step = agent.run_once().await?;
+
+        assert!(prompt.contains("- math_eval: "));
+        assert!(!prompt.contains("expression (string, required)"));
+        assert!(prompt.contains("- describe_tool: Shows a tool's full description and arguments. Call it before a tool you haven't used yet Arguments: name (string, required)"));
+        assert!(matches!(step, AgentStep::ToolExecuted { tool, .. } if tool == DESCRIBE_TOOL));
+        assert!(agent
+            .history()
+            .last()
+            .unwrap()
+            .content
+            .contains("expression (string, required)"));
+        Ok(())
+    }
+}
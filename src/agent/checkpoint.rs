@@ -0,0 +1,289 @@
+use super::quota::SideEffects;
+use super::{Agent, Plan};
+use crate::error::Result;
+use crate::model_client::ChatMessage;
+use crate::task::{Artifact, Task, TurnTiming};
+use crate::transcript::TranscriptEntry;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+// Where a task stood at its last safe point: between turns, between plan steps, or after the
+// model asked for a tool call that has not run yet
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct Checkpoint {
+    pub task: Task,
+    pub history: Vec<ChatMessage>,
+    // The task's transcript so far
+    pub transcript: Vec<TranscriptEntry>,
+    pub transcribed_messages: usize,
+    pub turns: usize,
+    pub error: Option<String>,
+    pub answers: Vec<Artifact>,
+    #[serde(default)]
+    pub output: Option<Value>,
+    #[serde(default)]
+    pub timings: Vec<TurnTiming>,
+    pub plan: Option<Plan>,
+    // The plan step in progress, and whether the model has been told about it yet
+    pub plan_step: usize,
+    pub step_announced: bool,
+    pub scratchpad: BTreeMap<String, String>,
+    // The last tool call and how many times in a row it was made, for the loop guard
+    pub repeated_call: Option<(Value, usize)>,
+    // Runs first on resume instead of asking the model again
+    pub pending_call: Option<Value>,
+    // Counted against the run's quotas
+    #[serde(default)]
+    pub side_effects: SideEffects,
+}
+
+impl Checkpoint {
+    pub fn load(path: &Path) -> Result<Self> {
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    // Written next to the target and renamed over it, so a crash mid-write keeps the last one
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".partial");
+        std::fs::write(&partial, serde_json::to_string(self)?)?;
+        std::fs::rename(&partial, path)?;
+        Ok(())
+    }
+}
+
+// The run loop's own state for the task in progress
+#[derive(Default)]
+pub(super) struct Progress {
+    pub task: Task,
+    // Where the task's entries start in the transcript
+    pub first_entry: usize,
+    pub turns: usize,
+    pub error: Option<String>,
+    pub answers: Vec<Artifact>,
+    pub output: Option<Value>,
+    pub timings: Vec<TurnTiming>,
+    pub plan: Option<Plan>,
+    pub plan_step: usize,
+    pub step_announced: bool,
+    pub side_effects: SideEffects,
+}
+
+impl Agent {
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            task: self.progress.task.clone(),
+            history: self.history.clone(),
+            transcript: self.transcript.entries()[self.progress.first_entry..].to_vec(),
+            transcribed_messages: self.transcribed_messages,
+            turns: self.progress.turns,
+            error: self.progress.error.clone(),
+            answers: self.progress.answers.clone(),
+            output: self.progress.output.clone(),
+            timings: self.progress.timings.clone(),
+            plan: self.progress.plan.clone(),
+            plan_step: self.progress.plan_step,
+            step_announced: self.progress.step_announced,
+            scratchpad: self
+                .scratchpad
+                .as_ref()
+                .map(|scratchpad| scratchpad.entries())
+                .unwrap_or_default(),
+            repeated_call: self.last_call.clone(),
+            pending_call: self.pending_call.clone(),
+            side_effects: self.progress.side_effects.clone(),
+        }
+    }
+
+    // A no-op unless a checkpoint file is set
+    pub(super) fn save_checkpoint(&self) -> Result<()> {
+        match &self.checkpoint_file {
+            Some(path) => self.checkpoint().save(path),
+            None => Ok(()),
+        }
+    }
+
+    // The task finished, so there is nothing left to resume
+    pub(super) fn clear_checkpoint(&self) -> Result<()> {
+        match &self.checkpoint_file {
+            Some(path) if path.exists() => Ok(std::fs::remove_file(path)?),
+            _ => Ok(()),
+        }
+    }
+
+    pub(super) fn restore(&mut self, checkpoint: Checkpoint) {
+        let first_entry = self.transcript.entries().len();
+        for entry in checkpoint.transcript {
+            self.transcript.record(entry);
+        }
+        self.history = checkpoint.history;
+        self.transcribed_messages = checkpoint.transcribed_messages;
+        if let Some(scratchpad) = &self.scratchpad {
+            scratchpad.replace(checkpoint.scratchpad);
+        }
+        self.last_call = checkpoint.repeated_call;
+        self.pending_call = checkpoint.pending_call;
+        self.progress = Progress {
+            task: checkpoint.task,
+            first_entry,
+            turns: checkpoint.turns,
+            error: checkpoint.error,
+            answers: checkpoint.answers,
+            output: checkpoint.output,
+            timings: checkpoint.timings,
+            plan: checkpoint.plan,
+            plan_step: checkpoint.plan_step,
+            step_announced: checkpoint.step_announced,
+            side_effects: checkpoint.side_effects,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::RunOptions;
+    use crate::events::{AgentEvent, AgentObserver};
+    use crate::mcp_client::MCPClient;
+    use crate::native_tools::Scratchpad;
+    use crate::replay::{Fixture, Interaction, McpExchange, ReplayModelClient};
+    use serde_json::json;
+    use std::path::PathBuf;
+    use std::sync::{Arc, Mutex};
+
+    // Copies the checkpoint file as it stood when write_file failed
+    struct Snapshot {
+        file: PathBuf,
+        taken: Mutex<Option<Checkpoint>>,
+    }
+
+    impl AgentObserver for Snapshot {
+        fn on_event(&self, event: &AgentEvent) {
+            if let AgentEvent::ToolError { tool, .. } = event {
+                if tool == "write_file" {
+                    *self.taken.lock().unwrap() = Checkpoint::load(&self.file).ok();
+                }
+            }
+        }
+    }
+
+    async fn agent(model: Vec<Interaction>, mcp: Vec<Interaction>, file: &Path) -> Result<Agent> {
+        let mut mcp_client = MCPClient::replaying(&Fixture { interactions: mcp });
+        mcp_client.init().await?;
+        Agent::builder()
+            .model(Box::new(ReplayModelClient::new(&Fixture {
+                interactions: model,
+            })))
+            .mcp_client(mcp_client)
+            .system_prompt("You are a poet")
+            .scratchpad(Scratchpad::new())
+            .checkpoint_file(file)
+            .options(RunOptions {
+                max_turns: 3,
+                ..RunOptions::default()
+            })
+            .build()
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_resume_runs_the_pending_call_and_finishes_the_task() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let file = dir.path().join("checkpoint.json");
+        let write = json!({
+            "jsonrpc": "2.0",
+            "method": "tools/call",
+            "params": {"name": "write_file", "arguments": {"path": "pond.txt", "content": "An old pond"}},
+            "id": 1
+        });
+        let init = || Interaction::Mcp(McpExchange::new(&json!({}), &Ok(json!({}))));
+
+        // The first server has nothing to replay for write_file, as if the process died on it
+        let snapshot = Arc::new(Snapshot {
+            file: file.clone(),
+            taken: Mutex::new(None),
+        });
+        let mut first = agent(vec![Interaction::model_reply(&write)], vec![init()], &file).await?;
+        first.add_observer(snapshot.clone());
+        let failed = first
+            .run_task(&Task::new("Write a haiku to pond.txt"))
+            .await?;
+        let checkpoint = snapshot
+            .taken
+            .lock()
+            .unwrap()
+            .take()
+            .expect("checkpoint saved");
+
+        assert!(!failed.success);
+        assert!(!file.exists());
+        assert_eq!(checkpoint.turns, 1);
+        assert_eq!(checkpoint.pending_call, Some(write.clone()));
+
+        checkpoint.save(&file)?;
+        let mut second = agent(
+            vec![Interaction::model_reply(
+                json!({"final_answer": "Wrote pond.txt"}),
+            )],
+            vec![
+                init(),
+                Interaction::Mcp(McpExchange::new(
+                    &write,
+                    &Ok(json!({"content": [{"type": "text", "text": "ok"}]})),
+                )),
+            ],
+            &file,
+        )
+        .await?;
+        let result = second.resume(Checkpoint::load(&file)?).await?;
+
+        assert!(result.success);
+        assert_eq!(result.turns, 3);
+        assert_eq!(
+            result.artifacts,
+            [
+                Artifact::File {
+                    path: "pond.txt".to_string(),
+                    stored: None,
+                },
+                Artifact::Text {
+                    text: "Wrote pond.txt".to_string()
+                }
+            ]
+        );
+        assert_eq!(
+            second.history()[..checkpoint.history.len()],
+            checkpoint.history
+        );
+        assert_eq!(second.history()[checkpoint.history.len()].content, "ok");
+        assert!(!file.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_restore_brings_back_the_scratchpad_and_loop_guard() {
+        let checkpoint = Checkpoint {
+            task: Task::new("Write a haiku"),
+            turns: 3,
+            scratchpad: BTreeMap::from([("draft".to_string(), "An old pond".to_string())]),
+            repeated_call: Some((json!(["tools/call", {"name": "read_file"}]), 2)),
+            ..Checkpoint::default()
+        };
+        let scratchpad = Scratchpad::new();
+        let mut agent = Agent::new(
+            Box::new(ReplayModelClient::new(&Fixture {
+                interactions: Vec::new(),
+            })),
+            MCPClient::new(),
+            Vec::new(),
+        );
+        agent.set_scratchpad(scratchpad.clone());
+
+        agent.restore(checkpoint.clone());
+
+        assert_eq!(scratchpad.get("draft").as_deref(), Some("An old pond"));
+        assert_eq!(agent.checkpoint(), checkpoint);
+    }
+}
@@ -0,0 +1,198 @@
+use super::Agent;
+use crate::error::{AgentError, Result};
+use crate::mcp_client::ToolCall;
+use crate::patch;
+use rmcp::model::{CallToolResult, Content, Tool};
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+pub const EDIT_FILE_TOOL: &str = "edit_file";
+const READ_FILE_TOOL: &str = "read_file";
+pub(super) const WRITE_FILE_TOOL: &str = "write_file";
+
+// Replaces the filesystem server's own edit_file, whose exact-text edits models get wrong
+pub fn edit_file_tool() -> Tool {
+    let schema = json!({
+        "type": "object",
+        "properties": {
+            "path": {"type": "string"},
+            "edit": {
+                "type": "string",
+                "description": "Search/replace blocks or a unified diff; leave out to see the file"
+            }
+        },
+        "required": ["path"]
+    });
+    Tool::new(
+        EDIT_FILE_TOOL,
+        "Changes part of an existing file. Without an edit, shows the file",
+        Arc::new(schema.as_object().cloned().unwrap_or_default()),
+    )
+}
+
+fn instructions() -> String {
+    format!(
+        "To change an existing file, call '{}' with its path and an edit instead of rewriting it with write_file. The edit is one or more search/replace blocks:\n<<<<<<< SEARCH\nthe exact lines to change\n=======\nwhat replaces them\n>>>>>>> REPLACE\nor a unified diff with @@ hunks. Call it with only the path to see the file first.",
+        EDIT_FILE_TOOL
+    )
+}
+
+impl Agent {
+    // Offer edit_file, applied here through the server's read_file and write_file
+    pub fn enable_file_editing(&mut self) {
+        self.file_editing = true;
+        self.add_instructions(instructions());
+    }
+
+    // Reads the file and applies the edit to it. A missing edit returns the file to show the
+    // model; an edit that doesn't apply fails with why along with the file as it is, to try again
+    pub(super) async fn edit_file(&mut self, call: &ToolCall) -> Result<Value> {
+        let path = call.arguments["path"]
+            .as_str()
+            .ok_or_else(|| AgentError::ToolError("Missing string argument 'path'".to_string()))?
+            .to_string();
+        let read = self
+            .mcp_client
+            .call_tool(&ToolCall::new(READ_FILE_TOOL, json!({"path": path})))
+            .await?;
+        if read.is_error {
+            return Err(AgentError::ToolError(read.text()));
+        }
+        let content = read.text();
+        let shown = format!("Contents of {}:\n{}", path, content);
+        let Some(edit) = call.arguments["edit"]
+            .as_str()
+            .filter(|edit| !edit.trim().is_empty())
+        else {
+            return Ok(text_result(shown));
+        };
+        let updated = match patch::parse(edit).and_then(|edits| patch::apply(&content, &edits)) {
+            Ok(updated) => updated,
+            Err(err) => {
+                let problem = match err {
+                    AgentError::ToolError(problem) => problem,
+                    other => other.to_string(),
+                };
+                return Err(AgentError::ToolError(format!("{}\n\n{}", problem, shown)));
+            }
+        };
+        let written = self
+            .mcp_client
+            .call_tool(&ToolCall::new(
+                WRITE_FILE_TOOL,
+                json!({"path": path, "content": updated}),
+            ))
+            .await?;
+        if written.is_error {
+            return Err(AgentError::ToolError(written.text()));
+        }
+        Ok(text_result(format!("Edited {}", path)))
+    }
+}
+
+fn text_result(text: String) -> Value {
+    json!(CallToolResult::success(vec![Content::text(text)]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mcp_client::{Framing, MCPClient, ServerConfig};
+    use crate::model_client::{ChatMessage, Role};
+    use crate::replay::{Fixture, Interaction, ReplayModelClient};
+    use crate::task::{Artifact, Task};
+
+    fn edit(edit: &str) -> Interaction {
+        let call = json!({
+            "jsonrpc": "2.0",
+            "method": "tools/call",
+            "params": {"name": "edit_file", "arguments": {"path": "haiku.txt", "edit": edit}},
+            "id": 1
+        });
+        Interaction::model_reply(&call)
+    }
+
+    #[tokio::test]
+    async fn test_edits_are_applied_through_read_and_write_or_shown_why_not() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let written = dir.path().join("write_file.json");
+        // Serves the same haiku to both reads and saves what it is asked to write
+        let script = format!(
+            r#"reply() {{
+read request
+id=$(echo "$request" | sed 's/.*"id":\([0-9]*\).*/\1/')
+printf '%s\n' "{{\"jsonrpc\":\"2.0\",\"id\":$id,\"result\":$1}}"
+}}
+haiku='{{"content":[{{"type":"text","text":"An old silent pond\nA frog jumps into the pond\n"}}]}}'
+reply '{{}}'
+read initialized
+reply "$haiku"
+reply "$haiku"
+read -r request
+printf '%s\n' "$request" > '{}'
+echo '{{"jsonrpc":"2.0","id":4,"result":{{"content":[]}}}}'
+cat > /dev/null"#,
+            written.display()
+        );
+        let mut mcp_client = MCPClient::with_server(ServerConfig {
+            name: "filesystem".to_string(),
+            command: "sh".to_string(),
+            args: vec!["-c".to_string(), script],
+            framing: Framing::Newline,
+            request_limit: None,
+            log_level: None,
+        });
+        mcp_client.init().await?;
+        let model = ReplayModelClient::new(&Fixture {
+            interactions: vec![
+                edit("<<<<<<< SEARCH\nA crow\n=======\nA heron\n>>>>>>> REPLACE"),
+                edit("@@ -2 +2 @@\n-A frog jumps into the pond\n+A frog leaps in\n"),
+                Interaction::model_reply(r#"{"final_answer": "Fixed"}"#),
+            ],
+        });
+        let mut agent = Agent::new(
+            Box::new(model),
+            mcp_client,
+            vec![ChatMessage::system("You are a poet")],
+        );
+        agent.enable_file_editing();
+
+        let result = agent.run_task(&Task::new("Fix the second line")).await?;
+
+        agent.shutdown().await?;
+        assert!(result.success);
+        assert_eq!(
+            result.artifacts,
+            [
+                Artifact::File {
+                    path: "haiku.txt".to_string(),
+                    stored: None
+                },
+                Artifact::Text {
+                    text: "Fixed".to_string()
+                }
+            ]
+        );
+        let request: Value = serde_json::from_str(&std::fs::read_to_string(&written)?)?;
+        assert_eq!(request["params"]["name"], "write_file");
+        assert_eq!(
+            request["params"]["arguments"]["content"],
+            "An old silent pond\nA frog leaps in\n"
+        );
+        let retry = agent
+            .history()
+            .iter()
+            .find(|message| message.content.starts_with("Edit 1 does not apply"))
+            .unwrap();
+        assert!(retry
+            .content
+            .contains("Contents of haiku.txt:\nAn old silent pond"));
+        let tool_messages = agent
+            .history()
+            .iter()
+            .filter(|message| message.role == Role::Tool)
+            .count();
+        assert_eq!(tool_messages, 2);
+        Ok(())
+    }
+}
@@ -0,0 +1,159 @@
+use crate::error::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::path::Path;
+
+// One worked request and the tool call that answers it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FewShotExample {
+    pub tool: String,
+    pub request: String,
+    pub arguments: Value,
+}
+
+impl FewShotExample {
+    pub fn new(tool: &str, request: &str, arguments: Value) -> Self {
+        Self {
+            tool: tool.to_string(),
+            request: request.to_string(),
+            arguments,
+        }
+    }
+
+    pub fn call(&self) -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "method": "tools/call",
+            "params": {"name": self.tool, "arguments": self.arguments},
+            "id": 1
+        })
+    }
+
+    pub fn render(&self) -> String {
+        format!("User: {}\nAssistant: {}", self.request, self.call())
+    }
+}
+
+// Examples keyed by tool, for small models that don't get JSON tool calls right zero-shot
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FewShotLibrary {
+    examples: Vec<FewShotExample>,
+}
+
+impl FewShotLibrary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Curated examples for the filesystem server and the built-in tools
+    pub fn builtin() -> Self {
+        let examples = vec![
+            FewShotExample::new(
+                "write_file",
+                "Save a note saying hello to notes/hello.txt",
+                json!({"path": "notes/hello.txt", "content": "hello"}),
+            ),
+            FewShotExample::new(
+                "read_file",
+                "What does README.md say?",
+                json!({"path": "README.md"}),
+            ),
+            FewShotExample::new(
+                "list_directory",
+                "Which files are in the haiku folder?",
+                json!({"path": "haiku"}),
+            ),
+            FewShotExample::new(
+                "create_directory",
+                "Make a drafts folder",
+                json!({"path": "drafts"}),
+            ),
+            FewShotExample::new("current_time", "What time is it?", json!({})),
+            FewShotExample::new(
+                "math_eval",
+                "How much is 17 times 23?",
+                json!({"expression": "17 * 23"}),
+            ),
+            FewShotExample::new(
+                "fetch_url",
+                "Summarise https://docs.rs/tokio",
+                json!({"url": "https://docs.rs/tokio"}),
+            ),
+            FewShotExample::new(
+                "run_command",
+                "Count the lines in src/main.rs",
+                json!({"command": "wc -l src/main.rs"}),
+            ),
+            FewShotExample::new(
+                "remember",
+                "Keep the draft title for later: Autumn Pond",
+                json!({"key": "title", "value": "Autumn Pond"}),
+            ),
+            FewShotExample::new(
+                "recall",
+                "What was the draft title?",
+                json!({"key": "title"}),
+            ),
+        ];
+        Self { examples }
+    }
+
+    // A JSON array of examples, for tools the built-in set doesn't cover
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let examples = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        Ok(Self { examples })
+    }
+
+    pub fn add(&mut self, example: FewShotExample) {
+        self.examples.push(example);
+    }
+
+    pub fn extend(&mut self, other: FewShotLibrary) {
+        self.examples.extend(other.examples);
+    }
+
+    // At most `per_tool` examples for each named tool, in the order the tools are given
+    pub fn for_tools<'a>(&'a self, tools: &[&str], per_tool: usize) -> Vec<&'a FewShotExample> {
+        tools
+            .iter()
+            .flat_map(|tool| {
+                self.examples
+                    .iter()
+                    .filter(move |example| example.tool == *tool)
+                    .take(per_tool)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_examples_are_picked_per_tool_in_tool_order() {
+        let mut library = FewShotLibrary::builtin();
+        library.add(FewShotExample::new(
+            "read_file",
+            "Show me Cargo.toml",
+            json!({"path": "Cargo.toml"}),
+        ));
+
+        let examples = library.for_tools(&["read_file", "write_file", "unknown"], 1);
+
+        let tools: Vec<&str> = examples
+            .iter()
+            .map(|example| example.tool.as_str())
+            .collect();
+        assert_eq!(tools, ["read_file", "write_file"]);
+        assert_eq!(library.for_tools(&["read_file"], 5).len(), 2);
+        assert_eq!(
+            examples[0].render(),
+            format!(
+                "User: What does README.md say?\nAssistant: {}",
+                examples[0].call()
+            )
+        );
+        assert_eq!(examples[0].call()["params"]["name"], "read_file");
+    }
+}
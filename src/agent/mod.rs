@@ -1,67 +1,1307 @@
-use crate::mcp_client::MCPClient;
-use crate::model_client::ModelClient;
-use anyhow::Result;
+mod best_of;
+mod builder;
+mod catalog;
+mod checkpoint;
+mod edit;
+mod few_shot;
+mod options;
+mod outcomes;
+mod permissions;
+mod plan;
+mod prompt;
+mod quota;
+mod reflection;
+mod step;
+mod stop;
+mod tool_result;
+mod tool_selection;
+
+pub use builder::AgentBuilder;
+pub use catalog::{describe_tool_tool, DESCRIBE_TOOL};
+pub use checkpoint::Checkpoint;
+pub use edit::{edit_file_tool, EDIT_FILE_TOOL};
+pub use few_shot::{FewShotExample, FewShotLibrary};
+pub use options::{
+    RunOptions, DEFAULT_MAX_REPEATED_CALLS, DEFAULT_MAX_TOOL_RESULT_BYTES,
+    DEFAULT_SAMPLE_TEMPERATURE,
+};
+pub use outcomes::{is_outcome, outcome_summary, PAST_TASK_PREFIX};
+pub use permissions::{
+    PermissionProfile, ADMIN_PROFILE, DEVELOPER_APPROVAL_TOOLS, DEVELOPER_PROFILE,
+    READ_ONLY_PROFILE, READ_ONLY_TOOLS,
+};
+pub use plan::{Plan, PlanStep, PLAN_FORMAT};
+pub use prompt::SystemPrompt;
+pub use quota::{Quotas, SideEffects};
+pub use step::{AgentStep, REPLY_FORMAT};
+pub use stop::{RunState, StopCondition};
+pub use tool_result::ToolResultPolicy;
+pub use tool_selection::ToolSelector;
+
+use crate::artifacts::ArtifactStore;
+use crate::audit::{Approval, AuditLog, AuditRecord};
+use crate::clock::{system_clock, Clock};
+use crate::error::{AgentError, Result};
+use crate::events::{AgentEvent, AgentObserver};
+use crate::guardrails::InjectionGuard;
+use crate::ids::{random_ids, IdGenerator};
+use crate::knowledge::{self, KnowledgeBase};
+use crate::mcp_client::{prompt_content_text, MCPClient, ToolCall, ToolResult};
+use crate::memory::MemoryStore;
+use crate::model_client::repair::repair_json;
+use crate::model_client::stop::{restore_closing_brace, OutputFormat, JSON_STOP_SEQUENCES};
+use crate::model_client::usage::{MeteredModelClient, Usage, UsageMeter};
+use crate::model_client::{
+    estimate_message_tokens, ChatMessage, CompletionRequest, GenerationParams, ImagePart,
+    ModelCapabilities, ModelClient, ModelResponse, Role,
+};
+use crate::native_tools::{
+    ask_user_instructions, scratchpad_instructions, AskUserTool, Clarifier, CommandApproval,
+    NativeTool, NativeTools, Scratchpad,
+};
+use crate::redaction::Redactor;
+use crate::schema;
+use crate::storage::{RunRecord, RunStore};
+use crate::task::{artifacts_from, Artifact, Task, TaskResult, TurnTiming};
+use crate::transcript::{Transcript, TranscriptEntry, TranscriptFormat};
+use crate::validation::Validator;
+use crate::workspace;
+use checkpoint::Progress;
 use rmcp::model;
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Instant;
 
 pub struct Agent {
     model: Box<dyn ModelClient>,
+    meter: UsageMeter,
     mcp_client: MCPClient,
-    context: String,
+    // Conversation every task starts from, e.g. the system prompt
+    base_messages: Vec<ChatMessage>,
+    history: Vec<ChatMessage>,
+    capabilities: ModelCapabilities,
+    params: GenerationParams,
+    options: RunOptions,
+    transcript: Transcript,
+    transcribed_messages: usize,
+    allowed_tools: Option<HashSet<String>>,
+    denied_tools: HashSet<String>,
+    // Tools the user has to approve each call to, and how that is decided
+    approval_required: HashSet<String>,
+    approval: CommandApproval,
+    memory: Option<MemoryStore>,
+    knowledge: Option<KnowledgeBase>,
+    native_tools: NativeTools,
+    audit_log: Option<AuditLog>,
+    observers: Vec<Arc<dyn AgentObserver>>,
+    redactor: Redactor,
+    injection_guard: Option<InjectionGuard>,
+    // Input schemas of the server's tools, known once list_tools has been called
+    tool_schemas: HashMap<String, serde_json::Value>,
+    // The tools list_tools last found, for describe_tool and the tool selector
+    tool_catalog: Vec<model::Tool>,
+    compact_catalog_above: Option<usize>,
+    tool_selector: Option<ToolSelector>,
+    validators: Vec<Box<dyn Validator>>,
+    scratchpad: Option<Scratchpad>,
+    // Answers the model's questions about the task, when the user is there to ask
+    clarifier: Option<Arc<dyn Clarifier>>,
+    // The last tool call and how many times in a row the model has made it
+    last_call: Option<(serde_json::Value, usize)>,
+    run_store: Option<RunStore>,
+    workspace: Option<PathBuf>,
+    artifact_store: Option<ArtifactStore>,
+    // Whether edit_file is offered and applied by the agent itself
+    file_editing: bool,
+    clock: Arc<dyn Clock>,
+    ids: Arc<dyn IdGenerator>,
+    progress: Progress,
+    // A tool call the model asked for that has not run yet
+    pending_call: Option<serde_json::Value>,
+    checkpoint_file: Option<PathBuf>,
+    stop_conditions: Vec<StopCondition>,
+    // The prompt set_generated_prompt started from and what it rendered to, for re-rendering
+    // when the server's tools change
+    generated_prompt: Option<(SystemPrompt, String)>,
 }
 
+const MEMORY_RECALL_LIMIT: usize = 3;
+const KNOWLEDGE_SERVER: &str = "knowledge";
+const NATIVE_SERVER: &str = "native";
+// The only requests the model may send; the rest of the protocol is the client's business
+const MODEL_METHODS: [&str; 2] = ["tools/call", "resources/read"];
+
 impl Agent {
-    pub fn new(
+    pub fn builder() -> AgentBuilder {
+        AgentBuilder::new()
+    }
+
+    pub(crate) fn new(
         model: Box<dyn ModelClient>,
         mcp_client: MCPClient,
-        initial_context: String,
+        initial_messages: Vec<ChatMessage>,
     ) -> Self {
+        let capabilities = model.capabilities();
+        let model = MeteredModelClient::new(model);
+        let meter = model.meter();
         Self {
-            model,
+            model: Box::new(model),
+            meter,
             mcp_client,
-            context: initial_context,
+            base_messages: initial_messages.clone(),
+            history: initial_messages,
+            capabilities,
+            params: GenerationParams::default(),
+            options: RunOptions::default(),
+            transcript: Transcript::new(),
+            transcribed_messages: 0,
+            allowed_tools: None,
+            denied_tools: HashSet::new(),
+            approval_required: HashSet::new(),
+            approval: CommandApproval::Ask,
+            memory: None,
+            knowledge: None,
+            native_tools: NativeTools::new(),
+            audit_log: None,
+            observers: Vec::new(),
+            redactor: Redactor::new(),
+            injection_guard: None,
+            tool_schemas: HashMap::new(),
+            tool_catalog: Vec::new(),
+            compact_catalog_above: None,
+            tool_selector: None,
+            validators: Vec::new(),
+            scratchpad: None,
+            clarifier: None,
+            last_call: None,
+            run_store: None,
+            workspace: None,
+            artifact_store: None,
+            file_editing: false,
+            clock: system_clock(),
+            ids: random_ids(),
+            progress: Progress::default(),
+            pending_call: None,
+            checkpoint_file: None,
+            stop_conditions: Vec::new(),
+            generated_prompt: None,
+        }
+    }
+
+    pub fn set_memory(&mut self, memory: MemoryStore) {
+        self.memory = Some(memory);
+    }
+
+    pub fn memory_mut(&mut self) -> Option<&mut MemoryStore> {
+        self.memory.as_mut()
+    }
+
+    // Expose the search_knowledge tool over these documents
+    pub fn set_knowledge(&mut self, knowledge: KnowledgeBase) {
+        self.knowledge = Some(knowledge);
+        self.add_instructions(knowledge::tool_instructions());
+    }
+
+    // Expose remember and recall over a scratchpad that is cleared at the start of each task
+    pub fn set_scratchpad(&mut self, scratchpad: Scratchpad) {
+        for tool in scratchpad.tools() {
+            self.native_tools.register(tool);
+        }
+        self.scratchpad = Some(scratchpad);
+        self.add_instructions(scratchpad_instructions());
+    }
+
+    // Offer the ask_user tool, and answer clarification replies through the clarifier instead
+    // of ending the task on them
+    pub fn set_clarifier(&mut self, clarifier: Arc<dyn Clarifier>) {
+        self.native_tools
+            .register(Box::new(AskUserTool::new(clarifier.clone())));
+        self.clarifier = Some(clarifier);
+        self.add_instructions(ask_user_instructions());
+    }
+
+    // Puts the user's answer to the model's question into the conversation; false when there is
+    // no one to ask or they did not answer
+    async fn clarify(&mut self, question: &str) -> Result<bool> {
+        let Some(clarifier) = &self.clarifier else {
+            return Ok(false);
+        };
+        let Some(answer) = clarifier.ask(question).await? else {
+            return Ok(false);
+        };
+        self.history.push(ChatMessage::user(format!(
+            "Answer to your question \"{}\": {}",
+            question, answer
+        )));
+        Ok(true)
+    }
+
+    // The directory this agent's MCP server is rooted at; reported on every task result
+    pub fn set_workspace(&mut self, workspace: PathBuf) {
+        self.add_instructions(workspace::instructions(&workspace));
+        self.workspace = Some(workspace);
+    }
+
+    // Keep a copy of the files each task writes, in a directory per run under the store
+    pub fn set_artifact_store(&mut self, store: ArtifactStore) {
+        self.artifact_store = Some(store);
+    }
+
+    // Lead with a system prompt describing the tools this agent may call and where it may write
+    pub async fn set_generated_prompt(&mut self, prompt: SystemPrompt) -> Result<()> {
+        let tools = self.list_tools().await?;
+        let text = self.render_prompt(&prompt, tools);
+        let message = ChatMessage::system(text.clone());
+        self.base_messages.insert(0, message.clone());
+        self.history.insert(0, message);
+        self.generated_prompt = Some((prompt, text));
+        Ok(())
+    }
+
+    fn render_prompt(&self, prompt: &SystemPrompt, tools: Vec<model::Tool>) -> String {
+        let compact = tools.iter().any(|tool| tool.name == DESCRIBE_TOOL);
+        let mut prompt = prompt.clone().with_tools(tools);
+        if compact {
+            prompt = prompt.with_compact_tools();
+        }
+        if let Some(workspace) = &self.workspace {
+            prompt = prompt.with_root(workspace.clone());
+        }
+        prompt.render()
+    }
+
+    // The server announced different tools: validate against the new schemas and, with a
+    // generated prompt, describe the new tools from the next turn on
+    async fn refresh_tools(&mut self) -> Result<()> {
+        let tools = self.list_tools().await?;
+        let names = tools.iter().map(|tool| tool.name.to_string()).collect();
+        self.render_generated_prompt(tools);
+        self.emit(AgentEvent::ToolsChanged { tools: names });
+        Ok(())
+    }
+
+    // Describe these tools in the generated prompt from the next completion on
+    fn render_generated_prompt(&mut self, tools: Vec<model::Tool>) {
+        if let Some((prompt, old)) = self.generated_prompt.take() {
+            let text = self.render_prompt(&prompt, tools);
+            for messages in [&mut self.base_messages, &mut self.history] {
+                for message in messages.iter_mut().filter(|message| message.content == old) {
+                    message.content = text.clone();
+                }
+            }
+            self.generated_prompt = Some((prompt, text));
+        }
+    }
+
+    // Add a system message after the existing ones, for this and every later task
+    fn add_instructions(&mut self, instructions: String) {
+        let instructions = ChatMessage::system(instructions);
+        for messages in [&mut self.base_messages, &mut self.history] {
+            let position = messages
+                .iter()
+                .take_while(|message| message.role == Role::System)
+                .count();
+            messages.insert(position, instructions.clone());
+        }
+    }
+
+    // Final answers must pass every validator; failures are sent back for another turn
+    pub fn add_validator(&mut self, validator: Box<dyn Validator>) {
+        self.validators.push(validator);
+    }
+
+    // Save every run_task call, with its transcript and usage, to this store
+    pub fn set_run_store(&mut self, run_store: RunStore) {
+        self.run_store = Some(run_store);
+    }
+
+    // Timestamps and durations of recorded runs come from this clock
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    // Names of the files the run spills tool output to come from these ids
+    pub fn set_ids(&mut self, ids: Arc<dyn IdGenerator>) {
+        self.ids = ids;
+    }
+
+    // Keep the task's state in this file at every safe point, for resume after a restart;
+    // it is removed once the task finishes
+    pub fn set_checkpoint_file(&mut self, path: PathBuf) {
+        self.checkpoint_file = Some(path);
+    }
+
+    // Estimated model usage since the agent was created
+    pub fn usage(&self) -> Usage {
+        self.meter.usage()
+    }
+
+    pub fn set_audit_log(&mut self, audit_log: AuditLog) {
+        self.audit_log = Some(audit_log);
+    }
+
+    pub fn set_native_tools(&mut self, tools: NativeTools) {
+        self.native_tools = tools;
+    }
+
+    pub fn register_native_tool(&mut self, tool: Box<dyn NativeTool>) {
+        self.native_tools.register(tool);
+    }
+
+    // The server's tools followed by the in-process ones, minus any the agent may not call;
+    // also enables argument validation
+    pub async fn list_tools(&mut self) -> Result<Vec<model::Tool>> {
+        // e.g. a server that only offers resources
+        let mut tools = if self.mcp_client.supports("tools") {
+            self.mcp_client.list_tools().await?
+        } else {
+            Vec::new()
+        };
+        if self.file_editing {
+            tools.retain(|tool| tool.name != EDIT_FILE_TOOL);
+            tools.push(edit_file_tool());
+        }
+        tools.extend(self.native_tools.definitions());
+        tools.retain(|tool| self.tool_permitted(&tool.name));
+        self.tool_schemas = tools
+            .iter()
+            .map(|tool| (tool.name.to_string(), tool.schema_as_json_value()))
+            .collect();
+        self.tool_catalog = tools.clone();
+        if self.compacts(&tools) {
+            tools.push(describe_tool_tool());
         }
+        Ok(tools)
+    }
+
+    fn tool_schema(&self, tool: &str) -> Option<serde_json::Value> {
+        match self.native_tools.get(tool) {
+            Some(native) => Some(native.definition().schema_as_json_value()),
+            None => self.tool_schemas.get(tool).cloned(),
+        }
+    }
+
+    // Restrict which tools the model may call; everything is allowed by default
+    pub fn set_allowed_tools<I, S>(&mut self, tools: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.allowed_tools = Some(tools.into_iter().map(Into::into).collect());
+    }
+
+    // Tools the model may never call, even when the allowlist names them
+    pub fn set_denied_tools<I, S>(&mut self, tools: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.denied_tools = tools.into_iter().map(Into::into).collect();
+    }
+
+    pub fn set_approval_required<I, S>(&mut self, tools: I, approval: CommandApproval)
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.approval_required = tools.into_iter().map(Into::into).collect();
+        self.approval = approval;
+    }
+
+    pub fn tool_permitted(&self, tool: &str) -> bool {
+        !self.denied_tools.contains(tool)
+            && self
+                .allowed_tools
+                .as_ref()
+                .is_none_or(|allowed| allowed.contains(tool))
     }
 
-    pub async fn run_once(&mut self) -> Result<()> {
+    pub fn set_options(&mut self, options: RunOptions) {
+        self.options = options;
+    }
+
+    pub fn set_generation_params(&mut self, params: GenerationParams) {
+        self.params = params;
+    }
+
+    pub fn add_observer(&mut self, observer: Arc<dyn AgentObserver>) {
+        self.mcp_client.add_observer(observer.clone());
+        self.observers.push(observer);
+    }
+
+    // Mask secrets in prompts, transcripts, events, logs and the audit log
+    pub fn set_redactor(&mut self, redactor: Redactor) {
+        self.mcp_client.set_redactor(redactor.clone());
+        self.redactor = redactor;
+    }
+
+    // Screen tool output for prompt injection before the model reads it
+    pub fn set_injection_guard(&mut self, guard: InjectionGuard) {
+        self.injection_guard = Some(guard);
+    }
+
+    // What can't be redacted is dropped, never passed on as is
+    fn emit(&self, event: AgentEvent) {
+        let Ok(event) = self.redactor.redact_record(&event) else {
+            tracing::warn!("Dropping an event that could not be redacted");
+            return;
+        };
+        for observer in &self.observers {
+            observer.on_event(&event);
+        }
+    }
+
+    fn record(&mut self, entry: TranscriptEntry) {
+        let Ok(entry) = self.redactor.redact_record(&entry) else {
+            tracing::warn!("Dropping a transcript entry that could not be redacted");
+            return;
+        };
+        self.transcript.record(entry);
+    }
+
+    pub fn capabilities(&self) -> &ModelCapabilities {
+        &self.capabilities
+    }
+
+    // Cancel a tool call left running server-side by an abandoned turn
+    pub async fn cancel(&mut self, reason: &str) -> Result<()> {
+        self.mcp_client.cancel_in_flight(reason).await
+    }
+
+    pub async fn shutdown(&mut self) -> Result<()> {
+        self.mcp_client.shutdown().await
+    }
+
+    pub fn history(&self) -> &[ChatMessage] {
+        &self.history
+    }
+
+    pub fn transcript(&self) -> &Transcript {
+        &self.transcript
+    }
+
+    pub fn export_transcript(&self, format: TranscriptFormat) -> Result<String> {
+        self.transcript.export(format)
+    }
+
+    // Record history messages the transcript hasn't seen yet
+    fn transcribe_history(&mut self) {
+        let messages = self.history[self.transcribed_messages..].to_vec();
+        for message in messages {
+            self.record(TranscriptEntry::Message { message });
+        }
+        self.transcribed_messages = self.history.len();
+    }
+
+    // Insert a server-published prompt template after the system messages
+    pub async fn seed_prompt(
+        &mut self,
+        name: &str,
+        arguments: serde_json::Map<String, serde_json::Value>,
+    ) -> Result<()> {
+        let prompt = self.mcp_client.get_prompt(name, arguments).await?;
+        let seeded = prompt.messages.iter().map(|message| {
+            let role = match message.role {
+                model::PromptMessageRole::User => Role::User,
+                model::PromptMessageRole::Assistant => Role::Assistant,
+            };
+            ChatMessage::new(role, prompt_content_text(&message.content))
+        });
+        let seeded: Vec<ChatMessage> = seeded.collect();
+        for messages in [&mut self.base_messages, &mut self.history] {
+            let position = messages
+                .iter()
+                .take_while(|message| message.role == Role::System)
+                .count();
+            messages.splice(position..position, seeded.iter().cloned());
+        }
+        Ok(())
+    }
+
+    // Run a task in a fresh conversation, taking up to max_turns turns to reach a final answer
+    pub async fn run_task(&mut self, task: &Task) -> Result<TaskResult> {
+        self.run_recorded(task, None).await
+    }
+
+    // Carry on with a task from a checkpoint, e.g. one saved before the process restarted
+    pub async fn resume(&mut self, checkpoint: Checkpoint) -> Result<TaskResult> {
+        let task = checkpoint.task.clone();
+        self.run_recorded(&task, Some(checkpoint)).await
+    }
+
+    async fn run_recorded(
+        &mut self,
+        task: &Task,
+        checkpoint: Option<Checkpoint>,
+    ) -> Result<TaskResult> {
+        let started_at = self.clock.now();
+        let usage = self.usage();
+        let first_entry = self.transcript.entries().len();
+        let mut result = match checkpoint {
+            Some(checkpoint) => {
+                self.restore(checkpoint);
+                self.continue_task().await
+            }
+            None => self.attempt_task(task).await,
+        };
+        self.pending_call = None;
+        self.clear_checkpoint()?;
+        if let Ok(result) = &mut result {
+            result.workspace = self.workspace.clone();
+            if let Some(store) = &self.artifact_store {
+                store.collect(&mut result.artifacts, self.workspace.as_deref());
+            }
+        }
+        self.emit(match &result {
+            Ok(result) => AgentEvent::TaskFinished {
+                success: result.success,
+                turns: result.turns,
+                error: result.error.clone(),
+            },
+            Err(err) => AgentEvent::TaskFinished {
+                success: false,
+                turns: 0,
+                error: Some(err.to_string()),
+            },
+        });
+        self.remember_outcome(task, &result, first_entry).await;
+        if let Some(run_store) = &self.run_store {
+            let outcome = match &result {
+                Ok(result) => result.clone(),
+                Err(err) => TaskResult {
+                    success: false,
+                    artifacts: Vec::new(),
+                    turns: 0,
+                    error: Some(err.to_string()),
+                    workspace: self.workspace.clone(),
+                    output: None,
+                    timings: self.progress.timings.clone(),
+                },
+            };
+            run_store.record(&RunRecord {
+                started_at: started_at.to_rfc3339(),
+                task: task.clone(),
+                result: outcome,
+                usage: self.usage().since(&usage),
+                duration_ms: (self.clock.now() - started_at).num_milliseconds() as u64,
+                transcript: self.transcript.entries()[first_entry..].to_vec(),
+            })?;
+        }
+        result
+    }
+
+    async fn attempt_task(&mut self, task: &Task) -> Result<TaskResult> {
+        self.start_task(task).await?;
+        self.continue_task().await
+    }
+
+    async fn continue_task(&mut self) -> Result<TaskResult> {
+        if self.options.plan_first {
+            return self.run_planned().await;
+        }
+        let task = self.progress.task.clone();
+        while self.progress.turns < self.options.max_turns {
+            self.save_checkpoint()?;
+            self.progress.turns += 1;
+            let step = self.run_once().await;
+            if self.should_stop() {
+                if let Ok(AgentStep::FinalAnswer(text)) = step {
+                    self.progress.answers.push(Artifact::Text { text });
+                }
+                self.progress.error = None;
+                return Ok(self.task_result());
+            }
+            match step {
+                // The model carries on from the tool's result, or the call it would have made
+                Ok(AgentStep::ToolExecuted { .. } | AgentStep::ToolSkipped { .. }) => {
+                    self.progress.error = None;
+                }
+                Ok(AgentStep::NeedsClarification(question)) => {
+                    if self.clarify(&question).await? {
+                        continue;
+                    }
+                    self.progress.error = Some(format!("Model needs clarification: {}", question));
+                    return Ok(self.task_result());
+                }
+                Ok(AgentStep::FinalAnswer(text)) => {
+                    let problems: Vec<String> = self
+                        .validators
+                        .iter()
+                        .flat_map(|validator| validator.validate(&text))
+                        .collect();
+                    if !problems.is_empty() {
+                        self.history.push(ChatMessage::user(format!(
+                            "Your answer failed validation:\n- {}\nFix it and answer again.",
+                            problems.join("\n- ")
+                        )));
+                        self.progress.error =
+                            Some(format!("Validation failed: {}", problems.join("; ")));
+                        continue;
+                    }
+                    if let Err(problems) = self.accept_answer(&text) {
+                        self.history.push(ChatMessage::user(format!(
+                            "Your answer does not match the required output schema:\n- {}\nAnswer again with JSON that does.",
+                            problems.join("\n- ")
+                        )));
+                        self.progress.error =
+                            Some(format!("Output schema not met: {}", problems.join("; ")));
+                        continue;
+                    }
+                    // The critique is advisory: if it can't be parsed, keep the result
+                    if self.options.reflect {
+                        match self.critique(&task).await {
+                            Ok(Some(problems)) => {
+                                self.history.push(ChatMessage::user(format!(
+                                    "The result does not meet the task: {}. Correct it.",
+                                    problems
+                                )));
+                                self.progress.error =
+                                    Some(format!("Critique failed: {}", problems));
+                                continue;
+                            }
+                            Ok(None) => {}
+                            Err(err) => tracing::warn!("Skipping critique: {}", err),
+                        }
+                    }
+                    self.progress.answers.push(Artifact::Text { text });
+                    self.progress.error = None;
+                    return Ok(self.task_result());
+                }
+                Err(err @ (AgentError::LoopDetected(_) | AgentError::QuotaExceeded(_))) => {
+                    return Err(err)
+                }
+                Err(err) => {
+                    tracing::warn!(
+                        "Task turn {} failed: {}",
+                        self.progress.turns,
+                        self.redactor.redact(&err.to_string())
+                    );
+                    self.progress.error = Some(err.to_string());
+                }
+            }
+        }
+        if self.progress.error.is_none() {
+            self.progress.error = Some(format!(
+                "No final answer within {} turns",
+                self.options.max_turns
+            ));
+        }
+        Ok(self.task_result())
+    }
+
+    fn task_result(&self) -> TaskResult {
+        let mut artifacts = artifacts_from(&self.transcript.entries()[self.progress.first_entry..]);
+        artifacts.extend(self.progress.answers.clone());
+        TaskResult {
+            success: self.progress.error.is_none(),
+            artifacts,
+            turns: self.progress.turns,
+            error: self.progress.error.clone(),
+            workspace: None,
+            output: self.progress.output.clone(),
+            timings: self.progress.timings.clone(),
+        }
+    }
+
+    // Keeps the answer as the task's output if it matches the task's output schema
+    fn accept_answer(&mut self, text: &str) -> std::result::Result<(), Vec<String>> {
+        if let Some(schema) = &self.progress.task.output_schema {
+            self.progress.output = Some(step::structured_answer(schema, text)?);
+        }
+        Ok(())
+    }
+
+    // Fresh conversation: base messages, recalled memories, then the task itself
+    async fn start_task(&mut self, task: &Task) -> Result<()> {
+        self.history = self.base_messages.clone();
+        if let Some(scratchpad) = &self.scratchpad {
+            scratchpad.clear();
+        }
+        if let Some(memory) = &self.memory {
+            let memories = memory
+                .recall_where(&task.description, MEMORY_RECALL_LIMIT, |record| {
+                    !is_outcome(record)
+                })
+                .await?;
+            if !memories.is_empty() {
+                let facts: Vec<String> = memories
+                    .iter()
+                    .map(|memory| format!("- {}", memory.text))
+                    .collect();
+                self.history.push(ChatMessage::system(format!(
+                    "Relevant memories from earlier sessions:\n{}",
+                    facts.join("\n")
+                )));
+            }
+        }
+        if let Some(outcomes) = self.recall_outcomes(task).await? {
+            self.history.push(outcomes);
+        }
+        self.history.push(task.to_message());
+        self.transcribed_messages = 0;
+        self.progress = Progress {
+            task: task.clone(),
+            first_entry: self.transcript.entries().len(),
+            ..Progress::default()
+        };
+        Ok(())
+    }
+
+    pub async fn run_once(&mut self) -> Result<AgentStep> {
+        let Some(deadline) = self.options.turn_deadline else {
+            return self.turn().await;
+        };
+        match tokio::time::timeout(deadline, self.turn()).await {
+            Ok(result) => result,
+            Err(_) => {
+                let reason = format!("Turn exceeded deadline of {:?}", deadline);
+                self.cancel(&reason).await?;
+                Err(AgentError::Timeout(reason))
+            }
+        }
+    }
+
+    // Send the history to the model, recording and announcing what it replied
+    async fn complete(&mut self, format: Option<serde_json::Value>) -> Result<ModelResponse> {
+        let request = self.completion_request(format);
+        let model_response = self.timed_completion(&request).await?;
+        self.record_response(&model_response);
+        Ok(model_response)
+    }
+
+    fn completion_request(&mut self, format: Option<serde_json::Value>) -> CompletionRequest {
+        self.transcribe_history();
+        CompletionRequest {
+            messages: self
+                .history
+                .iter()
+                .map(|message| ChatMessage {
+                    content: self.redactor.redact(&message.content),
+                    ..message.clone()
+                })
+                .collect(),
+            params: self.generation_params(),
+            format,
+        }
+    }
+
+    // Every reply is a JSON object, so it can end where the object does
+    fn generation_params(&self) -> GenerationParams {
+        let mut params = self.params.clone();
+        if self.options.stop_sequences && self.capabilities.stop_sequences {
+            for stop in OutputFormat::JsonObject.stop_sequences() {
+                if !params.stop.contains(&stop) {
+                    params.stop.push(stop);
+                }
+            }
+        }
+        params
+    }
+
+    async fn timed_completion(&self, request: &CompletionRequest) -> Result<ModelResponse> {
+        let response = match self.options.model_timeout {
+            Some(limit) => tokio::time::timeout(limit, self.model.complete(request))
+                .await
+                .map_err(|_| {
+                    AgentError::Timeout(format!("Model did not respond within {:?}", limit))
+                })?,
+            None => self.model.complete(request).await,
+        }?;
+        let stopped_at_json = JSON_STOP_SEQUENCES
+            .iter()
+            .any(|stop| request.params.stop.iter().any(|set| set == stop));
+        match restore_closing_brace(&response.response).filter(|_| stopped_at_json) {
+            Some(restored) => Ok(ModelResponse {
+                response: restored,
+                ..response
+            }),
+            None => Ok(response),
+        }
+    }
+
+    fn record_response(&mut self, model_response: &ModelResponse) {
+        if let Some(reasoning) = &model_response.reasoning {
+            self.record(TranscriptEntry::Reasoning {
+                text: reasoning.clone(),
+            });
+        }
+        self.record(TranscriptEntry::ModelResponse {
+            response: model_response.response.clone(),
+        });
+        self.emit(AgentEvent::ModelResponse {
+            text: model_response.response.clone(),
+        });
+    }
+
+    async fn turn(&mut self) -> Result<AgentStep> {
+        let mut timing = TurnTiming {
+            turn: self.progress.turns,
+            ..TurnTiming::default()
+        };
+        let step = self.timed_turn(&mut timing).await;
+        self.progress.timings.push(timing);
+        step
+    }
+
+    async fn timed_turn(&mut self, timing: &mut TurnTiming) -> Result<AgentStep> {
+        if let Some(pending) = self.pending_call.clone() {
+            let request = serde_json::from_value(pending)?;
+            return self.timed_dispatch(request, timing).await;
+        }
+        if self.mcp_client.take_tools_changed() {
+            self.refresh_tools().await?;
+        }
+        self.select_tools().await?;
+
+        // Fail early rather than let the backend silently truncate the context
+        if let Some(max_context) = self.capabilities.max_context {
+            let tokens = estimate_message_tokens(&self.history);
+            if tokens > max_context {
+                return Err(AgentError::ModelError(format!(
+                    "Context of ~{} tokens exceeds the model limit of {}",
+                    tokens, max_context
+                )));
+            }
+        }
+
         // Ask model what actions to take
-        let model_response = self.model.complete(&self.context).await?;
+        // Tool calls are JSON, so constrain the output where the backend allows it, down to
+        // the shape of the final answer when the task has an output schema
+        let format = match &self.progress.task.output_schema {
+            Some(schema) if self.capabilities.json_schema => Some(step::reply_schema(schema)),
+            _ => self.capabilities.json_schema.then(|| json!("json")),
+        };
+        let started = Instant::now();
+        let model_response = if self.options.samples > 1 {
+            self.complete_best_of(format).await
+        } else {
+            self.complete(format).await
+        };
+        timing.model_ms = elapsed_ms(started);
+        let model_response = model_response?;
+
+        let parsing = Instant::now();
+        let reply = self.repaired(&model_response.response);
+        if let Some(step) = step::parse_reply(&reply) {
+            timing.parse_ms = elapsed_ms(parsing);
+            self.last_call = None;
+            self.history.push(ChatMessage::assistant(reply));
+            return Ok(step);
+        }
 
         // Parse the model's JSON response to get MCP requests
-        let mcp_request: model::JsonRpcRequest =
-            match serde_json::from_str(&model_response.response) {
-                Ok(response) => response,
-                Err(e) => {
-                    // Log the invalid response for debugging
-                    eprintln!("Failed to parse model response: {}", e);
-                    eprintln!("Raw response: {}", model_response.response);
-                    return Err(anyhow::anyhow!("Invalid JSON response from model"));
-                }
+        let parsed = serde_json::from_str(&reply);
+        timing.parse_ms = elapsed_ms(parsing);
+        let mcp_request: model::JsonRpcRequest = match parsed {
+            Ok(response) => response,
+            Err(e) => {
+                // Log the invalid response for debugging
+                tracing::warn!(
+                    "Failed to parse model response: {}; raw response: {}",
+                    e,
+                    self.redactor.redact(&model_response.response)
+                );
+                return Err(AgentError::ParseError(format!(
+                    "Invalid JSON response from model: {}",
+                    e
+                )));
+            }
+        };
+
+        // The call stays in the conversation, followed by its result once dispatched
+        self.history.push(ChatMessage::assistant(reply));
+        self.record(TranscriptEntry::ToolCall {
+            method: mcp_request.request.method.clone(),
+            params: json!(mcp_request.request.params),
+        });
+        let call = ToolCall::from_request(&mcp_request);
+        let called = call
+            .as_ref()
+            .map_or(&mcp_request.request.method, |call| &call.name)
+            .to_string();
+        self.emit(AgentEvent::ToolCall {
+            tool: called,
+            arguments: json!(mcp_request.request.params),
+        });
+        self.pending_call = Some(json!(mcp_request));
+        self.save_checkpoint()?;
+        self.timed_dispatch(mcp_request, timing).await
+    }
+
+    // The reply with almost-valid JSON fixed, noting the fixes in the transcript
+    fn repaired(&mut self, reply: &str) -> String {
+        let Some(repair) = repair_json(reply) else {
+            return reply.to_string();
+        };
+        tracing::debug!(fixes = ?repair.fixes, "Repaired model JSON");
+        self.record(TranscriptEntry::RepairedJson {
+            original: reply.to_string(),
+            repaired: repair.json.clone(),
+            fixes: repair.fixes,
+        });
+        repair.json
+    }
+
+    async fn timed_dispatch(
+        &mut self,
+        mcp_request: model::JsonRpcRequest,
+        timing: &mut TurnTiming,
+    ) -> Result<AgentStep> {
+        timing.tool = Some(
+            ToolCall::from_request(&mcp_request)
+                .map_or(mcp_request.request.method.clone(), |call| call.name),
+        );
+        let started = Instant::now();
+        let step = self.dispatch(mcp_request).await;
+        timing.tool_ms = elapsed_ms(started);
+        step
+    }
+
+    // Run a tool call the model asked for
+    async fn dispatch(&mut self, mcp_request: model::JsonRpcRequest) -> Result<AgentStep> {
+        self.pending_call = None;
+        let call = ToolCall::from_request(&mcp_request);
+        let called = call
+            .as_ref()
+            .map_or(&mcp_request.request.method, |call| &call.name)
+            .to_string();
+        if !MODEL_METHODS.contains(&mcp_request.request.method.as_str()) {
+            let err = AgentError::ToolError(format!(
+                "Method '{}' is not allowed; use one of {}",
+                mcp_request.request.method,
+                MODEL_METHODS.join(", ")
+            ));
+            let audit = audit_record(
+                &mcp_request,
+                self.mcp_client.server_name(),
+                Approval::Denied,
+            );
+            return self.finish_tool_call(called, audit, Err(err)).await;
+        }
+        if self.options.dry_run {
+            tracing::info!("Dry run: not executing {}", called);
+            self.emit(AgentEvent::ToolSkipped {
+                tool: called.clone(),
+                arguments: json!(mcp_request.request.params),
+            });
+            return Ok(AgentStep::ToolSkipped { tool: called });
+        }
+
+        // Resource reads feed the context; everything else goes to the server as is
+        if mcp_request.request.method == "resources/read" {
+            self.check_repetition(&mcp_request, &called)?;
+            let uri = mcp_request
+                .request
+                .params
+                .as_ref()
+                .and_then(|params| params.inner.get("uri"))
+                .and_then(|uri| uri.as_str())
+                .ok_or_else(|| {
+                    AgentError::ParseError("resources/read request without uri".to_string())
+                })?
+                .to_string();
+            let audit = audit_record(
+                &mcp_request,
+                self.mcp_client.server_name(),
+                Approval::Allowed,
+            );
+            let contents = match self
+                .mcp_client
+                .read_resource(&uri, self.options.default_tool_timeout)
+                .await
+            {
+                Ok(contents) => contents,
+                Err(err) => return self.finish_tool_call(called, audit, Err(err)).await,
             };
+            let result = self.record_tool_outcome(audit, Ok(json!(contents)))?;
+            let contents: Vec<model::ResourceContents> = serde_json::from_value(result.clone())?;
+            let mut message = self
+                .tool_message(&format_resource_contents(&contents))
+                .await;
+            if self.capabilities.vision {
+                message.images = resource_images(&contents);
+            }
+            self.history.push(message);
+            self.transcribe_history();
+            return Ok(AgentStep::ToolExecuted {
+                tool: called,
+                result,
+            });
+        }
+
+        // Only reads the catalog, so it needs no permission
+        let describe_call = call.as_ref().filter(|call| call.name == DESCRIBE_TOOL);
+        if let (true, Some(call)) = (self.compacts(&self.tool_catalog), describe_call) {
+            self.check_repetition(&mcp_request, &called)?;
+            let audit = audit_record(&mcp_request, NATIVE_SERVER, Approval::Allowed);
+            let outcome = self.show_tool(call).await;
+            return self.finish_tool_call(called, audit, outcome).await;
+        }
 
         // Execute each MCP request through the server
-        self.mcp_client.do_request(mcp_request).await?;
+        let tool = call.as_ref().map(|call| call.name.clone());
+        let server = self.tool_server(tool.as_deref()).to_string();
+        if let Some(tool) = &tool {
+            if !self.tool_permitted(tool) {
+                let err = AgentError::ToolError(format!("Tool '{}' is not allowed", tool));
+                let audit = audit_record(&mcp_request, &server, Approval::Denied);
+                return self.finish_tool_call(called, audit, Err(err)).await;
+            }
+            if self.approval_required.contains(tool) {
+                let arguments = call
+                    .as_ref()
+                    .map(|call| self.redactor.redact_value(&call.arguments))
+                    .unwrap_or_default();
+                if !permissions::approve(self.approval, tool, &arguments).await? {
+                    let err =
+                        AgentError::ToolError(format!("The call to '{}' was not approved", tool));
+                    let audit = audit_record(&mcp_request, &server, Approval::Denied);
+                    return self.finish_tool_call(called, audit, Err(err)).await;
+                }
+            }
+        }
+        let audit = audit_record(&mcp_request, &server, Approval::Allowed);
+        self.check_repetition(&mcp_request, &called)?;
+        if let Some(tool) = &tool {
+            if let (Some(schema), Some(call)) = (self.tool_schema(tool), &call) {
+                let errors = schema::validate(&schema, &call.arguments);
+                if !errors.is_empty() {
+                    // Shown to the model on the next attempt so it can correct the call
+                    let message = format!(
+                        "Invalid arguments for tool '{}': {}",
+                        tool,
+                        errors.join("; ")
+                    );
+                    let err = AgentError::ToolError(message);
+                    return self.finish_tool_call(called, audit, Err(err)).await;
+                }
+            }
+        }
+        if let Some(call) = &call {
+            if let Err(err) = self.check_quotas(call).await {
+                let audit = audit_record(&mcp_request, &server, Approval::Denied);
+                return self.finish_tool_call(called, audit, Err(err)).await;
+            }
+        }
+        let timeout = match &tool {
+            Some(tool) => self.options.tool_timeout(tool),
+            None => self.options.default_tool_timeout,
+        };
+        let knowledge_call = call
+            .as_ref()
+            .filter(|call| call.name == knowledge::SEARCH_KNOWLEDGE_TOOL);
+        if let (Some(knowledge), Some(call)) = (&self.knowledge, knowledge_call) {
+            let found = knowledge.call_tool(call).await;
+            let result = self.record_tool_outcome(audit, found.map(|found| json!(found)))?;
+            let message = self.tool_message(result.as_str().unwrap_or_default()).await;
+            self.history.push(message);
+            self.transcribe_history();
+            return Ok(AgentStep::ToolExecuted {
+                tool: called,
+                result,
+            });
+        }
+        let edit_call = call.as_ref().filter(|call| call.name == EDIT_FILE_TOOL);
+        if let (true, Some(call)) = (self.file_editing, edit_call) {
+            self.mcp_client.keepalive().await?;
+            let outcome = self.edit_file(call).await;
+            return self.finish_tool_call(called, audit, outcome).await;
+        }
+        if let Some(native) = tool.as_deref().and_then(|tool| self.native_tools.get(tool)) {
+            let arguments = call
+                .as_ref()
+                .map(ToolCall::arguments_object)
+                .unwrap_or_default();
+            let result = match tokio::time::timeout(timeout, native.call(&arguments)).await {
+                Ok(result) => result.map(|result| json!(result)),
+                Err(_) => Err(AgentError::Timeout(format!(
+                    "Tool did not respond within {:?}",
+                    timeout
+                ))),
+            };
+            return self.finish_tool_call(called, audit, result).await;
+        }
+        self.mcp_client.keepalive().await?;
+        let result = self
+            .mcp_client
+            .do_request_with_timeout(mcp_request, timeout)
+            .await;
+        self.finish_tool_call(called, audit, result).await
+    }
 
-        Ok(())
+    // Fails once the same allowed call with the same arguments comes back max_repeated_calls times
+    fn check_repetition(&mut self, request: &model::JsonRpcRequest, tool: &str) -> Result<()> {
+        let call = json!([request.request.method, request.request.params]);
+        let count = match &self.last_call {
+            Some((last, count)) if *last == call => count + 1,
+            _ => 1,
+        };
+        self.last_call = Some((call, count));
+        match self.options.max_repeated_calls {
+            Some(limit) if count >= limit => {
+                self.last_call = None;
+                Err(AgentError::LoopDetected(format!(
+                    "'{}' was called {} times in a row with the same arguments {}",
+                    tool,
+                    count,
+                    self.redactor.redact_value(&json!(request.request.params))
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Records the outcome and shows it to the model, errors included so it can correct the call
+    async fn finish_tool_call(
+        &mut self,
+        tool: String,
+        audit: AuditRecord,
+        outcome: Result<serde_json::Value>,
+    ) -> Result<AgentStep> {
+        let shown = match &outcome {
+            Ok(result) => result_text(result),
+            Err(AgentError::ToolError(message)) => message.clone(),
+            Err(err) => err.to_string(),
+        };
+        let result = self.record_tool_outcome(audit, outcome);
+        let message = self.tool_message(&shown).await;
+        self.history.push(message);
+        Ok(AgentStep::ToolExecuted {
+            tool,
+            result: result?,
+        })
+    }
+
+    // Where a tool call is executed: the knowledge base, in-process, or the MCP server
+    fn tool_server(&self, tool: Option<&str>) -> &str {
+        match tool {
+            Some(knowledge::SEARCH_KNOWLEDGE_TOOL) if self.knowledge.is_some() => KNOWLEDGE_SERVER,
+            Some(tool) if self.native_tools.get(tool).is_some() => NATIVE_SERVER,
+            _ => self.mcp_client.server_name(),
+        }
+    }
+
+    fn record_tool_outcome(
+        &mut self,
+        audit: AuditRecord,
+        outcome: Result<serde_json::Value>,
+    ) -> Result<serde_json::Value> {
+        match &outcome {
+            Ok(result) => {
+                self.record(TranscriptEntry::ToolResult {
+                    result: result.clone(),
+                });
+                self.emit(AgentEvent::ToolResult {
+                    tool: audit.tool.clone(),
+                    result: result.clone(),
+                });
+            }
+            Err(err) => {
+                self.record(TranscriptEntry::ToolError {
+                    error: err.to_string(),
+                });
+                self.emit(AgentEvent::ToolError {
+                    tool: audit.tool.clone(),
+                    error: err.to_string(),
+                });
+            }
+        }
+        if let Some(audit_log) = &self.audit_log {
+            match self.redactor.redact_record(&audit.with_outcome(&outcome)) {
+                Ok(record) => audit_log.append(&record)?,
+                Err(err) => tracing::warn!(
+                    "Dropping an audit record that could not be redacted: {}",
+                    err
+                ),
+            }
+        }
+        outcome
+    }
+}
+
+fn elapsed_ms(since: Instant) -> f64 {
+    since.elapsed().as_secs_f64() * 1000.0
+}
+
+fn audit_record(request: &model::JsonRpcRequest, server: &str, approval: Approval) -> AuditRecord {
+    let tool =
+        ToolCall::from_request(request).map_or(request.request.method.clone(), |call| call.name);
+    AuditRecord::new(server, &tool, json!(request.request.params), approval)
+}
+
+// A tool call result as the model reads it
+fn result_text(result: &serde_json::Value) -> String {
+    ToolResult::from_value(result).text()
+}
+
+fn format_resource_contents(contents: &[model::ResourceContents]) -> String {
+    let mut formatted = String::new();
+    for content in contents {
+        match content {
+            model::ResourceContents::TextResourceContents { uri, text, .. } => {
+                formatted.push_str(&format!("\nRESOURCE {}:\n{}\n", uri, text));
+            }
+            model::ResourceContents::BlobResourceContents { uri, mime_type, .. } => {
+                formatted.push_str(&format!(
+                    "\nRESOURCE {}: <binary {}>\n",
+                    uri,
+                    mime_type.as_deref().unwrap_or("content")
+                ));
+            }
+        }
     }
+    formatted
+}
+
+// Image blobs a vision-capable model can look at directly
+fn resource_images(contents: &[model::ResourceContents]) -> Vec<ImagePart> {
+    contents
+        .iter()
+        .filter_map(|content| match content {
+            model::ResourceContents::BlobResourceContents {
+                mime_type: Some(mime_type),
+                blob,
+                ..
+            } if mime_type.starts_with("image/") => {
+                Some(ImagePart::from_base64(mime_type.clone(), blob.clone()))
+            }
+            _ => None,
+        })
+        .collect()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{mcp_client, model_client::LocalOllamaClient};
-    use serde_json::json;
+    use crate::mcp_client;
+    use crate::memory::tests::LetterEmbedder;
+    use crate::model_client::ModelResponse;
+    use crate::model_client::{LocalOllamaClient, DEFAULT_OLLAMA_URL};
+    use crate::replay::{Fixture, Interaction, McpExchange, ReplayModelClient};
+    use async_trait::async_trait;
     use std::fs;
     use tokio::time::{timeout, Duration};
 
+    #[derive(Default)]
+    struct RecordingObserver(std::sync::Mutex<Vec<AgentEvent>>);
+
+    impl AgentObserver for RecordingObserver {
+        fn on_event(&self, event: &AgentEvent) {
+            self.0.lock().unwrap().push(event.clone());
+        }
+    }
+
     #[tokio::test]
     async fn acceptance_test_agent_stores_model_response() -> Result<()> {
         // Create a temporary directory for test outputs
         let expected_path = "./nature_inspired.txt";
 
         // Initial prompt that specifies the task using MCP
-        let initial_prompt = format!(
-            "SYSTEM: You are an agent with MCP capabilities. You have access to the filesystem write_file call, where the method is 'tools/call'. You must ONLY output valid JSON, with NO explanations or thinking process.
-HUMAN: Generate a haiku about nature and return it as the contents of a file named {} with the write_file command for the filesystem MCP server in the JSON-RPC format. For example:
+        let system_prompt = "You are an agent with MCP capabilities. You have access to the filesystem write_file call, where the method is 'tools/call'. You must ONLY output valid JSON, with NO explanations or thinking process.";
+        let task_prompt = format!(
+            "Generate a haiku about nature and return it as the contents of a file named {} with the write_file command for the filesystem MCP server in the JSON-RPC format. For example:
 {}
 
 Requirements:
@@ -69,8 +1309,7 @@ Requirements:
 2. Replace <first line>, <second line>, <third line> with your haiku
 3. DO NOT include any text outside the JSON
 4. DO NOT explain your thinking
-5. DO NOT add any formatting or indentation
-ASSISTANT: Output the JSON now:",
+5. DO NOT add any formatting or indentation",
 
             expected_path,
         json!({
@@ -86,16 +1325,26 @@ ASSISTANT: Output the JSON now:",
             "id": 1
         }),
         );
+        let initial_messages = vec![
+            ChatMessage::system(system_prompt),
+            ChatMessage::user(task_prompt),
+        ];
 
-        let model = LocalOllamaClient::new("qwen3".to_string());
+        let model = LocalOllamaClient::new("qwen3".to_string(), DEFAULT_OLLAMA_URL, None);
         let mut mcp_client = mcp_client::MCPClient::new();
         mcp_client.init().await?;
-        let mut agent = Agent::new(Box::new(model), mcp_client, initial_prompt);
+        let mut agent = Agent::new(Box::new(model), mcp_client, initial_messages);
 
         // Run the agent once with a 60-second timeout
         match timeout(Duration::from_secs(60), agent.run_once()).await {
-            Ok(result) => result?,
-            Err(_) => anyhow::bail!("Agent timed out after 60 seconds"),
+            Ok(result) => {
+                result?;
+            }
+            Err(_) => {
+                return Err(AgentError::Timeout(
+                    "Agent timed out after 60 seconds".to_string(),
+                ))
+            }
         }
 
         // Verify the file exists and has three lines (haiku structure)
@@ -112,5 +1361,865 @@ ASSISTANT: Output the JSON now:",
 
         Ok(())
     }
+
+    #[test]
+    fn test_format_resource_contents_includes_text_and_uri() {
+        let contents = vec![
+            model::ResourceContents::text("hello", "file:///notes.txt"),
+            model::ResourceContents::BlobResourceContents {
+                uri: "file:///image.png".to_string(),
+                mime_type: Some("image/png".to_string()),
+                blob: "aGVsbG8=".to_string(),
+            },
+        ];
+
+        let formatted = format_resource_contents(&contents);
+
+        assert!(formatted.contains("RESOURCE file:///notes.txt:\nhello"));
+        assert!(formatted.contains("RESOURCE file:///image.png: <binary image/png>"));
+    }
+
+    struct SmallContextModel;
+
+    #[async_trait]
+    impl ModelClient for SmallContextModel {
+        async fn complete(&self, _request: &CompletionRequest) -> Result<ModelResponse> {
+            panic!("model should not be called when the context is too large");
+        }
+
+        fn capabilities(&self) -> ModelCapabilities {
+            ModelCapabilities {
+                max_context: Some(4),
+                ..ModelCapabilities::default()
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_task_reports_written_files_as_artifacts() -> Result<()> {
+        let tool_call = json!({
+            "jsonrpc": "2.0",
+            "method": "tools/call",
+            "params": {"name": "write_file", "arguments": {"path": "haiku.txt", "content": "..."}},
+            "id": 1
+        });
+        let fixture = Fixture {
+            interactions: vec![
+                Interaction::model_reply("not json"),
+                Interaction::model_reply(&tool_call),
+                Interaction::Mcp(McpExchange::new(&tool_call, &Ok(json!({})))),
+                Interaction::model_reply(r#"{"final_answer": "Wrote haiku.txt"}"#),
+            ],
+        };
+        let mut agent = Agent::new(
+            Box::new(ReplayModelClient::new(&fixture)),
+            mcp_client::MCPClient::replaying(&fixture),
+            vec![ChatMessage::system("You are a poet")],
+        );
+
+        let result = agent.run_task(&Task::new("Write a haiku")).await?;
+
+        assert!(result.success);
+        assert_eq!(result.turns, 3);
+        assert_eq!(
+            result.artifacts,
+            vec![
+                Artifact::File {
+                    path: "haiku.txt".to_string(),
+                    stored: None,
+                },
+                Artifact::Text {
+                    text: "Wrote haiku.txt".to_string()
+                }
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_run_task_injects_relevant_memories() -> Result<()> {
+        let mut memory = MemoryStore::in_memory(Box::new(LetterEmbedder));
+        memory
+            .remember("haiku files go in the haiku directory")
+            .await?;
+        let mut agent = Agent::new(
+            Box::new(ReplayModelClient::new(&Fixture::default())),
+            mcp_client::MCPClient::replaying(&Fixture::default()),
+            vec![ChatMessage::system("You are a poet")],
+        );
+        agent.set_memory(memory);
+
+        agent.run_task(&Task::new("Write a haiku")).await?;
+
+        assert!(agent.history()[1]
+            .content
+            .contains("- haiku files go in the haiku directory"));
+        Ok(())
+    }
+
+    // Answers "answer 1", "answer 2", ... and keeps the questions
+    #[derive(Default)]
+    struct ScriptedClarifier {
+        questions: std::sync::Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl Clarifier for ScriptedClarifier {
+        async fn ask(&self, question: &str) -> Result<Option<String>> {
+            let mut questions = self.questions.lock().unwrap();
+            questions.push(question.to_string());
+            Ok(Some(format!("answer {}", questions.len())))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_clarification_is_answered_and_the_task_continues() -> Result<()> {
+        let fixture = Fixture::from_replies([
+            r#"{"clarification": "Which pond?"}"#,
+            r#"{"final_answer": "An old silent pond"}"#,
+        ]);
+        let clarifier = Arc::new(ScriptedClarifier::default());
+        let mut agent = Agent::new(
+            Box::new(ReplayModelClient::new(&fixture)),
+            mcp_client::MCPClient::replaying(&fixture),
+            vec![ChatMessage::system("You are a poet")],
+        );
+        agent.set_clarifier(clarifier.clone());
+
+        let result = agent.run_task(&Task::new("Write a haiku")).await?;
+
+        assert!(result.success);
+        assert_eq!(result.turns, 2);
+        assert_eq!(*clarifier.questions.lock().unwrap(), vec!["Which pond?"]);
+        assert!(agent
+            .history()
+            .iter()
+            .any(|message| message.content == "Answer to your question \"Which pond?\": answer 1"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_search_knowledge_is_answered_without_the_server() -> Result<()> {
+        let tool_call = json!({
+            "jsonrpc": "2.0",
+            "method": "tools/call",
+            "params": {"name": "search_knowledge", "arguments": {"query": "syllables"}},
+            "id": 1
+        });
+        let fixture = Fixture::from_replies([&tool_call, &json!({"final_answer": "17"})]);
+        let mut knowledge = KnowledgeBase::new(Box::new(LetterEmbedder));
+        knowledge
+            .ingest_text("style.md", "A haiku has 17 syllables")
+            .await?;
+        let mut agent = Agent::new(
+            Box::new(ReplayModelClient::new(&fixture)),
+            mcp_client::MCPClient::replaying(&fixture),
+            vec![ChatMessage::system("You are a poet")],
+        );
+        agent.set_knowledge(knowledge);
+
+        let result = agent.run_task(&Task::new("Write a haiku")).await?;
+
+        assert!(result.success);
+        assert!(agent
+            .history()
+            .iter()
+            .any(|message| message.role == Role::Tool
+                && message
+                    .content
+                    .contains("DOCUMENT style.md:\nA haiku has 17 syllables")));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_native_tools_are_called_in_process() -> Result<()> {
+        let tool_call = json!({
+            "jsonrpc": "2.0",
+            "method": "tools/call",
+            "params": {"name": "math_eval", "arguments": {"expression": "5 + 7 + 5"}},
+            "id": 1
+        });
+        let fixture = Fixture::from_replies([&tool_call, &json!({"final_answer": "17"})]);
+        let mut agent = Agent::new(
+            Box::new(ReplayModelClient::new(&fixture)),
+            mcp_client::MCPClient::replaying(&fixture),
+            vec![ChatMessage::system("You are a poet")],
+        );
+        agent.register_native_tool(Box::new(crate::native_tools::MathEvalTool));
+        let dir = tempfile::tempdir()?;
+        agent.set_audit_log(AuditLog::new(dir.path().join("audit.jsonl")));
+
+        let result = agent.run_task(&Task::new("Count syllables")).await?;
+
+        assert!(result.success);
+        let audited = AuditLog::new(dir.path().join("audit.jsonl"))
+            .query(&crate::audit::AuditQuery::default())?;
+        assert_eq!(audited[0].server, "native");
+        assert_eq!(audited[0].tool, "math_eval");
+        assert!(agent.transcript().entries().iter().any(|entry| matches!(
+            entry,
+            TranscriptEntry::ToolResult { result } if result["content"][0]["text"] == "17"
+        )));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_run_task_goes_on_after_tool_calls_until_the_final_answer() -> Result<()> {
+        let tool_call = json!({
+            "jsonrpc": "2.0",
+            "method": "tools/call",
+            "params": {"name": "math_eval", "arguments": {"expression": "5 + 7 + 5"}},
+            "id": 1
+        });
+        let answered = Fixture::from_replies([&tool_call, &json!({"final_answer": "17"})]);
+        let unanswered = Fixture::from_replies([&tool_call, &tool_call]);
+        let mut results = Vec::new();
+        for fixture in [answered, unanswered] {
+            let mut agent = Agent::new(
+                Box::new(ReplayModelClient::new(&fixture)),
+                mcp_client::MCPClient::replaying(&fixture),
+                vec![ChatMessage::system("You are a poet")],
+            );
+            agent.register_native_tool(Box::new(crate::native_tools::MathEvalTool));
+            agent.set_options(RunOptions {
+                max_turns: 2,
+                ..RunOptions::default()
+            });
+            results.push(agent.run_task(&Task::new("Count syllables")).await?);
+        }
+
+        assert!(results[0].success);
+        assert_eq!(results[0].turns, 2);
+        assert_eq!(
+            results[0].artifacts,
+            [Artifact::Text {
+                text: "17".to_string()
+            }]
+        );
+        assert!(!results[1].success);
+        assert_eq!(
+            results[1].error.as_deref(),
+            Some("No final answer within 2 turns")
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_the_next_request_shows_the_call_and_its_result() -> Result<()> {
+        let tool_call = json!({
+            "jsonrpc": "2.0",
+            "method": "tools/call",
+            "params": {"name": "math_eval", "arguments": {"expression": "5 + 7 + 5"}},
+            "id": 1
+        });
+        let fixture = Fixture::from_replies([
+            tool_call.to_string(),
+            r#"{"final_answer": "17"}"#.to_string(),
+        ]);
+        let recorder = crate::replay::Recorder::new();
+        let mut agent = Agent::new(
+            Box::new(crate::replay::RecordingModelClient::new(
+                Box::new(ReplayModelClient::new(&fixture)),
+                recorder.clone(),
+            )),
+            mcp_client::MCPClient::replaying(&fixture),
+            vec![ChatMessage::system("You are a poet")],
+        );
+        agent.register_native_tool(Box::new(crate::native_tools::MathEvalTool));
+
+        agent.start_task(&Task::new("Count syllables")).await?;
+        agent.run_once().await?;
+        agent.run_once().await?;
+
+        let Some(Interaction::Model(second)) = recorder.fixture().interactions.pop() else {
+            panic!("no second request");
+        };
+        let shown: Vec<(Role, &str)> = second.request.messages[2..]
+            .iter()
+            .map(|message| (message.role, message.content.as_str()))
+            .collect();
+        assert_eq!(
+            shown,
+            vec![
+                (Role::Assistant, tool_call.to_string().as_str()),
+                (Role::Tool, "17")
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_recalled_scratchpad_values_return_to_the_conversation() -> Result<()> {
+        let call = |name: &str, arguments: serde_json::Value| {
+            Interaction::model_reply(json!({
+                "jsonrpc": "2.0",
+                "method": "tools/call",
+                "params": {"name": name, "arguments": arguments},
+                "id": 1
+            }))
+        };
+        let fixture = Fixture {
+            interactions: vec![
+                call(
+                    "remember",
+                    json!({"key": "draft", "value": "An old silent pond"}),
+                ),
+                call("recall", json!({"key": "draft"})),
+                Interaction::model_reply(json!({"final_answer": "An old silent pond"})),
+            ],
+        };
+        let mut agent = Agent::new(
+            Box::new(ReplayModelClient::new(&fixture)),
+            mcp_client::MCPClient::replaying(&fixture),
+            vec![ChatMessage::system("You are a poet")],
+        );
+        let scratchpad = Scratchpad::new();
+        agent.set_scratchpad(scratchpad.clone());
+
+        let result = agent.run_task(&Task::new("Draft a haiku")).await?;
+
+        assert!(result.success);
+        assert!(agent.history()[1].content.contains("scratchpad"));
+        assert!(agent
+            .history()
+            .iter()
+            .any(|message| message.role == Role::Tool && message.content == "An old silent pond"));
+        agent.run_task(&Task::new("Start over")).await.ok();
+        assert!(scratchpad.keys().is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_identical_tool_calls_in_a_row_stop_the_loop() -> Result<()> {
+        let tool_call = json!({
+            "jsonrpc": "2.0",
+            "method": "tools/call",
+            "params": {"name": "math_eval", "arguments": {"expression": "1 + 1"}},
+            "id": 1
+        });
+        let reply = Interaction::model_reply(&tool_call);
+        let fixture = Fixture {
+            interactions: vec![reply.clone(), reply.clone(), reply],
+        };
+        let mut agent = Agent::new(
+            Box::new(ReplayModelClient::new(&fixture)),
+            mcp_client::MCPClient::replaying(&fixture),
+            vec![ChatMessage::system("You are a calculator")],
+        );
+        agent.register_native_tool(Box::new(crate::native_tools::MathEvalTool));
+
+        agent.run_once().await?;
+        agent.run_once().await?;
+        let err = agent.run_once().await.unwrap_err();
+
+        assert!(matches!(err, AgentError::LoopDetected(_)));
+        assert!(err.to_string().contains("'math_eval' was called 3 times"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_denied_tools_are_hidden_and_refused() -> Result<()> {
+        let tool = |name: &str| json!({"name": name, "description": name, "inputSchema": {"type": "object"}});
+        let call = json!({
+            "jsonrpc": "2.0",
+            "method": "tools/call",
+            "params": {"name": "write_file", "arguments": {"path": "a", "content": "b"}},
+            "id": 1
+        });
+        let fixture = Fixture {
+            interactions: vec![
+                Interaction::Mcp(McpExchange::new(
+                    &json!({"method": "tools/list"}),
+                    &Ok(
+                        json!({"tools": [tool("read_file"), tool("write_file"), tool("move_file")]}),
+                    ),
+                )),
+                Interaction::model_reply(&call),
+                Interaction::model_reply(
+                    json!({"jsonrpc": "2.0", "method": "resources/subscribe", "params": {"uri": "file:///a"}, "id": 2}),
+                ),
+            ],
+        };
+        let mut agent = Agent::new(
+            Box::new(ReplayModelClient::new(&fixture)),
+            mcp_client::MCPClient::replaying(&fixture),
+            vec![ChatMessage::system("You manage files")],
+        );
+        agent.set_allowed_tools(["read_file", "write_file"]);
+        agent.set_denied_tools(["write_file"]);
+
+        let names: Vec<String> = agent
+            .list_tools()
+            .await?
+            .iter()
+            .map(|tool| tool.name.to_string())
+            .collect();
+        assert_eq!(names, ["read_file"]);
+
+        agent.start_task(&Task::new("Write a file")).await?;
+        let err = agent.run_once().await.unwrap_err();
+        assert!(err.to_string().contains("Tool 'write_file' is not allowed"));
+        let err = agent.run_once().await.unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Method 'resources/subscribe' is not allowed"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_does_not_execute_tool_calls() -> Result<()> {
+        let tool_call = json!({
+            "jsonrpc": "2.0",
+            "method": "tools/call",
+            "params": {"name": "write_file", "arguments": {"path": "haiku.txt", "content": "..."}},
+            "id": 1
+        });
+        // No MCP exchange is recorded, so executing the call would fail
+        let fixture = Fixture::from_replies([&tool_call, &json!({"final_answer": "Done"})]);
+        let mut agent = Agent::new(
+            Box::new(ReplayModelClient::new(&fixture)),
+            mcp_client::MCPClient::replaying(&fixture),
+            vec![ChatMessage::system("You are a poet")],
+        );
+        agent.set_options(RunOptions {
+            dry_run: true,
+            ..RunOptions::default()
+        });
+        let events = Arc::new(RecordingObserver::default());
+        agent.add_observer(events.clone());
+
+        let result = agent.run_task(&Task::new("Write a haiku")).await?;
+
+        assert!(result.success);
+        assert!(!result
+            .artifacts
+            .iter()
+            .any(|artifact| matches!(artifact, Artifact::File { .. })));
+        assert!(agent.transcript().entries().iter().any(|entry| matches!(
+            entry,
+            TranscriptEntry::ToolCall { method, .. } if method == "tools/call"
+        )));
+        assert!(events.0.lock().unwrap().iter().any(|event| matches!(
+            event,
+            AgentEvent::ToolSkipped { tool, .. } if tool == "write_file"
+        )));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_secrets_are_redacted_from_prompts_and_transcript() -> Result<()> {
+        let fixture = Fixture::default();
+        let mut agent = Agent::new(
+            Box::new(ReplayModelClient::new(&fixture)),
+            mcp_client::MCPClient::replaying(&fixture),
+            vec![ChatMessage::system("Use the key hunter2-password")],
+        );
+        agent.set_redactor(Redactor::new().with_secret("hunter2-password"));
+
+        agent.run_task(&Task::new("Write a haiku")).await?;
+
+        let transcript = agent.export_transcript(TranscriptFormat::Jsonl)?;
+        assert!(transcript.contains("Use the key [REDACTED]"));
+        assert!(!transcript.contains("hunter2-password"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_invalid_tool_arguments_are_reported_back_to_the_model() -> Result<()> {
+        let invalid = json!({
+            "jsonrpc": "2.0",
+            "method": "tools/call",
+            "params": {"name": "math_eval", "arguments": {"expr": "1 + 1"}},
+            "id": 1
+        });
+        let valid = json!({
+            "jsonrpc": "2.0",
+            "method": "tools/call",
+            "params": {"name": "math_eval", "arguments": {"expression": "1 + 1"}},
+            "id": 2
+        });
+        let fixture = Fixture::from_replies([invalid, valid, json!({"final_answer": "2"})]);
+        let mut agent = Agent::new(
+            Box::new(ReplayModelClient::new(&fixture)),
+            mcp_client::MCPClient::replaying(&fixture),
+            vec![ChatMessage::system("You are a calculator")],
+        );
+        agent.register_native_tool(Box::new(crate::native_tools::MathEvalTool));
+
+        let result = agent.run_task(&Task::new("Add one and one")).await?;
+
+        assert!(result.success);
+        assert_eq!(result.turns, 3);
+        assert!(agent.history().iter().any(|message| message.content
+            == "Invalid arguments for tool 'math_eval': arguments is missing required field 'expression'"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_failed_resource_reads_are_reported_back_to_the_model() -> Result<()> {
+        let read = json!({
+            "jsonrpc": "2.0",
+            "method": "resources/read",
+            "params": {"uri": "file:///haiku.txt"},
+            "id": 1
+        });
+        let fixture = Fixture {
+            interactions: vec![
+                Interaction::model_reply(&read),
+                Interaction::Mcp(crate::replay::McpExchange::new(
+                    &read,
+                    &Err(AgentError::ToolError("Resource not found".to_string())),
+                )),
+                Interaction::model_reply(json!({"final_answer": "No haiku"})),
+            ],
+        };
+        let mut agent = Agent::new(
+            Box::new(ReplayModelClient::new(&fixture)),
+            mcp_client::MCPClient::replaying(&fixture),
+            vec![ChatMessage::system("You are a poet")],
+        );
+
+        let result = agent.run_task(&Task::new("Read the haiku")).await?;
+
+        assert!(result.success);
+        assert_eq!(result.turns, 2);
+        let tool_messages: Vec<&str> = agent
+            .history()
+            .iter()
+            .filter(|message| message.role == Role::Tool)
+            .map(|message| message.content.as_str())
+            .collect();
+        assert_eq!(tool_messages, ["tool error: Resource not found"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_final_answer_ends_the_task_as_a_text_artifact() -> Result<()> {
+        let fixture = Fixture::from_replies([json!({"final_answer": "An old silent pond"})]);
+        let mut agent = Agent::new(
+            Box::new(ReplayModelClient::new(&fixture)),
+            mcp_client::MCPClient::replaying(&fixture),
+            vec![ChatMessage::system(REPLY_FORMAT)],
+        );
+
+        let result = agent.run_task(&Task::new("Recite the first line")).await?;
+
+        assert!(result.success);
+        assert_eq!(
+            result.artifacts,
+            vec![Artifact::Text {
+                text: "An old silent pond".to_string()
+            }]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_almost_valid_json_is_repaired_and_noted() -> Result<()> {
+        let fixture = Fixture::from_replies(["{'final_answer': 'An old silent pond',"]);
+        let mut agent = Agent::new(
+            Box::new(ReplayModelClient::new(&fixture)),
+            mcp_client::MCPClient::replaying(&fixture),
+            vec![ChatMessage::system(REPLY_FORMAT)],
+        );
+
+        let result = agent.run_task(&Task::new("Recite the first line")).await?;
+
+        assert!(result.success, "{:?}", result.error);
+        assert!(agent
+            .transcript()
+            .entries()
+            .contains(&TranscriptEntry::RepairedJson {
+                original: "{'final_answer': 'An old silent pond',".to_string(),
+                repaired: "{\"final_answer\": \"An old silent pond\"}".to_string(),
+                fixes: vec![
+                    "replaced single quotes".to_string(),
+                    "removed trailing commas".to_string(),
+                    "closed unbalanced braces".to_string(),
+                ],
+            }));
+        Ok(())
+    }
+
+    // Stops where the backend would, dropping the stop sequence it matched
+    struct StoppingModel(std::sync::Mutex<Vec<String>>);
+
+    #[async_trait]
+    impl ModelClient for StoppingModel {
+        async fn complete(&self, request: &CompletionRequest) -> Result<ModelResponse> {
+            *self.0.lock().unwrap() = request.params.stop.clone();
+            let generated =
+                "{\"final_answer\": \"An old silent pond\"}\n\nI answered with the line.";
+            let end = request
+                .params
+                .stop
+                .iter()
+                .filter_map(|stop| generated.find(stop.as_str()))
+                .min()
+                .unwrap_or(generated.len());
+            Ok(ModelResponse {
+                response: generated[..end].to_string(),
+                reasoning: None,
+            })
+        }
+
+        fn capabilities(&self) -> ModelCapabilities {
+            ModelCapabilities {
+                stop_sequences: true,
+                ..ModelCapabilities::default()
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replies_stop_at_the_end_of_their_json() -> Result<()> {
+        let model = Arc::new(StoppingModel(Default::default()));
+        let mut agent = Agent::new(
+            Box::new(model.clone()),
+            mcp_client::MCPClient::new(),
+            vec![ChatMessage::system(REPLY_FORMAT)],
+        );
+        agent.set_generation_params(GenerationParams {
+            stop: vec!["Observation:".to_string()],
+            ..GenerationParams::default()
+        });
+
+        let step = agent.run_once().await?;
+
+        assert_eq!(
+            step,
+            AgentStep::FinalAnswer("An old silent pond".to_string())
+        );
+        assert_eq!(
+            *model.0.lock().unwrap(),
+            ["Observation:", "}\n\n", "}\n```"]
+        );
+        assert!(!agent
+            .transcript()
+            .entries()
+            .iter()
+            .any(|entry| matches!(entry, TranscriptEntry::RepairedJson { .. })));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_run_task_is_saved_to_the_run_store() -> Result<()> {
+        let fixture = Fixture::from_replies([json!({"final_answer": "An old silent pond"})]);
+        let mut agent = Agent::new(
+            Box::new(ReplayModelClient::new(&fixture)),
+            mcp_client::MCPClient::replaying(&fixture),
+            vec![ChatMessage::system(REPLY_FORMAT)],
+        );
+        let store = RunStore::in_memory()?;
+        agent.set_run_store(store.clone());
+
+        agent.run_task(&Task::new("Recite the first line")).await?;
+
+        let runs = store.runs(&crate::storage::RunQuery::default())?;
+        assert_eq!(runs.len(), 1);
+        assert!(runs[0].run.result.success);
+        assert_eq!(runs[0].run.task.description, "Recite the first line");
+        assert_eq!(runs[0].run.usage.requests, 1);
+        assert!(matches!(
+            runs[0].run.transcript.last(),
+            Some(TranscriptEntry::ModelResponse { .. })
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_invalid_final_answer_is_sent_back_with_problems() -> Result<()> {
+        let answer = |text: &str| Interaction::model_reply(json!({ "final_answer": text }));
+        let fixture = Fixture {
+            interactions: vec![answer("seventeen"), answer("17")],
+        };
+        let mut agent = Agent::new(
+            Box::new(ReplayModelClient::new(&fixture)),
+            mcp_client::MCPClient::replaying(&fixture),
+            vec![ChatMessage::system(REPLY_FORMAT)],
+        );
+        agent.add_validator(Box::new(crate::validation::RegexValidator::new(r"^\d+$")?));
+
+        let result = agent.run_task(&Task::new("Count the syllables")).await?;
+
+        assert!(result.success);
+        assert_eq!(result.turns, 2);
+        assert!(agent
+            .history()
+            .iter()
+            .any(|message| message.content.contains("- output does not match /^\\d+$/")));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_final_answer_must_match_the_output_schema() -> Result<()> {
+        #[derive(serde::Deserialize)]
+        struct Haiku {
+            title: String,
+            lines: Vec<String>,
+        }
+        let answer =
+            |answer: serde_json::Value| Interaction::model_reply(json!({ "final_answer": answer }));
+        let fixture = Fixture {
+            interactions: vec![
+                answer(json!("An old silent pond")),
+                answer(json!({"title": "Pond", "lines": ["An old silent pond"]})),
+            ],
+        };
+        let mut agent = Agent::new(
+            Box::new(ReplayModelClient::new(&fixture)),
+            mcp_client::MCPClient::replaying(&fixture),
+            vec![ChatMessage::system(REPLY_FORMAT)],
+        );
+        let task = Task::new("Write a haiku").with_output_schema(json!({
+            "type": "object",
+            "properties": {
+                "title": {"type": "string"},
+                "lines": {"type": "array", "items": {"type": "string"}}
+            },
+            "required": ["title", "lines"]
+        }));
+
+        let result = agent.run_task(&task).await?;
+
+        assert!(result.success);
+        assert_eq!(result.turns, 2);
+        assert!(agent.history().iter().any(|message| message
+            .content
+            .contains("- answer should be object but is string")));
+        let haiku: Haiku = result.output_as()?;
+        assert_eq!(haiku.title, "Pond");
+        assert_eq!(haiku.lines, ["An old silent pond"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_result_times_every_turn() -> Result<()> {
+        let call = json!({
+            "jsonrpc": "2.0",
+            "method": "tools/call",
+            "params": {"name": "math_eval", "arguments": {"expression": "5 + 7 + 5"}},
+            "id": 1
+        });
+        let fixture = Fixture::from_replies([
+            "five, seven, five".to_string(),
+            call.to_string(),
+            json!({"final_answer": "17"}).to_string(),
+        ]);
+        let mut agent = Agent::new(
+            Box::new(ReplayModelClient::new(&fixture)),
+            mcp_client::MCPClient::replaying(&fixture),
+            vec![ChatMessage::system(REPLY_FORMAT)],
+        );
+        agent.register_native_tool(Box::new(crate::native_tools::MathEvalTool));
+
+        let result = agent
+            .run_task(&Task::new("Count a haiku's syllables"))
+            .await?;
+
+        assert!(result.success);
+        let turns: Vec<(usize, Option<&str>)> = result
+            .timings
+            .iter()
+            .map(|timing| (timing.turn, timing.tool.as_deref()))
+            .collect();
+        assert_eq!(turns, [(1, None), (2, Some("math_eval")), (3, None)]);
+        Ok(())
+    }
+
+    struct SlowModel;
+
+    #[async_trait]
+    impl ModelClient for SlowModel {
+        async fn complete(&self, _request: &CompletionRequest) -> Result<ModelResponse> {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Ok(ModelResponse {
+                response: "{}".to_string(),
+                reasoning: None,
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_once_respects_turn_deadline() {
+        let mut agent = Agent::new(
+            Box::new(SlowModel),
+            mcp_client::MCPClient::new(),
+            vec![ChatMessage::user("hello")],
+        );
+        agent.set_options(RunOptions {
+            turn_deadline: Some(Duration::from_millis(10)),
+            ..RunOptions::default()
+        });
+
+        let result = agent.run_once().await;
+
+        assert!(matches!(result, Err(AgentError::Timeout(_))));
+    }
+
+    #[tokio::test]
+    async fn test_run_once_rejects_context_beyond_model_limit() {
+        let mut agent = Agent::new(
+            Box::new(SmallContextModel),
+            mcp_client::MCPClient::new(),
+            vec![ChatMessage::user(
+                "a prompt that is clearly longer than sixteen characters",
+            )],
+        );
+
+        let result = agent.run_once().await;
+
+        assert!(matches!(result, Err(AgentError::ModelError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_changed_tools_are_described_from_the_next_turn() -> Result<()> {
+        // Offers write_file, says its tools changed while answering the call, then adds read_file
+        let script = r#"reply() {
+read request
+id=$(echo "$request" | sed 's/.*"id":\([0-9]*\).*/\1/')
+[ -n "$2" ] && echo "$2"
+echo "{\"jsonrpc\":\"2.0\",\"id\":$id,\"result\":$1}"
 }
+tool() { echo "{\"name\":\"$1\",\"description\":\"$1\",\"inputSchema\":{\"type\":\"object\"}}"; }
+reply '{}'
+read initialized
+reply "{\"tools\":[$(tool write_file)]}"
+reply '{"content":[]}' '{"jsonrpc":"2.0","method":"notifications/tools/list_changed"}'
+reply "{\"tools\":[$(tool write_file),$(tool read_file)]}"
+cat > /dev/null"#;
+        let mut mcp_client = mcp_client::MCPClient::with_server(mcp_client::ServerConfig {
+            name: "changing".to_string(),
+            command: "sh".to_string(),
+            args: vec!["-c".to_string(), script.to_string()],
+            framing: mcp_client::Framing::Newline,
+            request_limit: None,
+            log_level: None,
+        });
+        mcp_client.init().await?;
+        let model = ReplayModelClient::new(&Fixture::from_replies([
+            json!({
+                "jsonrpc": "2.0",
+                "method": "tools/call",
+                "params": {"name": "write_file", "arguments": {}},
+                "id": 1
+            }),
+            json!({"final_answer": "Done"}),
+        ]));
+        let mut agent = Agent::builder()
+            .model(Box::new(model))
+            .mcp_client(mcp_client)
+            .generated_system_prompt(SystemPrompt::new("You are a poet"))
+            .build()
+            .await?;
+        let before = agent.history()[0].content.clone();
+
+        agent.run_task(&Task::new("Write a haiku")).await?;
+        agent.run_task(&Task::new("Read it back")).await?;
 
+        agent.shutdown().await?;
+        assert!(!before.contains("read_file"));
+        assert!(agent.history()[0].content.contains("read_file"));
+        Ok(())
+    }
+}
@@ -0,0 +1,107 @@
+use super::quota::Quotas;
+use super::tool_result::ToolResultPolicy;
+use crate::model_client::usage::Pricing;
+use crate::native_tools::CommandApproval;
+use std::collections::HashMap;
+use std::time::Duration;
+
+pub const DEFAULT_TOOL_TIMEOUT: Duration = Duration::from_secs(5);
+pub const DEFAULT_MAX_REPEATED_CALLS: usize = 3;
+pub const DEFAULT_MAX_TOOL_RESULT_BYTES: usize = 32 * 1024;
+pub const DEFAULT_SAMPLE_TEMPERATURE: f32 = 0.7;
+
+#[derive(Debug, Clone)]
+pub struct RunOptions {
+    pub default_tool_timeout: Duration,
+    // Overrides for slow tools such as search or build, keyed by tool name
+    pub tool_timeouts: HashMap<String, Duration>,
+    pub model_timeout: Option<Duration>,
+    // Upper bound on a whole turn: model completion plus tool execution
+    pub turn_deadline: Option<Duration>,
+    // Turns run_task takes, tool calls and failed attempts alike, to reach a final answer
+    pub max_turns: usize,
+    // Record the tool calls the model asks for without executing any of them
+    pub dry_run: bool,
+    // Ask for a step-by-step plan first, then execute and verify it one step at a time
+    pub plan_first: bool,
+    // Have the model check each result against the success criteria and correct it if it fails
+    pub reflect: bool,
+    // Stop once the model makes the identical tool call this many times in a row
+    pub max_repeated_calls: Option<usize>,
+    // Prices a plan's estimated usage; None when the backend's price is unknown
+    pub pricing: Option<Pricing>,
+    // Don't execute a plan estimated to cost more than this many US dollars
+    pub budget: Option<f64>,
+    // Tool output put into the conversation is fitted to this size by tool_result_policy
+    pub max_tool_result_bytes: usize,
+    pub tool_result_policy: ToolResultPolicy,
+    // Completions requested at once for each turn; the first valid tool call or answer is used
+    pub samples: usize,
+    // Temperature for the samples when the generation params don't set a higher one, so they
+    // differ
+    pub sample_temperature: f32,
+    // Limits on files written, bytes written and commands run; going over pauses the run
+    pub quotas: Quotas,
+    // Whether a run that would go over a quota asks the user, goes on or stops. Asking without a
+    // terminal stops it. Kept apart from tool approval, so approving every command doesn't lift
+    // the quotas too
+    pub quota_approval: CommandApproval,
+    // End replies at the close of their JSON object with stop sequences, on backends that
+    // honour them
+    pub stop_sequences: bool,
+    // Keep a summary of how each task went in the memory store, recalled for similar tasks
+    pub remember_outcomes: bool,
+}
+
+impl Default for RunOptions {
+    fn default() -> Self {
+        Self {
+            default_tool_timeout: DEFAULT_TOOL_TIMEOUT,
+            tool_timeouts: HashMap::new(),
+            model_timeout: None,
+            turn_deadline: None,
+            max_turns: 3,
+            dry_run: false,
+            plan_first: false,
+            reflect: false,
+            max_repeated_calls: Some(DEFAULT_MAX_REPEATED_CALLS),
+            pricing: None,
+            budget: None,
+            max_tool_result_bytes: DEFAULT_MAX_TOOL_RESULT_BYTES,
+            tool_result_policy: ToolResultPolicy::Truncate,
+            samples: 1,
+            sample_temperature: DEFAULT_SAMPLE_TEMPERATURE,
+            quotas: Quotas::default(),
+            quota_approval: CommandApproval::Ask,
+            stop_sequences: true,
+            remember_outcomes: false,
+        }
+    }
+}
+
+impl RunOptions {
+    pub fn with_tool_timeout(mut self, tool: &str, timeout: Duration) -> Self {
+        self.tool_timeouts.insert(tool.to_string(), timeout);
+        self
+    }
+
+    pub fn tool_timeout(&self, tool: &str) -> Duration {
+        self.tool_timeouts
+            .get(tool)
+            .copied()
+            .unwrap_or(self.default_tool_timeout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tool_timeout_falls_back_to_default() {
+        let options = RunOptions::default().with_tool_timeout("search", Duration::from_secs(60));
+
+        assert_eq!(options.tool_timeout("search"), Duration::from_secs(60));
+        assert_eq!(options.tool_timeout("write_file"), DEFAULT_TOOL_TIMEOUT);
+    }
+}
@@ -0,0 +1,171 @@
+use super::Agent;
+use crate::error::Result;
+use crate::memory::MemoryRecord;
+use crate::model_client::ChatMessage;
+use crate::task::{Artifact, Task, TaskResult};
+use crate::transcript::TranscriptEntry;
+use serde_json::Value;
+
+// Marks the memories that are how a past task went, as opposed to facts
+pub const PAST_TASK_PREFIX: &str = "Past task: ";
+
+const OUTCOME_RECALL_LIMIT: usize = 2;
+// Enough of a task, answer or error to tell what happened
+const MAX_DETAIL_CHARS: usize = 300;
+
+pub fn is_outcome(record: &MemoryRecord) -> bool {
+    record.text.starts_with(PAST_TASK_PREFIX)
+}
+
+// A few sentences on how a task went: whether it worked, its answer or what went wrong, and
+// the tools it called
+pub fn outcome_summary(task: &Task, result: &Result<TaskResult>, tools: &[String]) -> String {
+    let mut sentences = vec![format!(
+        "{}\"{}\".",
+        PAST_TASK_PREFIX,
+        truncate(&task.description)
+    )];
+    match result {
+        Ok(result) if result.success => {
+            sentences.push(format!("Succeeded in {} turns.", result.turns));
+            let answer = result
+                .artifacts
+                .iter()
+                .rev()
+                .find_map(|artifact| match artifact {
+                    Artifact::Text { text } => Some(text),
+                    _ => None,
+                });
+            if let Some(answer) = answer {
+                sentences.push(format!("Answer: {}", truncate(answer)));
+            }
+        }
+        Ok(result) => sentences.push(format!(
+            "Failed after {} turns because: {}",
+            result.turns,
+            truncate(result.error.as_deref().unwrap_or("unknown"))
+        )),
+        Err(err) => sentences.push(format!("Stopped because: {}", truncate(&err.to_string()))),
+    }
+    if !tools.is_empty() {
+        sentences.push(format!("Tools called: {}.", tools.join(", ")));
+    }
+    sentences.join(" ")
+}
+
+fn truncate(text: &str) -> String {
+    let text = text.trim();
+    match text.char_indices().nth(MAX_DETAIL_CHARS) {
+        Some((end, _)) => format!("{}...", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+// Each tool once, in the order first called
+fn tools_called(entries: &[TranscriptEntry]) -> Vec<String> {
+    let mut tools: Vec<String> = Vec::new();
+    for entry in entries {
+        if let TranscriptEntry::ToolCall { params, .. } = entry {
+            if let Some(tool) = params.get("name").and_then(Value::as_str) {
+                if !tools.iter().any(|known| known == tool) {
+                    tools.push(tool.to_string());
+                }
+            }
+        }
+    }
+    tools
+}
+
+impl Agent {
+    // Keeps how the task went in long-term memory, for similar tasks later. Failing to is not
+    // worth failing the task over
+    pub(super) async fn remember_outcome(
+        &mut self,
+        task: &Task,
+        result: &Result<TaskResult>,
+        first_entry: usize,
+    ) {
+        if !self.options.remember_outcomes {
+            return;
+        }
+        let tools = tools_called(&self.transcript.entries()[first_entry..]);
+        let summary = self.redactor.redact(&outcome_summary(task, result, &tools));
+        if let Some(memory) = &mut self.memory {
+            if let Err(err) = memory.remember(&summary).await {
+                tracing::warn!("Could not remember the task's outcome: {}", err);
+            }
+        }
+    }
+
+    // How the most similar past tasks went, to repeat what worked and avoid what didn't
+    pub(super) async fn recall_outcomes(&self, task: &Task) -> Result<Option<ChatMessage>> {
+        let Some(memory) = &self.memory else {
+            return Ok(None);
+        };
+        let outcomes = memory
+            .recall_where(&task.description, OUTCOME_RECALL_LIMIT, is_outcome)
+            .await?;
+        if outcomes.is_empty() {
+            return Ok(None);
+        }
+        let outcomes: Vec<String> = outcomes
+            .iter()
+            .map(|outcome| format!("- {}", &outcome.text[PAST_TASK_PREFIX.len()..]))
+            .collect();
+        Ok(Some(ChatMessage::system(format!(
+            "Similar tasks from earlier sessions and how they went:\n{}",
+            outcomes.join("\n")
+        ))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::RunOptions;
+    use crate::mcp_client::MCPClient;
+    use crate::memory::tests::LetterEmbedder;
+    use crate::memory::MemoryStore;
+    use crate::replay::{Fixture, ReplayModelClient};
+
+    #[tokio::test]
+    async fn test_outcomes_are_remembered_and_recalled_for_similar_tasks() -> Result<()> {
+        let fixture = Fixture::from_replies([
+            "A haiku about the pond",
+            r#"{"final_answer": "An old silent pond"}"#,
+        ]);
+        let mut memory = MemoryStore::in_memory(Box::new(LetterEmbedder));
+        memory.remember("the user prefers haiku").await?;
+        let mut agent = Agent::new(
+            Box::new(ReplayModelClient::new(&fixture)),
+            MCPClient::replaying(&fixture),
+            vec![ChatMessage::system("You are a poet")],
+        );
+        agent.set_memory(memory);
+        agent.set_options(RunOptions {
+            max_turns: 1,
+            remember_outcomes: true,
+            ..RunOptions::default()
+        });
+
+        let failed = agent.run_task(&Task::new("Write a haiku")).await?;
+        let succeeded = agent.run_task(&Task::new("Write a haiku")).await?;
+
+        assert!(!failed.success);
+        assert!(succeeded.success);
+        let recalled = agent
+            .history()
+            .iter()
+            .find(|message| message.content.starts_with("Similar tasks"))
+            .unwrap();
+        assert!(recalled
+            .content
+            .contains("- \"Write a haiku\". Failed after 1 turns because: parse error"));
+        assert!(agent
+            .history()
+            .iter()
+            .any(|message| message.content.ends_with("- the user prefers haiku")));
+        assert_eq!(agent.memory_mut().unwrap().len(), 3);
+        Ok(())
+    }
+}
@@ -0,0 +1,191 @@
+use crate::error::{AgentError, Result};
+use crate::knowledge::SEARCH_KNOWLEDGE_TOOL;
+use crate::mcp_client::git::{
+    GIT_COMMIT_TOOL, GIT_DIFF_STAGED_TOOL, GIT_DIFF_UNSTAGED_TOOL, GIT_STATUS_TOOL,
+};
+use crate::native_tools::{
+    CommandApproval, FETCH_URL_TOOL, READ_MESSAGES_TOOL, RECALL_TOOL, REMEMBER_TOOL,
+};
+use crate::terminal;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::Path;
+
+pub const READ_ONLY_PROFILE: &str = "read-only";
+pub const DEVELOPER_PROFILE: &str = "developer";
+pub const ADMIN_PROFILE: &str = "admin";
+
+// Tools that look but don't touch: the filesystem server's readers, web search and fetching,
+// and the in-process tools without side effects
+pub const READ_ONLY_TOOLS: &[&str] = &[
+    "read_file",
+    "read_text_file",
+    "read_media_file",
+    "read_multiple_files",
+    "list_directory",
+    "list_directory_with_sizes",
+    "directory_tree",
+    "search_files",
+    "get_file_info",
+    "list_allowed_directories",
+    GIT_STATUS_TOOL,
+    GIT_DIFF_STAGED_TOOL,
+    GIT_DIFF_UNSTAGED_TOOL,
+    "brave_web_search",
+    "brave_local_search",
+    "search",
+    "fetch_content",
+    FETCH_URL_TOOL,
+    SEARCH_KNOWLEDGE_TOOL,
+    REMEMBER_TOOL,
+    RECALL_TOOL,
+    READ_MESSAGES_TOOL,
+    "current_time",
+    "math_eval",
+];
+
+// Hard to undo, so a developer confirms each call
+pub const DEVELOPER_APPROVAL_TOOLS: &[&str] = &[GIT_COMMIT_TOOL, "move_file"];
+
+// Which tools a run may call and which of them need the user's approval first
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PermissionProfile {
+    pub name: String,
+    // None allows every tool not denied
+    #[serde(default)]
+    pub allowed_tools: Option<Vec<String>>,
+    #[serde(default)]
+    pub denied_tools: Vec<String>,
+    #[serde(default)]
+    pub approval_required: Vec<String>,
+    // How those approvals, and run_command's, are decided
+    pub approval: CommandApproval,
+}
+
+impl PermissionProfile {
+    pub fn read_only() -> Self {
+        Self {
+            name: READ_ONLY_PROFILE.to_string(),
+            allowed_tools: Some(
+                READ_ONLY_TOOLS
+                    .iter()
+                    .map(|tool| tool.to_string())
+                    .collect(),
+            ),
+            denied_tools: Vec::new(),
+            approval_required: Vec::new(),
+            approval: CommandApproval::Never,
+        }
+    }
+
+    pub fn developer() -> Self {
+        Self {
+            name: DEVELOPER_PROFILE.to_string(),
+            allowed_tools: None,
+            denied_tools: Vec::new(),
+            approval_required: DEVELOPER_APPROVAL_TOOLS
+                .iter()
+                .map(|tool| tool.to_string())
+                .collect(),
+            approval: CommandApproval::Ask,
+        }
+    }
+
+    pub fn admin() -> Self {
+        Self {
+            name: ADMIN_PROFILE.to_string(),
+            allowed_tools: None,
+            denied_tools: Vec::new(),
+            approval_required: Vec::new(),
+            approval: CommandApproval::Always,
+        }
+    }
+
+    // A built-in profile, or one of these custom ones, which take precedence
+    pub fn named(name: &str, custom: &[PermissionProfile]) -> Result<Self> {
+        if let Some(profile) = custom.iter().find(|profile| profile.name == name) {
+            return Ok(profile.clone());
+        }
+        match name {
+            READ_ONLY_PROFILE => Ok(Self::read_only()),
+            DEVELOPER_PROFILE => Ok(Self::developer()),
+            ADMIN_PROFILE => Ok(Self::admin()),
+            _ => Err(AgentError::ConfigError(format!(
+                "Unknown permission profile '{}'",
+                name
+            ))),
+        }
+    }
+
+    // A JSON array of profiles
+    pub fn load(path: impl AsRef<Path>) -> Result<Vec<Self>> {
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+}
+
+// Whether a call that needs approval may go ahead; Ask leaves it to the user on the terminal
+pub(super) async fn approve(
+    approval: CommandApproval,
+    tool: &str,
+    arguments: &Value,
+) -> Result<bool> {
+    match approval {
+        CommandApproval::Always => Ok(true),
+        CommandApproval::Never => Ok(false),
+        CommandApproval::Ask => {
+            eprintln!("The model wants to call {}:", tool);
+            eprintln!("  {}", arguments);
+            terminal::confirm("Allow?").await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::Agent;
+    use crate::mcp_client::MCPClient;
+    use crate::replay::{Fixture, ReplayModelClient};
+    use crate::task::Task;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_profiles_limit_tools_and_require_approval() -> Result<()> {
+        let commit = json!({
+            "jsonrpc": "2.0",
+            "method": "tools/call",
+            "params": {"name": GIT_COMMIT_TOOL, "arguments": {"message": "Add haiku"}},
+            "id": 1
+        });
+        let fixture = Fixture::from_replies([&commit]);
+        let custom: Vec<PermissionProfile> = serde_json::from_value(json!([{
+            "name": "reviewer",
+            "allowed_tools": ["read_file", GIT_COMMIT_TOOL],
+            "approval_required": [GIT_COMMIT_TOOL],
+            "approval": "never"
+        }]))?;
+        let mut agent = Agent::builder()
+            .model(Box::new(ReplayModelClient::new(&fixture)))
+            .mcp_client(MCPClient::replaying(&fixture))
+            .system_prompt("You review changes")
+            .allow_tools(["read_file", "write_file", GIT_COMMIT_TOOL])
+            .permission_profile(PermissionProfile::named("reviewer", &custom)?)
+            .build()
+            .await?;
+
+        agent.start_task(&Task::new("Commit the haiku")).await?;
+        let err = agent.run_once().await.unwrap_err();
+
+        assert!(err
+            .to_string()
+            .contains("The call to 'git_commit' was not approved"));
+        assert!(agent.tool_permitted("read_file"));
+        assert!(!agent.tool_permitted("write_file"));
+        assert!(!PermissionProfile::read_only()
+            .allowed_tools
+            .unwrap()
+            .contains(&"write_file".to_string()));
+        assert!(PermissionProfile::named("root", &custom).is_err());
+        Ok(())
+    }
+}
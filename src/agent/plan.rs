@@ -0,0 +1,368 @@
+use super::{Agent, AgentStep};
+use crate::error::{AgentError, Result};
+use crate::events::AgentEvent;
+use crate::model_client::usage::{CostEstimate, Usage};
+use crate::model_client::{estimate_message_tokens, estimate_tokens, ChatMessage};
+use crate::task::{Artifact, TaskResult};
+use crate::transcript::TranscriptEntry;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+pub const PLAN_FORMAT: &str = r#"Do not act yet. Reply only with a step-by-step plan as {"steps": [{"description": "<what to do>", "tool": "<tool the step calls, or null if it only answers>"}]}."#;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PlanStep {
+    pub description: String,
+    #[serde(default)]
+    pub tool: Option<String>,
+}
+
+impl PlanStep {
+    fn summary(&self) -> String {
+        match &self.tool {
+            Some(tool) => format!("{} (call {})", self.description, tool),
+            None => self.description.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Plan {
+    pub steps: Vec<PlanStep>,
+}
+
+// Assumed length of a step's reply when max_tokens doesn't bound it
+const ESTIMATED_REPLY_TOKENS: usize = 100;
+
+fn announcement(index: usize, plan: &Plan) -> String {
+    format!(
+        "Step {}/{}: {}",
+        index + 1,
+        plan.steps.len(),
+        plan.steps[index].summary()
+    )
+}
+
+fn plan_schema() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "steps": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "description": {"type": "string"},
+                        "tool": {"type": ["string", "null"]}
+                    },
+                    "required": ["description"]
+                }
+            }
+        },
+        "required": ["steps"]
+    })
+}
+
+// What a step actually did, or why it doesn't count as the planned step
+fn verify(planned: &PlanStep, step: &AgentStep) -> std::result::Result<String, String> {
+    match (step, &planned.tool) {
+        (
+            AgentStep::ToolExecuted { tool, .. } | AgentStep::ToolSkipped { tool },
+            Some(expected),
+        ) if tool != expected => Err(format!("called '{}' instead of '{}'", tool, expected)),
+        (AgentStep::ToolExecuted { tool, .. } | AgentStep::ToolSkipped { tool }, _) => {
+            Ok(format!("called {}", tool))
+        }
+        (AgentStep::FinalAnswer(_), Some(expected)) => {
+            Err(format!("answered without calling '{}'", expected))
+        }
+        (AgentStep::FinalAnswer(text), None) => Ok(format!("answered: {}", text)),
+        (AgentStep::NeedsClarification(question), _) => {
+            Err(format!("needs clarification: {}", question))
+        }
+    }
+}
+
+impl Agent {
+    // One turn per step, each prompt carrying the conversation so far plus every earlier
+    // step's announcement, reply and tool result
+    fn estimate_cost(&self, plan: &Plan) -> CostEstimate {
+        let reply = self
+            .params
+            .max_tokens
+            .map_or(ESTIMATED_REPLY_TOKENS, |max| max as usize);
+        let mut context = estimate_message_tokens(&self.history);
+        let mut usage = Usage::default();
+        for index in 0..plan.steps.len() {
+            context += estimate_tokens(&announcement(index, plan));
+            usage.requests += 1;
+            usage.prompt_tokens += context;
+            usage.completion_tokens += reply;
+            context += 2 * reply;
+        }
+        CostEstimate {
+            usage,
+            cost: self.options.pricing.map(|pricing| pricing.cost(&usage)),
+        }
+    }
+
+    // Phase one asks for a plan; phase two runs it a step at a time, checking each
+    // step did what was planned before moving on
+    // Resumed from a checkpoint, it picks up at the step in progress
+    pub(super) async fn run_planned(&mut self) -> Result<TaskResult> {
+        if self.progress.plan.is_none() {
+            self.history.push(ChatMessage::user(PLAN_FORMAT));
+        }
+        let format = if self.capabilities.json_schema {
+            Some(plan_schema())
+        } else {
+            None
+        };
+
+        let mut error = None;
+        while self.progress.plan.is_none() && self.progress.turns < self.options.max_turns {
+            self.progress.turns += 1;
+            match self.complete(format.clone()).await.and_then(|response| {
+                let reply = self.repaired(&response.response);
+                Ok((serde_json::from_str::<Plan>(&reply)?, response.response))
+            }) {
+                Ok((parsed, raw)) => {
+                    self.history.push(ChatMessage::assistant(raw));
+                    self.record(TranscriptEntry::Plan {
+                        steps: parsed.steps.iter().map(PlanStep::summary).collect(),
+                    });
+                    self.progress.plan = Some(parsed);
+                    error = None;
+                }
+                Err(err) => error = Some(format!("No usable plan: {}", err)),
+            }
+        }
+        let Some(plan) = self.progress.plan.clone() else {
+            return Ok(TaskResult {
+                success: false,
+                artifacts: Vec::new(),
+                turns: self.progress.turns,
+                error,
+                workspace: None,
+                output: None,
+                timings: Vec::new(),
+            });
+        };
+
+        let fresh = self.progress.plan_step == 0 && !self.progress.step_announced;
+        if fresh && (self.options.dry_run || self.options.budget.is_some()) {
+            let estimate = self.estimate_cost(&plan);
+            self.record(TranscriptEntry::CostEstimate { estimate });
+            self.emit(AgentEvent::CostEstimated { estimate });
+            if let (Some(cost), Some(budget)) = (estimate.cost, self.options.budget) {
+                if cost > budget {
+                    self.progress.error = Some(format!(
+                        "Estimated cost ${:.4} is over the budget of ${:.4}",
+                        cost, budget
+                    ));
+                    return Ok(self.task_result());
+                }
+            }
+        }
+
+        for (index, planned) in plan.steps.iter().enumerate().skip(self.progress.plan_step) {
+            if !self.progress.step_announced {
+                self.save_checkpoint()?;
+                self.history
+                    .push(ChatMessage::user(announcement(index, &plan)));
+                self.progress.step_announced = true;
+            }
+            let mut actual = None;
+            let mut failure = String::from("not attempted");
+            for _ in 0..self.options.max_turns {
+                self.progress.turns += 1;
+                let step = self.run_once().await;
+                if self.should_stop() {
+                    if let Ok(AgentStep::FinalAnswer(text)) = step {
+                        self.progress.answers.push(Artifact::Text { text });
+                    }
+                    self.progress.error = None;
+                    return Ok(self.task_result());
+                }
+                let step = match step {
+                    Ok(step) => step,
+                    Err(err @ (AgentError::LoopDetected(_) | AgentError::QuotaExceeded(_))) => {
+                        return Err(err)
+                    }
+                    Err(err) => {
+                        failure = err.to_string();
+                        continue;
+                    }
+                };
+                let verdict = verify(planned, &step).and_then(|done| match &step {
+                    AgentStep::FinalAnswer(text) => {
+                        self.accept_answer(text).map(|_| done).map_err(|problems| {
+                            format!(
+                                "answer does not match the output schema: {}",
+                                problems.join("; ")
+                            )
+                        })
+                    }
+                    _ => Ok(done),
+                });
+                match verdict {
+                    Ok(done) => {
+                        if let AgentStep::FinalAnswer(text) = step {
+                            self.progress.answers.push(Artifact::Text { text });
+                        }
+                        actual = Some(done);
+                        break;
+                    }
+                    Err(mismatch) => {
+                        failure = mismatch;
+                        if let AgentStep::NeedsClarification(question) = &step {
+                            if self.clarify(question).await? {
+                                continue;
+                            }
+                            break;
+                        }
+                        self.history.push(ChatMessage::user(format!(
+                            "That did not complete step {} as planned: {}. Try the step again.",
+                            index + 1,
+                            failure
+                        )));
+                    }
+                }
+            }
+
+            self.record(TranscriptEntry::PlanStep {
+                step: index + 1,
+                planned: planned.summary(),
+                actual: actual.clone().unwrap_or_else(|| failure.clone()),
+                verified: actual.is_some(),
+            });
+            if actual.is_none() {
+                self.progress.error = Some(format!("Step {} failed: {}", index + 1, failure));
+                break;
+            }
+            self.progress.plan_step = index + 1;
+            self.progress.step_announced = false;
+        }
+        Ok(self.task_result())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::RunOptions;
+    use crate::mcp_client::MCPClient;
+    use crate::replay::{Fixture, ReplayModelClient};
+    use crate::task::Task;
+
+    #[tokio::test]
+    async fn test_plan_is_executed_and_verified_step_by_step() -> Result<()> {
+        let fixture = Fixture::from_replies([
+            json!({"steps": [
+                {"description": "Add the syllables", "tool": "math_eval"},
+                {"description": "Report the total", "tool": null}
+            ]}),
+            json!({"final_answer": "too early"}),
+            json!({
+                "jsonrpc": "2.0",
+                "method": "tools/call",
+                "params": {"name": "math_eval", "arguments": {"expression": "5 + 7 + 5"}},
+                "id": 1
+            }),
+            json!({"final_answer": "17"}),
+        ]);
+        let mut agent = Agent::new(
+            Box::new(ReplayModelClient::new(&fixture)),
+            MCPClient::replaying(&fixture),
+            vec![ChatMessage::system("You are a calculator")],
+        );
+        agent.register_native_tool(Box::new(crate::native_tools::MathEvalTool));
+        agent.set_options(RunOptions {
+            plan_first: true,
+            ..RunOptions::default()
+        });
+
+        let result = agent
+            .run_task(&Task::new("Count a haiku's syllables"))
+            .await?;
+
+        assert!(result.success);
+        assert_eq!(result.turns, 4);
+        assert_eq!(
+            result.artifacts,
+            vec![Artifact::Text {
+                text: "17".to_string()
+            }]
+        );
+        let steps: Vec<_> = agent
+            .transcript()
+            .entries()
+            .iter()
+            .filter_map(|entry| match entry {
+                TranscriptEntry::PlanStep {
+                    actual, verified, ..
+                } => Some((actual.as_str(), *verified)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            steps,
+            vec![("called math_eval", true), ("answered: 17", true)]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_plan_over_budget_is_estimated_but_not_executed() -> Result<()> {
+        let fixture = Fixture::from_replies([json!({"steps": [
+            {"description": "Write the haiku", "tool": "write_file"},
+            {"description": "Report the path", "tool": null}
+        ]})]);
+        let mut agent = Agent::new(
+            Box::new(ReplayModelClient::new(&fixture)),
+            MCPClient::replaying(&fixture),
+            vec![ChatMessage::system("You are a poet")],
+        );
+        agent.set_generation_params(crate::model_client::GenerationParams {
+            max_tokens: Some(50),
+            ..Default::default()
+        });
+        agent.set_options(RunOptions {
+            plan_first: true,
+            dry_run: true,
+            pricing: Some(crate::model_client::usage::Pricing {
+                prompt_per_million: 1_000.0,
+                completion_per_million: 2_000.0,
+            }),
+            budget: Some(0.01),
+            ..RunOptions::default()
+        });
+
+        let result = agent.run_task(&Task::new("Write a haiku")).await?;
+
+        let estimate = agent
+            .transcript()
+            .entries()
+            .iter()
+            .find_map(|entry| match entry {
+                TranscriptEntry::CostEstimate { estimate } => Some(*estimate),
+                _ => None,
+            })
+            .expect("an estimate");
+        // Two turns of 50 reply tokens each; the second prompt also carries the first reply
+        // and its tool result
+        assert_eq!(estimate.usage.requests, 2);
+        assert_eq!(estimate.usage.completion_tokens, 100);
+        assert!(estimate.usage.prompt_tokens > 100);
+        assert_eq!(
+            estimate.cost,
+            Some((estimate.usage.prompt_tokens as f64 * 1_000.0 + 100.0 * 2_000.0) / 1_000_000.0)
+        );
+        assert!(!result.success);
+        assert_eq!(result.turns, 1);
+        assert!(result
+            .error
+            .is_some_and(|error| error.contains("over the budget of $0.0100")));
+        Ok(())
+    }
+}
@@ -0,0 +1,259 @@
+use super::catalog::{summary, DESCRIBE_TOOL};
+use super::{FewShotLibrary, REPLY_FORMAT};
+use rmcp::model::Tool;
+use serde_json::{json, Map, Value};
+use std::path::PathBuf;
+
+const JSON_ONLY_RULE: &str =
+    "You must ONLY output valid JSON, with NO explanations or thinking process.";
+
+// A system prompt assembled from what the agent can actually do, so it can't drift from the tools
+#[derive(Debug, Clone, PartialEq)]
+pub struct SystemPrompt {
+    role: String,
+    tools: Vec<Tool>,
+    roots: Vec<PathBuf>,
+    rules: Vec<String>,
+    examples: FewShotLibrary,
+    examples_per_tool: usize,
+    // Name and one line per tool, leaving the arguments to describe_tool
+    compact: bool,
+}
+
+impl SystemPrompt {
+    pub fn new(role: impl Into<String>) -> Self {
+        Self {
+            role: role.into(),
+            tools: Vec::new(),
+            roots: Vec::new(),
+            rules: vec![JSON_ONLY_RULE.to_string(), REPLY_FORMAT.to_string()],
+            examples: FewShotLibrary::new(),
+            examples_per_tool: 0,
+            compact: false,
+        }
+    }
+
+    pub fn with_tools(mut self, tools: impl IntoIterator<Item = Tool>) -> Self {
+        self.tools.extend(tools);
+        self
+    }
+
+    // A directory the tools are confined to
+    pub fn with_root(mut self, root: impl Into<PathBuf>) -> Self {
+        let root = root.into();
+        if !self.roots.contains(&root) {
+            self.roots.push(root);
+        }
+        self
+    }
+
+    pub fn with_rule(mut self, rule: impl Into<String>) -> Self {
+        self.rules.push(rule.into());
+        self
+    }
+
+    // Worked examples for the listed tools, up to `per_tool` each
+    pub fn with_examples(mut self, examples: FewShotLibrary, per_tool: usize) -> Self {
+        self.examples = examples;
+        self.examples_per_tool = per_tool;
+        self
+    }
+
+    pub fn with_compact_tools(mut self) -> Self {
+        self.compact = true;
+        self
+    }
+
+    pub fn render(&self) -> String {
+        let mut sections = vec![self.role.clone()];
+        let describer = self
+            .tools
+            .iter()
+            .find(|tool| self.compact && tool.name == DESCRIBE_TOOL);
+        if let Some(first) = describer.or(self.tools.first()) {
+            let tools: Vec<String> = self
+                .tools
+                .iter()
+                .map(|tool| match describer {
+                    Some(describer) if tool.name != describer.name => {
+                        format!("- {}: {}", tool.name, summary(tool))
+                    }
+                    _ => describe_tool(tool),
+                })
+                .collect();
+            sections.push(format!("Tools:\n{}", tools.join("\n")));
+            if describer.is_some() {
+                sections.push(format!(
+                    "Only the tools' names are listed. Call {} with a tool's name to see its arguments before calling it.",
+                    DESCRIBE_TOOL
+                ));
+            }
+            sections.push(format!(
+                "Call a tool by replying with a JSON-RPC request, for example:\n{}",
+                example_call(first)
+            ));
+            let names: Vec<&str> = self.tools.iter().map(|tool| tool.name.as_ref()).collect();
+            let examples: Vec<String> = self
+                .examples
+                .for_tools(&names, self.examples_per_tool)
+                .iter()
+                .map(|example| example.render())
+                .collect();
+            if !examples.is_empty() {
+                sections.push(format!("Examples:\n{}", examples.join("\n\n")));
+            }
+        }
+        if !self.roots.is_empty() {
+            let roots: Vec<String> = self
+                .roots
+                .iter()
+                .map(|root| root.display().to_string())
+                .collect();
+            sections.push(format!(
+                "Files can only be read and written under: {}",
+                roots.join(", ")
+            ));
+        }
+        let rules: Vec<String> = self
+            .rules
+            .iter()
+            .map(|rule| format!("- {}", rule))
+            .collect();
+        sections.push(format!("Rules:\n{}", rules.join("\n")));
+        sections.join("\n\n")
+    }
+}
+
+// "- name: description Arguments: path (string, required), ...", required arguments first
+pub(super) fn describe_tool(tool: &Tool) -> String {
+    let schema = tool.schema_as_json_value();
+    let required = required_arguments(&schema);
+    let mut arguments = Vec::new();
+    if let Some(properties) = properties(&schema) {
+        let kind = |name: &str| {
+            properties[name]["type"]
+                .as_str()
+                .unwrap_or("any")
+                .to_string()
+        };
+        for name in required
+            .iter()
+            .filter(|name| properties.contains_key(**name))
+        {
+            arguments.push(format!("{} ({}, required)", name, kind(name)));
+        }
+        for name in properties.keys() {
+            if !required.contains(&name.as_str()) {
+                arguments.push(format!("{} ({})", name, kind(name)));
+            }
+        }
+    }
+    let mut line = format!("- {}: {}", tool.name, tool.description.trim());
+    if !arguments.is_empty() {
+        line.push_str(&format!(" Arguments: {}", arguments.join(", ")));
+    }
+    line
+}
+
+// A call with a placeholder for every required argument, shaped by its type
+pub(super) fn example_call(tool: &Tool) -> Value {
+    let schema = tool.schema_as_json_value();
+    let required = required_arguments(&schema);
+    let mut arguments = Map::new();
+    if let Some(properties) = properties(&schema) {
+        for (name, property) in properties {
+            if !required.contains(&name.as_str()) {
+                continue;
+            }
+            let placeholder = match property["type"].as_str() {
+                Some("integer" | "number") => json!(0),
+                Some("boolean") => json!(false),
+                Some("array") => json!([]),
+                Some("object") => json!({}),
+                _ => json!(format!("<{}>", name)),
+            };
+            arguments.insert(name.clone(), placeholder);
+        }
+    }
+    json!({
+        "jsonrpc": "2.0",
+        "method": "tools/call",
+        "params": {"name": tool.name, "arguments": arguments},
+        "id": 1
+    })
+}
+
+fn properties(schema: &Value) -> Option<&Map<String, Value>> {
+    schema.get("properties").and_then(Value::as_object)
+}
+
+fn required_arguments(schema: &Value) -> Vec<&str> {
+    schema
+        .get("required")
+        .and_then(Value::as_array)
+        .map(|required| required.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_file() -> Tool {
+        serde_json::from_value(json!({
+            "name": "write_file",
+            "description": "Create or overwrite a file",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "path": {"type": "string"},
+                    "content": {"type": "string"},
+                    "append": {"type": "boolean"}
+                },
+                "required": ["path", "content"]
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_render_describes_tools_roots_and_rules() {
+        let prompt = SystemPrompt::new("You write haikus.")
+            .with_tools([write_file()])
+            .with_root("./haiku")
+            .with_rule("One haiku per file.")
+            .render();
+
+        assert!(prompt.starts_with("You write haikus.\n\nTools:\n"));
+        assert!(prompt.contains(
+            "- write_file: Create or overwrite a file Arguments: path (string, required), content (string, required), append (boolean)"
+        ));
+        assert!(prompt.contains(&example_call(&write_file()).to_string()));
+        assert_eq!(
+            example_call(&write_file())["params"]["arguments"],
+            json!({"path": "<path>", "content": "<content>"})
+        );
+        assert!(prompt.contains("Files can only be read and written under: ./haiku"));
+        assert!(prompt.ends_with("- One haiku per file."));
+    }
+
+    #[test]
+    fn test_render_includes_examples_for_listed_tools_only() {
+        let prompt = SystemPrompt::new("You write haikus.")
+            .with_tools([write_file()])
+            .with_examples(FewShotLibrary::builtin(), 1)
+            .render();
+
+        assert!(prompt.contains("Examples:\nUser: Save a note saying hello to notes/hello.txt"));
+        assert!(!prompt.contains("User: What does README.md say?"));
+    }
+
+    #[test]
+    fn test_render_without_tools_keeps_the_rules() {
+        let prompt = SystemPrompt::new("You answer questions.").render();
+
+        assert!(!prompt.contains("Tools:"));
+        assert!(prompt.contains(JSON_ONLY_RULE));
+        assert!(prompt.contains(REPLY_FORMAT));
+    }
+}
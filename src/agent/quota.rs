@@ -0,0 +1,222 @@
+use super::edit::WRITE_FILE_TOOL;
+use super::{Agent, EDIT_FILE_TOOL};
+use crate::error::{AgentError, Result};
+use crate::mcp_client::ToolCall;
+use crate::native_tools::{CommandApproval, RUN_COMMAND_TOOL, RUN_TESTS_TOOL};
+use crate::terminal;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::io::IsTerminal;
+
+// Limits on a run's side effects; None is unlimited
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Quotas {
+    // Distinct paths written or edited
+    pub max_files_written: Option<usize>,
+    // Content written, counting the new text of edits
+    pub max_bytes_written: Option<u64>,
+    // Shell commands and test runs
+    pub max_commands: Option<usize>,
+}
+
+impl Quotas {
+    pub fn is_unlimited(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+// The side effects of the run so far, counted against its quotas
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SideEffects {
+    pub files_written: BTreeSet<String>,
+    pub bytes_written: u64,
+    pub commands: usize,
+}
+
+impl SideEffects {
+    // These effects plus what the call would add
+    fn with_call(&self, call: &ToolCall) -> Self {
+        let mut effects = self.clone();
+        let arguments = &call.arguments;
+        match call.name.as_str() {
+            WRITE_FILE_TOOL | EDIT_FILE_TOOL => {
+                if let Some(path) = arguments["path"].as_str() {
+                    effects.files_written.insert(path.to_string());
+                }
+                effects.bytes_written += written_bytes(arguments);
+            }
+            RUN_COMMAND_TOOL | RUN_TESTS_TOOL => effects.commands += 1,
+            _ => {}
+        }
+        effects
+    }
+
+    // The quotas these effects go over, described
+    fn exceeded(&self, quotas: &Quotas) -> Vec<String> {
+        let mut exceeded = Vec::new();
+        if let Some(max) = quotas.max_files_written {
+            if self.files_written.len() > max {
+                exceeded.push(format!("more than {} files written", max));
+            }
+        }
+        if let Some(max) = quotas.max_bytes_written {
+            if self.bytes_written > max {
+                exceeded.push(format!("more than {} bytes written", max));
+            }
+        }
+        if let Some(max) = quotas.max_commands {
+            if self.commands > max {
+                exceeded.push(format!("more than {} commands", max));
+            }
+        }
+        exceeded
+    }
+}
+
+// write_file's content, the filesystem server's edit_file newText, or our edit_file's edit
+fn written_bytes(arguments: &Value) -> u64 {
+    let text = |value: &Value| value.as_str().map_or(0, |text| text.len() as u64);
+    let edits: u64 = arguments["edits"]
+        .as_array()
+        .map(|edits| edits.iter().map(|edit| text(&edit["newText"])).sum())
+        .unwrap_or_default();
+    text(&arguments["content"]) + text(&arguments["edit"]) + edits
+}
+
+impl Agent {
+    // Counts the call's side effects, pausing the run when they would go over a quota. If the
+    // user continues, the quotas start over; otherwise the run stops
+    pub(super) async fn check_quotas(&mut self, call: &ToolCall) -> Result<()> {
+        let quotas = self.options.quotas;
+        let effects = self.progress.side_effects.with_call(call);
+        // Counted without quotas too, for files_written
+        if quotas.is_unlimited() {
+            self.progress.side_effects = effects;
+            return Ok(());
+        }
+        let exceeded = effects.exceeded(&quotas);
+        if exceeded.is_empty() {
+            self.progress.side_effects = effects;
+            return Ok(());
+        }
+        let reason = format!(
+            "calling {} would go over the run's quotas: {}",
+            call.name,
+            exceeded.join(", ")
+        );
+        let proceed = match self.options.quota_approval {
+            CommandApproval::Always => true,
+            CommandApproval::Never => false,
+            CommandApproval::Ask if !std::io::stdin().is_terminal() => {
+                tracing::warn!("Stopping the run: {}, and no terminal to ask on", reason);
+                false
+            }
+            CommandApproval::Ask => ask_to_continue(&reason).await?,
+        };
+        if !proceed {
+            return Err(AgentError::QuotaExceeded(reason));
+        }
+        tracing::info!("Run continued past its quotas");
+        self.progress.side_effects = SideEffects::default().with_call(call);
+        Ok(())
+    }
+
+    // The paths write_file and edit_file were called on since the quotas last started over, as
+    // the model gave them
+    pub fn files_written(&self) -> &BTreeSet<String> {
+        &self.progress.side_effects.files_written
+    }
+}
+
+async fn ask_to_continue(reason: &str) -> Result<bool> {
+    eprintln!("The run is paused: {}", reason);
+    terminal::confirm("Continue with fresh quotas?").await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::RunOptions;
+    use crate::mcp_client::MCPClient;
+    use crate::model_client::ChatMessage;
+    use crate::replay::{Fixture, Interaction, McpExchange, ReplayModelClient};
+    use crate::task::Task;
+    use serde_json::json;
+
+    #[test]
+    fn test_side_effects_are_counted_against_quotas() {
+        let quotas = Quotas {
+            max_files_written: Some(1),
+            max_bytes_written: Some(20),
+            max_commands: Some(1),
+        };
+        let write = |path: &str, content: &str| {
+            ToolCall::new(WRITE_FILE_TOOL, json!({"path": path, "content": content}))
+        };
+
+        let effects = SideEffects::default()
+            .with_call(&write("haiku.txt", "An old silent pond"))
+            .with_call(&write("haiku.txt", "A frog"))
+            .with_call(&ToolCall::new(RUN_COMMAND_TOOL, json!({"command": "ls"})));
+
+        assert_eq!(effects.files_written.len(), 1);
+        assert_eq!(effects.bytes_written, 24);
+        assert_eq!(effects.commands, 1);
+        assert_eq!(
+            effects.with_call(&write("pond.txt", "")).exceeded(&quotas),
+            ["more than 1 files written", "more than 20 bytes written"]
+        );
+        let edit = ToolCall::new(
+            "edit_file",
+            json!({"path": "haiku.txt", "edits": [{"oldText": "frog", "newText": "toad"}]}),
+        );
+        assert_eq!(SideEffects::default().with_call(&edit).bytes_written, 4);
+    }
+
+    #[tokio::test]
+    async fn test_run_stops_at_a_quota_the_user_does_not_lift() -> Result<()> {
+        let write = |path: &str| {
+            json!({
+                "jsonrpc": "2.0",
+                "method": "tools/call",
+                "params": {"name": WRITE_FILE_TOOL, "arguments": {"path": path, "content": "..."}},
+                "id": 1
+            })
+        };
+        let fixture = Fixture {
+            interactions: vec![
+                Interaction::model_reply(write("haiku.txt")),
+                Interaction::Mcp(McpExchange::new(&write("haiku.txt"), &Ok(json!({})))),
+                Interaction::model_reply(write("pond.txt")),
+            ],
+        };
+        let mut agent = Agent::new(
+            Box::new(ReplayModelClient::new(&fixture)),
+            MCPClient::replaying(&fixture),
+            vec![ChatMessage::system("You are a poet")],
+        );
+        // Approving every tool call doesn't approve going over a quota
+        agent.set_approval_required(Vec::<String>::new(), CommandApproval::Always);
+        agent.set_options(RunOptions {
+            quotas: Quotas {
+                max_files_written: Some(1),
+                ..Quotas::default()
+            },
+            quota_approval: CommandApproval::Never,
+            ..RunOptions::default()
+        });
+
+        agent.start_task(&Task::new("Write two haiku")).await?;
+        agent.run_once().await?;
+        let err = agent.run_once().await.unwrap_err();
+
+        assert!(matches!(err, AgentError::QuotaExceeded(_)));
+        assert!(err.to_string().contains("more than 1 files written"));
+        assert_eq!(
+            agent.checkpoint().side_effects.files_written,
+            BTreeSet::from(["haiku.txt".to_string()])
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,100 @@
+use super::Agent;
+use crate::error::{AgentError, Result};
+use crate::model_client::ChatMessage;
+use crate::task::Task;
+use serde::Deserialize;
+use serde_json::json;
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum Verdict {
+    Pass,
+    Fail,
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+struct Critique {
+    verdict: Verdict,
+    #[serde(default)]
+    problems: Option<String>,
+}
+
+fn critique_prompt(task: &Task) -> String {
+    let mut prompt = String::from("Check the result above against ");
+    if task.success_criteria.is_empty() {
+        prompt.push_str(&format!("the task: {}", task.description));
+    } else {
+        prompt.push_str("the task's success criteria:");
+        for criterion in &task.success_criteria {
+            prompt.push_str(&format!("\n- {}", criterion));
+        }
+    }
+    prompt.push_str(
+        "\n\nReply only with {\"verdict\": \"pass\"} or {\"verdict\": \"fail\", \"problems\": \"<what is wrong>\"}.",
+    );
+    prompt
+}
+
+impl Agent {
+    // Asks the model to grade its own result; returns the problems it found, if any
+    pub(super) async fn critique(&mut self, task: &Task) -> Result<Option<String>> {
+        self.history.push(ChatMessage::user(critique_prompt(task)));
+        let format = self.capabilities.json_schema.then(|| json!("json"));
+        let response = self.complete(format).await?;
+        self.history
+            .push(ChatMessage::assistant(response.response.clone()));
+        let critique: Critique = serde_json::from_str(&self.repaired(&response.response))
+            .map_err(|err| AgentError::ParseError(format!("Invalid critique: {}", err)))?;
+        Ok(match critique.verdict {
+            Verdict::Pass => None,
+            Verdict::Fail => Some(
+                critique
+                    .problems
+                    .unwrap_or_else(|| "the result does not meet the criteria".to_string()),
+            ),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::RunOptions;
+    use crate::mcp_client::MCPClient;
+    use crate::replay::{Fixture, ReplayModelClient};
+    use crate::task::Artifact;
+
+    #[tokio::test]
+    async fn test_failed_critique_triggers_a_corrective_turn() -> Result<()> {
+        let fixture = Fixture::from_replies([
+            json!({"final_answer": "Autumn leaves"}),
+            json!({"verdict": "fail", "problems": "not 5-7-5"}),
+            json!({"final_answer": "An old silent pond"}),
+            json!({"verdict": "pass"}),
+        ]);
+        let mut agent = Agent::new(
+            Box::new(ReplayModelClient::new(&fixture)),
+            MCPClient::replaying(&fixture),
+            vec![ChatMessage::system("You are a poet")],
+        );
+        agent.set_options(RunOptions {
+            reflect: true,
+            ..RunOptions::default()
+        });
+        let task =
+            Task::new("Write a haiku").with_success_criterion("Lines of 5, 7 and 5 syllables");
+
+        let result = agent.run_task(&task).await?;
+
+        assert!(result.success);
+        assert_eq!(result.turns, 2);
+        assert_eq!(
+            result.artifacts,
+            vec![Artifact::Text {
+                text: "An old silent pond".to_string()
+            }]
+        );
+        assert!(critique_prompt(&task).contains("- Lines of 5, 7 and 5 syllables"));
+        Ok(())
+    }
+}
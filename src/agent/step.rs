@@ -0,0 +1,85 @@
+use crate::schema;
+use serde_json::{json, Value};
+
+// Tells the model how to end a turn without a tool call; add it to the system prompt
+pub const REPLY_FORMAT: &str = r#"When no tool call is needed, reply with {"final_answer": "<answer>"}. If the task is too ambiguous to act on, reply with {"clarification": "<question for the user>"}."#;
+
+// What a single turn produced
+#[derive(Debug, Clone, PartialEq)]
+pub enum AgentStep {
+    ToolExecuted { tool: String, result: Value },
+    // Recorded but not executed because of RunOptions::dry_run
+    ToolSkipped { tool: String },
+    FinalAnswer(String),
+    NeedsClarification(String),
+}
+
+// Replies in REPLY_FORMAT; anything else should be a tool call
+pub(super) fn parse_reply(response: &str) -> Option<AgentStep> {
+    let value: Value = serde_json::from_str(response).ok()?;
+    let text = |value: &Value| match value {
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    };
+    if let Some(answer) = value.get("final_answer") {
+        return Some(AgentStep::FinalAnswer(text(answer)));
+    }
+    value
+        .get("clarification")
+        .map(|question| AgentStep::NeedsClarification(text(question)))
+}
+
+// Either a tool call, a question, or a final answer matching the task's output schema, for
+// backends that constrain decoding to a JSON schema
+pub(super) fn reply_schema(output: &Value) -> Value {
+    json!({
+        "anyOf": [
+            {
+                "type": "object",
+                "properties": {"jsonrpc": {"type": "string"}, "method": {"type": "string"}},
+                "required": ["jsonrpc", "method"]
+            },
+            {
+                "type": "object",
+                "properties": {"final_answer": output},
+                "required": ["final_answer"]
+            },
+            {
+                "type": "object",
+                "properties": {"clarification": {"type": "string"}},
+                "required": ["clarification"]
+            }
+        ]
+    })
+}
+
+// A final answer that is not JSON is taken as a string
+pub(super) fn structured_answer(schema: &Value, text: &str) -> Result<Value, Vec<String>> {
+    let answer = serde_json::from_str(text).unwrap_or_else(|_| Value::String(text.to_string()));
+    let problems = schema::validate_named(schema, &answer, "answer");
+    if problems.is_empty() {
+        Ok(answer)
+    } else {
+        Err(problems)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_reply_recognizes_answers_and_questions() {
+        assert_eq!(
+            parse_reply(r#"{"final_answer": "Done"}"#),
+            Some(AgentStep::FinalAnswer("Done".to_string()))
+        );
+        assert_eq!(
+            parse_reply(r#"{"clarification": "Which directory?"}"#),
+            Some(AgentStep::NeedsClarification(
+                "Which directory?".to_string()
+            ))
+        );
+        assert_eq!(parse_reply(r#"{"jsonrpc": "2.0", "method": "ping"}"#), None);
+    }
+}
@@ -0,0 +1,154 @@
+use super::Agent;
+use crate::transcript::TranscriptEntry;
+use std::path::{Path, PathBuf};
+
+// What a stop condition gets to look at after a turn
+pub struct RunState<'a> {
+    // This task's entries only
+    pub transcript: &'a [TranscriptEntry],
+    // The agent's workspace, or the current directory without one
+    pub workspace: &'a Path,
+    pub turns: usize,
+}
+
+type Predicate = Box<dyn Fn(&RunState) -> bool + Send + Sync>;
+
+// Ends the task successfully as soon as its predicate holds, checked after every turn
+pub struct StopCondition {
+    name: String,
+    predicate: Predicate,
+}
+
+impl StopCondition {
+    pub fn new<F>(name: &str, predicate: F) -> Self
+    where
+        F: Fn(&RunState) -> bool + Send + Sync + 'static,
+    {
+        Self {
+            name: name.to_string(),
+            predicate: Box::new(predicate),
+        }
+    }
+
+    // Relative paths are resolved against the workspace
+    pub fn file_exists(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        Self::new(&format!("{} exists", path.display()), move |state| {
+            state.workspace.join(&path).is_file()
+        })
+    }
+
+    pub fn json_file(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        Self::new(
+            &format!("{} parses as JSON", path.display()),
+            move |state| {
+                std::fs::read_to_string(state.workspace.join(&path)).is_ok_and(|content| {
+                    serde_json::from_str::<serde_json::Value>(&content).is_ok()
+                })
+            },
+        )
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn holds(&self, state: &RunState) -> bool {
+        (self.predicate)(state)
+    }
+}
+
+impl Agent {
+    pub fn add_stop_condition(&mut self, condition: StopCondition) {
+        self.stop_conditions.push(condition);
+    }
+
+    // Records the first condition that holds, if any
+    pub(super) fn should_stop(&mut self) -> bool {
+        let workspace = self.workspace.clone().unwrap_or_else(|| PathBuf::from("."));
+        let state = RunState {
+            transcript: &self.transcript.entries()[self.progress.first_entry..],
+            workspace: &workspace,
+            turns: self.progress.turns,
+        };
+        let Some(condition) = self
+            .stop_conditions
+            .iter()
+            .find(|condition| condition.holds(&state))
+        else {
+            return false;
+        };
+        let condition = condition.name().to_string();
+        self.record(TranscriptEntry::Stopped { condition });
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::RunOptions;
+    use crate::error::Result;
+    use crate::mcp_client::MCPClient;
+    use crate::replay::{Fixture, ReplayModelClient};
+    use crate::task::Task;
+
+    #[test]
+    fn test_file_conditions_resolve_against_the_workspace() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let state = RunState {
+            transcript: &[],
+            workspace: dir.path(),
+            turns: 1,
+        };
+        let exists = StopCondition::file_exists("output.json");
+        let parses = StopCondition::json_file("output.json");
+
+        assert!(!exists.holds(&state));
+        std::fs::write(dir.path().join("output.json"), "{\"haiku\": ")?;
+        assert!(exists.holds(&state));
+        assert!(!parses.holds(&state));
+        std::fs::write(
+            dir.path().join("output.json"),
+            "{\"haiku\": \"An old pond\"}",
+        )?;
+        assert!(parses.holds(&state));
+        assert_eq!(parses.name(), "output.json parses as JSON");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_run_ends_once_a_condition_holds() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        std::fs::write(dir.path().join("output.json"), "[]")?;
+        let fixture = Fixture::from_replies(["Let me think about that"]);
+        let mut mcp_client = MCPClient::replaying(&Fixture::initialized_mcp());
+        mcp_client.init().await?;
+        let mut agent = Agent::builder()
+            .model(Box::new(ReplayModelClient::new(&fixture)))
+            .mcp_client(mcp_client)
+            .system_prompt("You are a poet")
+            .workspace(dir.path().to_path_buf())
+            .stop_condition(StopCondition::json_file("output.json"))
+            .options(RunOptions {
+                max_turns: 3,
+                ..RunOptions::default()
+            })
+            .build()
+            .await?;
+
+        let result = agent.run_task(&Task::new("Write output.json")).await?;
+
+        // The turn itself failed to parse, but the condition settles the task
+        assert!(result.success);
+        assert_eq!(result.turns, 1);
+        assert_eq!(
+            agent.transcript().entries().last(),
+            Some(&TranscriptEntry::Stopped {
+                condition: "output.json parses as JSON".to_string()
+            })
+        );
+        Ok(())
+    }
+}
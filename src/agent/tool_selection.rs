@@ -0,0 +1,142 @@
+use super::{describe_tool_tool, Agent};
+use crate::error::Result;
+use crate::memory::cosine_similarity;
+use crate::model_client::embedding::EmbeddingClient;
+use crate::model_client::Role;
+use rmcp::model::Tool;
+use std::collections::HashMap;
+
+// The latest message can be a whole file; its start says enough about what comes next
+const MAX_QUERY_CHARS: usize = 2000;
+
+// Picks the tools most relevant to where the task stands, so the prompt describes a handful of
+// a large server's tools instead of all of them
+pub struct ToolSelector {
+    embedder: Box<dyn EmbeddingClient>,
+    top_k: usize,
+    // Tool descriptions embedded so far, keyed by the text that was embedded
+    embeddings: HashMap<String, Vec<f32>>,
+}
+
+impl ToolSelector {
+    pub fn new(embedder: Box<dyn EmbeddingClient>, top_k: usize) -> Self {
+        Self {
+            embedder,
+            top_k,
+            embeddings: HashMap::new(),
+        }
+    }
+
+    // The top_k tools closest to the query, in the order they came in
+    pub async fn select(&mut self, query: &str, tools: &[Tool]) -> Result<Vec<Tool>> {
+        if tools.len() <= self.top_k {
+            return Ok(tools.to_vec());
+        }
+        let texts: Vec<String> = tools.iter().map(tool_text).collect();
+        let missing: Vec<String> = texts
+            .iter()
+            .filter(|text| !self.embeddings.contains_key(*text))
+            .cloned()
+            .collect();
+        if !missing.is_empty() {
+            let embedded = self.embedder.embed(&missing).await?;
+            self.embeddings.extend(missing.into_iter().zip(embedded));
+        }
+        let query = self.embedder.embed_one(query).await?;
+        let mut scored: Vec<(f32, usize)> = texts
+            .iter()
+            .enumerate()
+            .map(|(index, text)| (cosine_similarity(&query, &self.embeddings[text]), index))
+            .collect();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        let mut chosen: Vec<usize> = scored
+            .into_iter()
+            .take(self.top_k)
+            .map(|(_, index)| index)
+            .collect();
+        chosen.sort_unstable();
+        Ok(chosen
+            .into_iter()
+            .map(|index| tools[index].clone())
+            .collect())
+    }
+}
+
+fn tool_text(tool: &Tool) -> String {
+    format!("{}: {}", tool.name, tool.description)
+}
+
+impl Agent {
+    pub fn set_tool_selector(&mut self, selector: ToolSelector) {
+        self.tool_selector = Some(selector);
+    }
+
+    // With a selector and a generated prompt, describes only the tools relevant to the task
+    // and its latest message in this turn's prompt. The others can still be called
+    pub(super) async fn select_tools(&mut self) -> Result<()> {
+        if self.generated_prompt.is_none() || self.tool_catalog.is_empty() {
+            return Ok(());
+        }
+        let query = self.relevance_query();
+        let Some(selector) = &mut self.tool_selector else {
+            return Ok(());
+        };
+        let mut tools = selector.select(&query, &self.tool_catalog).await?;
+        tracing::debug!(
+            tools = ?tools.iter().map(|tool| tool.name.as_ref()).collect::<Vec<_>>(),
+            "Selected tools for the turn"
+        );
+        if self.compacts(&self.tool_catalog) {
+            tools.push(describe_tool_tool());
+        }
+        self.render_generated_prompt(tools);
+        Ok(())
+    }
+
+    fn relevance_query(&self) -> String {
+        let latest: String = self
+            .history
+            .iter()
+            .rev()
+            .find(|message| message.role != Role::System)
+            .map(|message| message.content.chars().take(MAX_QUERY_CHARS).collect())
+            .unwrap_or_default();
+        format!("{}\n{}", self.progress.task.description, latest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::tests::LetterEmbedder;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    fn tool(name: &str, description: &str) -> Tool {
+        Tool::new(
+            name.to_string(),
+            description.to_string(),
+            Arc::new(json!({"type": "object"}).as_object().cloned().unwrap()),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_selects_the_tools_closest_to_the_query() -> Result<()> {
+        let tools = [
+            tool("write_file", "Write a file"),
+            tool("brave_web_search", "Search the web"),
+            tool("git_commit", "Commit staged changes"),
+            tool("math_eval", "Evaluate arithmetic"),
+        ];
+        let mut selector = ToolSelector::new(Box::new(LetterEmbedder), 2);
+
+        let selected = selector.select("commit the changes to git", &tools).await?;
+        let few = selector.select("anything", &tools[..2]).await?;
+
+        let names: Vec<&str> = selected.iter().map(|tool| tool.name.as_ref()).collect();
+        assert_eq!(names.len(), 2);
+        assert!(names.contains(&"git_commit"));
+        assert_eq!(few, tools[..2]);
+        Ok(())
+    }
+}
@@ -0,0 +1,140 @@
+use crate::ids::{random_ids, IdGenerator};
+use crate::task::Artifact;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+// Keeps a copy of the files each run writes in a directory of its own, so they outlive the
+// workspace and aren't overwritten by the next run
+#[derive(Clone)]
+pub struct ArtifactStore {
+    base: PathBuf,
+    ids: Arc<dyn IdGenerator>,
+}
+
+impl ArtifactStore {
+    pub fn under(base: impl Into<PathBuf>) -> Self {
+        Self {
+            base: base.into(),
+            ids: random_ids(),
+        }
+    }
+
+    pub fn with_ids(mut self, ids: Arc<dyn IdGenerator>) -> Self {
+        self.ids = ids;
+        self
+    }
+
+    pub fn base(&self) -> &Path {
+        &self.base
+    }
+
+    // Copies the run's files into a new directory under base, recording each copy on its
+    // artifact. Relative paths are taken from the workspace, or the current directory without
+    // one. A file that can't be copied, e.g. because it was deleted again, keeps no copy
+    pub fn collect(&self, artifacts: &mut [Artifact], workspace: Option<&Path>) {
+        let files: Vec<(&String, &mut Option<PathBuf>)> = artifacts
+            .iter_mut()
+            .filter_map(|artifact| match artifact {
+                Artifact::File { path, stored } => Some((&*path, stored)),
+                Artifact::Text { .. } => None,
+            })
+            .collect();
+        if files.is_empty() {
+            return;
+        }
+        let run_dir = self.base.join(self.ids.new_id().simple().to_string());
+        for (path, stored) in files {
+            let source = match workspace {
+                Some(workspace) => workspace.join(path),
+                None => PathBuf::from(path),
+            };
+            let destination = run_dir.join(stored_name(&source, workspace));
+            let copied = destination
+                .parent()
+                .map_or(Ok(()), std::fs::create_dir_all)
+                .and_then(|_| std::fs::copy(&source, &destination));
+            match copied {
+                Ok(_) => *stored = Some(destination),
+                Err(err) => tracing::warn!("Could not keep artifact {}: {}", source.display(), err),
+            }
+        }
+    }
+}
+
+// Where a file goes inside the run directory: its path within the workspace, or its relative
+// path as written. Files from elsewhere keep only their name, and .. can't climb out
+fn stored_name(source: &Path, workspace: Option<&Path>) -> PathBuf {
+    let relative = match workspace.and_then(|workspace| source.strip_prefix(workspace).ok()) {
+        Some(relative) => relative,
+        None if source.is_absolute() => Path::new(source.file_name().unwrap_or_default()),
+        None => source,
+    };
+    relative
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Result;
+    use crate::ids::SequentialIds;
+
+    fn file(path: &str) -> Artifact {
+        Artifact::File {
+            path: path.to_string(),
+            stored: None,
+        }
+    }
+
+    #[test]
+    fn test_written_files_are_copied_into_a_directory_per_run() -> Result<()> {
+        let workspace = tempfile::tempdir()?;
+        let outside = tempfile::tempdir()?;
+        let base = tempfile::tempdir()?;
+        std::fs::create_dir(workspace.path().join("poems"))?;
+        std::fs::write(
+            workspace.path().join("poems/pond.txt"),
+            "An old silent pond",
+        )?;
+        let elsewhere = outside.path().join("frog.txt");
+        std::fs::write(&elsewhere, "A frog jumps into the pond")?;
+        let store = ArtifactStore::under(base.path()).with_ids(Arc::new(SequentialIds::new()));
+        let mut artifacts = vec![
+            file("poems/pond.txt"),
+            file(&elsewhere.to_string_lossy()),
+            file("../deleted.txt"),
+            Artifact::Text {
+                text: "Done".to_string(),
+            },
+        ];
+
+        store.collect(&mut artifacts, Some(workspace.path()));
+
+        let run_dir = base.path().join("00000000000000000000000000000001");
+        let stored: Vec<Option<PathBuf>> = artifacts[..3]
+            .iter()
+            .map(|artifact| match artifact {
+                Artifact::File { stored, .. } => stored.clone(),
+                Artifact::Text { .. } => None,
+            })
+            .collect();
+        assert_eq!(
+            stored,
+            [
+                Some(run_dir.join("poems/pond.txt")),
+                Some(run_dir.join("frog.txt")),
+                None
+            ]
+        );
+        assert_eq!(
+            std::fs::read_to_string(run_dir.join("poems/pond.txt"))?,
+            "An old silent pond"
+        );
+        Ok(())
+    }
+}
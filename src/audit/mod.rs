@@ -0,0 +1,155 @@
+use crate::error::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Approval {
+    Allowed,
+    Denied,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AuditRecord {
+    pub timestamp: String,
+    pub server: String,
+    pub tool: String,
+    pub arguments: Value,
+    pub approval: Approval,
+    // SHA-256 of the serialized result, so outcomes can be compared without storing them
+    pub result_hash: Option<String>,
+    pub error: Option<String>,
+}
+
+impl AuditRecord {
+    pub fn new(server: &str, tool: &str, arguments: Value, approval: Approval) -> Self {
+        Self {
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            server: server.to_string(),
+            tool: tool.to_string(),
+            arguments,
+            approval,
+            result_hash: None,
+            error: None,
+        }
+    }
+
+    pub fn with_outcome(mut self, outcome: &Result<Value>) -> Self {
+        match outcome {
+            Ok(result) => self.result_hash = Some(hash_result(result)),
+            Err(err) => self.error = Some(err.to_string()),
+        }
+        self
+    }
+}
+
+pub fn hash_result(result: &Value) -> String {
+    format!("{:x}", Sha256::digest(result.to_string().as_bytes()))
+}
+
+// Append-only JSONL; existing lines are never rewritten
+pub struct AuditLog {
+    path: PathBuf,
+}
+
+impl AuditLog {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn append(&self, record: &AuditRecord) -> Result<()> {
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", serde_json::to_string(record)?)?;
+        Ok(())
+    }
+
+    pub fn query(&self, query: &AuditQuery) -> Result<Vec<AuditRecord>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+        let mut records = Vec::new();
+        for line in std::fs::read_to_string(&self.path)?.lines() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record: AuditRecord = serde_json::from_str(line)?;
+            if query.matches(&record) {
+                records.push(record);
+            }
+        }
+        Ok(records)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AuditQuery {
+    pub tool: Option<String>,
+    pub server: Option<String>,
+    pub approval: Option<Approval>,
+    // RFC 3339 timestamps compare correctly as strings when both are UTC
+    pub since: Option<String>,
+}
+
+impl AuditQuery {
+    pub fn matches(&self, record: &AuditRecord) -> bool {
+        self.tool.as_ref().is_none_or(|tool| &record.tool == tool)
+            && self
+                .server
+                .as_ref()
+                .is_none_or(|server| &record.server == server)
+            && self
+                .approval
+                .is_none_or(|approval| record.approval == approval)
+            && self
+                .since
+                .as_ref()
+                .is_none_or(|since| record.timestamp.as_str() >= since.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::AgentError;
+    use serde_json::json;
+
+    #[test]
+    fn test_query_filters_appended_records() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let log = AuditLog::new(dir.path().join("audit.jsonl"));
+        let written = AuditRecord::new(
+            "filesystem",
+            "write_file",
+            json!({"path": "haiku.txt"}),
+            Approval::Allowed,
+        )
+        .with_outcome(&Ok(json!({"content": []})));
+        let denied = AuditRecord::new("filesystem", "move_file", json!({}), Approval::Denied)
+            .with_outcome(&Err(AgentError::ToolError("not allowed".to_string())));
+        log.append(&written)?;
+        log.append(&denied)?;
+
+        let query = AuditQuery {
+            approval: Some(Approval::Denied),
+            ..AuditQuery::default()
+        };
+
+        assert_eq!(log.query(&query)?, vec![denied]);
+        assert_eq!(log.query(&AuditQuery::default())?.len(), 2);
+        assert_eq!(
+            written.result_hash,
+            Some(hash_result(&json!({"content": []})))
+        );
+        Ok(())
+    }
+}
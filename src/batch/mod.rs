@@ -0,0 +1,481 @@
+use crate::agent::{Agent, PermissionProfile, RunOptions, SystemPrompt};
+use crate::artifacts::ArtifactStore;
+use crate::error::{AgentError, Result};
+use crate::model_client::usage::{MeteredModelClient, Usage};
+use crate::model_client::{ChatMessage, ModelClient};
+use crate::orchestrator::McpClientFactory;
+use crate::redaction::Redactor;
+use crate::storage::RunStore;
+use crate::task::{Task, TaskResult};
+use crate::workspace::Workspaces;
+use futures_util::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+
+pub const DEFAULT_CONCURRENCY: usize = 4;
+
+// One line of a tasks file: a task plus an optional id to report it under
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BatchTask {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(flatten)]
+    pub task: Task,
+}
+
+// Tasks without an id are named after their line number
+pub fn load_tasks(path: &Path) -> Result<Vec<BatchTask>> {
+    let contents = std::fs::read_to_string(path)?;
+    let mut tasks = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let mut task: BatchTask = serde_json::from_str(line).map_err(|err| {
+            AgentError::ParseError(format!("{} line {}: {}", path.display(), index + 1, err))
+        })?;
+        task.id.get_or_insert_with(|| (index + 1).to_string());
+        tasks.push(task);
+    }
+    Ok(tasks)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BatchOutcome {
+    pub id: String,
+    pub success: bool,
+    pub turns: usize,
+    pub error: Option<String>,
+    pub usage: Usage,
+    pub duration_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workspace: Option<PathBuf>,
+    // The structured answer of a task with an output_schema
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<serde_json::Value>,
+    // The paths the task called write_file or edit_file on, as the model gave them
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub files_written: BTreeSet<String>,
+}
+
+impl BatchOutcome {
+    // Whether the task succeeded, what it took and where it ran, on one line
+    pub fn summary(&self) -> String {
+        let mut summary = format!(
+            "{} {}: {} turns, ~{} tokens, {}ms",
+            if self.success { "ok  " } else { "FAIL" },
+            self.id,
+            self.turns,
+            self.usage.total_tokens(),
+            self.duration_ms
+        );
+        if let Some(workspace) = &self.workspace {
+            summary.push_str(&format!(" in {}", workspace.display()));
+        }
+        if let Some(error) = &self.error {
+            summary.push_str(&format!(" ({})", error));
+        }
+        summary
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct BatchReport {
+    pub outcomes: Vec<BatchOutcome>,
+}
+
+impl BatchReport {
+    pub fn succeeded(&self) -> usize {
+        self.outcomes
+            .iter()
+            .filter(|outcome| outcome.success)
+            .count()
+    }
+
+    pub fn usage(&self) -> Usage {
+        let mut total = Usage::default();
+        for outcome in &self.outcomes {
+            total.add(&outcome.usage);
+        }
+        total
+    }
+
+    // One line per task followed by the totals
+    pub fn summary(&self) -> String {
+        let mut summary = String::new();
+        for outcome in &self.outcomes {
+            summary.push_str(&outcome.summary());
+            summary.push('\n');
+        }
+        summary.push_str(&format!(
+            "{}/{} tasks succeeded, ~{} tokens in {} model requests\n",
+            self.succeeded(),
+            self.outcomes.len(),
+            self.usage().total_tokens(),
+            self.usage().requests
+        ));
+        summary
+    }
+}
+
+// Runs independent tasks side by side, each on a fresh agent with its own MCP session
+pub struct BatchRunner {
+    model: Arc<dyn ModelClient>,
+    mcp_factory: McpClientFactory,
+    base_messages: Vec<ChatMessage>,
+    generated_prompt: Option<SystemPrompt>,
+    options: RunOptions,
+    concurrency: usize,
+    run_store: Option<RunStore>,
+    workspaces: Option<Workspaces>,
+    artifact_store: Option<ArtifactStore>,
+    permission_profile: Option<PermissionProfile>,
+    redactor: Option<Redactor>,
+}
+
+impl BatchRunner {
+    pub fn new(
+        model: Arc<dyn ModelClient>,
+        mcp_factory: McpClientFactory,
+        base_messages: Vec<ChatMessage>,
+    ) -> Self {
+        Self {
+            model,
+            mcp_factory,
+            base_messages,
+            generated_prompt: None,
+            options: RunOptions::default(),
+            concurrency: DEFAULT_CONCURRENCY,
+            run_store: None,
+            workspaces: None,
+            artifact_store: None,
+            permission_profile: None,
+            redactor: None,
+        }
+    }
+
+    pub fn with_options(mut self, options: RunOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    // Generated per task, so each prompt lists that session's tools and workspace
+    pub fn with_generated_prompt(mut self, prompt: SystemPrompt) -> Self {
+        self.generated_prompt = Some(prompt);
+        self
+    }
+
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    pub fn concurrency(&self) -> usize {
+        self.concurrency
+    }
+
+    pub fn with_run_store(mut self, run_store: RunStore) -> Self {
+        self.run_store = Some(run_store);
+        self
+    }
+
+    // Give every task a directory of its own and root its MCP server there
+    pub fn with_workspaces(mut self, workspaces: Workspaces) -> Self {
+        self.workspaces = Some(workspaces);
+        self
+    }
+
+    // Keep each task's files in a directory of its own under the store
+    pub fn with_artifact_store(mut self, store: ArtifactStore) -> Self {
+        self.artifact_store = Some(store);
+        self
+    }
+
+    // Every task's agent runs under this profile
+    pub fn with_permission_profile(mut self, profile: PermissionProfile) -> Self {
+        self.permission_profile = Some(profile);
+        self
+    }
+
+    // Masks secrets in what every task sends to the model and keeps in transcripts
+    pub fn with_redactor(mut self, redactor: Redactor) -> Self {
+        self.redactor = Some(redactor);
+        self
+    }
+
+    // Outcomes are reported in the order the tasks were given
+    pub async fn run(&self, tasks: &[BatchTask]) -> BatchReport {
+        let outcomes = stream::iter(tasks)
+            .map(|task| self.run_one(task))
+            .buffered(self.concurrency)
+            .collect()
+            .await;
+        BatchReport { outcomes }
+    }
+
+    // A single task, e.g. one a trigger started, on a fresh agent as in a batch
+    pub async fn run_one(&self, task: &BatchTask) -> BatchOutcome {
+        let started = Instant::now();
+        let model = MeteredModelClient::new(Box::new(self.model.clone()));
+        let meter = model.meter();
+        let id = task.id.clone().unwrap_or_default();
+        let workspace = self
+            .workspaces
+            .as_ref()
+            .map(|workspaces| workspaces.create(&id));
+        let result = match workspace {
+            Some(Err(err)) => Err(err),
+            Some(Ok(workspace)) => self.session(model, &task.task, Some(workspace)).await,
+            None => self.session(model, &task.task, None).await,
+        };
+        let (success, turns, error, workspace, output, files_written) = match result {
+            Ok((result, files_written)) => (
+                result.success,
+                result.turns,
+                result.error,
+                result.workspace,
+                result.output,
+                files_written,
+            ),
+            Err(err) => (false, 0, Some(err.to_string()), None, None, BTreeSet::new()),
+        };
+        BatchOutcome {
+            id,
+            success,
+            turns,
+            error,
+            usage: meter.usage(),
+            duration_ms: started.elapsed().as_millis() as u64,
+            workspace,
+            output,
+            files_written,
+        }
+    }
+
+    async fn session(
+        &self,
+        model: MeteredModelClient,
+        task: &Task,
+        workspace: Option<PathBuf>,
+    ) -> Result<(TaskResult, BTreeSet<String>)> {
+        let root = workspace.clone().unwrap_or_else(|| PathBuf::from("."));
+        let mut mcp_client = (self.mcp_factory)(&root);
+        mcp_client.init().await?;
+        let mut builder = Agent::builder()
+            .model(Box::new(model))
+            .mcp_client(mcp_client)
+            .options(self.options.clone());
+        for message in &self.base_messages {
+            builder = builder.message(message.clone());
+        }
+        if let Some(prompt) = &self.generated_prompt {
+            builder = builder.generated_system_prompt(prompt.clone());
+        }
+        if let Some(workspace) = workspace {
+            builder = builder.workspace(workspace);
+        }
+        if let Some(run_store) = &self.run_store {
+            builder = builder.run_store(run_store.clone());
+        }
+        if let Some(store) = &self.artifact_store {
+            builder = builder.artifact_store(store.clone());
+        }
+        if let Some(profile) = &self.permission_profile {
+            builder = builder.permission_profile(profile.clone());
+        }
+        if let Some(redactor) = &self.redactor {
+            builder = builder.redactor(redactor.clone());
+        }
+        let mut agent = builder.build().await?;
+        let result = agent.run_task(task).await;
+        agent.shutdown().await?;
+        Ok((result?, agent.files_written().clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mcp_client::MCPClient;
+    use crate::model_client::{CompletionRequest, ModelResponse};
+    use crate::replay::{Fixture, ReplayModelClient};
+    use async_trait::async_trait;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_load_tasks_names_tasks_without_an_id_by_line() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("tasks.jsonl");
+        std::fs::write(
+            &path,
+            "{\"id\": \"pond\", \"description\": \"A haiku about a pond\"}\n\n{\"description\": \"A haiku about rain\"}\n",
+        )?;
+
+        let tasks = load_tasks(&path)?;
+
+        assert_eq!(tasks.len(), 2);
+        assert_eq!(tasks[0].id.as_deref(), Some("pond"));
+        assert_eq!(tasks[1].id.as_deref(), Some("3"));
+        assert_eq!(tasks[1].task.description, "A haiku about rain");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_run_reports_each_task_in_order() {
+        let fixture =
+            Fixture::from_replies([r#"{"final_answer": "An old silent pond"}"#, "not json"]);
+        let runner = BatchRunner::new(
+            Arc::new(ReplayModelClient::new(&fixture)),
+            Box::new(|_: &Path| MCPClient::replaying(&Fixture::initialized_mcp())),
+            vec![ChatMessage::system("You are a poet")],
+        )
+        .with_concurrency(1)
+        .with_options(RunOptions {
+            max_turns: 1,
+            ..RunOptions::default()
+        });
+        let tasks = vec![
+            BatchTask {
+                id: Some("pond".to_string()),
+                task: Task::new("A haiku about a pond"),
+            },
+            BatchTask {
+                id: Some("rain".to_string()),
+                task: Task::new("A haiku about rain"),
+            },
+        ];
+
+        let report = runner.run(&tasks).await;
+
+        assert_eq!(report.succeeded(), 1);
+        assert_eq!(report.outcomes[0].id, "pond");
+        assert!(!report.outcomes[1].success);
+        assert_eq!(report.usage().requests, 2);
+        assert!(report.summary().contains("1/2 tasks succeeded"));
+    }
+
+    #[tokio::test]
+    async fn test_every_task_runs_under_the_permission_profile() {
+        let write = serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": "tools/call",
+            "params": {"name": "write_file", "arguments": {"path": "pond.md", "content": "..."}},
+            "id": 1
+        });
+        let runner = |profile: Option<PermissionProfile>| {
+            let runner = BatchRunner::new(
+                Arc::new(ReplayModelClient::new(&Fixture::from_replies([&write]))),
+                Box::new(|_: &Path| MCPClient::replaying(&Fixture::initialized_mcp())),
+                Vec::new(),
+            )
+            .with_options(RunOptions {
+                max_turns: 1,
+                ..RunOptions::default()
+            });
+            match profile {
+                Some(profile) => runner.with_permission_profile(profile),
+                None => runner,
+            }
+        };
+        let task = BatchTask {
+            id: Some("pond".to_string()),
+            task: Task::new("Write a haiku"),
+        };
+
+        let unrestricted = runner(None).run_one(&task).await;
+        let read_only = runner(Some(PermissionProfile::read_only()))
+            .run_one(&task)
+            .await;
+
+        assert!(unrestricted.files_written.contains("pond.md"));
+        assert!(read_only.files_written.is_empty());
+    }
+
+    // Keeps every prompt it is sent
+    #[derive(Default)]
+    struct Prompts(Mutex<Vec<String>>);
+
+    #[async_trait]
+    impl ModelClient for Prompts {
+        async fn complete(&self, request: &CompletionRequest) -> Result<ModelResponse> {
+            let mut prompts = self.0.lock().unwrap();
+            prompts.extend(
+                request
+                    .messages
+                    .iter()
+                    .map(|message| message.content.clone()),
+            );
+            Ok(ModelResponse {
+                response: r#"{"final_answer": "done"}"#.to_string(),
+                reasoning: None,
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_secrets_are_redacted_from_every_tasks_prompts() {
+        let model = Arc::new(Prompts::default());
+        let runner = BatchRunner::new(
+            model.clone(),
+            Box::new(|_: &Path| MCPClient::replaying(&Fixture::initialized_mcp())),
+            vec![ChatMessage::system("Use the key hunter2-password")],
+        )
+        .with_redactor(Redactor::new().with_secret("hunter2-password"));
+        let task = BatchTask {
+            id: Some("pond".to_string()),
+            task: Task::new("Write a haiku"),
+        };
+
+        assert!(runner.run_one(&task).await.success);
+
+        let prompts = model.0.lock().unwrap();
+        assert!(prompts.contains(&"Use the key [REDACTED]".to_string()));
+        assert!(!prompts
+            .iter()
+            .any(|prompt| prompt.contains("hunter2-password")));
+    }
+
+    #[tokio::test]
+    async fn test_each_task_runs_in_its_own_workspace() -> Result<()> {
+        let fixture =
+            Fixture::from_replies([r#"{"final_answer": "done"}"#, r#"{"final_answer": "done"}"#]);
+        let dir = tempfile::tempdir()?;
+        let roots = Arc::new(Mutex::new(Vec::new()));
+        let seen = roots.clone();
+        let runner = BatchRunner::new(
+            Arc::new(ReplayModelClient::new(&fixture)),
+            Box::new(move |root: &Path| {
+                seen.lock().unwrap().push(root.to_path_buf());
+                MCPClient::replaying(&Fixture::initialized_mcp())
+            }),
+            Vec::new(),
+        )
+        .with_workspaces(Workspaces::under(dir.path()));
+        let tasks: Vec<BatchTask> = ["pond", "rain"]
+            .into_iter()
+            .map(|id| BatchTask {
+                id: Some(id.to_string()),
+                task: Task::new("Write a haiku"),
+            })
+            .collect();
+
+        let report = runner.run(&tasks).await;
+
+        let workspaces: Vec<PathBuf> = report
+            .outcomes
+            .iter()
+            .filter_map(|outcome| outcome.workspace.clone())
+            .collect();
+        assert_eq!(workspaces.len(), 2);
+        assert_ne!(workspaces[0], workspaces[1]);
+        let mut roots = roots.lock().unwrap().clone();
+        roots.sort();
+        let mut expected = workspaces.clone();
+        expected.sort();
+        assert_eq!(roots, expected);
+        Ok(())
+    }
+}
@@ -0,0 +1,89 @@
+use thiserror::Error;
+
+pub type Result<T> = std::result::Result<T, AgentError>;
+
+#[derive(Debug, Error)]
+pub enum AgentError {
+    #[error("model error: {0}")]
+    ModelError(String),
+    #[error("tool error: {0}")]
+    ToolError(String),
+    #[error("parse error: {0}")]
+    ParseError(String),
+    #[error("protocol error: {0}")]
+    ProtocolError(String),
+    #[error("timeout: {0}")]
+    Timeout(String),
+    // The model keeps making the same tool call; retrying won't help
+    #[error("loop detected: {0}")]
+    LoopDetected(String),
+    // The run used up a side-effect quota and the user did not continue it
+    #[error("quota exceeded: {0}")]
+    QuotaExceeded(String),
+    #[error("configuration error: {0}")]
+    ConfigError(String),
+    // The server did not declare the capability a request needs, e.g. prompts for prompts/list
+    #[error("unsupported capability: {0}")]
+    UnsupportedCapability(String),
+}
+
+impl From<reqwest::Error> for AgentError {
+    fn from(err: reqwest::Error) -> Self {
+        if err.is_timeout() {
+            AgentError::Timeout(err.to_string())
+        } else {
+            AgentError::ModelError(err.to_string())
+        }
+    }
+}
+
+impl From<serde_json::Error> for AgentError {
+    fn from(err: serde_json::Error) -> Self {
+        AgentError::ParseError(err.to_string())
+    }
+}
+
+impl From<std::io::Error> for AgentError {
+    fn from(err: std::io::Error) -> Self {
+        AgentError::ProtocolError(err.to_string())
+    }
+}
+
+impl From<rusqlite::Error> for AgentError {
+    fn from(err: rusqlite::Error) -> Self {
+        AgentError::ProtocolError(err.to_string())
+    }
+}
+
+#[cfg(feature = "redis")]
+impl From<redis::RedisError> for AgentError {
+    fn from(err: redis::RedisError) -> Self {
+        AgentError::ProtocolError(err.to_string())
+    }
+}
+
+#[cfg(feature = "grpc")]
+impl From<tonic::transport::Error> for AgentError {
+    fn from(err: tonic::transport::Error) -> Self {
+        AgentError::ProtocolError(err.to_string())
+    }
+}
+
+impl From<tokio::time::error::Elapsed> for AgentError {
+    fn from(err: tokio::time::error::Elapsed) -> Self {
+        AgentError::Timeout(err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_errors_map_to_parse_error() {
+        let err: AgentError = serde_json::from_str::<serde_json::Value>("{")
+            .unwrap_err()
+            .into();
+        assert!(matches!(err, AgentError::ParseError(_)));
+    }
+}
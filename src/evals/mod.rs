@@ -0,0 +1,332 @@
+use crate::batch::{BatchOutcome, BatchRunner, BatchTask};
+use crate::error::{AgentError, Result};
+use crate::model_client::usage::Usage;
+use crate::workspace::Workspaces;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+// What must be true of a task's workspace once the agent is done; paths are relative to it and
+// may use * in the file name
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Check {
+    FileExists { path: String },
+    FileMatches { path: String, pattern: String },
+}
+
+impl Check {
+    pub fn passes(&self, workspace: &Path) -> Result<bool> {
+        match self {
+            Check::FileExists { path } => Ok(!matching_files(workspace, path)?.is_empty()),
+            Check::FileMatches { path, pattern } => {
+                let pattern = Regex::new(pattern)
+                    .map_err(|err| AgentError::ParseError(format!("Invalid pattern: {}", err)))?;
+                Ok(matching_files(workspace, path)?.iter().any(|file| {
+                    std::fs::read_to_string(file).is_ok_and(|content| pattern.is_match(&content))
+                }))
+            }
+        }
+    }
+}
+
+fn matching_files(workspace: &Path, path: &str) -> Result<Vec<PathBuf>> {
+    let path = workspace.join(path);
+    let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+        return Ok(Vec::new());
+    };
+    let name = name.to_string_lossy();
+    if !name.contains('*') {
+        return Ok(if path.is_file() {
+            vec![path]
+        } else {
+            Vec::new()
+        });
+    }
+    let wildcard = Regex::new(&format!("^{}$", regex::escape(&name).replace(r"\*", ".*")))
+        .map_err(|err| AgentError::ParseError(format!("Invalid path: {}", err)))?;
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.path().is_file() && wildcard.is_match(&entry.file_name().to_string_lossy()) {
+            files.push(entry.path());
+        }
+    }
+    Ok(files)
+}
+
+// One line of an evals file: a batch task plus the checks that decide whether it passed
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct EvalCase {
+    #[serde(flatten)]
+    pub task: BatchTask,
+    #[serde(default)]
+    pub checks: Vec<Check>,
+}
+
+// Cases without an id are named after their line number
+pub fn load_cases(path: &Path) -> Result<Vec<EvalCase>> {
+    let contents = std::fs::read_to_string(path)?;
+    let mut cases = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let mut case: EvalCase = serde_json::from_str(line).map_err(|err| {
+            AgentError::ParseError(format!("{} line {}: {}", path.display(), index + 1, err))
+        })?;
+        case.task.id.get_or_insert_with(|| (index + 1).to_string());
+        cases.push(case);
+    }
+    Ok(cases)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct EvalResult {
+    pub config: String,
+    pub case: String,
+    // Whether every check held; the agent's own verdict is in the outcome
+    pub passed: bool,
+    pub checks_passed: usize,
+    pub checks_total: usize,
+    pub outcome: BatchOutcome,
+}
+
+// Runs every case in a fresh workspace and checks what each run left behind
+pub struct Evals {
+    cases: Vec<EvalCase>,
+    workspaces: Workspaces,
+}
+
+impl Evals {
+    pub fn new(cases: Vec<EvalCase>) -> Self {
+        Self {
+            cases,
+            workspaces: Workspaces::temp(),
+        }
+    }
+
+    pub fn with_workspaces(mut self, workspaces: Workspaces) -> Self {
+        self.workspaces = workspaces;
+        self
+    }
+
+    // `config` names the model or settings the runner was set up with on the scoreboard
+    pub async fn run(&self, config: &str, runner: BatchRunner) -> Vec<EvalResult> {
+        let tasks: Vec<BatchTask> = self.cases.iter().map(|case| case.task.clone()).collect();
+        let report = runner
+            .with_workspaces(self.workspaces.clone())
+            .run(&tasks)
+            .await;
+        self.cases
+            .iter()
+            .zip(report.outcomes)
+            .map(|(case, outcome)| {
+                let checks_passed = match &outcome.workspace {
+                    Some(workspace) => case
+                        .checks
+                        .iter()
+                        .filter(|check| check.passes(workspace).unwrap_or(false))
+                        .count(),
+                    None => 0,
+                };
+                let passed = if case.checks.is_empty() {
+                    outcome.success
+                } else {
+                    checks_passed == case.checks.len()
+                };
+                EvalResult {
+                    config: config.to_string(),
+                    case: outcome.id.clone(),
+                    passed,
+                    checks_passed,
+                    checks_total: case.checks.len(),
+                    outcome,
+                }
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct Scoreboard {
+    pub results: Vec<EvalResult>,
+}
+
+impl Scoreboard {
+    pub fn add(&mut self, results: Vec<EvalResult>) {
+        self.results.extend(results);
+    }
+
+    // Configs in the order they were run
+    pub fn configs(&self) -> Vec<&str> {
+        let mut configs: Vec<&str> = Vec::new();
+        for result in &self.results {
+            if !configs.contains(&result.config.as_str()) {
+                configs.push(&result.config);
+            }
+        }
+        configs
+    }
+
+    pub fn passed(&self, config: &str) -> usize {
+        self.results_for(config)
+            .filter(|result| result.passed)
+            .count()
+    }
+
+    fn results_for<'a>(&'a self, config: &'a str) -> impl Iterator<Item = &'a EvalResult> {
+        self.results
+            .iter()
+            .filter(move |result| result.config == config)
+    }
+
+    // One line per config, best pass rate first, then the cases each config failed
+    pub fn summary(&self) -> String {
+        let mut scores: Vec<ConfigScore> = self
+            .configs()
+            .into_iter()
+            .map(|config| {
+                let mut score = ConfigScore {
+                    config,
+                    ..ConfigScore::default()
+                };
+                for result in self.results_for(config) {
+                    score.cases += 1;
+                    score.passed += usize::from(result.passed);
+                    score.turns += result.outcome.turns;
+                    score.usage.add(&result.outcome.usage);
+                    score.duration_ms += result.outcome.duration_ms;
+                }
+                score
+            })
+            .collect();
+        scores.sort_by(|a, b| b.pass_rate().total_cmp(&a.pass_rate()));
+        let mut summary = String::new();
+        for score in scores {
+            summary.push_str(&format!(
+                "{}: {}/{} passed ({:.0}%), {:.1} turns per case, ~{} tokens, {}ms\n",
+                score.config,
+                score.passed,
+                score.cases,
+                100.0 * score.pass_rate(),
+                score.turns as f64 / score.cases.max(1) as f64,
+                score.usage.total_tokens(),
+                score.duration_ms
+            ));
+        }
+        for result in self.results.iter().filter(|result| !result.passed) {
+            summary.push_str(&format!(
+                "FAIL {} {}: {}/{} checks",
+                result.config, result.case, result.checks_passed, result.checks_total
+            ));
+            if let Some(error) = &result.outcome.error {
+                summary.push_str(&format!(" ({})", error));
+            }
+            summary.push('\n');
+        }
+        summary
+    }
+}
+
+#[derive(Default)]
+struct ConfigScore<'a> {
+    config: &'a str,
+    cases: usize,
+    passed: usize,
+    turns: usize,
+    usage: Usage,
+    duration_ms: u64,
+}
+
+impl ConfigScore<'_> {
+    fn pass_rate(&self) -> f64 {
+        self.passed as f64 / self.cases.max(1) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::RunOptions;
+    use crate::mcp_client::MCPClient;
+    use crate::model_client::ChatMessage;
+    use crate::replay::{Fixture, ReplayModelClient};
+    use serde_json::json;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_checks_match_file_names_and_contents() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        std::fs::create_dir(dir.path().join("haiku"))?;
+        std::fs::write(
+            dir.path().join("haiku/haiku-1f3a.txt"),
+            "An old silent pond\nA frog jumps into the pond\nSplash! Silence again",
+        )?;
+        let check = |json: serde_json::Value| -> Result<bool> {
+            serde_json::from_value::<Check>(json)?.passes(dir.path())
+        };
+
+        assert!(check(
+            json!({"type": "file_exists", "path": "haiku/haiku-*.txt"})
+        )?);
+        assert!(!check(
+            json!({"type": "file_exists", "path": "haiku/*.md"})
+        )?);
+        assert!(check(
+            json!({"type": "file_matches", "path": "haiku/*.txt", "pattern": "(?m)^Splash"})
+        )?);
+        assert!(!check(
+            json!({"type": "file_matches", "path": "haiku/*.txt", "pattern": "rain"})
+        )?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_scoreboard_compares_configs_on_their_checks() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let evals = Evals::new(vec![EvalCase {
+            task: BatchTask {
+                id: Some("pond".to_string()),
+                task: crate::task::Task::new("Write a haiku to pond.txt"),
+            },
+            checks: vec![Check::FileExists {
+                path: "pond.txt".to_string(),
+            }],
+        }])
+        .with_workspaces(Workspaces::under(dir.path()));
+        // Stands in for a server that wrote the file, or one that didn't
+        let runner = |writes: bool| {
+            let fixture = Fixture::from_replies([r#"{"final_answer": "done"}"#]);
+            BatchRunner::new(
+                Arc::new(ReplayModelClient::new(&fixture)),
+                Box::new(move |root: &Path| {
+                    if writes {
+                        std::fs::write(root.join("pond.txt"), "An old silent pond").unwrap();
+                    }
+                    MCPClient::replaying(&Fixture::initialized_mcp())
+                }),
+                vec![ChatMessage::system("You are a poet")],
+            )
+            .with_options(RunOptions {
+                max_turns: 1,
+                ..RunOptions::default()
+            })
+        };
+
+        let mut scoreboard = Scoreboard::default();
+        scoreboard.add(evals.run("lazy", runner(false)).await);
+        scoreboard.add(evals.run("diligent", runner(true)).await);
+
+        assert_eq!(scoreboard.configs(), ["lazy", "diligent"]);
+        assert_eq!(scoreboard.passed("diligent"), 1);
+        assert_eq!(scoreboard.passed("lazy"), 0);
+        let summary = scoreboard.summary();
+        assert!(summary.starts_with("diligent: 1/1 passed (100%)"));
+        assert!(summary.contains("FAIL lazy pond: 0/1 checks"));
+        Ok(())
+    }
+}
@@ -0,0 +1,77 @@
+use crate::model_client::usage::CostEstimate;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AgentEvent {
+    ServerRestarted {
+        reason: String,
+    },
+    ModelResponse {
+        text: String,
+    },
+    ToolCall {
+        tool: String,
+        arguments: Value,
+    },
+    ToolResult {
+        tool: String,
+        result: Value,
+    },
+    ToolError {
+        tool: String,
+        error: String,
+    },
+    // A call the dry run recorded instead of executing
+    ToolSkipped {
+        tool: String,
+        arguments: Value,
+    },
+    // From a server's notifications/progress for a call made with a progressToken
+    ToolProgress {
+        token: Value,
+        progress: f64,
+        total: Option<f64>,
+        message: Option<String>,
+    },
+    TaskFinished {
+        success: bool,
+        turns: usize,
+        error: Option<String>,
+    },
+    CostEstimated {
+        estimate: CostEstimate,
+    },
+    // The server's tool catalog changed and was fetched again
+    ToolsChanged {
+        tools: Vec<String>,
+    },
+}
+
+impl AgentEvent {
+    // "step 3/10: indexing" style summary for progress events
+    pub fn progress_summary(&self) -> Option<String> {
+        let AgentEvent::ToolProgress {
+            progress,
+            total,
+            message,
+            ..
+        } = self
+        else {
+            return None;
+        };
+        let mut summary = match total {
+            Some(total) => format!("step {}/{}", progress, total),
+            None => format!("step {}", progress),
+        };
+        if let Some(message) = message {
+            summary.push_str(&format!(": {}", message));
+        }
+        Some(summary)
+    }
+}
+
+pub trait AgentObserver: Send + Sync {
+    fn on_event(&self, event: &AgentEvent);
+}
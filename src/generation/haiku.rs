@@ -0,0 +1,80 @@
+use super::ContentSpec;
+use crate::validation::{SyllableCountValidator, Validator};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Haiku {
+    pub lines: Vec<String>,
+}
+
+impl fmt::Display for Haiku {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.lines.join("\n"))
+    }
+}
+
+// Three lines of 5, 7 and 5 syllables about the given topic
+pub struct HaikuSpec;
+
+impl ContentSpec for HaikuSpec {
+    type Output = Haiku;
+
+    fn instructions(&self) -> String {
+        "You are a poet. Reply with only the haiku: three lines of 5, 7 and 5 syllables, no title or explanation.".to_string()
+    }
+
+    fn request(&self, topic: &str) -> String {
+        format!("Write a haiku about {}.", topic)
+    }
+
+    fn parse(&self, text: &str) -> std::result::Result<Haiku, Vec<String>> {
+        let problems = SyllableCountValidator::haiku().validate(text);
+        if !problems.is_empty() {
+            return Err(problems);
+        }
+        Ok(Haiku {
+            lines: text
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(str::to_string)
+                .collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Result;
+    use crate::generation::tests::replies;
+    use crate::generation::ContentGenerator;
+
+    #[tokio::test]
+    async fn test_generator_retries_until_syllables_match() -> Result<()> {
+        let generator = ContentGenerator::new(
+            Box::new(replies(&[
+                "Autumn leaves\nfall",
+                "An old silent pond\nA frog jumps into the pond\nSplash! Silence again",
+            ])),
+            HaikuSpec,
+        );
+
+        let haiku = generator.generate("a pond").await?;
+
+        assert_eq!(haiku.lines.len(), 3);
+        assert_eq!(haiku.lines[1], "A frog jumps into the pond");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_generator_gives_up_after_max_attempts() {
+        let generator =
+            ContentGenerator::new(Box::new(replies(&["Autumn leaves\nfall"])), HaikuSpec)
+                .with_max_attempts(1);
+
+        let err = generator.generate("autumn").await.unwrap_err();
+
+        assert!(err.to_string().contains("expected 3 lines, got 2"));
+    }
+}
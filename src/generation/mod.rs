@@ -0,0 +1,139 @@
+pub mod haiku;
+pub mod tasks;
+
+use crate::error::{AgentError, Result};
+use crate::model_client::{ChatMessage, CompletionRequest, ModelClient};
+use crate::validation::Validator;
+
+pub const DEFAULT_MAX_ATTEMPTS: usize = 3;
+
+// Describes one kind of content: how to ask for it and how to read the reply back
+pub trait ContentSpec: Send + Sync {
+    type Output: Send;
+
+    fn instructions(&self) -> String;
+
+    fn request(&self, input: &str) -> String;
+
+    // Turns the model's text into the output, or lists what is wrong with it
+    fn parse(&self, text: &str) -> std::result::Result<Self::Output, Vec<String>>;
+}
+
+// Asks the model for content matching a spec, feeding validation problems back until
+// a reply passes or the attempts run out
+pub struct ContentGenerator<S: ContentSpec> {
+    model: Box<dyn ModelClient>,
+    spec: S,
+    validators: Vec<Box<dyn Validator>>,
+    max_attempts: usize,
+}
+
+impl<S: ContentSpec> ContentGenerator<S> {
+    pub fn new(model: Box<dyn ModelClient>, spec: S) -> Self {
+        Self {
+            model,
+            spec,
+            validators: Vec::new(),
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+        }
+    }
+
+    // Checks on the raw reply on top of what the spec's parse enforces
+    pub fn with_validator(mut self, validator: Box<dyn Validator>) -> Self {
+        self.validators.push(validator);
+        self
+    }
+
+    pub fn with_max_attempts(mut self, max_attempts: usize) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    fn check(&self, text: &str) -> std::result::Result<S::Output, Vec<String>> {
+        let problems: Vec<String> = self
+            .validators
+            .iter()
+            .flat_map(|validator| validator.validate(text))
+            .collect();
+        if !problems.is_empty() {
+            return Err(problems);
+        }
+        self.spec.parse(text)
+    }
+
+    pub async fn generate(&self, input: &str) -> Result<S::Output> {
+        let mut messages = vec![
+            ChatMessage::system(self.spec.instructions()),
+            ChatMessage::user(self.spec.request(input)),
+        ];
+        let mut problems = Vec::new();
+        for _ in 0..self.max_attempts {
+            let response = self
+                .model
+                .complete(&CompletionRequest::new(messages.clone()))
+                .await?;
+            match self.check(&response.response) {
+                Ok(output) => return Ok(output),
+                Err(found) => {
+                    messages.push(ChatMessage::assistant(response.response));
+                    messages.push(ChatMessage::user(format!(
+                        "That does not meet the requirements:\n- {}\nTry again.",
+                        found.join("\n- ")
+                    )));
+                    problems = found;
+                }
+            }
+        }
+        Err(AgentError::ModelError(format!(
+            "No valid output after {} attempts: {}",
+            self.max_attempts,
+            problems.join("; ")
+        )))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::replay::{Fixture, ReplayModelClient};
+    use crate::validation::RegexValidator;
+
+    pub(crate) fn replies(responses: &[&str]) -> ReplayModelClient {
+        let fixture = Fixture::from_replies(responses.iter());
+        ReplayModelClient::new(&fixture)
+    }
+
+    // A custom task: a product name of one or two capitalised words
+    struct ProductName;
+
+    impl ContentSpec for ProductName {
+        type Output = String;
+
+        fn instructions(&self) -> String {
+            "Reply with only the name.".to_string()
+        }
+
+        fn request(&self, input: &str) -> String {
+            format!("Name a product that {}.", input)
+        }
+
+        fn parse(&self, text: &str) -> std::result::Result<String, Vec<String>> {
+            match text.split_whitespace().count() {
+                1 | 2 => Ok(text.trim().to_string()),
+                words => Err(vec![format!("{} words, expected one or two", words)]),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_custom_spec_and_validators_drive_retries() -> Result<()> {
+        let generator = ContentGenerator::new(
+            Box::new(replies(&["the best pond", "pondly", "Pondly"])),
+            ProductName,
+        )
+        .with_validator(Box::new(RegexValidator::new(r"^[A-Z]")?));
+
+        assert_eq!(generator.generate("cleans ponds").await?, "Pondly");
+        Ok(())
+    }
+}
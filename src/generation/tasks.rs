@@ -0,0 +1,100 @@
+use super::ContentSpec;
+use crate::task::Task;
+use serde::Deserialize;
+
+pub const DEFAULT_MAX_SUBTASKS: usize = 10;
+
+#[derive(Deserialize)]
+struct TaskList {
+    tasks: Vec<Task>,
+}
+
+// Splits a goal into independent tasks that separate agents can each finish on their own
+pub struct TaskListSpec {
+    max_tasks: usize,
+}
+
+impl TaskListSpec {
+    pub fn new(max_tasks: usize) -> Self {
+        Self {
+            max_tasks: max_tasks.max(1),
+        }
+    }
+}
+
+impl Default for TaskListSpec {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_SUBTASKS)
+    }
+}
+
+impl ContentSpec for TaskListSpec {
+    type Output = Vec<Task>;
+
+    fn instructions(&self) -> String {
+        format!(
+            r#"You are a supervisor splitting work between agents that cannot see each other's conversations. Reply only with {{"tasks": [{{"description": "<self-contained task>", "success_criteria": ["<how to tell it is done>"]}}]}}, at most {} tasks, each one doable on its own."#,
+            self.max_tasks
+        )
+    }
+
+    fn request(&self, goal: &str) -> String {
+        format!("Split this goal into tasks: {}", goal)
+    }
+
+    fn parse(&self, text: &str) -> std::result::Result<Vec<Task>, Vec<String>> {
+        let list: TaskList =
+            serde_json::from_str(text).map_err(|err| vec![format!("not a task list: {}", err)])?;
+        let mut problems = Vec::new();
+        if list.tasks.is_empty() {
+            problems.push("no tasks".to_string());
+        }
+        if list.tasks.len() > self.max_tasks {
+            problems.push(format!(
+                "{} tasks, expected at most {}",
+                list.tasks.len(),
+                self.max_tasks
+            ));
+        }
+        if list
+            .tasks
+            .iter()
+            .any(|task| task.description.trim().is_empty())
+        {
+            problems.push("a task has no description".to_string());
+        }
+        if problems.is_empty() {
+            Ok(list.tasks)
+        } else {
+            Err(problems)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rejects_empty_and_oversized_lists() {
+        let spec = TaskListSpec::new(2);
+
+        assert_eq!(
+            spec.parse(r#"{"tasks": [{"description": "Write a haiku about rain"}]}"#),
+            Ok(vec![Task::new("Write a haiku about rain")])
+        );
+        assert_eq!(
+            spec.parse(r#"{"tasks": []}"#),
+            Err(vec!["no tasks".to_string()])
+        );
+        assert_eq!(
+            spec.parse(
+                r#"{"tasks": [{"description": "a"}, {"description": "b"}, {"description": " "}]}"#
+            ),
+            Err(vec![
+                "3 tasks, expected at most 2".to_string(),
+                "a task has no description".to_string()
+            ])
+        );
+    }
+}
@@ -0,0 +1,251 @@
+use crate::agent::Agent;
+use crate::error::{AgentError, Result};
+use crate::events::{AgentEvent, AgentObserver};
+use crate::ids::{random_ids, IdGenerator};
+use crate::server::EventBroadcaster;
+use crate::task::Task;
+use crate::transcript::TranscriptFormat;
+use futures_util::Stream;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tonic::{Request, Response, Status};
+
+pub mod proto {
+    tonic::include_proto!("agent");
+}
+
+use proto::agent_control_server::{AgentControl, AgentControlServer};
+
+// The HTTP API's operations over gRPC, for callers that want typed contracts. It drives a single
+// agent, so one task runs at a time
+pub struct AgentControlService {
+    agent: Arc<tokio::sync::Mutex<Agent>>,
+    events: Arc<EventBroadcaster>,
+    running: Mutex<Option<JoinHandle<()>>>,
+    ids: Arc<dyn IdGenerator>,
+}
+
+impl AgentControlService {
+    pub fn new(mut agent: Agent) -> Self {
+        let events = Arc::new(EventBroadcaster::new());
+        agent.add_observer(events.clone());
+        Self {
+            agent: Arc::new(tokio::sync::Mutex::new(agent)),
+            events,
+            running: Mutex::new(None),
+            ids: random_ids(),
+        }
+    }
+
+    pub fn with_ids(mut self, ids: Arc<dyn IdGenerator>) -> Self {
+        self.ids = ids;
+        self
+    }
+
+    fn is_running(&self) -> bool {
+        self.running
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|handle| !handle.is_finished())
+    }
+}
+
+pub async fn serve(addr: &str, service: AgentControlService) -> Result<()> {
+    let addr = addr.parse().map_err(|err| {
+        AgentError::ConfigError(format!("invalid listen address {}: {}", addr, err))
+    })?;
+    eprintln!("Serving the agent control API over gRPC on {}", addr);
+    tonic::transport::Server::builder()
+        .add_service(AgentControlServer::new(service))
+        .serve(addr)
+        .await?;
+    Ok(())
+}
+
+type EventStream = Pin<Box<dyn Stream<Item = std::result::Result<proto::Event, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl AgentControl for AgentControlService {
+    async fn start_task(
+        &self,
+        request: Request<proto::StartTaskRequest>,
+    ) -> std::result::Result<Response<proto::StartTaskResponse>, Status> {
+        let request = request.into_inner();
+        if request.description.trim().is_empty() {
+            return Err(Status::invalid_argument("the task needs a description"));
+        }
+        let mut running = self.running.lock().unwrap();
+        if running.as_ref().is_some_and(|handle| !handle.is_finished()) {
+            return Err(Status::failed_precondition("a task is already running"));
+        }
+        let mut task = Task::new(request.description);
+        task.success_criteria = request.success_criteria;
+        let agent = self.agent.clone();
+        // The outcome reaches clients as a task_finished event
+        *running = Some(tokio::spawn(async move {
+            let _ = agent.lock().await.run_task(&task).await;
+        }));
+        Ok(Response::new(proto::StartTaskResponse {
+            task_id: self.ids.new_id().to_string(),
+        }))
+    }
+
+    type StreamEventsStream = EventStream;
+
+    async fn stream_events(
+        &self,
+        _request: Request<proto::StreamEventsRequest>,
+    ) -> std::result::Result<Response<EventStream>, Status> {
+        let receiver = self.events.subscribe();
+        let stream = futures_util::stream::unfold(receiver, |mut receiver| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(event) => return Some((Ok(to_proto(&event)), receiver)),
+                    // A slow client misses events rather than stalling the agent
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        });
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    async fn cancel(
+        &self,
+        request: Request<proto::CancelRequest>,
+    ) -> std::result::Result<Response<proto::CancelResponse>, Status> {
+        let reason = request.into_inner().reason;
+        let handle = self.running.lock().unwrap().take();
+        let Some(handle) = handle.filter(|handle| !handle.is_finished()) else {
+            return Ok(Response::new(proto::CancelResponse { cancelled: false }));
+        };
+        handle.abort();
+        let _ = handle.await;
+        self.agent
+            .lock()
+            .await
+            .cancel(&reason)
+            .await
+            .map_err(|err| Status::internal(err.to_string()))?;
+        self.events.on_event(&AgentEvent::TaskFinished {
+            success: false,
+            turns: 0,
+            error: Some(format!("Cancelled: {}", reason)),
+        });
+        Ok(Response::new(proto::CancelResponse { cancelled: true }))
+    }
+
+    async fn get_transcript(
+        &self,
+        request: Request<proto::GetTranscriptRequest>,
+    ) -> std::result::Result<Response<proto::Transcript>, Status> {
+        let format = match request.into_inner().format.as_str() {
+            "" | "jsonl" => TranscriptFormat::Jsonl,
+            "markdown" => TranscriptFormat::Markdown,
+            other => {
+                return Err(Status::invalid_argument(format!(
+                    "expected jsonl or markdown, got '{}'",
+                    other
+                )))
+            }
+        };
+        if self.is_running() {
+            return Err(Status::failed_precondition(
+                "a task is running; stream its events to follow it",
+            ));
+        }
+        let content = self
+            .agent
+            .lock()
+            .await
+            .export_transcript(format)
+            .map_err(|err| Status::internal(err.to_string()))?;
+        Ok(Response::new(proto::Transcript { content }))
+    }
+}
+
+fn to_proto(event: &AgentEvent) -> proto::Event {
+    let json = serde_json::to_value(event).unwrap_or_default();
+    proto::Event {
+        kind: json["type"].as_str().unwrap_or_default().to_string(),
+        json: json.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ids::SequentialIds;
+    use crate::mcp_client::MCPClient;
+    use crate::replay::{Fixture, ReplayModelClient};
+    use futures_util::StreamExt;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_started_task_is_streamed_and_transcribed() -> Result<()> {
+        let model = ReplayModelClient::new(&Fixture::from_replies([
+            json!({"final_answer": "An old silent pond"}),
+        ]));
+        let mut mcp_client = MCPClient::replaying(&Fixture::initialized_mcp());
+        mcp_client.init().await?;
+        let agent = Agent::builder()
+            .model(Box::new(model))
+            .mcp_client(mcp_client)
+            .system_prompt("You are a poet")
+            .build()
+            .await?;
+        let service = AgentControlService::new(agent).with_ids(Arc::new(SequentialIds::new()));
+
+        let mut events = service
+            .stream_events(Request::new(proto::StreamEventsRequest {}))
+            .await
+            .unwrap()
+            .into_inner();
+        let started = service
+            .start_task(Request::new(proto::StartTaskRequest {
+                description: "Write a haiku".to_string(),
+                success_criteria: Vec::new(),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        let mut kinds = Vec::new();
+        while let Some(event) = events.next().await {
+            let event = event.unwrap();
+            kinds.push(event.kind.clone());
+            if event.kind == "task_finished" {
+                assert_eq!(
+                    serde_json::from_str::<serde_json::Value>(&event.json)?["success"],
+                    true
+                );
+                break;
+            }
+        }
+        while service.is_running() {
+            tokio::task::yield_now().await;
+        }
+        let transcript = service
+            .get_transcript(Request::new(proto::GetTranscriptRequest {
+                format: "markdown".to_string(),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        let cancelled = service
+            .cancel(Request::new(proto::CancelRequest {
+                reason: "done".to_string(),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+
+        assert_eq!(started.task_id, "00000000-0000-0000-0000-000000000001");
+        assert_eq!(kinds, ["model_response", "task_finished"]);
+        assert!(transcript.content.contains("An old silent pond"));
+        assert!(!cancelled.cancelled);
+        Ok(())
+    }
+}
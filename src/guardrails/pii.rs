@@ -0,0 +1,163 @@
+use crate::error::{AgentError, Result};
+use crate::model_client::{CompletionRequest, ModelCapabilities, ModelClient, ModelResponse, Role};
+use async_trait::async_trait;
+use regex::Regex;
+
+const EMAIL_PATTERN: &str =
+    r"\b[A-Za-z0-9._%+-]+@[A-Za-z0-9-]+(?:\.[A-Za-z0-9-]+)*\.[A-Za-z]{2,}\b";
+// Grouped digits with an optional country code or area code in parentheses, e.g.
+// +31 6 1234 5678 or (555) 123-4567
+const PHONE_PATTERN: &str =
+    r"(?:\+\d{1,3}[ -]?)?(?:\(\d{1,4}\)[ -]?)?\b\d{1,4}(?:[ -]\d{2,4}){1,4}\b|\+\d{8,15}\b";
+// Fewer digits is a short number or a date; more is no phone number
+const MIN_PHONE_DIGITS: usize = 9;
+const MAX_PHONE_DIGITS: usize = 15;
+
+#[derive(Debug, Clone)]
+struct Rule {
+    mask: String,
+    regex: Regex,
+    phone: bool,
+}
+
+// Masks personal data in what the model reads of local files and tool output: emails, phone
+// numbers and patterns of your own, e.g. customer ids
+#[derive(Debug, Clone)]
+pub struct PiiScrubber {
+    rules: Vec<Rule>,
+}
+
+impl Default for PiiScrubber {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PiiScrubber {
+    pub fn new() -> Self {
+        let rule = |mask: &str, pattern: &str, phone| Rule {
+            mask: mask.to_string(),
+            regex: Regex::new(pattern).expect("default patterns are valid"),
+            phone,
+        };
+        Self {
+            rules: vec![
+                rule("[EMAIL]", EMAIL_PATTERN, false),
+                rule("[PHONE]", PHONE_PATTERN, true),
+            ],
+        }
+    }
+
+    // Matches are masked as the name in capitals, e.g. [IBAN]
+    pub fn with_pattern(mut self, name: &str, pattern: &str) -> Result<Self> {
+        let regex = Regex::new(pattern).map_err(|err| {
+            AgentError::ConfigError(format!("Invalid PII pattern '{}': {}", pattern, err))
+        })?;
+        self.rules.push(Rule {
+            mask: format!("[{}]", name.to_uppercase()),
+            regex,
+            phone: false,
+        });
+        Ok(self)
+    }
+
+    pub fn scrub(&self, text: &str) -> String {
+        self.rules.iter().fold(text.to_string(), |text, rule| {
+            rule.regex
+                .replace_all(&text, |captures: &regex::Captures| {
+                    let found = &captures[0];
+                    if rule.phone && !phone_number(found) {
+                        found.to_string()
+                    } else {
+                        rule.mask.clone()
+                    }
+                })
+                .into_owned()
+        })
+    }
+}
+
+fn phone_number(text: &str) -> bool {
+    let digits = text.chars().filter(char::is_ascii_digit).count();
+    // ISO dates with a time, e.g. 2024-01-15 10, have enough digits too
+    let date = text.len() >= 10
+        && text
+            .char_indices()
+            .take(10)
+            .all(|(index, c)| matches!(index, 4 | 7) == (c == '-'));
+    (MIN_PHONE_DIGITS..=MAX_PHONE_DIGITS).contains(&digits) && !date
+}
+
+// Scrubs tool messages, which carry file contents and other tool output, before a request
+// leaves for the backend. System and user messages are the operator's own and go as they are
+pub struct ScrubbingModelClient {
+    inner: Box<dyn ModelClient>,
+    scrubber: PiiScrubber,
+}
+
+impl ScrubbingModelClient {
+    pub fn new(inner: Box<dyn ModelClient>, scrubber: PiiScrubber) -> Self {
+        Self { inner, scrubber }
+    }
+}
+
+#[async_trait]
+impl ModelClient for ScrubbingModelClient {
+    async fn complete(&self, request: &CompletionRequest) -> Result<ModelResponse> {
+        let mut request = request.clone();
+        for message in &mut request.messages {
+            if message.role == Role::Tool {
+                message.content = self.scrubber.scrub(&message.content);
+            }
+        }
+        self.inner.complete(&request).await
+    }
+
+    fn capabilities(&self) -> ModelCapabilities {
+        self.inner.capabilities()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model_client::ChatMessage;
+    use std::sync::{Arc, Mutex};
+
+    // Keeps the messages it was sent
+    struct Remote(Arc<Mutex<Vec<ChatMessage>>>);
+
+    #[async_trait]
+    impl ModelClient for Remote {
+        async fn complete(&self, request: &CompletionRequest) -> Result<ModelResponse> {
+            *self.0.lock().unwrap() = request.messages.clone();
+            Ok(ModelResponse {
+                response: "{}".to_string(),
+                reasoning: None,
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tool_output_is_scrubbed_before_it_is_sent() -> Result<()> {
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let scrubber = PiiScrubber::new().with_pattern("customer", r"\bCUST-\d{6}\b")?;
+        let model = ScrubbingModelClient::new(Box::new(Remote(sent.clone())), scrubber);
+        let file = "Basho <basho@edo.example.jp>, +81 3 1234 5678, (555) 123-4567, CUST-001686\nWritten 1686-05-01 10:30, order 12 345";
+
+        model
+            .complete(&CompletionRequest::new(vec![
+                ChatMessage::user("Mail basho@edo.example.jp a haiku"),
+                ChatMessage::tool(file),
+            ]))
+            .await?;
+
+        let sent = sent.lock().unwrap();
+        assert_eq!(sent[0].content, "Mail basho@edo.example.jp a haiku");
+        assert_eq!(
+            sent[1].content,
+            "Basho <[EMAIL]>, [PHONE], [PHONE], [CUSTOMER]\nWritten 1686-05-01 10:30, order 12 345"
+        );
+        Ok(())
+    }
+}
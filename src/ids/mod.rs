@@ -0,0 +1,55 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use uuid::Uuid;
+
+// Source of unique ids for files, directories and records, injectable for predictable tests
+pub trait IdGenerator: Send + Sync {
+    fn new_id(&self) -> Uuid;
+}
+
+pub struct RandomIds;
+
+impl IdGenerator for RandomIds {
+    fn new_id(&self) -> Uuid {
+        Uuid::new_v4()
+    }
+}
+
+pub fn random_ids() -> Arc<dyn IdGenerator> {
+    Arc::new(RandomIds)
+}
+
+// 00000000-0000-0000-0000-000000000001, ...002 and so on
+#[derive(Default)]
+pub struct SequentialIds {
+    last: AtomicU64,
+}
+
+impl SequentialIds {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl IdGenerator for SequentialIds {
+    fn new_id(&self) -> Uuid {
+        Uuid::from_u128(u128::from(self.last.fetch_add(1, Ordering::SeqCst) + 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sequential_ids_count_up_from_one() {
+        let ids = SequentialIds::new();
+
+        assert_eq!(
+            ids.new_id().to_string(),
+            "00000000-0000-0000-0000-000000000001"
+        );
+        assert_eq!(ids.new_id().as_u128(), 2);
+        assert_ne!(RandomIds.new_id(), RandomIds.new_id());
+    }
+}
@@ -0,0 +1,231 @@
+use crate::error::Result;
+use crate::mcp_client::ToolCall;
+use crate::memory::cosine_similarity;
+use crate::model_client::embedding::EmbeddingClient;
+use serde::Deserialize;
+use std::path::Path;
+
+pub const SEARCH_KNOWLEDGE_TOOL: &str = "search_knowledge";
+pub const DEFAULT_SEARCH_LIMIT: usize = 3;
+
+const CHUNK_SIZE: usize = 1000;
+const CHUNK_OVERLAP: usize = 200;
+// Chunks per embedding request; providers cap the inputs per request (OpenAI at 2048)
+const EMBEDDING_BATCH_SIZE: usize = 256;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    pub source: String,
+    pub text: String,
+    embedding: Vec<f32>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KnowledgeHit {
+    pub source: String,
+    pub text: String,
+    pub score: f32,
+}
+
+pub struct KnowledgeBase {
+    embedder: Box<dyn EmbeddingClient>,
+    chunks: Vec<Chunk>,
+}
+
+impl KnowledgeBase {
+    pub fn new(embedder: Box<dyn EmbeddingClient>) -> Self {
+        Self {
+            embedder,
+            chunks: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    // Ingest a file, or every text file below a directory; returns the number of chunks added
+    pub async fn ingest_path(&mut self, path: &Path) -> Result<usize> {
+        if path.is_dir() {
+            let mut entries: Vec<_> = std::fs::read_dir(path)?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<std::io::Result<_>>()?;
+            entries.sort();
+            let mut added = 0;
+            for entry in entries {
+                let hidden = entry
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| name.starts_with('.'));
+                if !hidden {
+                    added += Box::pin(self.ingest_path(&entry)).await?;
+                }
+            }
+            return Ok(added);
+        }
+
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            // Binary files can't be grounded on, so skip them
+            Err(err) if err.kind() == std::io::ErrorKind::InvalidData => return Ok(0),
+            Err(err) => return Err(err.into()),
+        };
+        self.ingest_text(&path.display().to_string(), &text).await
+    }
+
+    pub async fn ingest_text(&mut self, source: &str, text: &str) -> Result<usize> {
+        let pieces = chunk_text(text, CHUNK_SIZE, CHUNK_OVERLAP);
+        if pieces.is_empty() {
+            return Ok(0);
+        }
+        let mut embeddings = Vec::with_capacity(pieces.len());
+        for batch in pieces.chunks(EMBEDDING_BATCH_SIZE) {
+            embeddings.extend(self.embedder.embed(batch).await?);
+        }
+        let added = pieces.len();
+        for (text, embedding) in pieces.into_iter().zip(embeddings) {
+            self.chunks.push(Chunk {
+                source: source.to_string(),
+                text,
+                embedding,
+            });
+        }
+        Ok(added)
+    }
+
+    pub async fn search(&self, query: &str, limit: usize) -> Result<Vec<KnowledgeHit>> {
+        if self.chunks.is_empty() {
+            return Ok(Vec::new());
+        }
+        let query = self.embedder.embed_one(query).await?;
+        let mut hits: Vec<KnowledgeHit> = self
+            .chunks
+            .iter()
+            .map(|chunk| KnowledgeHit {
+                source: chunk.source.clone(),
+                text: chunk.text.clone(),
+                score: cosine_similarity(&query, &chunk.embedding),
+            })
+            .collect();
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+        hits.truncate(limit);
+        Ok(hits)
+    }
+
+    // Handle a search_knowledge tool call
+    pub async fn call_tool(&self, call: &ToolCall) -> Result<String> {
+        let arguments: SearchArguments = call.arguments_as()?;
+        let limit = arguments.limit.unwrap_or(DEFAULT_SEARCH_LIMIT);
+        Ok(format_hits(&self.search(&arguments.query, limit).await?))
+    }
+}
+
+#[derive(Deserialize)]
+struct SearchArguments {
+    query: String,
+    limit: Option<usize>,
+}
+
+// Tells the model the internal tool exists and how to call it
+pub fn tool_instructions() -> String {
+    format!(
+        "You can search the user's documents with the '{}' tool, e.g. {{\"jsonrpc\": \"2.0\", \"method\": \"tools/call\", \"params\": {{\"name\": \"{}\", \"arguments\": {{\"query\": \"...\"}}}}, \"id\": 1}}. Ground your answers in what it returns.",
+        SEARCH_KNOWLEDGE_TOOL, SEARCH_KNOWLEDGE_TOOL
+    )
+}
+
+pub fn format_hits(hits: &[KnowledgeHit]) -> String {
+    if hits.is_empty() {
+        return "No matching documents found.".to_string();
+    }
+    let mut formatted = String::new();
+    for hit in hits {
+        formatted.push_str(&format!("\nDOCUMENT {}:\n{}\n", hit.source, hit.text));
+    }
+    formatted
+}
+
+// Fixed-size character windows that overlap so sentences aren't lost at the seams
+pub fn chunk_text(text: &str, size: usize, overlap: usize) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    if chars.iter().all(|c| c.is_whitespace()) {
+        return Vec::new();
+    }
+    let step = size.saturating_sub(overlap).max(1);
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    loop {
+        let end = (start + size).min(chars.len());
+        chunks.push(chars[start..end].iter().collect());
+        if end == chars.len() {
+            break;
+        }
+        start += step;
+    }
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::tests::LetterEmbedder;
+    use serde_json::json;
+
+    #[test]
+    fn test_chunk_text_overlaps_windows() {
+        assert_eq!(chunk_text("abcdefgh", 4, 2), vec!["abcd", "cdef", "efgh"]);
+        assert_eq!(chunk_text("abc", 4, 2), vec!["abc"]);
+        assert!(chunk_text("  \n", 4, 2).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_search_knowledge_returns_matching_document() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        std::fs::write(dir.path().join("haiku.md"), "haiku have five seven five")?;
+        std::fs::write(dir.path().join("zzz.txt"), "zzz buzz fizz jazz")?;
+        std::fs::write(dir.path().join("image.bin"), [0xff, 0xfe, 0x00])?;
+        let mut knowledge = KnowledgeBase::new(Box::new(LetterEmbedder));
+
+        assert_eq!(knowledge.ingest_path(dir.path()).await?, 2);
+        let result = knowledge
+            .call_tool(&ToolCall::new(
+                SEARCH_KNOWLEDGE_TOOL,
+                json!({"query": "haiku five seven", "limit": 1}),
+            ))
+            .await?;
+
+        assert!(result.contains("haiku.md"));
+        assert!(!result.contains("zzz.txt"));
+        Ok(())
+    }
+
+    struct BatchSizes(std::sync::Arc<std::sync::Mutex<Vec<usize>>>);
+
+    #[async_trait::async_trait]
+    impl EmbeddingClient for BatchSizes {
+        async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+            self.0.lock().unwrap().push(texts.len());
+            Ok(vec![vec![1.0]; texts.len()])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_large_documents_are_embedded_in_batches() -> Result<()> {
+        let sizes = std::sync::Arc::default();
+        let mut knowledge = KnowledgeBase::new(Box::new(BatchSizes(std::sync::Arc::clone(&sizes))));
+        let text = "a".repeat((CHUNK_SIZE - CHUNK_OVERLAP) * (EMBEDDING_BATCH_SIZE + 10));
+
+        let added = knowledge.ingest_text("long.txt", &text).await?;
+
+        assert_eq!(added, knowledge.len());
+        assert_eq!(
+            *sizes.lock().unwrap(),
+            vec![EMBEDDING_BATCH_SIZE, added - EMBEDDING_BATCH_SIZE]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,35 @@
+pub mod agent;
+pub mod artifacts;
+pub mod audit;
+pub mod batch;
+pub mod clock;
+pub mod error;
+pub mod evals;
+pub mod events;
+pub mod generation;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod guardrails;
+pub mod ids;
+pub mod knowledge;
+pub mod mcp_client;
+pub mod memory;
+pub mod model_client;
+pub mod native_tools;
+pub mod orchestrator;
+pub mod patch;
+pub mod presets;
+pub mod process;
+pub mod queue;
+pub mod redaction;
+pub mod replay;
+pub mod schedule;
+pub mod schema;
+pub mod server;
+pub mod storage;
+pub mod task;
+pub mod terminal;
+pub mod transcript;
+pub mod validation;
+pub mod watch;
+pub mod workspace;
@@ -1,50 +1,1761 @@
-pub mod agent;
-pub mod mcp_client;
-pub mod model_client;
-
-use mcp_client::MCPClient;
+use agent::agent::{
+    Agent, Checkpoint, FewShotLibrary, PermissionProfile, Quotas, RunOptions, StopCondition,
+    SystemPrompt, ToolResultPolicy, ToolSelector, DEFAULT_MAX_REPEATED_CALLS,
+    DEFAULT_MAX_TOOL_RESULT_BYTES, REPLY_FORMAT,
+};
+use agent::artifacts::ArtifactStore;
+use agent::audit::{Approval, AuditLog, AuditQuery};
+use agent::batch::{load_tasks, BatchRunner, DEFAULT_CONCURRENCY};
+use agent::error::AgentError;
+use agent::evals::{load_cases, Evals, Scoreboard};
+use agent::events::{AgentEvent, AgentObserver};
+use agent::generation::haiku::HaikuSpec;
+use agent::generation::ContentGenerator;
+use agent::guardrails::{InjectionGuard, InjectionPolicy, PiiScrubber, ScrubbingModelClient};
+use agent::knowledge::KnowledgeBase;
+use agent::mcp_client::git::{GitTools, COMMIT_ASSISTANT_PROMPT, GIT_COMMIT_TOOL};
+use agent::mcp_client::search::SearchEngine;
+use agent::mcp_client::{
+    render_prompt_messages, LogLevel, MCPClient, Sampler, SamplingApproval, ServerConfig, ToolCall,
+    LOG_TARGET,
+};
+use agent::memory::MemoryStore;
+use agent::model_client::azure_openai::AzureOpenAiClient;
+use agent::model_client::bedrock::BedrockClient;
+use agent::model_client::cache::CachedModelClient;
+use agent::model_client::embedding::OllamaEmbeddingClient;
+use agent::model_client::llama_cpp::LlamaCppClient;
+use agent::model_client::probe::Probe;
+use agent::model_client::rate_limit::{RateLimitedModelClient, RateLimits};
+use agent::model_client::usage::Pricing;
+use agent::model_client::{
+    ChatMessage, CompletionRequest, LocalOllamaClient, ModelClient, DEFAULT_OLLAMA_URL,
+};
+use agent::native_tools::{
+    CommandApproval, FetchUrlTool, NativeTools, RunCommandTool, Scratchpad, TerminalClarifier,
+};
+use agent::orchestrator::McpClientFactory;
+use agent::presets::{CodePreset, Report, ResearchPreset, DEFAULT_TEST_COMMAND};
+use agent::queue::{InMemoryQueue, Supervisor, TaskQueue, DEFAULT_WORKERS};
+use agent::redaction::Redactor;
+use agent::replay::{Fixture, Recorder, RecordingModelClient, ReplayModelClient};
+use agent::schedule::{load_schedules, ScheduledTask, Scheduler};
+use agent::server::hooks::{load_hooks, Webhooks};
+use agent::server::openai::{ChatCompletions, DEFAULT_MODEL_NAME};
+use agent::server::{self, EventBroadcaster};
+use agent::storage::{RunQuery, RunStore};
+use agent::task::{timing_table, Task};
+use agent::transcript::TranscriptFormat;
+use agent::watch::{FileWatch, DEFAULT_DEBOUNCE};
+use agent::workspace::Workspaces;
+use clap::{Args, Parser, Subcommand};
 use serde_json::json;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing_subscriber::EnvFilter;
+
+const MODEL: &str = "qwen3";
+const EMBEDDING_MODEL: &str = "nomic-embed-text";
+const MEMORY_CACHE_CAPACITY: usize = 128;
+// The rest of the system prompt is generated from the tools the agent ends up with
+const AGENT_ROLE: &str = "You are an agent with MCP capabilities.";
+
+#[derive(Parser)]
+#[command(name = "agent", about = "An MCP-capable agent driven by a local model")]
+struct Cli {
+    /// Server-published prompt template to seed the haiku loop with
+    #[arg(long)]
+    seed_prompt: Option<String>,
+
+    /// Cache model completions on disk in this directory
+    #[arg(long)]
+    cache_dir: Option<PathBuf>,
+
+    /// Write the run transcript here on exit (.md for Markdown, otherwise JSONL)
+    #[arg(long)]
+    transcript: Option<PathBuf>,
+
+    /// Record every model and MCP interaction to this fixture file
+    #[arg(long, conflicts_with = "replay")]
+    record: Option<PathBuf>,
+
+    /// Replay a recorded fixture instead of talking to live backends
+    #[arg(long)]
+    replay: Option<PathBuf>,
+
+    /// Persist long-term memories in this file and recall them into each task
+    #[arg(long)]
+    memory: Option<PathBuf>,
+
+    /// Remember how each task went in --memory, and recall similar past tasks into new ones
+    #[arg(long, requires = "memory")]
+    remember_outcomes: bool,
+
+    /// Ingest these files or directories so the model can search them
+    #[arg(long)]
+    knowledge: Vec<PathBuf>,
+
+    /// Show the model this many worked tool-call examples per tool, for small models
+    #[arg(long, default_value_t = 0)]
+    few_shot: usize,
+
+    /// Extra few-shot examples (a JSON array of {tool, request, arguments}) on top of the built-in ones
+    #[arg(long)]
+    few_shot_file: Option<PathBuf>,
+
+    /// Give the model remember/recall tools for intermediate results
+    #[arg(long)]
+    scratchpad: bool,
+
+    /// Let the model ask you about an ambiguous task on the terminal instead of guessing
+    #[arg(long)]
+    ask_user: bool,
+
+    /// Have the model change files with diffs or search/replace blocks through an edit_file tool
+    #[arg(long)]
+    edit_files: bool,
+
+    /// Let the model fetch pages from this domain and its subdomains through fetch_url (repeatable)
+    #[arg(long)]
+    allow_domain: Vec<String>,
+
+    /// Only let run_command run this program (repeatable); needs --run-commands
+    #[arg(long)]
+    allow_command: Vec<String>,
+
+    /// Give the model a run_command tool for shell command lines in the current directory
+    #[arg(long)]
+    run_commands: bool,
+
+    /// Never let run_command run this program, on top of the built-in denylist (repeatable)
+    #[arg(long)]
+    deny_command: Vec<String>,
+
+    /// Only offer the model this tool, by name; every other tool is hidden and refused (repeatable)
+    #[arg(long)]
+    allow_tool: Vec<String>,
+
+    /// Hide this tool from the model and refuse calls to it (repeatable)
+    #[arg(long)]
+    deny_tool: Vec<String>,
+
+    /// Run under this permission profile: read-only, developer, admin, or one from --profiles
+    #[arg(long, value_name = "NAME")]
+    profile: Option<String>,
+
+    /// JSON file of custom permission profiles
+    #[arg(long, value_name = "FILE", requires = "profile")]
+    profiles: Option<PathBuf>,
+
+    /// Whether run_command, and calls the profile requires approval for, ask first: ask, always
+    /// or never [default: the profile's, else ask]. With --run-commands, always needs
+    /// --allow-command
+    #[arg(long, value_parser = parse_command_approval)]
+    command_approval: Option<CommandApproval>,
+
+    /// Print the tool calls the model asks for instead of executing them
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Have the model plan the task first, then execute and verify the plan step by step
+    #[arg(long)]
+    plan: bool,
+
+    /// Have the model critique each result against the task and retry if it falls short
+    #[arg(long)]
+    reflect: bool,
+
+    /// Stop when the model makes the identical tool call this many times in a row (0 disables)
+    #[arg(long, default_value_t = DEFAULT_MAX_REPEATED_CALLS)]
+    max_repeated_calls: usize,
+
+    /// Pause the run for confirmation before it writes more than this many files
+    #[arg(long)]
+    max_files_written: Option<usize>,
+
+    /// Pause the run for confirmation before it writes more than this many bytes
+    #[arg(long)]
+    max_bytes_written: Option<u64>,
+
+    /// Pause the run for confirmation before it runs more than this many shell commands
+    #[arg(long)]
+    max_commands: Option<usize>,
+
+    /// Whether a run going over a --max-* quota asks to continue, continues or stops: ask,
+    /// always or never. Asking without a terminal stops it
+    #[arg(long, value_parser = parse_command_approval, default_value = "ask")]
+    quota_approval: CommandApproval,
+
+    /// Don't end replies at the close of their JSON object with stop sequences
+    #[arg(long)]
+    no_stop_sequences: bool,
+
+    /// With more tools than this, list only their names in the prompt and let the model look up arguments with describe_tool
+    #[arg(long, value_name = "N")]
+    compact_tools: Option<usize>,
+
+    /// Describe only this many tools in each turn's prompt, the ones whose descriptions are closest to the task and latest message
+    #[arg(long, value_name = "N")]
+    tools_per_turn: Option<usize>,
+
+    /// Ask the model for this many replies at once each turn and use the first valid one
+    #[arg(long, default_value_t = 1)]
+    samples: usize,
+
+    /// What to do with tool output over --max-tool-result-bytes: truncate, summarize or spill:DIR
+    #[arg(long, default_value = "truncate", value_parser = parse_tool_result_policy)]
+    tool_results: ToolResultPolicy,
+
+    /// Largest tool output, in bytes, put into the conversation as is
+    #[arg(long, default_value_t = DEFAULT_MAX_TOOL_RESULT_BYTES)]
+    max_tool_result_bytes: usize,
+
+    /// What to do with tool output that looks like a prompt injection: sanitize, ask, withhold
+    /// or off
+    #[arg(long, default_value = "sanitize", value_parser = parse_injection_guard)]
+    injection_guard: std::option::Option<InjectionPolicy>,
+
+    /// Whether MCP servers may request completions from the model: ask, always or never.
+    /// Without a terminal to ask on, ask denies
+    #[arg(long, default_value = "ask", value_parser = parse_sampling_approval)]
+    sampling: SamplingApproval,
+
+    /// Ask MCP servers to log at this level and above: debug, info, notice, warning, error,
+    /// critical, alert or emergency; RUST_LOG=mcp=LEVEL filters what is shown
+    #[arg(long, value_parser = parse_log_level)]
+    server_log_level: Option<LogLevel>,
+
+    /// Copy the files each run writes into a directory of its own under DIR
+    #[arg(long, value_name = "DIR")]
+    artifacts: Option<PathBuf>,
+
+    /// Append every executed tool call to this audit log
+    #[arg(long)]
+    audit_log: Option<PathBuf>,
+
+    /// Save every run (task, transcript, tool calls, outcome, usage) to this SQLite database
+    #[arg(long)]
+    history: Option<PathBuf>,
+
+    /// Print how long each turn spent on the model, parsing its reply and the tool call
+    #[arg(long)]
+    timing: bool,
+
+    /// Keep the task's state in this file as it runs; if the file exists at startup, resume from it
+    #[arg(long)]
+    checkpoint: Option<PathBuf>,
+
+    /// End the task as soon as this file exists (repeatable)
+    #[arg(long, value_name = "FILE")]
+    stop_when_exists: Vec<PathBuf>,
+
+    /// End the task as soon as this file exists and parses as JSON (repeatable)
+    #[arg(long, value_name = "FILE")]
+    stop_when_json: Vec<PathBuf>,
+
+    /// Ollama server for chat and embeddings; set OLLAMA_API_KEY if it sits behind an authenticating proxy
+    #[arg(long, default_value = DEFAULT_OLLAMA_URL)]
+    ollama_url: String,
+
+    /// Stream Ollama's replies and stop generating as soon as a complete tool call has arrived
+    #[arg(long)]
+    stream: bool,
+
+    #[command(flatten)]
+    backend: BackendArgs,
+
+    /// Mask emails and phone numbers in tool output and file contents before they reach the model
+    #[arg(long)]
+    scrub_pii: bool,
+
+    /// Also mask matches of this regex, as [NAME]; implies --scrub-pii (repeatable)
+    #[arg(long = "scrub-pattern", value_name = "NAME=REGEX", value_parser = parse_key_value)]
+    scrub_patterns: Vec<(String, String)>,
+
+    /// Cap model requests per minute
+    #[arg(long)]
+    requests_per_minute: Option<u32>,
+
+    /// Cap estimated model tokens (prompt plus max output) per minute
+    #[arg(long)]
+    tokens_per_minute: Option<u32>,
+
+    /// US dollars per million prompt tokens, for cost estimates; Ollama and llama.cpp are free by default
+    #[arg(long, value_name = "USD")]
+    prompt_price: Option<f64>,
+
+    /// US dollars per million completion tokens, for cost estimates
+    #[arg(long, value_name = "USD")]
+    completion_price: Option<f64>,
+
+    /// With --plan, don't execute a plan estimated to cost more than this many US dollars
+    #[arg(long, value_name = "USD")]
+    budget: Option<f64>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum ToolCommand {
+    /// Call a tool and print its result; exits with an error if the tool reports one
+    Call {
+        /// filesystem or git (on the current directory), brave-search, duckduckgo, or the command line that starts the server
+        server: String,
+        tool: String,
+        /// Tool arguments as a JSON object
+        #[arg(long, default_value = "{}")]
+        args: String,
+        /// Print the raw result, including structuredContent, as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum ModelCommand {
+    /// Send one prompt and print the response, the JSON extracted from it, latency and tokens
+    Test {
+        #[arg(long)]
+        prompt: String,
+        /// System message sent before the prompt
+        #[arg(long)]
+        system: Option<String>,
+        /// Ollama model to use; other backends are used as configured
+        #[arg(long, default_value = MODEL)]
+        model: String,
+    },
+}
+
+// Live model backends other than the default local Ollama
+#[derive(Args, Clone)]
+#[group(multiple = false)]
+struct BackendArgs {
+    /// Use the llama.cpp server at this URL instead of Ollama
+    #[arg(long, value_name = "URL")]
+    llama_cpp: Option<String>,
+
+    /// Use this AWS Bedrock model (e.g. anthropic.claude-3-haiku-20240307-v1:0) instead of Ollama
+    #[arg(long, value_name = "MODEL_ID")]
+    bedrock: Option<String>,
+
+    /// Use this Azure OpenAI deployment instead of Ollama (endpoint and auth from AZURE_OPENAI_* variables)
+    #[arg(long, value_name = "DEPLOYMENT")]
+    azure_deployment: Option<String>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List the prompt templates published by the MCP server, or render one by name
+    Prompts {
+        name: Option<String>,
+        /// Prompt arguments as key=value pairs
+        #[arg(long = "arg", value_parser = parse_key_value)]
+        args: Vec<(String, String)>,
+    },
+    /// Use MCP servers directly, without the model
+    Tool {
+        #[command(subcommand)]
+        command: ToolCommand,
+    },
+    /// Check the configured model backend outside the agent loop
+    Model {
+        #[command(subcommand)]
+        command: ModelCommand,
+    },
+    /// Run the agent loop while streaming its events over a WebSocket at /events
+    Serve {
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        listen: String,
+        /// JSON array of webhooks (name, task template, secret_env or unsigned) to accept at /hooks/<name>
+        #[arg(long)]
+        hooks: Option<PathBuf>,
+    },
+    /// Serve the agent as an OpenAI-compatible model at /v1/chat/completions, one task per request
+    ServeOpenai {
+        #[arg(long, default_value = "127.0.0.1:8000")]
+        listen: String,
+        /// Model name clients see in /v1/models and select the agent by
+        #[arg(long, default_value = DEFAULT_MODEL_NAME)]
+        model_name: String,
+    },
+    /// Serve the agent's control API (start, cancel, events, transcript) over gRPC (needs the grpc feature)
+    ServeGrpc {
+        #[arg(long, default_value = "127.0.0.1:50051")]
+        listen: String,
+    },
+    /// Generate a single haiku about a topic, retrying until it scans 5-7-5
+    Haiku { topic: String },
+    /// Have the model write a message for the staged changes and commit them through the git MCP server
+    Commit {
+        #[arg(long, default_value = ".")]
+        repo: String,
+    },
+    /// Make a code change in a repository: plan it, edit files with diffs, check git and run the tests
+    Code {
+        /// What to change
+        task: String,
+        #[arg(long, default_value = ".")]
+        repo: PathBuf,
+        /// Command that runs the repository's tests
+        #[arg(long, default_value = DEFAULT_TEST_COMMAND)]
+        test_command: String,
+    },
+    /// Answer a question from web search results, citing the pages the answer comes from
+    Research {
+        question: String,
+        /// brave or duckduckgo; Brave when BRAVE_API_KEY is set, otherwise DuckDuckGo
+        #[arg(long, value_parser = parse_search_engine)]
+        search: Option<SearchEngine>,
+        /// Print the answer and its sources as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Run independent tasks from a JSONL file, each in its own session, and report on them
+    Batch {
+        #[arg(long)]
+        file: PathBuf,
+        /// Tasks run at the same time
+        #[arg(long, default_value_t = DEFAULT_CONCURRENCY)]
+        concurrency: usize,
+        /// Also write the report as JSON to this file
+        #[arg(long)]
+        report: Option<PathBuf>,
+        /// Give each task its own directory to work in, under DIR or the system temp directory
+        #[arg(long, value_name = "DIR")]
+        workspaces: Option<Option<PathBuf>>,
+    },
+    /// Run tasks on a cron cadence (UTC), each run in its own session, until stopped
+    Schedule {
+        /// Five-field cron expression, e.g. "0 9 * * *" for every day at 09:00
+        #[arg(long, requires = "task", required_unless_present = "file")]
+        cron: Option<String>,
+        /// What to do each time the cron expression comes due
+        #[arg(long, requires = "cron")]
+        task: Option<String>,
+        /// JSONL file of scheduled tasks, each a task with an id and a cron field
+        #[arg(long, conflicts_with = "cron")]
+        file: Option<PathBuf>,
+        /// Stop after this many runs instead of running until interrupted
+        #[arg(long)]
+        runs: Option<usize>,
+        /// Give each run its own directory to work in, under DIR or the system temp directory
+        #[arg(long, value_name = "DIR")]
+        workspaces: Option<Option<PathBuf>>,
+    },
+    /// Run a task whenever files matching a glob change, until stopped
+    Watch {
+        /// Glob relative to --dir, e.g. "**/*.md"; * stays within one directory
+        #[arg(long)]
+        glob: String,
+        /// What to do after a change; {path} is replaced by the changed file
+        #[arg(long)]
+        task: String,
+        /// Directory to watch, with its subdirectories
+        #[arg(long, default_value = ".")]
+        dir: PathBuf,
+        /// How long a file has to stay unchanged before its run starts
+        #[arg(long, default_value_t = DEFAULT_DEBOUNCE.as_millis() as u64)]
+        debounce_ms: u64,
+        /// Stop after this many runs instead of running until interrupted
+        #[arg(long)]
+        runs: Option<usize>,
+    },
+    /// Run eval cases (JSONL tasks with checks) against one or more models and print a scoreboard
+    Eval {
+        #[arg(long)]
+        file: PathBuf,
+        /// Ollama models to compare, comma-separated; other backends are evaluated as configured
+        #[arg(long, value_delimiter = ',', default_value = MODEL)]
+        models: Vec<String>,
+        /// Cases run at the same time
+        #[arg(long, default_value_t = DEFAULT_CONCURRENCY)]
+        concurrency: usize,
+        /// Also write the scoreboard as JSON to this file
+        #[arg(long)]
+        report: Option<PathBuf>,
+        /// Create each case's directory under DIR instead of the system temp directory
+        #[arg(long, value_name = "DIR")]
+        workspaces: Option<PathBuf>,
+    },
+    /// Have the model split a goal into tasks on a queue, then work through them with several agents
+    Supervise {
+        goal: String,
+        /// Agents pulling tasks from the queue at the same time
+        #[arg(long, default_value_t = DEFAULT_WORKERS)]
+        workers: usize,
+        /// Keep the queue in Redis at this URL instead of in memory (needs the redis feature)
+        #[arg(long)]
+        redis: Option<String>,
+        /// Prefix of the Redis keys holding the queue
+        #[arg(long, default_value = "agent")]
+        queue_prefix: String,
+        /// Give each task its own directory to work in, under DIR or the system temp directory
+        #[arg(long, value_name = "DIR")]
+        workspaces: Option<Option<PathBuf>>,
+    },
+    /// Query a run history database, or show one run's transcript with --id
+    History {
+        path: PathBuf,
+        #[arg(long)]
+        id: Option<i64>,
+        /// Only runs that failed
+        #[arg(long)]
+        failed: bool,
+        /// Only runs that called this tool
+        #[arg(long)]
+        tool: Option<String>,
+        /// Only runs started at or after this RFC 3339 UTC timestamp
+        #[arg(long)]
+        since: Option<String>,
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Query a tool execution audit log
+    Audit {
+        path: PathBuf,
+        #[arg(long)]
+        tool: Option<String>,
+        #[arg(long)]
+        server: Option<String>,
+        /// allowed or denied
+        #[arg(long, value_parser = parse_approval)]
+        approval: Option<Approval>,
+        /// Only calls at or after this RFC 3339 UTC timestamp
+        #[arg(long)]
+        since: Option<String>,
+    },
+}
+
+#[cfg(feature = "redis")]
+async fn task_queue(redis: Option<&str>, prefix: &str) -> anyhow::Result<Arc<dyn TaskQueue>> {
+    Ok(match redis {
+        Some(url) => Arc::new(agent::queue::RedisQueue::connect(url, prefix).await?),
+        None => Arc::new(InMemoryQueue::new()),
+    })
+}
+
+#[cfg(not(feature = "redis"))]
+async fn task_queue(redis: Option<&str>, _prefix: &str) -> anyhow::Result<Arc<dyn TaskQueue>> {
+    if redis.is_some() {
+        anyhow::bail!("This build has no Redis support; rebuild with --features redis");
+    }
+    Ok(Arc::new(InMemoryQueue::new()))
+}
+
+#[cfg(feature = "grpc")]
+async fn serve_grpc(listen: &str, agent: Agent) -> anyhow::Result<()> {
+    agent::grpc::serve(listen, agent::grpc::AgentControlService::new(agent)).await?;
+    Ok(())
+}
+
+#[cfg(not(feature = "grpc"))]
+async fn serve_grpc(_listen: &str, _agent: Agent) -> anyhow::Result<()> {
+    anyhow::bail!("This build has no gRPC support; rebuild with --features grpc");
+}
+
+fn parse_approval(raw: &str) -> Result<Approval, String> {
+    serde_json::from_value(json!(raw))
+        .map_err(|_| format!("expected allowed or denied, got '{}'", raw))
+}
+
+fn parse_log_level(raw: &str) -> Result<LogLevel, String> {
+    serde_json::from_value(json!(raw)).map_err(|_| {
+        format!(
+            "expected debug, info, notice, warning, error, critical, alert or emergency, got '{}'",
+            raw
+        )
+    })
+}
+
+fn parse_search_engine(raw: &str) -> Result<SearchEngine, String> {
+    match raw {
+        "brave" => Ok(SearchEngine::Brave),
+        "duckduckgo" => Ok(SearchEngine::DuckDuckGo),
+        _ => Err(format!("expected brave or duckduckgo, got '{}'", raw)),
+    }
+}
+
+fn parse_injection_guard(raw: &str) -> Result<Option<InjectionPolicy>, String> {
+    match raw {
+        "sanitize" => Ok(Some(InjectionPolicy::Sanitize)),
+        "ask" => Ok(Some(InjectionPolicy::Ask)),
+        "withhold" => Ok(Some(InjectionPolicy::Withhold)),
+        "off" => Ok(None),
+        _ => Err(format!(
+            "expected sanitize, ask, withhold or off, got '{}'",
+            raw
+        )),
+    }
+}
+
+fn parse_sampling_approval(raw: &str) -> Result<SamplingApproval, String> {
+    match raw {
+        "ask" => Ok(SamplingApproval::Ask),
+        "always" => Ok(SamplingApproval::Always),
+        "never" => Ok(SamplingApproval::Never),
+        _ => Err(format!("expected ask, always or never, got '{}'", raw)),
+    }
+}
+
+fn parse_tool_result_policy(raw: &str) -> Result<ToolResultPolicy, String> {
+    match raw {
+        "truncate" => Ok(ToolResultPolicy::Truncate),
+        "summarize" => Ok(ToolResultPolicy::Summarize),
+        _ => match raw.strip_prefix("spill:") {
+            Some(dir) if !dir.is_empty() => Ok(ToolResultPolicy::SpillToFile(PathBuf::from(dir))),
+            _ => Err(format!(
+                "expected truncate, summarize or spill:DIR, got '{}'",
+                raw
+            )),
+        },
+    }
+}
+
+fn parse_command_approval(raw: &str) -> Result<CommandApproval, String> {
+    match raw {
+        "ask" => Ok(CommandApproval::Ask),
+        "always" => Ok(CommandApproval::Always),
+        "never" => Ok(CommandApproval::Never),
+        _ => Err(format!("expected ask, always or never, got '{}'", raw)),
+    }
+}
+
+fn parse_key_value(raw: &str) -> Result<(String, String), String> {
+    raw.split_once('=')
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .ok_or_else(|| format!("expected key=value, got '{}'", raw))
+}
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let model = model_client::LocalOllamaClient::new("qwen3".to_string());
-    let mut mcp_client = MCPClient::new();
+    let cli = Cli::parse();
+    // Server logs are filtered by the servers themselves, at --server-log-level
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_env_filter(
+            EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| EnvFilter::new(format!("warn,{}=debug", LOG_TARGET))),
+        )
+        .init();
+    let prompt = agent_prompt(cli.few_shot, cli.few_shot_file.as_deref())?;
+
+    let fixture = cli.replay.as_ref().map(Fixture::load).transpose()?;
+    let recorder = cli.record.as_ref().map(|_| Recorder::new());
+    let flags = model_flags(&cli, fixture.clone(), recorder.clone())?;
+    let profile = permission_profile(
+        cli.profile.as_deref(),
+        cli.profiles.as_deref(),
+        cli.command_approval,
+    )?;
+    let command_approval = cli
+        .command_approval
+        .or(profile.as_ref().map(|profile| profile.approval))
+        .unwrap_or(CommandApproval::Ask);
+    if !cli.run_commands && !cli.allow_command.is_empty() {
+        anyhow::bail!("--allow-command lists the programs run_command may run; add --run-commands");
+    }
+    if cli.run_commands
+        && command_approval == CommandApproval::Always
+        && cli.allow_command.is_empty()
+    {
+        anyhow::bail!(
+            "run_command only runs without asking when --allow-command lists the programs it may run"
+        );
+    }
+    let options = run_options(&cli);
+
+    match cli.command {
+        Some(Command::Audit {
+            path,
+            tool,
+            server,
+            approval,
+            since,
+        }) => {
+            let query = AuditQuery {
+                tool,
+                server,
+                approval,
+                since,
+            };
+            for record in AuditLog::new(path).query(&query)? {
+                println!("{}", serde_json::to_string(&record)?);
+            }
+            Ok(())
+        }
+        Some(Command::History {
+            path, id: Some(id), ..
+        }) => {
+            let store = RunStore::open(path)?;
+            let run = store
+                .run(id)?
+                .ok_or_else(|| anyhow::anyhow!("No run with id {}", id))?;
+            print!(
+                "{}",
+                run.run.transcript().export(TranscriptFormat::Markdown)?
+            );
+            Ok(())
+        }
+        Some(Command::History {
+            path,
+            id: None,
+            failed,
+            tool,
+            since,
+            limit,
+        }) => {
+            let query = RunQuery {
+                success: failed.then_some(false),
+                tool,
+                since,
+                limit: Some(limit),
+            };
+            for stored in RunStore::open(path)?.runs(&query)? {
+                let run = &stored.run;
+                println!(
+                    "{}\t{}\t{}\t{} turns\t~{} tokens\t{}ms\t{}",
+                    stored.id,
+                    run.started_at,
+                    if run.result.success { "ok" } else { "FAIL" },
+                    run.result.turns,
+                    run.usage.total_tokens(),
+                    run.duration_ms,
+                    run.task.description.lines().next().unwrap_or_default()
+                );
+            }
+            Ok(())
+        }
+        Some(Command::Prompts { name: None, .. }) => {
+            let mut mcp_client = connect(
+                fixture.as_ref(),
+                recorder.as_ref(),
+                None,
+                cli.server_log_level,
+            )
+            .await?;
+            for prompt in mcp_client.list_prompts().await? {
+                println!(
+                    "{}\t{}",
+                    prompt.name,
+                    prompt.description.unwrap_or_default()
+                );
+                for argument in prompt.arguments.unwrap_or_default() {
+                    println!(
+                        "  --arg {}=<{}>{}",
+                        argument.name,
+                        argument.description.unwrap_or_default(),
+                        if argument.required.unwrap_or(false) {
+                            " (required)"
+                        } else {
+                            ""
+                        }
+                    );
+                }
+            }
+            mcp_client.shutdown().await?;
+            Ok(())
+        }
+        Some(Command::Prompts {
+            name: Some(name),
+            args,
+        }) => {
+            let mut mcp_client = connect(
+                fixture.as_ref(),
+                recorder.as_ref(),
+                None,
+                cli.server_log_level,
+            )
+            .await?;
+            let arguments = args
+                .into_iter()
+                .map(|(key, value)| (key, json!(value)))
+                .collect();
+            let prompt = mcp_client.get_prompt(&name, arguments).await?;
+            println!("{}", render_prompt_messages(&prompt.messages));
+            mcp_client.shutdown().await?;
+            Ok(())
+        }
+        Some(Command::Tool {
+            command:
+                ToolCommand::Call {
+                    server,
+                    tool,
+                    args,
+                    json,
+                },
+        }) => {
+            let arguments: serde_json::Value = serde_json::from_str(&args)
+                .map_err(|err| anyhow::anyhow!("--args is not valid JSON: {}", err))?;
+            if !arguments.is_object() {
+                anyhow::bail!("--args must be a JSON object");
+            }
+            let mut mcp_client = match &fixture {
+                Some(fixture) => MCPClient::replaying(fixture),
+                None => MCPClient::with_server(with_log_level(
+                    ServerConfig::from_spec(&server)?,
+                    cli.server_log_level,
+                )),
+            };
+            if let Some(recorder) = &recorder {
+                mcp_client = mcp_client.with_recorder(recorder.clone());
+            }
+            mcp_client.init().await?;
+            let result = mcp_client.call_tool(&ToolCall::new(tool, arguments)).await;
+            mcp_client.shutdown().await?;
+            let result = result?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&result.to_value())?);
+            } else {
+                println!("{}", result.text());
+            }
+            if result.is_error {
+                anyhow::bail!("The tool reported an error");
+            }
+            Ok(())
+        }
+        Some(Command::Model {
+            command:
+                ModelCommand::Test {
+                    prompt,
+                    system,
+                    model,
+                },
+        }) => {
+            let model = build_model(&flags, &model).await?;
+            let messages = system
+                .map(ChatMessage::system)
+                .into_iter()
+                .chain([ChatMessage::user(prompt)])
+                .collect();
+            let probe = Probe::run(model.as_ref(), &CompletionRequest::new(messages)).await?;
+            println!("{}", probe.report());
+            Ok(())
+        }
+        Some(Command::Haiku { topic }) => {
+            let model = build_model(&flags, MODEL).await?;
+            let haiku = ContentGenerator::new(model, HaikuSpec)
+                .generate(&topic)
+                .await?;
+            println!("{}", haiku);
+            Ok(())
+        }
+        Some(Command::Commit { repo }) => {
+            let model = build_model(&flags, MODEL).await?;
+            let mut mcp_client = match &fixture {
+                Some(fixture) => MCPClient::replaying(fixture),
+                None => MCPClient::with_server(with_log_level(
+                    ServerConfig::git(&repo),
+                    cli.server_log_level,
+                )),
+            };
+            if let Some(recorder) = &recorder {
+                mcp_client = mcp_client.with_recorder(recorder.clone());
+            }
+            mcp_client.init().await?;
+            let git = GitTools::new(&repo);
+            let status = git.status(&mut mcp_client).await?;
+            let staged = git.diff_staged(&mut mcp_client).await?;
+            if staged.trim().is_empty() {
+                println!("Nothing staged to commit");
+                mcp_client.shutdown().await?;
+                return Ok(());
+            }
+            let mut builder = Agent::builder()
+                .model(model)
+                .mcp_client(mcp_client)
+                .system_prompt(COMMIT_ASSISTANT_PROMPT)
+                .message(ChatMessage::system(REPLY_FORMAT))
+                .allow_tools([GIT_COMMIT_TOOL])
+                .options(options)
+                .redactor(Redactor::from_env())
+                .observer(Arc::new(ConsoleProgress));
+            if let Some(profile) = &profile {
+                builder = builder.permission_profile(profile.clone());
+            }
+            if let Some(path) = &cli.history {
+                builder = builder.run_store(RunStore::open(path)?);
+            }
+            let mut agent = builder.build().await?;
+            let result = agent.run_task(&git.commit_task(&status, &staged)).await?;
+            agent.shutdown().await?;
+            if cli.timing {
+                eprint!("{}", timing_table(&result.timings));
+            }
+            if let (Some(path), Some(recorder)) = (cli.record, recorder) {
+                recorder.fixture().save(&path)?;
+                println!("Fixture recorded to {}", path.display());
+            }
+            match result.error {
+                None => Ok(()),
+                Some(error) => anyhow::bail!("commit failed: {}", error),
+            }
+        }
+        Some(Command::Code {
+            task,
+            repo,
+            test_command,
+        }) => {
+            let model = build_model(&flags, MODEL).await?;
+            let preset = CodePreset::new(&repo)
+                .with_test_command(test_command)
+                .with_command_approval(command_approval);
+            let mut mcp_client = match &fixture {
+                Some(fixture) => MCPClient::replaying(fixture),
+                None => {
+                    MCPClient::with_server(with_log_level(preset.server(), cli.server_log_level))
+                }
+            };
+            if let Some(recorder) = &recorder {
+                mcp_client = mcp_client.with_recorder(recorder.clone());
+            }
+            mcp_client.init().await?;
+            let mut builder = preset
+                .builder()?
+                .model(model)
+                .mcp_client(mcp_client)
+                .options(preset.options_over(options))
+                .redactor(Redactor::from_env())
+                .observer(Arc::new(ConsoleProgress));
+            if let Some(policy) = cli.injection_guard {
+                builder = builder.injection_guard(InjectionGuard::new(policy));
+            }
+            if let Some(above) = cli.compact_tools {
+                builder = builder.compact_tool_catalog(above);
+            }
+            if let Some(profile) = &profile {
+                builder = builder.permission_profile(profile.clone());
+            }
+            if let Some(path) = cli.audit_log {
+                builder = builder.audit_log(AuditLog::new(path));
+            }
+            if let Some(path) = &cli.history {
+                builder = builder.run_store(RunStore::open(path)?);
+            }
+            if let Some(dir) = cli.artifacts {
+                builder = builder.artifact_store(ArtifactStore::under(dir));
+            }
+            let mut agent = builder.build().await?;
+            let result = agent.run_task(&Task::new(task)).await?;
+            agent.shutdown().await?;
+            if cli.timing {
+                eprint!("{}", timing_table(&result.timings));
+            }
+            for artifact in &result.artifacts {
+                println!("Produced {:?}", artifact);
+            }
+            if let (Some(path), Some(recorder)) = (cli.record, recorder) {
+                recorder.fixture().save(&path)?;
+                println!("Fixture recorded to {}", path.display());
+            }
+            match result.error {
+                None => Ok(()),
+                Some(error) => anyhow::bail!("code change failed: {}", error),
+            }
+        }
+        Some(Command::Research {
+            question,
+            search,
+            json,
+        }) => {
+            let model = build_model(&flags, MODEL).await?;
+            let preset = ResearchPreset::new(search.unwrap_or_else(SearchEngine::from_env));
+            let mut mcp_client = match &fixture {
+                Some(fixture) => MCPClient::replaying(fixture),
+                None => {
+                    MCPClient::with_server(with_log_level(preset.server(), cli.server_log_level))
+                }
+            };
+            if let Some(recorder) = &recorder {
+                mcp_client = mcp_client.with_recorder(recorder.clone());
+            }
+            mcp_client.init().await?;
+            let mut builder = preset
+                .builder()?
+                .model(model)
+                .mcp_client(mcp_client)
+                .options(preset.options_over(options))
+                .redactor(Redactor::from_env())
+                .observer(Arc::new(ConsoleProgress));
+            if let Some(policy) = cli.injection_guard {
+                builder = builder.injection_guard(InjectionGuard::new(policy));
+            }
+            if let Some(above) = cli.compact_tools {
+                builder = builder.compact_tool_catalog(above);
+            }
+            if let Some(profile) = &profile {
+                builder = builder.permission_profile(profile.clone());
+            }
+            if let Some(path) = cli.audit_log {
+                builder = builder.audit_log(AuditLog::new(path));
+            }
+            if let Some(path) = &cli.history {
+                builder = builder.run_store(RunStore::open(path)?);
+            }
+            let mut agent = builder.build().await?;
+            let result = agent.run_task(&preset.task(&question)).await?;
+            agent.shutdown().await?;
+            if cli.timing {
+                eprint!("{}", timing_table(&result.timings));
+            }
+            if let (Some(path), Some(recorder)) = (cli.record, recorder) {
+                recorder.fixture().save(&path)?;
+                println!("Fixture recorded to {}", path.display());
+            }
+            if let Some(error) = &result.error {
+                anyhow::bail!("research failed: {}", error);
+            }
+            let report: Report = result.output_as()?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                println!("{}", report.render());
+            }
+            Ok(())
+        }
+        Some(Command::Batch {
+            file,
+            concurrency,
+            report,
+            workspaces,
+        }) => {
+            let tasks = load_tasks(&file)?;
+            let model: Arc<dyn ModelClient> = build_model(&flags, MODEL).await?.into();
+            let runner = BatchRunner::new(
+                model,
+                filesystem_factory(fixture.clone(), recorder.clone(), cli.server_log_level),
+                Vec::new(),
+            )
+            .with_generated_prompt(match workspaces {
+                // Each task's workspace is added as its root
+                Some(_) => prompt,
+                None => prompt.with_root("."),
+            })
+            .with_concurrency(concurrency)
+            .with_options(options)
+            .with_redactor(Redactor::from_env());
+            let runner = match &profile {
+                Some(profile) => runner.with_permission_profile(profile.clone()),
+                None => runner,
+            };
+            let runner = match workspaces {
+                Some(Some(dir)) => runner.with_workspaces(Workspaces::under(dir)),
+                Some(None) => runner.with_workspaces(Workspaces::temp()),
+                None => runner,
+            };
+            let runner = match &cli.history {
+                Some(path) => runner.with_run_store(RunStore::open(path)?),
+                None => runner,
+            };
+            let runner = match cli.artifacts {
+                Some(dir) => runner.with_artifact_store(ArtifactStore::under(dir)),
+                None => runner,
+            };
+            let outcome = runner.run(&tasks).await;
+            print!("{}", outcome.summary());
+            if let Some(path) = report {
+                std::fs::write(&path, serde_json::to_string_pretty(&outcome)?)?;
+                println!("Report written to {}", path.display());
+            }
+            if let (Some(path), Some(recorder)) = (cli.record, recorder) {
+                recorder.fixture().save(&path)?;
+                println!("Fixture recorded to {}", path.display());
+            }
+            if outcome.succeeded() < outcome.outcomes.len() {
+                anyhow::bail!(
+                    "{} of {} tasks failed",
+                    outcome.outcomes.len() - outcome.succeeded(),
+                    outcome.outcomes.len()
+                );
+            }
+            Ok(())
+        }
+        Some(Command::Schedule {
+            cron,
+            task,
+            file,
+            runs,
+            workspaces,
+        }) => {
+            let schedules = match (file, cron, task) {
+                (Some(file), _, _) => load_schedules(&file)?,
+                (None, Some(cron), Some(description)) => vec![ScheduledTask {
+                    id: Some("task".to_string()),
+                    cron,
+                    task: Task::new(description),
+                }],
+                _ => anyhow::bail!("Give --cron with --task, or --file"),
+            };
+            let model: Arc<dyn ModelClient> = build_model(&flags, MODEL).await?.into();
+            let runner = BatchRunner::new(
+                model,
+                filesystem_factory(fixture.clone(), recorder.clone(), cli.server_log_level),
+                Vec::new(),
+            )
+            .with_generated_prompt(match workspaces {
+                Some(_) => prompt,
+                None => prompt.with_root("."),
+            })
+            .with_options(options)
+            .with_redactor(Redactor::from_env());
+            let runner = match &profile {
+                Some(profile) => runner.with_permission_profile(profile.clone()),
+                None => runner,
+            };
+            let runner = match workspaces {
+                Some(Some(dir)) => runner.with_workspaces(Workspaces::under(dir)),
+                Some(None) => runner.with_workspaces(Workspaces::temp()),
+                None => runner,
+            };
+            let runner = match &cli.history {
+                Some(path) => runner.with_run_store(RunStore::open(path)?),
+                None => runner,
+            };
+            let runner = match cli.artifacts {
+                Some(dir) => runner.with_artifact_store(ArtifactStore::under(dir)),
+                None => runner,
+            };
+            Scheduler::new(runner, schedules)?
+                .run(runs, |outcome| println!("{}", outcome.summary()))
+                .await?;
+            Ok(())
+        }
+        Some(Command::Watch {
+            glob,
+            task,
+            dir,
+            debounce_ms,
+            runs,
+        }) => {
+            let model: Arc<dyn ModelClient> = build_model(&flags, MODEL).await?.into();
+            let runner = BatchRunner::new(
+                model,
+                filesystem_factory(fixture.clone(), recorder.clone(), cli.server_log_level),
+                Vec::new(),
+            )
+            .with_generated_prompt(prompt.with_root("."))
+            .with_options(options)
+            .with_redactor(Redactor::from_env());
+            let runner = match &profile {
+                Some(profile) => runner.with_permission_profile(profile.clone()),
+                None => runner,
+            };
+            let runner = match &cli.history {
+                Some(path) => runner.with_run_store(RunStore::open(path)?),
+                None => runner,
+            };
+            let runner = match cli.artifacts {
+                Some(dir) => runner.with_artifact_store(ArtifactStore::under(dir)),
+                None => runner,
+            };
+            println!("Watching {} for changes to {}", dir.display(), glob);
+            FileWatch::new(runner, dir, &glob, Task::new(task))?
+                .with_debounce(Duration::from_millis(debounce_ms))
+                .watch(runs, |outcome| println!("{}", outcome.summary()))
+                .await?;
+            Ok(())
+        }
+        Some(Command::Eval {
+            file,
+            models,
+            concurrency,
+            report,
+            workspaces,
+        }) => {
+            let mut evals = Evals::new(load_cases(&file)?);
+            if let Some(dir) = workspaces {
+                evals = evals.with_workspaces(Workspaces::under(dir));
+            }
+            let ollama = cli.backend.llama_cpp.is_none()
+                && cli.backend.bedrock.is_none()
+                && cli.backend.azure_deployment.is_none();
+            let configs = if ollama && fixture.is_none() {
+                models
+            } else {
+                vec![MODEL.to_string()]
+            };
+            let mut scoreboard = Scoreboard::default();
+            for config in &configs {
+                let model: Arc<dyn ModelClient> = build_model(&flags, config).await?.into();
+                let runner = BatchRunner::new(
+                    model,
+                    filesystem_factory(fixture.clone(), recorder.clone(), cli.server_log_level),
+                    Vec::new(),
+                )
+                .with_generated_prompt(prompt.clone())
+                .with_concurrency(concurrency)
+                .with_options(options.clone())
+                .with_redactor(Redactor::from_env());
+                let runner = match &profile {
+                    Some(profile) => runner.with_permission_profile(profile.clone()),
+                    None => runner,
+                };
+                scoreboard.add(evals.run(config, runner).await);
+            }
+            print!("{}", scoreboard.summary());
+            if let Some(path) = report {
+                std::fs::write(&path, serde_json::to_string_pretty(&scoreboard)?)?;
+                println!("Scoreboard written to {}", path.display());
+            }
+            if let (Some(path), Some(recorder)) = (cli.record, recorder) {
+                recorder.fixture().save(&path)?;
+                println!("Fixture recorded to {}", path.display());
+            }
+            Ok(())
+        }
+        Some(Command::ServeOpenai { listen, model_name }) => {
+            let model: Arc<dyn ModelClient> = build_model(&flags, MODEL).await?.into();
+            let completions = ChatCompletions::new(
+                model,
+                filesystem_factory(fixture.clone(), recorder.clone(), cli.server_log_level),
+            )
+            .with_generated_prompt(prompt.with_root("."))
+            .with_model_name(model_name)
+            .with_options(options)
+            .with_redactor(Redactor::from_env());
+            let completions = match &profile {
+                Some(profile) => completions.with_permission_profile(profile.clone()),
+                None => completions,
+            };
+            server::openai::serve(&listen, Arc::new(completions)).await?;
+            Ok(())
+        }
+        Some(Command::Supervise {
+            goal,
+            workers,
+            redis,
+            queue_prefix,
+            workspaces,
+        }) => {
+            let queue = task_queue(redis.as_deref(), &queue_prefix).await?;
+            let model: Arc<dyn ModelClient> = build_model(&flags, MODEL).await?.into();
+            let supervisor = Supervisor::new(
+                model,
+                filesystem_factory(fixture.clone(), recorder.clone(), cli.server_log_level),
+                queue,
+                Vec::new(),
+            )
+            .with_generated_prompt(match workspaces {
+                Some(_) => prompt,
+                None => prompt.with_root("."),
+            })
+            .with_workers(workers)
+            .with_options(options)
+            .with_redactor(Redactor::from_env());
+            let supervisor = match &profile {
+                Some(profile) => supervisor.with_permission_profile(profile.clone()),
+                None => supervisor,
+            };
+            let supervisor = match workspaces {
+                Some(Some(dir)) => supervisor.with_workspaces(Workspaces::under(dir)),
+                Some(None) => supervisor.with_workspaces(Workspaces::temp()),
+                None => supervisor,
+            };
+            let result = supervisor.run(&goal).await?;
+            print!("{}", result.summary());
+            if let (Some(path), Some(recorder)) = (cli.record, recorder) {
+                recorder.fixture().save(&path)?;
+                println!("Fixture recorded to {}", path.display());
+            }
+            if !result.success() {
+                anyhow::bail!("Not every task succeeded");
+            }
+            Ok(())
+        }
+        command @ (None | Some(Command::Serve { .. }) | Some(Command::ServeGrpc { .. })) => {
+            let model: Arc<dyn ModelClient> = build_model(&flags, MODEL).await?.into();
+            // Each webhook payload runs on a fresh agent of its own, next to the main loop
+            let hooks = match &command {
+                Some(Command::Serve {
+                    hooks: Some(path), ..
+                }) => {
+                    let runner = BatchRunner::new(
+                        model.clone(),
+                        filesystem_factory(fixture.clone(), recorder.clone(), cli.server_log_level),
+                        Vec::new(),
+                    )
+                    .with_generated_prompt(prompt.clone().with_root("."))
+                    .with_options(options.clone())
+                    .with_redactor(Redactor::from_env());
+                    let runner = match &profile {
+                        Some(profile) => runner.with_permission_profile(profile.clone()),
+                        None => runner,
+                    };
+                    let runner = match &cli.history {
+                        Some(path) => runner.with_run_store(RunStore::open(path)?),
+                        None => runner,
+                    };
+                    let hooks = Arc::new(Webhooks::new(runner, load_hooks(path)?)?);
+                    let mut outcomes = hooks.subscribe();
+                    tokio::spawn(async move {
+                        while let Ok(outcome) = outcomes.recv().await {
+                            eprintln!("{}", outcome.summary());
+                        }
+                    });
+                    Some(hooks)
+                }
+                _ => None,
+            };
+            let sampler = Sampler::new(model.clone(), MODEL, cli.sampling);
+            let mcp_client = connect(
+                fixture.as_ref(),
+                recorder.as_ref(),
+                Some(sampler),
+                cli.server_log_level,
+            )
+            .await?;
+            let memory = cli
+                .memory
+                .map(|path| {
+                    MemoryStore::open(
+                        Box::new(OllamaEmbeddingClient::new(
+                            EMBEDDING_MODEL.to_string(),
+                            &cli.ollama_url,
+                            ollama_token(),
+                        )),
+                        path,
+                    )
+                })
+                .transpose()?;
+            let knowledge = if cli.knowledge.is_empty() {
+                None
+            } else {
+                let mut knowledge = KnowledgeBase::new(Box::new(OllamaEmbeddingClient::new(
+                    EMBEDDING_MODEL.to_string(),
+                    &cli.ollama_url,
+                    ollama_token(),
+                )));
+                for path in &cli.knowledge {
+                    let chunks = knowledge.ingest_path(path).await?;
+                    println!("Ingested {} chunks from {}", chunks, path.display());
+                }
+                Some(knowledge)
+            };
+            let mut native_tools = NativeTools::with_defaults();
+            if cli.run_commands {
+                let mut run_command = RunCommandTool::new(".")?
+                    .with_denied(cli.deny_command)
+                    .with_approval(command_approval);
+                if !cli.allow_command.is_empty() {
+                    run_command = run_command.with_allowed(cli.allow_command.clone());
+                }
+                native_tools.register(Box::new(run_command));
+            }
+            if !cli.allow_domain.is_empty() {
+                native_tools.register(Box::new(FetchUrlTool::new(cli.allow_domain)?));
+            }
+            let mut builder = Agent::builder()
+                .model(Box::new(model))
+                .mcp_client(mcp_client)
+                .generated_system_prompt(prompt.with_root("."))
+                .native_tools(native_tools)
+                .options(RunOptions {
+                    remember_outcomes: cli.remember_outcomes,
+                    ..options
+                })
+                .redactor(Redactor::from_env())
+                .observer(Arc::new(ConsoleProgress))
+                .deny_tools(cli.deny_tool);
+            if let Some(policy) = cli.injection_guard {
+                builder = builder.injection_guard(InjectionGuard::new(policy));
+            }
+            if let Some(above) = cli.compact_tools {
+                builder = builder.compact_tool_catalog(above);
+            }
+            if let Some(profile) = &profile {
+                builder = builder.permission_profile(profile.clone());
+            }
+            if !cli.allow_tool.is_empty() {
+                builder = builder.allow_tools(cli.allow_tool);
+            }
+            if cli.scratchpad {
+                builder = builder.scratchpad(Scratchpad::new());
+            }
+            if cli.ask_user {
+                builder = builder.clarifier(Arc::new(TerminalClarifier));
+            }
+            if cli.edit_files {
+                builder = builder.file_editing();
+            }
+            if let Some(memory) = memory {
+                builder = builder.memory(memory);
+            }
+            if let Some(knowledge) = knowledge {
+                builder = builder.knowledge(knowledge);
+            }
+            if let Some(top_k) = cli.tools_per_turn {
+                builder = builder.tool_selector(ToolSelector::new(
+                    Box::new(OllamaEmbeddingClient::new(
+                        EMBEDDING_MODEL.to_string(),
+                        &cli.ollama_url,
+                        ollama_token(),
+                    )),
+                    top_k,
+                ));
+            }
+            if let Some(path) = cli.audit_log {
+                builder = builder.audit_log(AuditLog::new(path));
+            }
+            if let Some(path) = &cli.history {
+                builder = builder.run_store(RunStore::open(path)?);
+            }
+            if let Some(dir) = cli.artifacts {
+                builder = builder.artifact_store(ArtifactStore::under(dir));
+            }
+            if let Some(path) = &cli.checkpoint {
+                builder = builder.checkpoint_file(path);
+            }
+            for path in cli.stop_when_exists {
+                builder = builder.stop_condition(StopCondition::file_exists(path));
+            }
+            for path in cli.stop_when_json {
+                builder = builder.stop_condition(StopCondition::json_file(path));
+            }
+            if let Some(Command::Serve { listen, .. }) = &command {
+                let listen = listen.clone();
+                let events = Arc::new(EventBroadcaster::new());
+                builder = builder.observer(events.clone());
+                tokio::spawn(async move {
+                    if let Err(err) = server::serve(&listen, events, hooks).await {
+                        eprintln!("event server stopped: {}", err);
+                    }
+                });
+            }
+            let agent = builder.build().await?;
+            if let Some(Command::ServeGrpc { listen }) = command {
+                return serve_grpc(&listen, agent).await;
+            }
+            run_haiku_loop(
+                agent,
+                cli.seed_prompt,
+                cli.checkpoint,
+                cli.transcript,
+                cli.timing,
+            )
+            .await?;
+            if let (Some(path), Some(recorder)) = (cli.record, recorder) {
+                recorder.fixture().save(&path)?;
+                println!("Fixture recorded to {}", path.display());
+            }
+            Ok(())
+        }
+    }
+}
+
+// Shows progress of long-running tool calls instead of a frozen terminal, and what a dry run
+// skipped
+struct ConsoleProgress;
+
+impl AgentObserver for ConsoleProgress {
+    fn on_event(&self, event: &AgentEvent) {
+        if let Some(summary) = event.progress_summary() {
+            eprintln!("{}", summary);
+        }
+        match event {
+            AgentEvent::CostEstimated { estimate } => {
+                eprintln!("Estimated: {}", estimate.summary())
+            }
+            AgentEvent::ToolSkipped { tool, arguments } => {
+                println!("Dry run: not executing {} {}", tool, arguments)
+            }
+            _ => {}
+        }
+    }
+}
+
+async fn connect(
+    fixture: Option<&Fixture>,
+    recorder: Option<&Recorder>,
+    sampler: Option<Sampler>,
+    log_level: Option<LogLevel>,
+) -> anyhow::Result<MCPClient> {
+    let mut mcp_client = match fixture {
+        Some(fixture) => MCPClient::replaying(fixture),
+        None => MCPClient::with_server(with_log_level(ServerConfig::filesystem("."), log_level)),
+    };
+    if let Some(recorder) = recorder {
+        mcp_client = mcp_client.with_recorder(recorder.clone());
+    }
+    if let Some(sampler) = sampler {
+        mcp_client = mcp_client.with_sampler(sampler);
+    }
     mcp_client.init().await?;
+    Ok(mcp_client)
+}
 
-    let file_prefix = "./haiku/haiku-";
-    let initial_prompt = format!(
-            "SYSTEM: You are an agent with MCP capabilities. You have access to the filesystem write_file call, where the method is 'tools/call'. You must ONLY output valid JSON, with NO explanations or thinking process.
-HUMAN: Generate a haiku about nature and return it as the contents of a file prefixed with {}, in the current directory, with a correct unique random uuid after the prefix and ending in a .txt extension, with the write_file command for the filesystem MCP server in the JSON-RPC format. For example:
-{}
-
-Requirements:
-1. The haiku must follow 5-7-5 syllable pattern
-2. Replace <first line>, <second line>, <third line> with your haiku
-3. DO NOT include any text outside the JSON
-4. DO NOT explain your thinking
-5. DO NOT add any formatting or indentation
-ASSISTANT: Output the JSON now:",
-
-            file_prefix,
-        json!({
-            "jsonrpc": "2.0",
-            "method": "tools/call",  // Method name
-            "params": {
-                "name": "write_file",
-                "arguments": {
-                    "path": "my_path",
-                    "content": "my_content"
-                    },
-            },
-            "id": 1
-        }),
+fn with_log_level(server: ServerConfig, log_level: Option<LogLevel>) -> ServerConfig {
+    match log_level {
+        Some(level) => server.with_log_level(level),
+        None => server,
+    }
+}
+
+// A filesystem server per run, rooted at the run's directory
+fn filesystem_factory(
+    fixture: Option<Fixture>,
+    recorder: Option<Recorder>,
+    log_level: Option<LogLevel>,
+) -> McpClientFactory {
+    Box::new(move |root: &Path| {
+        let mcp_client = match &fixture {
+            Some(fixture) => MCPClient::replaying(fixture),
+            None => MCPClient::with_server(with_log_level(
+                ServerConfig::filesystem(&root.to_string_lossy()),
+                log_level,
+            )),
+        };
+        match &recorder {
+            Some(recorder) => mcp_client.with_recorder(recorder.clone()),
+            None => mcp_client,
+        }
+    })
+}
+
+// What every command builds its model client from, taken from the flags once
+struct ModelFlags {
+    ollama_url: String,
+    backend: BackendArgs,
+    limits: RateLimits,
+    cache_dir: Option<PathBuf>,
+    fixture: Option<Fixture>,
+    recorder: Option<Recorder>,
+    scrubber: Option<PiiScrubber>,
+    stream: bool,
+}
+
+fn model_flags(
+    cli: &Cli,
+    fixture: Option<Fixture>,
+    recorder: Option<Recorder>,
+) -> anyhow::Result<ModelFlags> {
+    Ok(ModelFlags {
+        ollama_url: cli.ollama_url.clone(),
+        backend: cli.backend.clone(),
+        limits: RateLimits {
+            requests_per_minute: cli.requests_per_minute,
+            tokens_per_minute: cli.tokens_per_minute,
+        },
+        cache_dir: cli.cache_dir.clone(),
+        fixture,
+        recorder,
+        scrubber: pii_scrubber(cli.scrub_pii, &cli.scrub_patterns)?,
+        stream: cli.stream,
+    })
+}
+
+async fn build_model(
+    flags: &ModelFlags,
+    ollama_model: &str,
+) -> anyhow::Result<Box<dyn ModelClient>> {
+    if let Some(fixture) = &flags.fixture {
+        return Ok(Box::new(ReplayModelClient::new(fixture)));
+    }
+    let mut model: Box<dyn ModelClient> = match &flags.backend {
+        BackendArgs {
+            llama_cpp: Some(url),
+            ..
+        } => Box::new(LlamaCppClient::new(url)),
+        BackendArgs {
+            bedrock: Some(model_id),
+            ..
+        } => Box::new(BedrockClient::new(model_id)?),
+        BackendArgs {
+            azure_deployment: Some(deployment),
+            ..
+        } => Box::new(AzureOpenAiClient::from_env(deployment)?),
+        _ => Box::new(
+            LocalOllamaClient::new(ollama_model.to_string(), &flags.ollama_url, ollama_token())
+                .with_streaming(flags.stream)
+                .with_redactor(Redactor::from_env())
+                .with_detected_capabilities()
+                .await,
+        ),
+    };
+    if let Some(scrubber) = &flags.scrubber {
+        model = Box::new(ScrubbingModelClient::new(model, scrubber.clone()));
+    }
+    // Limit only what reaches the backend; cache hits are free
+    if flags.limits != RateLimits::default() {
+        model = Box::new(RateLimitedModelClient::new(model, flags.limits));
+    }
+    if let Some(dir) = &flags.cache_dir {
+        model = Box::new(
+            CachedModelClient::new(model, ollama_model, MEMORY_CACHE_CAPACITY)
+                .with_disk_cache(dir.clone()),
         );
-    let mut agent = agent::Agent::new(Box::new(model), mcp_client, initial_prompt);
+    }
+    if let Some(recorder) = &flags.recorder {
+        model = Box::new(RecordingModelClient::new(model, recorder.clone()));
+    }
+    Ok(model)
+}
+
+// The profile named, with --command-approval in place of its own approval if given
+fn permission_profile(
+    name: Option<&str>,
+    custom: Option<&Path>,
+    approval: Option<CommandApproval>,
+) -> anyhow::Result<Option<PermissionProfile>> {
+    let Some(name) = name else {
+        return Ok(None);
+    };
+    let custom = match custom {
+        Some(path) => PermissionProfile::load(path)?,
+        None => Vec::new(),
+    };
+    let mut profile = PermissionProfile::named(name, &custom)?;
+    if let Some(approval) = approval {
+        profile.approval = approval;
+    }
+    Ok(Some(profile))
+}
+
+fn pii_scrubber(
+    scrub_pii: bool,
+    patterns: &[(String, String)],
+) -> anyhow::Result<Option<PiiScrubber>> {
+    if !scrub_pii && patterns.is_empty() {
+        return Ok(None);
+    }
+    let mut scrubber = PiiScrubber::new();
+    for (name, pattern) in patterns {
+        scrubber = scrubber.with_pattern(name, pattern)?;
+    }
+    Ok(Some(scrubber))
+}
+
+// The run options every command shares, from the flags
+fn run_options(cli: &Cli) -> RunOptions {
+    RunOptions {
+        dry_run: cli.dry_run,
+        plan_first: cli.plan,
+        pricing: pricing(&cli.backend, cli.prompt_price, cli.completion_price),
+        budget: cli.budget,
+        reflect: cli.reflect,
+        max_repeated_calls: (cli.max_repeated_calls > 0).then_some(cli.max_repeated_calls),
+        max_tool_result_bytes: cli.max_tool_result_bytes,
+        tool_result_policy: cli.tool_results.clone(),
+        samples: cli.samples,
+        quotas: Quotas {
+            max_files_written: cli.max_files_written,
+            max_bytes_written: cli.max_bytes_written,
+            max_commands: cli.max_commands,
+        },
+        quota_approval: cli.quota_approval,
+        stop_sequences: !cli.no_stop_sequences,
+        ..RunOptions::default()
+    }
+}
+
+// Hosted backends bill per token at prices that depend on the model, so they need to be given
+fn pricing(
+    backend: &BackendArgs,
+    prompt_price: Option<f64>,
+    completion_price: Option<f64>,
+) -> Option<Pricing> {
+    if prompt_price.is_some() || completion_price.is_some() {
+        return Some(Pricing {
+            prompt_per_million: prompt_price.unwrap_or_default(),
+            completion_per_million: completion_price.unwrap_or_default(),
+        });
+    }
+    match backend {
+        BackendArgs {
+            bedrock: Some(_), ..
+        }
+        | BackendArgs {
+            azure_deployment: Some(_),
+            ..
+        } => None,
+        _ => Some(Pricing::free()),
+    }
+}
+
+fn agent_prompt(few_shot: usize, few_shot_file: Option<&Path>) -> anyhow::Result<SystemPrompt> {
+    let mut examples = FewShotLibrary::builtin();
+    if let Some(path) = few_shot_file {
+        examples.extend(FewShotLibrary::load(path)?);
+    }
+    Ok(SystemPrompt::new(AGENT_ROLE).with_examples(examples, few_shot))
+}
+
+fn ollama_token() -> Option<String> {
+    std::env::var("OLLAMA_API_KEY").ok()
+}
+
+async fn run_haiku_loop(
+    mut agent: Agent,
+    seed_prompt: Option<String>,
+    checkpoint: Option<PathBuf>,
+    transcript_path: Option<PathBuf>,
+    timing: bool,
+) -> anyhow::Result<()> {
+    let file_prefix = "./haiku/haiku-";
+    let task_prompt = format!(
+        "Generate a haiku about nature and write it with write_file to a new file prefixed with {}, with a random uuid after the prefix and a .txt extension. The haiku must follow the 5-7-5 syllable pattern.",
+        file_prefix
+    );
+    let task = Task::new(task_prompt)
+        .with_success_criterion("A new file containing a 5-7-5 haiku exists under ./haiku");
+    if let Some(name) = seed_prompt {
+        agent.seed_prompt(&name, serde_json::Map::new()).await?;
+    }
+    // Finish the task an earlier run was interrupted in before starting new ones
+    if let Some(path) = checkpoint.filter(|path| path.exists()) {
+        println!("Resuming from {}", path.display());
+        let result = agent.resume(Checkpoint::load(&path)?).await?;
+        if timing {
+            eprint!("{}", timing_table(&result.timings));
+        }
+        for artifact in result.artifacts {
+            println!("Produced {:?}", artifact);
+        }
+        if let Some(error) = result.error {
+            eprintln!(
+                "resumed task failed after {} turns: {}",
+                result.turns, error
+            );
+        }
+    }
 
     loop {
-        match agent.run_once().await {
-            Ok(_) => {}
-            Err(err) => eprintln!("error running agent: {}", err),
+        tokio::select! {
+            result = agent.run_task(&task) => {
+                if let (true, Ok(result)) = (timing, &result) {
+                    eprint!("{}", timing_table(&result.timings));
+                }
+                match result {
+                    Ok(result) if result.success => {
+                        for artifact in result.artifacts {
+                            println!("Produced {:?}", artifact);
+                        }
+                    }
+                    Ok(result) => eprintln!(
+                        "task failed after {} turns: {}",
+                        result.turns,
+                        result.error.unwrap_or_default()
+                    ),
+                    Err(err @ (AgentError::LoopDetected(_) | AgentError::QuotaExceeded(_))) => {
+                        eprintln!("stopping: {}", err);
+                        break;
+                    }
+                    Err(err) => eprintln!("error running agent: {}", err),
+                }
+            }
+            _ = tokio::signal::ctrl_c() => break,
+        }
+    }
+
+    agent.cancel("Interrupted by user").await?;
+    agent.shutdown().await?;
+    if let Some(path) = transcript_path {
+        let format = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("md") => TranscriptFormat::Markdown,
+            _ => TranscriptFormat::Jsonl,
         };
+        std::fs::write(&path, agent.export_transcript(format)?)?;
+        println!("Transcript written to {}", path.display());
     }
+    Ok(())
 }
@@ -0,0 +1,176 @@
+use crate::error::{AgentError, Result};
+use serde_json::Value;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt};
+
+const CONTENT_LENGTH: &str = "content-length:";
+
+// How JSON-RPC messages are delimited on a server's stdio
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Framing {
+    // One message per line, as the MCP stdio transport specifies
+    #[default]
+    Newline,
+    // LSP-style "Content-Length: N" headers and a blank line before each message
+    ContentLength,
+}
+
+impl Framing {
+    pub fn encode(&self, message: &Value) -> Vec<u8> {
+        let body = message.to_string();
+        match self {
+            Framing::Newline => format!("{}\n", body).into_bytes(),
+            Framing::ContentLength => {
+                format!("Content-Length: {}\r\n\r\n{}", body.len(), body).into_bytes()
+            }
+        }
+    }
+}
+
+pub enum Frame {
+    Message { body: String, framing: Framing },
+    // Read to its end but not kept, so the next message is still found
+    TooLarge { bytes: usize },
+}
+
+// Reads one message in either framing, so a server's framing can be learned from what it sends.
+// None at end of stream
+pub async fn read_frame<R>(reader: &mut R, max_bytes: usize) -> Result<Option<Frame>>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let Some((line, bytes)) = read_line(reader, max_bytes).await? else {
+        return Ok(None);
+    };
+    let Some(line) = line else {
+        return Ok(Some(Frame::TooLarge { bytes }));
+    };
+    let Some(length) = content_length(&line)? else {
+        return Ok(Some(Frame::Message {
+            body: line,
+            framing: Framing::Newline,
+        }));
+    };
+    // Other headers, such as Content-Type, run until the blank line
+    loop {
+        match read_line(reader, max_bytes).await? {
+            None => return Ok(None),
+            Some((Some(header), _)) if header.trim().is_empty() => break,
+            Some(_) => {}
+        }
+    }
+    if length > max_bytes {
+        tokio::io::copy(&mut reader.take(length as u64), &mut tokio::io::sink()).await?;
+        return Ok(Some(Frame::TooLarge { bytes: length }));
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).await?;
+    Ok(Some(Frame::Message {
+        body: utf8(body)?,
+        framing: Framing::ContentLength,
+    }))
+}
+
+// A line and its length in bytes, read a buffer at a time. The line is dropped rather than
+// collected once it grows past max_bytes
+async fn read_line<R>(reader: &mut R, max_bytes: usize) -> Result<Option<(Option<String>, usize)>>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let mut line = Vec::new();
+    let mut bytes = 0;
+    loop {
+        let buffer = reader.fill_buf().await?;
+        if buffer.is_empty() {
+            break;
+        }
+        let (chunk, ended) = match buffer.iter().position(|&byte| byte == b'\n') {
+            Some(end) => (&buffer[..=end], true),
+            None => (buffer, false),
+        };
+        let read = chunk.len();
+        bytes += read;
+        if bytes <= max_bytes {
+            line.extend_from_slice(chunk);
+        }
+        reader.consume(read);
+        if ended {
+            break;
+        }
+    }
+    match bytes {
+        0 => Ok(None),
+        bytes if bytes > max_bytes => Ok(Some((None, bytes))),
+        bytes => Ok(Some((Some(utf8(line)?), bytes))),
+    }
+}
+
+fn utf8(bytes: Vec<u8>) -> Result<String> {
+    String::from_utf8(bytes)
+        .map_err(|err| AgentError::ProtocolError(format!("Message is not UTF-8: {}", err)))
+}
+
+fn content_length(line: &str) -> Result<Option<usize>> {
+    let line = line.trim();
+    if !line.to_ascii_lowercase().starts_with(CONTENT_LENGTH) {
+        return Ok(None);
+    }
+    line[CONTENT_LENGTH.len()..]
+        .trim()
+        .parse()
+        .map(Some)
+        .map_err(|_| AgentError::ProtocolError(format!("Invalid header: {}", line)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn message_of(frame: Option<Frame>) -> (Value, Framing) {
+        match frame {
+            Some(Frame::Message { body, framing }) => {
+                (serde_json::from_str(&body).unwrap(), framing)
+            }
+            _ => panic!("expected a message"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_frames_are_read_in_either_framing() -> Result<()> {
+        let message = json!({"jsonrpc": "2.0", "id": 1, "result": {"text": "An old pond\n"}});
+        let mut stream = Framing::ContentLength.encode(&message);
+        stream.extend(Framing::Newline.encode(&message));
+        let mut reader = stream.as_slice();
+
+        let first = message_of(read_frame(&mut reader, 1024).await?);
+        let second = message_of(read_frame(&mut reader, 1024).await?);
+
+        assert_eq!(first, (message.clone(), Framing::ContentLength));
+        assert_eq!(second, (message, Framing::Newline));
+        assert!(read_frame(&mut reader, 1024).await?.is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_oversized_frames_are_skipped_whole() -> Result<()> {
+        let large = json!({"jsonrpc": "2.0", "id": 1, "result": {"text": "a".repeat(100)}});
+        let small = json!({"jsonrpc": "2.0", "id": 2, "result": {}});
+        let mut stream = Framing::Newline.encode(&large);
+        stream.extend(Framing::ContentLength.encode(&large));
+        stream.extend(Framing::Newline.encode(&small));
+        // Smaller than the buffer, so long lines arrive in several reads
+        let mut reader = tokio::io::BufReader::with_capacity(16, stream.as_slice());
+
+        let skipped = [
+            read_frame(&mut reader, 64).await?,
+            read_frame(&mut reader, 64).await?,
+        ];
+        let next = message_of(read_frame(&mut reader, 64).await?);
+
+        let size = large.to_string().len();
+        assert!(matches!(skipped[0], Some(Frame::TooLarge { bytes }) if bytes == size + 1));
+        assert!(matches!(skipped[1], Some(Frame::TooLarge { bytes }) if bytes == size));
+        assert_eq!(next, (small, Framing::Newline));
+        Ok(())
+    }
+}
@@ -0,0 +1,142 @@
+use super::{Framing, MCPClient, ServerConfig, ToolCall};
+use crate::error::{AgentError, Result};
+use crate::task::Task;
+use serde_json::{json, Value};
+
+pub const GIT_STATUS_TOOL: &str = "git_status";
+pub const GIT_DIFF_STAGED_TOOL: &str = "git_diff_staged";
+pub const GIT_DIFF_UNSTAGED_TOOL: &str = "git_diff_unstaged";
+pub const GIT_COMMIT_TOOL: &str = "git_commit";
+
+pub const COMMIT_ASSISTANT_PROMPT: &str = "You are a commit assistant with access to a git MCP server. Read the staged changes you are given and commit them with a message in the imperative mood: a subject line of at most 72 characters, then a blank line and a short body if the change needs explaining. You must ONLY output valid JSON, with NO explanations or thinking process.";
+
+impl ServerConfig {
+    // The reference git server, run through uv
+    pub fn git(repository: &str) -> Self {
+        Self {
+            name: "git".to_string(),
+            command: "uvx".to_string(),
+            args: vec![
+                "mcp-server-git".to_string(),
+                "--repository".to_string(),
+                repository.to_string(),
+            ],
+            framing: Framing::Newline,
+            request_limit: None,
+            log_level: None,
+        }
+    }
+}
+
+// Typed wrappers around the git server's tools for one repository
+pub struct GitTools {
+    repo_path: String,
+}
+
+impl GitTools {
+    pub fn new(repo_path: &str) -> Self {
+        Self {
+            repo_path: repo_path.to_string(),
+        }
+    }
+
+    pub async fn status(&self, client: &mut MCPClient) -> Result<String> {
+        self.call(client, GIT_STATUS_TOOL, json!({})).await
+    }
+
+    pub async fn diff_staged(&self, client: &mut MCPClient) -> Result<String> {
+        self.call(client, GIT_DIFF_STAGED_TOOL, json!({})).await
+    }
+
+    pub async fn diff_unstaged(&self, client: &mut MCPClient) -> Result<String> {
+        self.call(client, GIT_DIFF_UNSTAGED_TOOL, json!({})).await
+    }
+
+    pub async fn commit(&self, client: &mut MCPClient, message: &str) -> Result<String> {
+        self.call(client, GIT_COMMIT_TOOL, json!({"message": message}))
+            .await
+    }
+
+    async fn call(
+        &self,
+        client: &mut MCPClient,
+        tool: &str,
+        mut arguments: Value,
+    ) -> Result<String> {
+        arguments["repo_path"] = json!(self.repo_path);
+        let result = client.call_tool(&ToolCall::new(tool, arguments)).await?;
+        let text = result.text();
+        if result.is_error {
+            return Err(AgentError::ToolError(format!("{} failed: {}", tool, text)));
+        }
+        Ok(text)
+    }
+
+    // A task that has the model write the message and make the commit through git_commit
+    pub fn commit_task(&self, status: &str, staged_diff: &str) -> Task {
+        let example = json!({
+            "jsonrpc": "2.0",
+            "method": "tools/call",
+            "params": {
+                "name": GIT_COMMIT_TOOL,
+                "arguments": {"repo_path": self.repo_path, "message": "<commit message>"}
+            },
+            "id": 1
+        });
+        Task::new(format!(
+            "Commit the staged changes in {} with the {} tool in the JSON-RPC format. For example:\n{}\n\nStatus:\n{}\n\nStaged diff:\n{}",
+            self.repo_path, GIT_COMMIT_TOOL, example, status, staged_diff
+        ))
+        .with_success_criterion("The staged changes are committed")
+        .with_success_criterion("The message describes what the diff changes, not how it was made")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replay::{Fixture, Interaction, McpExchange};
+
+    fn server_replies(results: Vec<Value>) -> MCPClient {
+        let mut interactions = vec![Interaction::Mcp(McpExchange::new(
+            &json!({}),
+            &Ok(json!({})),
+        ))];
+        interactions.extend(
+            results
+                .into_iter()
+                .map(|result| Interaction::Mcp(McpExchange::new(&json!({}), &Ok(result)))),
+        );
+        MCPClient::replaying(&Fixture { interactions })
+    }
+
+    #[tokio::test]
+    async fn test_git_tools_return_text_and_surface_tool_errors() -> Result<()> {
+        let mut client = server_replies(vec![
+            json!({"content": [{"type": "text", "text": "On branch main"}], "isError": false}),
+            json!({"content": [{"type": "text", "text": "nothing to commit"}], "isError": true}),
+        ]);
+        client.init().await?;
+        let git = GitTools::new("/work/repo");
+
+        let status = git.status(&mut client).await?;
+        let commit = git.commit(&mut client, "Add haiku").await;
+
+        assert_eq!(status, "On branch main");
+        assert!(
+            matches!(commit, Err(AgentError::ToolError(message)) if message.contains("nothing to commit"))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_commit_task_includes_the_diff_and_the_call_format() {
+        let task = GitTools::new("/work/repo").commit_task("M haiku.txt", "+An old silent pond");
+
+        let message = task.to_message().content;
+
+        assert!(message.contains("+An old silent pond"));
+        assert!(message.contains("\"name\":\"git_commit\""));
+        assert!(message.contains("\"repo_path\":\"/work/repo\""));
+    }
+}
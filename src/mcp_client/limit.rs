@@ -0,0 +1,98 @@
+use crate::error::{AgentError, Result};
+use std::sync::Arc;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+// Caps the requests in flight across every client started from one server definition. Clones
+// share the permits, which are handed out first come, first served
+#[derive(Clone)]
+pub struct RequestLimit {
+    max: usize,
+    permits: Arc<Semaphore>,
+}
+
+impl RequestLimit {
+    pub fn new(max: usize) -> Self {
+        // With no permits nothing could ever be sent
+        let max = max.max(1);
+        Self {
+            max,
+            permits: Arc::new(Semaphore::new(max)),
+        }
+    }
+
+    pub fn max(&self) -> usize {
+        self.max
+    }
+
+    pub(super) async fn acquire(&self) -> Result<OwnedSemaphorePermit> {
+        self.permits
+            .clone()
+            .acquire_owned()
+            .await
+            .map_err(|err| AgentError::ProtocolError(err.to_string()))
+    }
+}
+
+impl std::fmt::Debug for RequestLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RequestLimit")
+            .field("max", &self.max)
+            .finish()
+    }
+}
+
+impl PartialEq for RequestLimit {
+    fn eq(&self, other: &Self) -> bool {
+        self.max == other.max
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Framing, MCPClient, ServerConfig};
+    use crate::error::Result;
+    use serde_json::json;
+    use std::time::{Duration, Instant};
+    use tokio::process::Command;
+
+    #[tokio::test]
+    async fn test_clients_of_one_definition_share_the_limit() -> Result<()> {
+        let slow = r#"read request
+sleep 0.3
+echo '{"jsonrpc":"2.0","id":1,"result":{}}'"#;
+        let server = ServerConfig {
+            name: "slow".to_string(),
+            command: "sh".to_string(),
+            args: vec!["-c".to_string(), slow.to_string()],
+            framing: Framing::Newline,
+            request_limit: None,
+            log_level: None,
+        }
+        .with_max_concurrent_requests(1);
+        let client = || -> Result<MCPClient> {
+            let mut client = MCPClient::with_server(server.clone());
+            client.attach(
+                Command::new(&server.command)
+                    .args(&server.args)
+                    .stdin(std::process::Stdio::piped())
+                    .stdout(std::process::Stdio::piped())
+                    .spawn()?,
+            );
+            Ok(client)
+        };
+        let (mut first, mut second) = (client()?, client()?);
+
+        let started = Instant::now();
+        let (a, b) = tokio::join!(
+            first.request("ping", json!({})),
+            second.request("ping", json!({}))
+        );
+        let elapsed = started.elapsed();
+
+        first.shutdown().await?;
+        second.shutdown().await?;
+        assert!(a.is_ok() && b.is_ok());
+        assert!(elapsed >= Duration::from_millis(600));
+        Ok(())
+    }
+}
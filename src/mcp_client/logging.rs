@@ -0,0 +1,116 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+// Where server log messages go, so they can be filtered with e.g. RUST_LOG=mcp=warn
+pub const LOG_TARGET: &str = "mcp";
+
+// The syslog severities MCP servers log at, least severe first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Debug,
+    Info,
+    Notice,
+    Warning,
+    Error,
+    Critical,
+    Alert,
+    Emergency,
+}
+
+impl LogLevel {
+    // tracing has fewer levels; notice folds into info and everything past error into error
+    pub fn tracing_level(self) -> tracing::Level {
+        match self {
+            LogLevel::Debug => tracing::Level::DEBUG,
+            LogLevel::Info | LogLevel::Notice => tracing::Level::INFO,
+            LogLevel::Warning => tracing::Level::WARN,
+            _ => tracing::Level::ERROR,
+        }
+    }
+}
+
+// Emits the params of a notifications/message as a tracing event. Levels we don't know are
+// logged as info rather than dropped
+pub(super) fn log_message(server: &str, params: &Value) {
+    let level = serde_json::from_value(params["level"].clone()).unwrap_or(LogLevel::Info);
+    let logger = params["logger"].as_str().unwrap_or_default();
+    let data = match &params["data"] {
+        Value::String(text) => text.clone(),
+        data => data.to_string(),
+    };
+    // The level has to be a constant in each tracing macro
+    match level.tracing_level() {
+        tracing::Level::DEBUG => {
+            tracing::debug!(target: LOG_TARGET, server, logger, "{}", data)
+        }
+        tracing::Level::INFO => tracing::info!(target: LOG_TARGET, server, logger, "{}", data),
+        tracing::Level::WARN => tracing::warn!(target: LOG_TARGET, server, logger, "{}", data),
+        _ => tracing::error!(target: LOG_TARGET, server, logger, "{}", data),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Framing, MCPClient, ServerConfig};
+    use super::*;
+    use crate::error::Result;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_log_level_is_set_on_start_and_messages_are_traced() -> Result<()> {
+        // Logs the level it was given at two severities, then acknowledges it
+        let script = r#"read request
+echo '{"jsonrpc":"2.0","id":1,"result":{"capabilities":{"logging":{}}}}'
+read initialized
+read request
+level=$(echo "$request" | sed 's/.*"level":"\([a-z]*\)".*/\1/')
+echo "{\"jsonrpc\":\"2.0\",\"method\":\"notifications/message\",\"params\":{\"level\":\"notice\",\"logger\":\"pond\",\"data\":\"level set to $level\"}}"
+echo '{"jsonrpc":"2.0","method":"notifications/message","params":{"level":"critical","data":{"frog":"jumped"}}}'
+echo '{"jsonrpc":"2.0","id":2,"result":{}}'"#;
+        let mut client = MCPClient::with_server(
+            ServerConfig {
+                name: "haiku".to_string(),
+                command: "sh".to_string(),
+                args: vec!["-c".to_string(), script.to_string()],
+                framing: Framing::Newline,
+                request_limit: None,
+                log_level: None,
+            }
+            .with_log_level(LogLevel::Warning),
+        );
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .without_time()
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        client.init().await?;
+
+        client.shutdown().await?;
+        let logs = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = logs.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with(" INFO mcp: level set to warning"));
+        assert!(lines[0].contains(r#"server="haiku""#) && lines[0].contains(r#"logger="pond""#));
+        assert!(lines[1].starts_with(r#"ERROR mcp: {"frog":"jumped"}"#));
+        Ok(())
+    }
+}
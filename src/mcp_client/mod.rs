@@ -1,96 +1,864 @@
-use anyhow::Result;
+mod framing;
+pub mod git;
+mod limit;
+mod logging;
+mod sampling;
+pub mod search;
+mod tool_call;
+mod tool_result;
+
+pub use framing::Framing;
+pub use limit::RequestLimit;
+pub use logging::{LogLevel, LOG_TARGET};
+pub use sampling::{Sampler, SamplingApproval};
+pub use tool_call::ToolCall;
+pub use tool_result::{ToolResult, ToolResultContent};
+
+use crate::error::{AgentError, Result};
+use crate::events::{AgentEvent, AgentObserver};
+use crate::process;
+use crate::redaction::Redactor;
+use crate::replay::{Fixture, Interaction, McpExchange, Recorder};
+use framing::Frame;
 use rmcp::model;
-use serde_json::json;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::process::Command;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+use tokio::io::{AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdout};
+use tokio::time::Duration;
+
+const SHUTDOWN_GRACE_PERIOD: Duration = Duration::from_secs(2);
+const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+// npx may have to download a server before it can answer
+pub const DEFAULT_READY_TIMEOUT: Duration = Duration::from_secs(30);
+pub const DEFAULT_KEEPALIVE_INTERVAL: Duration = Duration::from_secs(30);
+// How long each initialize attempt waits; doubled after every attempt the server misses
+const FIRST_READY_ATTEMPT: Duration = Duration::from_millis(250);
+const LONGEST_READY_ATTEMPT: Duration = Duration::from_secs(4);
+const PING_TIMEOUT: Duration = Duration::from_secs(2);
+const TOOLS_LIST_CHANGED: &str = "notifications/tools/list_changed";
+const PROTOCOL_VERSION: &str = "2024-11-05";
+const METHOD_NOT_FOUND: i64 = -32601;
+const REQUEST_FAILED: i64 = -1;
+pub const DEFAULT_MAX_MESSAGE_BYTES: usize = 16 * 1024 * 1024;
+// Large results, e.g. read_file on a big file, arrive over many reads of this size
+const READ_CHUNK_BYTES: usize = 64 * 1024;
+
+// Called with the params of a server-initiated notification
+pub type NotificationHandler = Box<dyn Fn(&Value) + Send + Sync>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerConfig {
+    pub name: String,
+    pub command: String,
+    pub args: Vec<String>,
+    pub framing: Framing,
+    pub request_limit: Option<RequestLimit>,
+    pub log_level: Option<LogLevel>,
+}
+
+impl ServerConfig {
+    pub fn filesystem(root: &str) -> Self {
+        Self {
+            name: "filesystem".to_string(),
+            command: "npx".to_string(),
+            args: vec![
+                "-y".to_string(),
+                "@modelcontextprotocol/server-filesystem".to_string(),
+                root.to_string(),
+            ],
+            framing: Framing::Newline,
+            request_limit: None,
+            log_level: None,
+        }
+    }
+
+    // For servers that only speak Content-Length framing; servers that use it unasked are
+    // detected from their first message
+    pub fn with_framing(mut self, framing: Framing) -> Self {
+        self.framing = framing;
+        self
+    }
+
+    // Each client sends one request at a time already; this bounds the requests in flight
+    // across every client made from clones of this definition, e.g. one per batch worker, for
+    // servers that share state and misbehave when written to concurrently
+    pub fn with_max_concurrent_requests(mut self, max: usize) -> Self {
+        self.request_limit = Some(RequestLimit::new(max));
+        self
+    }
+
+    // The least severe messages the server should send us, set right after it starts. Without
+    // one the server picks
+    pub fn with_log_level(mut self, level: LogLevel) -> Self {
+        self.log_level = Some(level);
+        self
+    }
+
+    // A server named on the command line: filesystem or git for those servers on the current
+    // directory, brave-search or duckduckgo, or else the command line that starts it, e.g.
+    // "uvx mcp-server-fetch"
+    pub fn from_spec(spec: &str) -> Result<Self> {
+        match spec {
+            "filesystem" => return Ok(Self::filesystem(".")),
+            "git" => return Ok(Self::git(".")),
+            "brave-search" => return Ok(Self::brave_search()),
+            "duckduckgo" => return Ok(Self::duckduckgo_search()),
+            _ => {}
+        }
+        let mut words = spec.split_whitespace().map(str::to_string);
+        let command = words
+            .next()
+            .ok_or_else(|| AgentError::ConfigError("MCP server command is empty".to_string()))?;
+        let name = std::path::Path::new(&command)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| command.clone());
+        Ok(Self {
+            name,
+            command,
+            args: words.collect(),
+            framing: Framing::Newline,
+            request_limit: None,
+            log_level: None,
+        })
+    }
+}
 
 pub struct MCPClient {
-    server_process: Option<tokio::process::Child>,
+    server: ServerConfig,
+    server_process: Option<Child>,
+    stdout: Option<BufReader<ChildStdout>>,
+    // What we write in: configured per server, switched when the server turns out to use
+    // Content-Length
+    framing: Framing,
+    max_message_bytes: usize,
+    ready_timeout: Duration,
+    keepalive_interval: Option<Duration>,
+    // What the server declared in its initialize result; None if it declared nothing, in which
+    // case nothing is gated
+    server_capabilities: Option<Value>,
+    // Set by notifications/tools/list_changed until someone re-lists the tools
+    tools_changed: bool,
+    // When the server last answered, for keepalive pings
+    last_response: Option<tokio::time::Instant>,
+    next_id: u32,
+    observers: Vec<Arc<dyn AgentObserver>>,
+    recorder: Option<Recorder>,
+    replay: Option<VecDeque<McpExchange>>,
+    notification_handlers: HashMap<String, Vec<NotificationHandler>>,
+    // Id of a request whose response we stopped waiting for, e.g. when a turn timed out
+    in_flight: Option<Value>,
+    sampler: Option<Sampler>,
+    // Masks secrets in the messages it logs
+    redactor: Redactor,
 }
 
 impl Drop for MCPClient {
     fn drop(&mut self) {
         if let Some(mut child) = self.server_process.take() {
-            // Try to kill the process on drop
-            let _ = child.kill();
+            // Last resort when shutdown() was not awaited
+            process::kill_tree(&mut child);
         }
     }
 }
 
+impl Default for MCPClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl MCPClient {
     pub fn new() -> Self {
+        Self::with_server(ServerConfig::filesystem("."))
+    }
+
+    pub fn with_server(server: ServerConfig) -> Self {
         Self {
+            framing: server.framing,
+            server,
             server_process: None,
+            stdout: None,
+            max_message_bytes: DEFAULT_MAX_MESSAGE_BYTES,
+            ready_timeout: DEFAULT_READY_TIMEOUT,
+            keepalive_interval: Some(DEFAULT_KEEPALIVE_INTERVAL),
+            last_response: None,
+            server_capabilities: None,
+            tools_changed: false,
+            next_id: 1,
+            observers: Vec::new(),
+            recorder: None,
+            replay: None,
+            notification_handlers: HashMap::new(),
+            in_flight: None,
+            sampler: None,
+            redactor: Redactor::new(),
         }
     }
 
+    // Answer requests from a recorded fixture instead of a live server
+    pub fn replaying(fixture: &Fixture) -> Self {
+        let mut client = Self::new();
+        client.replay = Some(fixture.mcp_exchanges());
+        client
+    }
+
+    pub fn with_recorder(mut self, recorder: Recorder) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    // Let the server request completions from our model (sampling/createMessage)
+    pub fn with_sampler(mut self, sampler: Sampler) -> Self {
+        self.sampler = Some(sampler);
+        self
+    }
+
+    // Messages over this size are skipped and the request they answer fails
+    pub fn with_max_message_bytes(mut self, max_message_bytes: usize) -> Self {
+        self.max_message_bytes = max_message_bytes;
+        self
+    }
+
+    // How long a starting server gets to answer initialize
+    pub fn with_ready_timeout(mut self, ready_timeout: Duration) -> Self {
+        self.ready_timeout = ready_timeout;
+        self
+    }
+
+    // None turns keepalive pings off
+    pub fn with_keepalive_interval(mut self, interval: Option<Duration>) -> Self {
+        self.keepalive_interval = interval;
+        self
+    }
+
+    // Whether the server declared a capability such as "tools", "resources", "prompts" or
+    // "logging" when it was initialized
+    pub fn supports(&self, capability: &str) -> bool {
+        self.server_capabilities
+            .as_ref()
+            .is_none_or(|capabilities| capabilities.get(capability).is_some())
+    }
+
+    // Whether the server said its tools changed since this was last asked
+    pub fn take_tools_changed(&mut self) -> bool {
+        std::mem::take(&mut self.tools_changed)
+    }
+
+    pub fn server_name(&self) -> &str {
+        &self.server.name
+    }
+
+    pub fn set_redactor(&mut self, redactor: Redactor) {
+        self.redactor = redactor;
+    }
+
+    pub fn add_observer(&mut self, observer: Arc<dyn AgentObserver>) {
+        self.observers.push(observer);
+    }
+
+    // e.g. "notifications/message", "notifications/progress" or
+    // "notifications/resources/list_changed"
+    pub fn on_notification<F>(&mut self, method: &str, handler: F)
+    where
+        F: Fn(&Value) + Send + Sync + 'static,
+    {
+        self.notification_handlers
+            .entry(method.to_string())
+            .or_default()
+            .push(Box::new(handler));
+    }
+
     pub async fn init(&mut self) -> Result<()> {
-        let child = Command::new("npx")
-            .arg("-y")
-            .arg("@modelcontextprotocol/server-filesystem")
-            .arg(".") // Or /tmp
+        if self.replay.is_some() {
+            self.initialize(DEFAULT_REQUEST_TIMEOUT).await?;
+            return Ok(());
+        }
+        self.start().await
+    }
+
+    // Whether the server is running and answers a ping
+    pub async fn is_healthy(&mut self) -> bool {
+        if self.replay.is_some() {
+            return true;
+        }
+        if self.server_process.is_none() || self.exit_status().is_some() {
+            return false;
+        }
+        let request = self.next_request("ping", json!({}));
+        self.exchange(&request, PING_TIMEOUT).await.is_ok()
+    }
+
+    // Pings a server that has been quiet for longer than the keepalive interval, restarting it
+    // if it no longer answers, so a server that hung while the model was thinking doesn't cost
+    // the next tool call
+    pub async fn keepalive(&mut self) -> Result<()> {
+        let (Some(interval), Some(last_response)) = (self.keepalive_interval, self.last_response)
+        else {
+            return Ok(());
+        };
+        if self.replay.is_some() || last_response.elapsed() < interval {
+            return Ok(());
+        }
+        if !self.is_healthy().await {
+            self.restart("MCP server did not answer a keepalive ping".to_string())
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn start(&mut self) -> Result<()> {
+        let child = process::command(&self.server.command)
+            .args(&self.server.args)
             .stdin(std::process::Stdio::piped())
             .stdout(std::process::Stdio::piped())
             .stderr(std::process::Stdio::piped()) // Capture stderr as well
             .spawn()?;
+        self.framing = self.server.framing;
+        self.attach(child);
+        self.await_ready().await?;
+        if let Some(level) = self.server.log_level.filter(|_| self.supports("logging")) {
+            // Noisier logs than asked for are no reason to give up on the server
+            let request = self.next_request("logging/setLevel", json!({"level": level}));
+            if let Err(err) = self.exchange(&request, DEFAULT_REQUEST_TIMEOUT).await {
+                tracing::warn!(
+                    server = self.server.name,
+                    "Could not set log level: {}",
+                    err
+                );
+            }
+        }
+        tracing::debug!("MCP server initialized");
+        Ok(())
+    }
 
-        // Store the child process
+    // Also kept for the server's restarts
+    pub async fn set_log_level(&mut self, level: LogLevel) -> Result<()> {
+        self.request("logging/setLevel", json!({"level": level}))
+            .await?;
+        self.server.log_level = Some(level);
+        Ok(())
+    }
+
+    // Retries initialize, waiting longer each time, until the server answers, exits or runs out
+    // of time. Answers to attempts that timed out are discarded as stale
+    async fn await_ready(&mut self) -> Result<()> {
+        let deadline = tokio::time::Instant::now() + self.ready_timeout;
+        let mut attempt = FIRST_READY_ATTEMPT;
+        loop {
+            if let Some(reason) = self.exit_status() {
+                return Err(AgentError::ProtocolError(format!(
+                    "MCP server did not start: {}",
+                    reason
+                )));
+            }
+            let remaining = deadline.saturating_duration_since(tokio::time::Instant::now());
+            if remaining.is_zero() {
+                return Err(AgentError::Timeout(format!(
+                    "MCP server not ready after {:?}",
+                    self.ready_timeout
+                )));
+            }
+            match self.initialize(attempt.min(remaining)).await {
+                Err(AgentError::Timeout(_)) => attempt = (attempt * 2).min(LONGEST_READY_ATTEMPT),
+                result => return result.map(|_| ()),
+            }
+        }
+    }
+
+    fn attach(&mut self, mut child: Child) {
+        self.stdout = child
+            .stdout
+            .take()
+            .map(|stdout| BufReader::with_capacity(READ_CHUNK_BYTES, stdout));
         self.server_process = Some(child);
+    }
 
-        // Wait a moment for the server to initialize
-        tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+    async fn initialize(&mut self, timeout: Duration) -> Result<Value> {
+        let request = self.next_request(
+            "initialize",
+            json!({
+                "protocolVersion": PROTOCOL_VERSION,
+                "capabilities": self.capabilities(),
+                "clientInfo": {
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION")
+                }
+            }),
+        );
+        let result = self.exchange(&request, timeout).await?;
+        self.server_capabilities = result.get("capabilities").cloned();
+        self.write_message(&json!({
+            "jsonrpc": "2.0",
+            "method": "notifications/initialized"
+        }))
+        .await?;
+        Ok(result)
+    }
+
+    fn capabilities(&self) -> Value {
+        match self.sampler {
+            Some(_) => json!({"sampling": {}}),
+            None => json!({}),
+        }
+    }
+
+    async fn restart(&mut self, reason: String) -> Result<()> {
+        tracing::warn!("Restarting MCP server: {}", reason);
+        self.shutdown().await?;
+        self.start().await?;
+        self.emit(AgentEvent::ServerRestarted { reason });
+        Ok(())
+    }
+
+    fn exit_status(&mut self) -> Option<String> {
+        let child = self.server_process.as_mut()?;
+        match child.try_wait() {
+            Ok(Some(status)) => Some(format!("MCP server exited with {}", status)),
+            Ok(None) => None,
+            Err(err) => Some(format!("MCP server state unknown: {}", err)),
+        }
+    }
+
+    fn emit(&self, event: AgentEvent) {
+        for observer in &self.observers {
+            observer.on_event(&event);
+        }
+    }
+
+    // Tell the server to stop work on a request we abandoned mid-flight
+    pub async fn cancel_in_flight(&mut self, reason: &str) -> Result<()> {
+        let Some(id) = self.in_flight.take() else {
+            return Ok(());
+        };
+        if self.server_process.is_none() {
+            return Ok(());
+        }
+        self.write_message(&json!({
+            "jsonrpc": "2.0",
+            "method": "notifications/cancelled",
+            "params": {"requestId": id, "reason": reason}
+        }))
+        .await
+    }
+
+    // Close stdin so the server can flush and exit, escalating to SIGTERM and kill
+    pub async fn shutdown(&mut self) -> Result<()> {
+        let Some(mut child) = self.server_process.take() else {
+            return Ok(());
+        };
+        drop(child.stdin.take());
+        self.stdout = None;
+
+        if tokio::time::timeout(SHUTDOWN_GRACE_PERIOD, child.wait())
+            .await
+            .is_ok()
+        {
+            tracing::debug!("MCP server exited");
+            return Ok(());
+        }
+
+        #[cfg(unix)]
+        if let Some(pid) = child.id() {
+            // SAFETY: pid belongs to a child we spawned and have not reaped yet
+            unsafe {
+                libc::kill(pid as libc::pid_t, libc::SIGTERM);
+            }
+            if tokio::time::timeout(SHUTDOWN_GRACE_PERIOD, child.wait())
+                .await
+                .is_ok()
+            {
+                tracing::debug!("MCP server terminated");
+                return Ok(());
+            }
+        }
 
-        println!("MCP server initialized");
+        process::kill_tree(&mut child);
+        child.wait().await?;
+        tracing::debug!("MCP server killed");
         Ok(())
     }
 
-    pub async fn do_request(&mut self, request: model::JsonRpcRequest) -> Result<()> {
+    pub async fn do_request(&mut self, request: model::JsonRpcRequest) -> Result<Value> {
+        self.do_request_with_timeout(request, DEFAULT_REQUEST_TIMEOUT)
+            .await
+    }
+
+    pub async fn do_request_with_timeout(
+        &mut self,
+        request: model::JsonRpcRequest,
+        timeout: Duration,
+    ) -> Result<Value> {
+        self.send(json!(request), timeout).await
+    }
+
+    pub async fn list_tools(&mut self) -> Result<Vec<model::Tool>> {
+        self.list_all("tools/list", "tools").await
+    }
+
+    pub async fn call_tool(&mut self, call: &ToolCall) -> Result<ToolResult> {
+        let result = self.request("tools/call", call.params()).await?;
+        Ok(ToolResult::from_value(&result))
+    }
+
+    pub async fn list_resources(&mut self) -> Result<Vec<model::Resource>> {
+        self.list_all("resources/list", "resources").await
+    }
+
+    // Follows nextCursor until the server has sent every page. A cursor seen before ends the
+    // listing, so a server that hands out the same page forever can't hang us
+    async fn list_all<T: DeserializeOwned>(&mut self, method: &str, key: &str) -> Result<Vec<T>> {
+        let mut items = Vec::new();
+        let mut seen = HashSet::new();
+        let mut params = json!({});
+        loop {
+            let mut page = self.request(method, params).await?;
+            let page_items = page.get_mut(key).map(Value::take).unwrap_or(json!([]));
+            items.extend(serde_json::from_value::<Vec<T>>(page_items)?);
+            match page["nextCursor"].as_str() {
+                Some(cursor) if seen.insert(cursor.to_string()) => {
+                    params = json!({"cursor": cursor});
+                }
+                _ => return Ok(items),
+            }
+        }
+    }
+
+    pub async fn read_resource(
+        &mut self,
+        uri: &str,
+        timeout: Duration,
+    ) -> Result<Vec<model::ResourceContents>> {
+        let result = self
+            .request_with_timeout(
+                "resources/read",
+                json!(model::ReadResourceRequestParam {
+                    uri: uri.to_string()
+                }),
+                timeout,
+            )
+            .await?;
+        let read: model::ReadResourceResult = serde_json::from_value(result)?;
+        Ok(read.contents)
+    }
+
+    pub async fn list_prompts(&mut self) -> Result<Vec<model::Prompt>> {
+        self.list_all("prompts/list", "prompts").await
+    }
+
+    pub async fn get_prompt(
+        &mut self,
+        name: &str,
+        arguments: serde_json::Map<String, Value>,
+    ) -> Result<model::GetPromptResult> {
+        let params = model::GetPromptRequestParam {
+            name: name.to_string(),
+            arguments: Some(arguments),
+        };
+        let result = self.request("prompts/get", json!(params)).await?;
+        Ok(serde_json::from_value(result)?)
+    }
+
+    async fn request(&mut self, method: &str, params: Value) -> Result<Value> {
+        self.request_with_timeout(method, params, DEFAULT_REQUEST_TIMEOUT)
+            .await
+    }
+
+    async fn request_with_timeout(
+        &mut self,
+        method: &str,
+        params: Value,
+        timeout: Duration,
+    ) -> Result<Value> {
+        let request = json!({"jsonrpc": "2.0", "method": method, "params": params});
+        self.send(request, timeout).await
+    }
+
+    fn next_request(&mut self, method: &str, params: Value) -> Value {
+        let id = self.next_id;
+        self.next_id += 1;
+        json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": params,
+            "id": id
+        })
+    }
+
+    async fn send(&mut self, mut request_json: Value, timeout: Duration) -> Result<Value> {
+        if let Some(capability) = request_json["method"]
+            .as_str()
+            .and_then(required_capability)
+        {
+            if !self.supports(capability) {
+                return Err(AgentError::UnsupportedCapability(format!(
+                    "MCP server '{}' does not support {}, which {} needs",
+                    self.server.name, capability, request_json["method"]
+                )));
+            }
+        }
+        // Every request goes out under an id of ours, whatever id the model gave it, so the late
+        // answer to an abandoned request can't pass for the answer to the next one
+        request_json["id"] = json!(self.next_id);
+        self.next_id += 1;
+        attach_progress_token(&mut request_json);
+        if let Some(reason) = self.exit_status() {
+            self.restart(reason).await?;
+        }
+
+        match self.exchange(&request_json, timeout).await {
+            // A dead pipe or a hung server won't recover on its own
+            Err(err @ (AgentError::ProtocolError(_) | AgentError::Timeout(_)))
+                if self.server_process.is_some() =>
+            {
+                self.restart(err.to_string()).await?;
+                Err(err)
+            }
+            result => result,
+        }
+    }
+
+    async fn exchange(&mut self, request_json: &Value, timeout: Duration) -> Result<Value> {
+        if let Some(replay) = self.replay.as_mut() {
+            return replay
+                .pop_front()
+                .ok_or_else(|| {
+                    AgentError::ProtocolError(
+                        "Replay fixture has no more MCP responses".to_string(),
+                    )
+                })?
+                .into_result();
+        }
+
+        let _permit = match &self.server.request_limit {
+            Some(limit) => Some(limit.acquire().await?),
+            None => None,
+        };
+        self.write_message(request_json).await?;
+        self.in_flight = request_json.get("id").cloned();
+        let outcome = self.read_response(&request_json["id"], timeout).await;
+        self.in_flight = None;
+        if outcome.is_ok() {
+            self.last_response = Some(tokio::time::Instant::now());
+        }
+        if let Some(recorder) = &self.recorder {
+            recorder.record(Interaction::Mcp(McpExchange::new(request_json, &outcome)));
+        }
+        outcome
+    }
+
+    async fn write_message(&mut self, message: &Value) -> Result<()> {
+        if self.replay.is_some() {
+            return Ok(());
+        }
         let child = self
             .server_process
             .as_mut()
-            .ok_or_else(|| anyhow::anyhow!("MCP server not initialized"))?;
+            .ok_or_else(|| AgentError::ProtocolError("MCP server not initialized".to_string()))?;
         let stdin = child
             .stdin
             .as_mut()
-            .ok_or_else(|| anyhow::anyhow!("Failed to get stdin"))?;
+            .ok_or_else(|| AgentError::ProtocolError("Failed to get stdin".to_string()))?;
 
         // Debug output to see what we're sending
-        let request_json = json!(request);
-        println!("Sending request: {}", request_json);
+        tracing::debug!(
+            "Sending request: {}",
+            self.redactor.redact(&message.to_string())
+        );
 
-        // Send the request
-        stdin.write_all(request_json.to_string().as_bytes()).await?;
-        stdin.write_all(b"\n").await?;
+        stdin.write_all(&self.framing.encode(message)).await?;
         stdin.flush().await?;
+        Ok(())
+    }
 
-        // Get the response with timeout protection
-        if let Some(stdout) = child.stdout.as_mut() {
-            let mut reader = BufReader::new(stdout);
-            let mut line = String::new();
-
-            // Set a timeout for reading the response
-            let read_future = reader.read_line(&mut line);
-            match tokio::time::timeout(std::time::Duration::from_secs(5), read_future).await {
-                Ok(result) => {
-                    result?;
-                    println!("Received response: {}", line);
-                    if line.is_empty() {
-                        return Err(anyhow::anyhow!("Empty response from MCP server"));
+    // Servers may interleave notifications and requests of their own with the response. The
+    // deadline stops while we answer their requests, which can wait on the user's approval
+    async fn read_response(&mut self, id: &Value, timeout: Duration) -> Result<Value> {
+        let mut deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let mut message = self.read_message(deadline).await?;
+            if let Some(method) = message.get("method").and_then(Value::as_str) {
+                let method = method.to_string();
+                match message.get("id").cloned() {
+                    Some(id) => {
+                        let answering = tokio::time::Instant::now();
+                        self.answer_server_request(id, &method, &message["params"])
+                            .await?;
+                        deadline += answering.elapsed();
                     }
+                    None => self.dispatch_notification(&method, &message["params"]),
+                }
+                continue;
+            }
+            // e.g. the late answer to a request that was cancelled
+            if &message["id"] != id {
+                tracing::debug!("Discarding response to stale request {}", message["id"]);
+                continue;
+            }
+
+            // Check for errors in the response
+            if let Some(error) = message.get("error") {
+                return Err(AgentError::ToolError(format!(
+                    "MCP server error: {}",
+                    error
+                )));
+            }
 
-                    let response: serde_json::Value = serde_json::from_str(&line)?;
+            return Ok(message
+                .get_mut("result")
+                .map(Value::take)
+                .unwrap_or(Value::Null));
+        }
+    }
 
-                    // Check for errors in the response
-                    if let Some(error) = response.get("error") {
-                        return Err(anyhow::anyhow!("MCP server error: {}", error));
+    async fn read_message(&mut self, deadline: tokio::time::Instant) -> Result<Value> {
+        let reader = self
+            .stdout
+            .as_mut()
+            .ok_or_else(|| AgentError::ProtocolError("Failed to get stdout".to_string()))?;
+        let frame = framing::read_frame(reader, self.max_message_bytes);
+        match tokio::time::timeout_at(deadline, frame).await {
+            Ok(result) => match result? {
+                None => Err(AgentError::ProtocolError(
+                    "Empty response from MCP server".to_string(),
+                )),
+                // Not a protocol error: the rest of the stream is intact, so the server is kept.
+                // Should it have been a notification, the response is discarded as stale later
+                Some(Frame::TooLarge { bytes }) => Err(AgentError::ToolError(format!(
+                    "MCP server sent a message of {} bytes, over the limit of {} bytes",
+                    bytes, self.max_message_bytes
+                ))),
+                Some(Frame::Message { body, framing }) => {
+                    tracing::debug!("Received response: {}", self.redactor.redact(&body));
+                    if framing == Framing::ContentLength {
+                        self.framing = framing;
                     }
+                    Ok(serde_json::from_str(&body)?)
                 }
-                Err(_) => {
-                    return Err(anyhow::anyhow!("Timeout waiting for MCP server response"));
+            },
+            Err(_) => Err(AgentError::Timeout(
+                "Timeout waiting for MCP server response".to_string(),
+            )),
+        }
+    }
+
+    fn dispatch_notification(&mut self, method: &str, params: &Value) {
+        if method == TOOLS_LIST_CHANGED {
+            self.tools_changed = true;
+        }
+        if method == "notifications/progress" {
+            if let Some(progress) = params["progress"].as_f64() {
+                self.emit(AgentEvent::ToolProgress {
+                    token: params["progressToken"].clone(),
+                    progress,
+                    total: params["total"].as_f64(),
+                    message: params["message"].as_str().map(str::to_string),
+                });
+            }
+        }
+        match self.notification_handlers.get(method) {
+            Some(handlers) => {
+                for handler in handlers {
+                    handler(params);
                 }
             }
+            // Server log messages are worth showing even when nobody subscribed
+            None if method == "notifications/message" => {
+                logging::log_message(&self.server.name, params)
+            }
+            None if method == "notifications/progress" || method == TOOLS_LIST_CHANGED => {}
+            None => tracing::debug!("Ignoring MCP notification {}", method),
         }
-        Ok(())
+    }
+
+    async fn answer_server_request(
+        &mut self,
+        id: Value,
+        method: &str,
+        params: &Value,
+    ) -> Result<()> {
+        let outcome = match (&self.sampler, method) {
+            (Some(sampler), "sampling/createMessage") => {
+                sampler.create_message(&self.server.name, params).await
+            }
+            _ => {
+                return self
+                    .write_message(&json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": {
+                            "code": METHOD_NOT_FOUND,
+                            "message": format!("Method not found: {}", method)
+                        }
+                    }))
+                    .await
+            }
+        };
+        let response = match outcome {
+            Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+            Err(err) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": {"code": REQUEST_FAILED, "message": err.to_string()}
+            }),
+        };
+        self.write_message(&response).await
+    }
+}
+
+// The server capability a request method belongs to, if any
+fn required_capability(method: &str) -> Option<&'static str> {
+    match method.split('/').next()? {
+        "tools" => Some("tools"),
+        "resources" => Some("resources"),
+        "prompts" => Some("prompts"),
+        "logging" => Some("logging"),
+        "completion" => Some("completions"),
+        _ => None,
+    }
+}
+
+// Ask the server to report progress on tool calls, keyed by the request id
+fn attach_progress_token(request: &mut Value) {
+    if request["method"] != "tools/call" {
+        return;
+    }
+    let id = request["id"].clone();
+    if let Some(params) = request.get_mut("params").and_then(Value::as_object_mut) {
+        let meta = params.entry("_meta").or_insert_with(|| json!({}));
+        if let Some(meta) = meta.as_object_mut() {
+            meta.entry("progressToken").or_insert(id);
+        }
+    }
+}
+
+pub fn render_prompt_messages(messages: &[model::PromptMessage]) -> String {
+    messages
+        .iter()
+        .map(|message| {
+            let role = match message.role {
+                model::PromptMessageRole::User => "HUMAN",
+                model::PromptMessageRole::Assistant => "ASSISTANT",
+            };
+            format!("{}: {}", role, prompt_content_text(&message.content))
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub fn prompt_content_text(content: &model::PromptMessageContent) -> String {
+    match content {
+        model::PromptMessageContent::Text { text } => text.clone(),
+        model::PromptMessageContent::Image { image } => format!("<image {}>", image.mime_type),
+        model::PromptMessageContent::Resource { resource } => match &resource.resource {
+            model::ResourceContents::TextResourceContents { text, .. } => text.clone(),
+            model::ResourceContents::BlobResourceContents { uri, .. } => {
+                format!("<resource {}>", uri)
+            }
+        },
     }
 }
 
@@ -98,6 +866,8 @@ impl MCPClient {
 mod tests {
     use super::*;
     use std::fs;
+    use tokio::process::Command;
+    use tokio::time::timeout;
     use uuid::Uuid;
 
     #[tokio::test]
@@ -148,7 +918,7 @@ mod tests {
         // Check if file exists before reading
         if !std::path::Path::new(&test_file_path).exists() {
             println!("File does not exist after write operation!");
-            return Err(anyhow::anyhow!("File was not created"));
+            return Err(AgentError::ToolError("File was not created".to_string()));
         }
 
         // Verify the file was written with correct content
@@ -161,4 +931,394 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_render_prompt_messages_prefixes_roles() {
+        let messages = vec![
+            model::PromptMessage::new_text(model::PromptMessageRole::User, "Write a haiku"),
+            model::PromptMessage::new_text(model::PromptMessageRole::Assistant, "Sure"),
+        ];
+
+        assert_eq!(
+            render_prompt_messages(&messages),
+            "HUMAN: Write a haiku\nASSISTANT: Sure"
+        );
+    }
+
+    const ECHO_SERVER: &str = r#"while read line; do
+case "$line" in *'"id"'*)
+id=$(echo "$line" | sed 's/.*"id":\([0-9]*\).*/\1/')
+echo "{\"jsonrpc\":\"2.0\",\"id\":$id,\"result\":{}}";;
+esac
+done"#;
+
+    fn client_with_process(program: &str, args: &[&str]) -> MCPClient {
+        let child = Command::new(program)
+            .args(args)
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut client = MCPClient::new();
+        client.attach(child);
+        client
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_lets_server_exit_on_closed_stdin() -> Result<()> {
+        let mut client = client_with_process("cat", &[]);
+
+        let started = std::time::Instant::now();
+        client.shutdown().await?;
+
+        assert!(client.server_process.is_none());
+        assert!(started.elapsed() < SHUTDOWN_GRACE_PERIOD);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_terminates_server_ignoring_stdin() -> Result<()> {
+        let mut client = client_with_process("sleep", &["30"]);
+
+        client.shutdown().await?;
+
+        assert!(client.server_process.is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_notifications_are_dispatched_before_the_response() -> Result<()> {
+        let script = r#"read request
+echo '{"jsonrpc":"2.0","method":"notifications/progress","params":{"progress":3,"total":10}}'
+echo '{"jsonrpc":"2.0","id":1,"result":{"ok":true}}'"#;
+        let mut client = client_with_process("sh", &["-c", script]);
+        let progress = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = progress.clone();
+        client.on_notification("notifications/progress", move |params| {
+            seen.lock().unwrap().push(params["progress"].clone());
+        });
+
+        let result = client.request("ping", json!({})).await?;
+
+        client.shutdown().await?;
+        assert_eq!(result, json!({"ok": true}));
+        assert_eq!(*progress.lock().unwrap(), vec![json!(3)]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_content_length_replies_switch_the_client_to_that_framing() -> Result<()> {
+        // Answers in Content-Length frames and reports whether the second request was framed
+        let script = r#"read request
+body='{"jsonrpc":"2.0","id":1,"result":{}}'
+printf 'Content-Length: %d\r\n\r\n%s' ${#body} "$body"
+read header
+case "$header" in Content-Length*) framed=true;; *) framed=false;; esac
+body="{\"jsonrpc\":\"2.0\",\"id\":2,\"result\":{\"framed\":$framed}}"
+printf 'Content-Length: %d\r\n\r\n%s' ${#body} "$body""#;
+        let mut client = client_with_process("sh", &["-c", script]);
+
+        client.request("ping", json!({})).await?;
+        let second = client.request("ping", json!({})).await?;
+
+        client.shutdown().await?;
+        assert_eq!(client.framing, Framing::ContentLength);
+        assert_eq!(second, json!({"framed": true}));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_oversized_response_fails_its_request_but_keeps_the_server() -> Result<()> {
+        let script = r#"read request
+printf '{"jsonrpc":"2.0","id":1,"result":{"text":"%0200d"}}\n' 0
+read request
+echo '{"jsonrpc":"2.0","id":2,"result":{"ok":true}}'"#;
+        let mut client = client_with_process("sh", &["-c", script]).with_max_message_bytes(64);
+
+        let oversized = client
+            .request("tools/call", json!({"name": "read_file"}))
+            .await;
+        let next = client.request("ping", json!({})).await?;
+
+        client.shutdown().await?;
+        assert!(
+            matches!(oversized, Err(AgentError::ToolError(message)) if message.contains("over the limit of 64 bytes"))
+        );
+        assert_eq!(next, json!({"ok": true}));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_tool_calls_carry_a_progress_token_reported_to_observers() -> Result<()> {
+        // Reports progress against whatever token the request carried
+        let script = r#"read request
+token=$(echo "$request" | sed 's/.*"progressToken":\([0-9]*\).*/\1/')
+echo "{\"jsonrpc\":\"2.0\",\"method\":\"notifications/progress\",\"params\":{\"progressToken\":$token,\"progress\":3,\"total\":10}}"
+echo '{"jsonrpc":"2.0","id":1,"result":{}}'"#;
+        let mut client = client_with_process("sh", &["-c", script]);
+        let observer = Arc::new(RecordingObserver(std::sync::Mutex::new(Vec::new())));
+        client.add_observer(observer.clone());
+
+        client
+            .request("tools/call", json!({"name": "index", "arguments": {}}))
+            .await?;
+
+        client.shutdown().await?;
+        let events = observer.0.lock().unwrap();
+        assert_eq!(
+            events[0],
+            AgentEvent::ToolProgress {
+                token: json!(1),
+                progress: 3.0,
+                total: Some(10.0),
+                message: None,
+            }
+        );
+        assert_eq!(events[0].progress_summary().as_deref(), Some("step 3/10"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_abandoned_request_is_cancelled_and_its_late_response_skipped() -> Result<()> {
+        // Answers the first request late, and the second only if the first was cancelled
+        let script = r#"read first
+read cancelled
+read second
+echo '{"jsonrpc":"2.0","id":1,"result":{"late":true}}'
+case "$cancelled" in *'"method":"notifications/cancelled"'*'"requestId":1'*)
+echo '{"jsonrpc":"2.0","id":2,"result":{"late":false}}';;
+esac"#;
+        let mut client = client_with_process("sh", &["-c", script]);
+
+        let abandoned = timeout(
+            Duration::from_millis(100),
+            client.request("tools/call", json!({"name": "slow"})),
+        )
+        .await;
+        client.cancel_in_flight("turn deadline passed").await?;
+        let result = client.request("ping", json!({})).await?;
+
+        client.shutdown().await?;
+        assert!(abandoned.is_err());
+        assert_eq!(result, json!({"late": false}));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_model_requests_with_the_same_id_are_told_apart() -> Result<()> {
+        // Answers the first request late, after the second has been sent
+        let script = r#"read first
+read cancelled
+read second
+first=$(echo "$first" | sed 's/.*"id":\([0-9]*\).*/\1/')
+second=$(echo "$second" | sed 's/.*"id":\([0-9]*\).*/\1/')
+echo "{\"jsonrpc\":\"2.0\",\"id\":$first,\"result\":{\"late\":true}}"
+echo "{\"jsonrpc\":\"2.0\",\"id\":$second,\"result\":{\"late\":false}}""#;
+        let mut client = client_with_process("sh", &["-c", script]);
+        // Models number their calls as the few-shot examples do, from 1 every time
+        let model_request = |tool: &str| -> Result<model::JsonRpcRequest> {
+            Ok(serde_json::from_value(json!({
+                "jsonrpc": "2.0",
+                "method": "tools/call",
+                "params": {"name": tool, "arguments": {}},
+                "id": 1
+            }))?)
+        };
+
+        let abandoned = timeout(
+            Duration::from_millis(100),
+            client.do_request(model_request("slow")?),
+        )
+        .await;
+        client.cancel_in_flight("turn deadline passed").await?;
+        let result = client.do_request(model_request("fast")?).await?;
+
+        client.shutdown().await?;
+        assert!(abandoned.is_err());
+        assert_eq!(result, json!({"late": false}));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_sampling_requests_are_answered_by_the_model() -> Result<()> {
+        // Asks for a completion, then returns our answer as the result of the original call
+        let script = r#"read request
+echo '{"jsonrpc":"2.0","id":"s1","method":"sampling/createMessage","params":{"messages":[{"role":"user","content":{"type":"text","text":"Name a season"}}],"maxTokens":8}}'
+read answer
+echo "{\"jsonrpc\":\"2.0\",\"id\":1,\"result\":$answer}""#;
+        let fixture = Fixture {
+            interactions: vec![Interaction::Model(crate::replay::ModelExchange {
+                request: crate::model_client::CompletionRequest::default(),
+                response: crate::model_client::ModelResponse {
+                    response: "Autumn".to_string(),
+                    reasoning: None,
+                },
+            })],
+        };
+        // Slower than the call's timeout, which must not run while the request is answered
+        struct SlowModel(crate::replay::ReplayModelClient);
+
+        #[async_trait::async_trait]
+        impl crate::model_client::ModelClient for SlowModel {
+            async fn complete(
+                &self,
+                request: &crate::model_client::CompletionRequest,
+            ) -> Result<crate::model_client::ModelResponse> {
+                tokio::time::sleep(Duration::from_millis(300)).await;
+                self.0.complete(request).await
+            }
+        }
+        let model = Arc::new(SlowModel(crate::replay::ReplayModelClient::new(&fixture)));
+        let mut client = client_with_process("sh", &["-c", script]).with_sampler(Sampler::new(
+            model,
+            "qwen3",
+            SamplingApproval::Always,
+        ));
+
+        let answer = client
+            .request_with_timeout(
+                "tools/call",
+                json!({"name": "summarize"}),
+                Duration::from_millis(200),
+            )
+            .await?;
+
+        client.shutdown().await?;
+        assert_eq!(answer["id"], "s1");
+        assert_eq!(answer["result"]["content"]["text"], "Autumn");
+        assert_eq!(answer["result"]["model"], "qwen3");
+        Ok(())
+    }
+
+    struct RecordingObserver(std::sync::Mutex<Vec<AgentEvent>>);
+
+    impl AgentObserver for RecordingObserver {
+        fn on_event(&self, event: &AgentEvent) {
+            self.0.lock().unwrap().push(event.clone());
+        }
+    }
+
+    fn sh_server(name: &str, script: &str) -> MCPClient {
+        MCPClient::with_server(ServerConfig {
+            name: name.to_string(),
+            command: "sh".to_string(),
+            args: vec!["-c".to_string(), script.to_string()],
+            framing: Framing::Newline,
+            request_limit: None,
+            log_level: None,
+        })
+    }
+
+    #[test]
+    fn test_servers_are_given_by_name_or_command_line() -> Result<()> {
+        let fetch =
+            ServerConfig::from_spec("/usr/local/bin/uvx  mcp-server-fetch --ignore-robots-txt")?;
+
+        assert_eq!(ServerConfig::from_spec("git")?, ServerConfig::git("."));
+        assert_eq!(
+            ServerConfig::from_spec("duckduckgo")?,
+            ServerConfig::duckduckgo_search()
+        );
+        assert_eq!(fetch.name, "uvx");
+        assert_eq!(fetch.command, "/usr/local/bin/uvx");
+        assert_eq!(fetch.args, ["mcp-server-fetch", "--ignore-robots-txt"]);
+        assert!(matches!(
+            ServerConfig::from_spec(" "),
+            Err(AgentError::ConfigError(_))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_slow_server_is_awaited_and_hung_or_exited_ones_are_not_healthy() -> Result<()> {
+        let mut slow = sh_server("slow", &format!("sleep 1\n{}", ECHO_SERVER));
+        let mut exiting = sh_server("exiting", "exit 3");
+        let mut hung = client_with_process("sleep", &["30"]);
+
+        let started = std::time::Instant::now();
+        slow.init().await?;
+        let ready_after = started.elapsed();
+        let slow_healthy = slow.is_healthy().await;
+        let exited = exiting.init().await;
+        let hung_healthy = hung.is_healthy().await;
+
+        slow.shutdown().await?;
+        hung.shutdown().await?;
+        assert!(ready_after >= Duration::from_secs(1) && ready_after < DEFAULT_READY_TIMEOUT);
+        assert!(slow_healthy);
+        assert!(matches!(exited, Err(AgentError::ProtocolError(_))));
+        assert!(!hung_healthy);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_tools_are_listed_across_pages() -> Result<()> {
+        // Sends the second page for the cursor the first one gave out, and no tools otherwise
+        let script = r#"tool() { echo "{\"name\":\"$1\",\"description\":\"$1\",\"inputSchema\":{\"type\":\"object\"}}"; }
+read request
+echo "{\"jsonrpc\":\"2.0\",\"id\":1,\"result\":{\"tools\":[$(tool write_file)],\"nextCursor\":\"page-2\"}}"
+read request
+case "$request" in *'"cursor":"page-2"'*) tools=$(tool read_file);; esac
+echo "{\"jsonrpc\":\"2.0\",\"id\":2,\"result\":{\"tools\":[$tools]}}""#;
+        let mut client = client_with_process("sh", &["-c", script]);
+
+        let tools = client.list_tools().await?;
+
+        client.shutdown().await?;
+        let names: Vec<&str> = tools.iter().map(|tool| tool.name.as_ref()).collect();
+        assert_eq!(names, ["write_file", "read_file"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_requests_need_a_declared_capability() -> Result<()> {
+        let script = r#"read request
+echo '{"jsonrpc":"2.0","id":1,"result":{"capabilities":{"tools":{}}}}'
+read initialized
+read request
+id=$(echo "$request" | sed 's/.*"id":\([0-9]*\).*/\1/')
+echo "{\"jsonrpc\":\"2.0\",\"id\":$id,\"result\":{\"tools\":[]}}""#;
+        let mut client = sh_server("tools-only", script);
+        client.init().await?;
+
+        let prompts = client.list_prompts().await;
+        let tools = client.list_tools().await?;
+
+        client.shutdown().await?;
+        assert!(
+            matches!(prompts, Err(AgentError::UnsupportedCapability(message)) if message.contains("prompts/list"))
+        );
+        assert!(tools.is_empty());
+        assert!(client.supports("tools") && !client.supports("resources"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_exited_server_is_restarted_before_request() -> Result<()> {
+        // Answers every request (but not notifications) with an empty result
+        let mut client = MCPClient::with_server(ServerConfig {
+            name: "echo".to_string(),
+            command: "sh".to_string(),
+            args: vec!["-c".to_string(), ECHO_SERVER.to_string()],
+            framing: Framing::Newline,
+            request_limit: None,
+            log_level: None,
+        });
+        let observer = Arc::new(RecordingObserver(std::sync::Mutex::new(Vec::new())));
+        client.add_observer(observer.clone());
+        let mut exited = Command::new("true")
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .spawn()?;
+        exited.wait().await?;
+        client.attach(exited);
+
+        client.request("ping", json!({})).await?;
+
+        client.shutdown().await?;
+        let events = observer.0.lock().unwrap();
+        assert_eq!(events.len(), 1);
+        assert!(matches!(events[0], AgentEvent::ServerRestarted { .. }));
+        Ok(())
+    }
 }
@@ -0,0 +1,127 @@
+use crate::error::{AgentError, Result};
+use crate::model_client::{ChatMessage, CompletionRequest, GenerationParams, ModelClient, Role};
+use crate::terminal;
+use rmcp::model;
+use serde_json::{json, Value};
+use std::io::IsTerminal;
+use std::sync::Arc;
+
+// Whether a server may use our model without asking the user each time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SamplingApproval {
+    Always,
+    Ask,
+    Never,
+}
+
+// Answers sampling/createMessage requests from MCP servers with our model
+pub struct Sampler {
+    model: Arc<dyn ModelClient>,
+    model_name: String,
+    approval: SamplingApproval,
+}
+
+impl Sampler {
+    pub fn new(model: Arc<dyn ModelClient>, model_name: &str, approval: SamplingApproval) -> Self {
+        Self {
+            model,
+            model_name: model_name.to_string(),
+            approval,
+        }
+    }
+
+    pub async fn create_message(&self, server: &str, params: &Value) -> Result<Value> {
+        let params: model::CreateMessageRequestParam = serde_json::from_value(params.clone())?;
+        let request = completion_request(&params);
+        if !self.approve(server, &request).await? {
+            return Err(AgentError::ToolError(
+                "User rejected the sampling request".to_string(),
+            ));
+        }
+        let response = self.model.complete(&request).await?;
+        Ok(json!(model::CreateMessageResult {
+            model: self.model_name.clone(),
+            stop_reason: Some(model::CreateMessageResult::STOP_REASON_END_TURN.to_string()),
+            message: model::SamplingMessage {
+                role: model::Role::Assistant,
+                content: model::Content::text(response.response),
+            },
+        }))
+    }
+
+    async fn approve(&self, server: &str, request: &CompletionRequest) -> Result<bool> {
+        match self.approval {
+            SamplingApproval::Always => Ok(true),
+            SamplingApproval::Never => Ok(false),
+            // Nobody can answer without a terminal, e.g. in serve mode
+            SamplingApproval::Ask if !std::io::stdin().is_terminal() => {
+                eprintln!(
+                    "Denying sampling request from MCP server '{}': no terminal to ask on",
+                    server
+                );
+                Ok(false)
+            }
+            SamplingApproval::Ask => {
+                eprintln!("MCP server '{}' asks the model to complete:", server);
+                for message in &request.messages {
+                    eprintln!("  {:?}: {}", message.role, message.content);
+                }
+                terminal::confirm("Allow?").await
+            }
+        }
+    }
+}
+
+pub fn completion_request(params: &model::CreateMessageRequestParam) -> CompletionRequest {
+    let mut messages = Vec::new();
+    if let Some(system_prompt) = &params.system_prompt {
+        messages.push(ChatMessage::system(system_prompt.clone()));
+    }
+    for message in &params.messages {
+        let role = match message.role {
+            model::Role::User => Role::User,
+            model::Role::Assistant => Role::Assistant,
+        };
+        let text = match message.content.raw.as_text() {
+            Some(text) => text.text.clone(),
+            None => "<non-text content>".to_string(),
+        };
+        messages.push(ChatMessage::new(role, text));
+    }
+    CompletionRequest {
+        messages,
+        params: GenerationParams {
+            temperature: params.temperature,
+            max_tokens: Some(params.max_tokens),
+            stop: params.stop_sequences.clone().unwrap_or_default(),
+            ..GenerationParams::default()
+        },
+        format: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_completion_request_maps_prompt_and_limits() -> Result<()> {
+        let params: model::CreateMessageRequestParam = serde_json::from_value(json!({
+            "messages": [{"role": "user", "content": {"type": "text", "text": "Summarize"}}],
+            "systemPrompt": "Be brief",
+            "maxTokens": 64
+        }))?;
+
+        let request = completion_request(&params);
+
+        assert_eq!(
+            request.messages,
+            vec![
+                ChatMessage::system("Be brief"),
+                ChatMessage::user("Summarize")
+            ]
+        );
+        assert_eq!(request.params.max_tokens, Some(64));
+        Ok(())
+    }
+}
@@ -0,0 +1,55 @@
+use super::{Framing, ServerConfig};
+
+pub const BRAVE_API_KEY_ENV: &str = "BRAVE_API_KEY";
+
+// Web search servers. Brave's needs an API key; DuckDuckGo's works without one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchEngine {
+    Brave,
+    DuckDuckGo,
+}
+
+impl SearchEngine {
+    // Brave when its API key is set, DuckDuckGo otherwise
+    pub fn from_env() -> Self {
+        match std::env::var(BRAVE_API_KEY_ENV) {
+            Ok(key) if !key.is_empty() => Self::Brave,
+            _ => Self::DuckDuckGo,
+        }
+    }
+
+    pub fn server(self) -> ServerConfig {
+        match self {
+            Self::Brave => ServerConfig::brave_search(),
+            Self::DuckDuckGo => ServerConfig::duckduckgo_search(),
+        }
+    }
+}
+
+impl ServerConfig {
+    // The reference Brave search server, which reads BRAVE_API_KEY from the environment
+    pub fn brave_search() -> Self {
+        Self {
+            name: "brave-search".to_string(),
+            command: "npx".to_string(),
+            args: vec![
+                "-y".to_string(),
+                "@modelcontextprotocol/server-brave-search".to_string(),
+            ],
+            framing: Framing::Newline,
+            request_limit: None,
+            log_level: None,
+        }
+    }
+
+    pub fn duckduckgo_search() -> Self {
+        Self {
+            name: "duckduckgo".to_string(),
+            command: "uvx".to_string(),
+            args: vec!["duckduckgo-mcp-server".to_string()],
+            framing: Framing::Newline,
+            request_limit: None,
+            log_level: None,
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use crate::error::{AgentError, Result};
+use rmcp::model::{self, JsonObject};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+// One tools/call, whoever ends up executing it: the MCP server, a native tool or the
+// knowledge base
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ToolCall {
+    pub name: String,
+    #[serde(default = "no_arguments")]
+    pub arguments: Value,
+}
+
+fn no_arguments() -> Value {
+    json!({})
+}
+
+impl ToolCall {
+    pub fn new(name: impl Into<String>, arguments: Value) -> Self {
+        Self {
+            name: name.into(),
+            arguments,
+        }
+    }
+
+    // The call named in a JSON-RPC request's params; None when nothing is named
+    pub fn from_request(request: &model::JsonRpcRequest) -> Option<Self> {
+        let params = request.request.params.as_ref()?;
+        Some(Self {
+            name: params.inner.get("name")?.as_str()?.to_string(),
+            arguments: params
+                .inner
+                .get("arguments")
+                .cloned()
+                .unwrap_or_else(no_arguments),
+        })
+    }
+
+    // The arguments as the tool's own type, e.g. a struct deriving Deserialize
+    pub fn arguments_as<T: DeserializeOwned>(&self) -> Result<T> {
+        serde_json::from_value(self.arguments.clone()).map_err(|err| {
+            AgentError::ToolError(format!(
+                "Invalid arguments for tool '{}': {}",
+                self.name, err
+            ))
+        })
+    }
+
+    pub fn arguments_object(&self) -> JsonObject {
+        self.arguments.as_object().cloned().unwrap_or_default()
+    }
+
+    pub fn params(&self) -> Value {
+        json!({"name": self.name, "arguments": self.arguments})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tool_call_comes_from_the_request_and_types_its_arguments() -> Result<()> {
+        #[derive(Debug, Deserialize)]
+        struct WriteFile {
+            path: String,
+            content: String,
+        }
+        let request: model::JsonRpcRequest = serde_json::from_value(json!({
+            "jsonrpc": "2.0",
+            "method": "tools/call",
+            "params": {"name": "write_file", "arguments": {"path": "pond.txt", "content": "An old pond"}},
+            "id": 1
+        }))?;
+        let listing: model::JsonRpcRequest = serde_json::from_value(json!({
+            "jsonrpc": "2.0",
+            "method": "tools/list",
+            "id": 2
+        }))?;
+
+        let call = ToolCall::from_request(&request).expect("a tools/call request");
+        let write: WriteFile = call.arguments_as()?;
+
+        assert_eq!(call.name, "write_file");
+        assert_eq!(
+            (write.path.as_str(), write.content.as_str()),
+            ("pond.txt", "An old pond")
+        );
+        assert_eq!(ToolCall::from_request(&listing), None);
+        let err = ToolCall::new("write_file", json!({"path": 3}))
+            .arguments_as::<WriteFile>()
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("tool error: Invalid arguments for tool 'write_file'"));
+        Ok(())
+    }
+}
@@ -0,0 +1,179 @@
+use serde_json::{json, Value};
+
+// One block of a tools/call result. Parsed from the raw JSON, since rmcp's CallToolResult
+// predates structuredContent and resource links
+#[derive(Debug, Clone, PartialEq)]
+pub enum ToolResultContent {
+    Text(String),
+    Image {
+        data: String,
+        mime_type: String,
+    },
+    // An embedded resource, or a link to one when text is None
+    Resource {
+        uri: String,
+        mime_type: Option<String>,
+        text: Option<String>,
+    },
+    // structuredContent: JSON matching the tool's outputSchema
+    Structured(Value),
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ToolResult {
+    pub content: Vec<ToolResultContent>,
+    pub is_error: bool,
+}
+
+impl ToolResult {
+    // Blocks of unknown types, such as audio, are skipped
+    pub fn from_value(result: &Value) -> Self {
+        let mut content: Vec<ToolResultContent> = result["content"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(parse_block)
+            .collect();
+        if let Some(structured) = result
+            .get("structuredContent")
+            .filter(|value| !value.is_null())
+        {
+            content.push(ToolResultContent::Structured(structured.clone()));
+        }
+        Self {
+            content,
+            is_error: result["isError"].as_bool().unwrap_or(false),
+        }
+    }
+
+    // Back into the shape of a tools/call result, less any blocks from_value skipped
+    pub fn to_value(&self) -> Value {
+        let content: Vec<Value> = self
+            .content
+            .iter()
+            .filter_map(|block| match block {
+                ToolResultContent::Text(text) => Some(json!({"type": "text", "text": text})),
+                ToolResultContent::Image { data, mime_type } => {
+                    Some(json!({"type": "image", "data": data, "mimeType": mime_type}))
+                }
+                ToolResultContent::Resource {
+                    uri,
+                    mime_type,
+                    text: Some(text),
+                } => Some(json!({
+                    "type": "resource",
+                    "resource": {"uri": uri, "mimeType": mime_type, "text": text}
+                })),
+                ToolResultContent::Resource { uri, mime_type, .. } => {
+                    Some(json!({"type": "resource_link", "uri": uri, "mimeType": mime_type}))
+                }
+                ToolResultContent::Structured(_) => None,
+            })
+            .collect();
+        let mut value = json!({"content": content, "isError": self.is_error});
+        if let Some(structured) = self.structured() {
+            value["structuredContent"] = structured.clone();
+        }
+        value
+    }
+
+    pub fn structured(&self) -> Option<&Value> {
+        self.content.iter().find_map(|block| match block {
+            ToolResultContent::Structured(value) => Some(value),
+            _ => None,
+        })
+    }
+
+    // The result as the model reads it, a block per line. Structured content usually repeats
+    // as text, so it only shows when there is no text
+    pub fn text(&self) -> String {
+        let has_text = self
+            .content
+            .iter()
+            .any(|block| matches!(block, ToolResultContent::Text(_)));
+        self.content
+            .iter()
+            .filter_map(|block| match block {
+                ToolResultContent::Text(text) => Some(text.clone()),
+                ToolResultContent::Image { mime_type, .. } => {
+                    Some(format!("[{} image]", mime_type))
+                }
+                ToolResultContent::Resource {
+                    text: Some(text), ..
+                } => Some(text.clone()),
+                ToolResultContent::Resource { uri, .. } => Some(format!("[resource {}]", uri)),
+                ToolResultContent::Structured(value) if !has_text => Some(value.to_string()),
+                ToolResultContent::Structured(_) => None,
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+fn parse_block(block: &Value) -> Option<ToolResultContent> {
+    let string = |value: &Value| value.as_str().map(str::to_string);
+    match block["type"].as_str()? {
+        "text" => Some(ToolResultContent::Text(string(&block["text"])?)),
+        "image" => Some(ToolResultContent::Image {
+            data: string(&block["data"])?,
+            mime_type: string(&block["mimeType"])?,
+        }),
+        "resource" => {
+            let resource = &block["resource"];
+            Some(ToolResultContent::Resource {
+                uri: string(&resource["uri"])?,
+                mime_type: string(&resource["mimeType"]),
+                text: string(&resource["text"]),
+            })
+        }
+        "resource_link" => Some(ToolResultContent::Resource {
+            uri: string(&block["uri"])?,
+            mime_type: string(&block["mimeType"]),
+            text: None,
+        }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_result_blocks_and_structured_content_are_typed() {
+        let result = ToolResult::from_value(&json!({
+            "content": [
+                {"type": "text", "text": "3 syllables"},
+                {"type": "image", "data": "iVBORw0KGgo=", "mimeType": "image/png"},
+                {"type": "resource", "resource": {"uri": "file:///pond.txt", "text": "An old pond"}},
+                {"type": "resource_link", "uri": "file:///frog.txt", "name": "frog"},
+                {"type": "audio", "data": "", "mimeType": "audio/wav"}
+            ],
+            "structuredContent": {"syllables": 3}
+        }));
+        let structured_only = ToolResult::from_value(&json!({
+            "content": [],
+            "structuredContent": {"syllables": 3},
+            "isError": true
+        }));
+
+        assert_eq!(result.content.len(), 5);
+        assert_eq!(
+            result.content[2],
+            ToolResultContent::Resource {
+                uri: "file:///pond.txt".to_string(),
+                mime_type: None,
+                text: Some("An old pond".to_string())
+            }
+        );
+        assert_eq!(result.structured(), Some(&json!({"syllables": 3})));
+        assert_eq!(
+            result.text(),
+            "3 syllables\n[image/png image]\nAn old pond\n[resource file:///frog.txt]"
+        );
+        assert_eq!(structured_only.text(), r#"{"syllables":3}"#);
+        assert!(structured_only.is_error);
+        assert_eq!(ToolResult::from_value(&result.to_value()), result);
+    }
+}
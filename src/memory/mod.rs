@@ -0,0 +1,219 @@
+use crate::error::Result;
+use crate::ids::{random_ids, IdGenerator};
+use crate::model_client::embedding::EmbeddingClient;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct MemoryRecord {
+    pub id: String,
+    pub text: String,
+    pub embedding: Vec<f32>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VectorIndex {
+    records: Vec<MemoryRecord>,
+}
+
+impl VectorIndex {
+    pub fn insert(&mut self, record: MemoryRecord) {
+        self.records.push(record);
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    // Brute-force cosine search; fine for the few thousand facts a local agent keeps
+    pub fn search(&self, query: &[f32], limit: usize) -> Vec<(f32, &MemoryRecord)> {
+        self.search_where(query, limit, |_| true)
+    }
+
+    pub fn search_where(
+        &self,
+        query: &[f32],
+        limit: usize,
+        keep: impl Fn(&MemoryRecord) -> bool,
+    ) -> Vec<(f32, &MemoryRecord)> {
+        let mut scored: Vec<(f32, &MemoryRecord)> = self
+            .records
+            .iter()
+            .filter(|record| keep(record))
+            .map(|record| (cosine_similarity(query, &record.embedding), record))
+            .collect();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        scored.truncate(limit);
+        scored
+    }
+}
+
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a * norm_b)
+}
+
+// Facts persisted as JSONL so they survive across sessions
+pub struct MemoryStore {
+    embedder: Box<dyn EmbeddingClient>,
+    index: VectorIndex,
+    path: Option<PathBuf>,
+    ids: Arc<dyn IdGenerator>,
+}
+
+impl MemoryStore {
+    pub fn in_memory(embedder: Box<dyn EmbeddingClient>) -> Self {
+        Self {
+            embedder,
+            index: VectorIndex::default(),
+            path: None,
+            ids: random_ids(),
+        }
+    }
+
+    pub fn with_ids(mut self, ids: Arc<dyn IdGenerator>) -> Self {
+        self.ids = ids;
+        self
+    }
+
+    pub fn open(embedder: Box<dyn EmbeddingClient>, path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let mut index = VectorIndex::default();
+        if path.exists() {
+            for line in std::fs::read_to_string(&path)?.lines() {
+                if !line.trim().is_empty() {
+                    index.insert(serde_json::from_str(line)?);
+                }
+            }
+        }
+        Ok(Self {
+            embedder,
+            index,
+            path: Some(path),
+            ids: random_ids(),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    pub async fn remember(&mut self, text: &str) -> Result<()> {
+        let record = MemoryRecord {
+            id: self.ids.new_id().to_string(),
+            text: text.to_string(),
+            embedding: self.embedder.embed_one(text).await?,
+        };
+        if let Some(path) = &self.path {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            let mut file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)?;
+            writeln!(file, "{}", serde_json::to_string(&record)?)?;
+        }
+        self.index.insert(record);
+        Ok(())
+    }
+
+    pub async fn recall(&self, query: &str, limit: usize) -> Result<Vec<MemoryRecord>> {
+        self.recall_where(query, limit, |_| true).await
+    }
+
+    // Only among the memories that pass keep, e.g. facts but not past task outcomes
+    pub async fn recall_where(
+        &self,
+        query: &str,
+        limit: usize,
+        keep: impl Fn(&MemoryRecord) -> bool,
+    ) -> Result<Vec<MemoryRecord>> {
+        if self.index.is_empty() {
+            return Ok(Vec::new());
+        }
+        let query = self.embedder.embed_one(query).await?;
+        Ok(self
+            .index
+            .search_where(&query, limit, keep)
+            .into_iter()
+            .map(|(_, record)| record.clone())
+            .collect())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use async_trait::async_trait;
+
+    // Letter-frequency vectors: crude, but texts sharing words score higher
+    pub(crate) struct LetterEmbedder;
+
+    #[async_trait]
+    impl EmbeddingClient for LetterEmbedder {
+        async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+            Ok(texts
+                .iter()
+                .map(|text| {
+                    let mut vector = vec![0.0; 26];
+                    for byte in text.to_lowercase().bytes().filter(u8::is_ascii_lowercase) {
+                        vector[(byte - b'a') as usize] += 1.0;
+                    }
+                    vector
+                })
+                .collect())
+        }
+    }
+
+    #[test]
+    fn test_cosine_similarity_of_identical_vectors_is_one() {
+        assert!((cosine_similarity(&[1.0, 2.0], &[1.0, 2.0]) - 1.0).abs() < 1e-6);
+        assert_eq!(cosine_similarity(&[1.0, 0.0], &[0.0, 1.0]), 0.0);
+        assert_eq!(cosine_similarity(&[1.0], &[1.0, 0.0]), 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_recall_ranks_relevant_memories_first() -> Result<()> {
+        let mut store = MemoryStore::in_memory(Box::new(LetterEmbedder));
+        store.remember("zzz quiz jazz").await?;
+        store.remember("haiku about autumn leaves").await?;
+
+        let recalled = store.recall("autumn haiku", 1).await?;
+
+        assert_eq!(recalled[0].text, "haiku about autumn leaves");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_memories_persist_across_sessions() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("memory.jsonl");
+
+        MemoryStore::open(Box::new(LetterEmbedder), &path)?
+            .remember("the user prefers haiku about the sea")
+            .await?;
+        let store = MemoryStore::open(Box::new(LetterEmbedder), &path)?;
+
+        assert_eq!(store.len(), 1);
+        Ok(())
+    }
+}
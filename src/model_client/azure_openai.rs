@@ -0,0 +1,200 @@
+use super::{
+    openai_content, CompletionRequest, ModelCapabilities, ModelClient, ModelResponse, Role,
+};
+use crate::error::{AgentError, Result};
+use async_trait::async_trait;
+use serde_json::{json, Value};
+
+pub const DEFAULT_API_VERSION: &str = "2024-06-01";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AzureAuth {
+    ApiKey(String),
+    // An Entra ID (Azure AD) access token for the cognitiveservices scope
+    BearerToken(String),
+}
+
+pub struct AzureOpenAiClient {
+    endpoint: String,
+    deployment: String,
+    api_version: String,
+    auth: AzureAuth,
+}
+
+impl AzureOpenAiClient {
+    pub fn new(endpoint: &str, deployment: &str, auth: AzureAuth) -> Self {
+        Self {
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            deployment: deployment.to_string(),
+            api_version: DEFAULT_API_VERSION.to_string(),
+            auth,
+        }
+    }
+
+    // AZURE_OPENAI_ENDPOINT plus AZURE_OPENAI_API_KEY or AZURE_OPENAI_AD_TOKEN;
+    // AZURE_OPENAI_API_VERSION overrides the default api-version
+    pub fn from_env(deployment: &str) -> Result<Self> {
+        let endpoint = std::env::var("AZURE_OPENAI_ENDPOINT")
+            .map_err(|_| AgentError::ModelError("AZURE_OPENAI_ENDPOINT is not set".to_string()))?;
+        let auth = match (
+            std::env::var("AZURE_OPENAI_API_KEY"),
+            std::env::var("AZURE_OPENAI_AD_TOKEN"),
+        ) {
+            (Ok(key), _) => AzureAuth::ApiKey(key),
+            (_, Ok(token)) => AzureAuth::BearerToken(token),
+            _ => {
+                return Err(AgentError::ModelError(
+                    "Set AZURE_OPENAI_API_KEY or AZURE_OPENAI_AD_TOKEN".to_string(),
+                ))
+            }
+        };
+        let mut client = Self::new(&endpoint, deployment, auth);
+        if let Ok(version) = std::env::var("AZURE_OPENAI_API_VERSION") {
+            client = client.with_api_version(&version);
+        }
+        Ok(client)
+    }
+
+    pub fn with_api_version(mut self, api_version: &str) -> Self {
+        self.api_version = api_version.to_string();
+        self
+    }
+
+    fn url(&self) -> String {
+        format!(
+            "{}/openai/deployments/{}/chat/completions?api-version={}",
+            self.endpoint, self.deployment, self.api_version
+        )
+    }
+}
+
+#[async_trait]
+impl ModelClient for AzureOpenAiClient {
+    async fn complete(&self, request: &CompletionRequest) -> Result<ModelResponse> {
+        let builder = reqwest::Client::new()
+            .post(self.url())
+            .json(&chat_completions_body(request));
+        let builder = match &self.auth {
+            AzureAuth::ApiKey(key) => builder.header("api-key", key),
+            AzureAuth::BearerToken(token) => builder.bearer_auth(token),
+        };
+        let response = builder.send().await?;
+        let status = response.status();
+        let response: Value = response.json().await?;
+        if !status.is_success() {
+            return Err(AgentError::ModelError(format!(
+                "Azure OpenAI returned {}: {}",
+                status, response["error"]["message"]
+            )));
+        }
+
+        let text = response["choices"][0]["message"]["content"]
+            .as_str()
+            .ok_or_else(|| {
+                AgentError::ModelError(format!("Unexpected Azure OpenAI response: {}", response))
+            })?;
+        Ok(ModelResponse {
+            response: text.to_string(),
+            reasoning: None,
+        })
+    }
+
+    fn capabilities(&self) -> ModelCapabilities {
+        ModelCapabilities {
+            streaming: true,
+            tools: true,
+            json_schema: true,
+            vision: true,
+            stop_sequences: true,
+            ..ModelCapabilities::default()
+        }
+    }
+}
+
+// OpenAI chat completions request; the deployment in the URL selects the model
+fn chat_completions_body(request: &CompletionRequest) -> Value {
+    let params = &request.params;
+    // The "tool" role requires a tool_call_id we don't track, so results go back as user turns
+    let messages: Vec<Value> = request
+        .messages
+        .iter()
+        .map(|message| {
+            let role = match message.role {
+                Role::System => "system",
+                Role::Assistant => "assistant",
+                Role::User | Role::Tool => "user",
+            };
+            json!({"role": role, "content": openai_content(message)})
+        })
+        .collect();
+    let mut body = json!({ "messages": messages });
+    if let Some(temperature) = params.temperature {
+        body["temperature"] = json!(temperature);
+    }
+    if let Some(top_p) = params.top_p {
+        body["top_p"] = json!(top_p);
+    }
+    if let Some(max_tokens) = params.max_tokens {
+        body["max_tokens"] = json!(max_tokens);
+    }
+    if !params.stop.is_empty() {
+        body["stop"] = json!(params.stop);
+    }
+    if let Some(seed) = params.seed {
+        body["seed"] = json!(seed);
+    }
+    match &request.format {
+        Some(Value::String(format)) if format == "json" => {
+            body["response_format"] = json!({"type": "json_object"})
+        }
+        Some(schema @ Value::Object(_)) => {
+            body["response_format"] = json!({
+                "type": "json_schema",
+                "json_schema": {"name": "response", "schema": schema},
+            })
+        }
+        _ => {}
+    }
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model_client::ChatMessage;
+
+    #[test]
+    fn test_url_routes_to_deployment_with_api_version() {
+        let client = AzureOpenAiClient::new(
+            "https://contoso.openai.azure.com/",
+            "gpt-4o-prod",
+            AzureAuth::ApiKey("key".to_string()),
+        )
+        .with_api_version("2024-10-21");
+
+        assert_eq!(
+            client.url(),
+            "https://contoso.openai.azure.com/openai/deployments/gpt-4o-prod/chat/completions?api-version=2024-10-21"
+        );
+    }
+
+    #[test]
+    fn test_body_maps_tool_turns_and_json_format() {
+        let request = CompletionRequest {
+            messages: vec![ChatMessage::user("Hi"), ChatMessage::tool("{}")],
+            format: Some(json!("json")),
+            ..CompletionRequest::default()
+        };
+
+        assert_eq!(
+            chat_completions_body(&request),
+            json!({
+                "messages": [
+                    {"role": "user", "content": "Hi"},
+                    {"role": "user", "content": "{}"},
+                ],
+                "response_format": {"type": "json_object"},
+            })
+        );
+    }
+}
@@ -0,0 +1,471 @@
+use super::{ChatMessage, CompletionRequest, ModelCapabilities, ModelClient, ModelResponse, Role};
+use crate::error::{AgentError, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+const SERVICE: &str = "bedrock";
+const ANTHROPIC_VERSION: &str = "bedrock-2023-05-31";
+const DEFAULT_MAX_TOKENS: u32 = 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AwsCredentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub session_token: Option<String>,
+}
+
+impl AwsCredentials {
+    // The standard chain minus instance metadata: environment variables, then the
+    // shared credentials file for AWS_PROFILE (or "default")
+    pub fn resolve() -> Result<Self> {
+        if let (Ok(access_key_id), Ok(secret_access_key)) = (
+            std::env::var("AWS_ACCESS_KEY_ID"),
+            std::env::var("AWS_SECRET_ACCESS_KEY"),
+        ) {
+            return Ok(Self {
+                access_key_id,
+                secret_access_key,
+                session_token: std::env::var("AWS_SESSION_TOKEN").ok(),
+            });
+        }
+
+        let path = std::env::var_os("AWS_SHARED_CREDENTIALS_FILE")
+            .map(PathBuf::from)
+            .or_else(|| aws_dir().map(|dir| dir.join("credentials")));
+        let profile = profile_name();
+        let section = path
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .map(|contents| ini_section(&contents, &profile))
+            .unwrap_or_default();
+        match (
+            section.get("aws_access_key_id"),
+            section.get("aws_secret_access_key"),
+        ) {
+            (Some(access_key_id), Some(secret_access_key)) => Ok(Self {
+                access_key_id: access_key_id.clone(),
+                secret_access_key: secret_access_key.clone(),
+                session_token: section.get("aws_session_token").cloned(),
+            }),
+            _ => Err(AgentError::ModelError(format!(
+                "No AWS credentials in the environment or for profile '{}'",
+                profile
+            ))),
+        }
+    }
+}
+
+// AWS_REGION, AWS_DEFAULT_REGION, then the region of the profile in ~/.aws/config
+pub fn resolve_region() -> Result<String> {
+    if let Some(region) = ["AWS_REGION", "AWS_DEFAULT_REGION"]
+        .iter()
+        .find_map(|name| std::env::var(name).ok())
+    {
+        return Ok(region);
+    }
+    let path = std::env::var_os("AWS_CONFIG_FILE")
+        .map(PathBuf::from)
+        .or_else(|| aws_dir().map(|dir| dir.join("config")));
+    let profile = match profile_name().as_str() {
+        "default" => "default".to_string(),
+        name => format!("profile {}", name),
+    };
+    path.and_then(|path| std::fs::read_to_string(path).ok())
+        .and_then(|contents| ini_section(&contents, &profile).remove("region"))
+        .ok_or_else(|| AgentError::ModelError("No AWS region configured".to_string()))
+}
+
+fn profile_name() -> String {
+    std::env::var("AWS_PROFILE").unwrap_or_else(|_| "default".to_string())
+}
+
+fn aws_dir() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".aws"))
+}
+
+fn ini_section(contents: &str, name: &str) -> HashMap<String, String> {
+    let mut values = HashMap::new();
+    let mut in_section = false;
+    for line in contents.lines().map(str::trim) {
+        if let Some(header) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            in_section = header.trim() == name;
+        } else if let (true, Some((key, value))) = (in_section, line.split_once('=')) {
+            values.insert(key.trim().to_string(), value.trim().to_string());
+        }
+    }
+    values
+}
+
+pub struct BedrockClient {
+    model_id: String,
+    region: String,
+    credentials: AwsCredentials,
+}
+
+impl BedrockClient {
+    // Resolves region and credentials up front so misconfiguration fails at startup
+    pub fn new(model_id: &str) -> Result<Self> {
+        Ok(Self::with_credentials(
+            model_id,
+            &resolve_region()?,
+            AwsCredentials::resolve()?,
+        ))
+    }
+
+    pub fn with_credentials(model_id: &str, region: &str, credentials: AwsCredentials) -> Self {
+        Self {
+            model_id: model_id.to_string(),
+            region: region.to_string(),
+            credentials,
+        }
+    }
+
+    fn is_titan(&self) -> bool {
+        self.model_id.starts_with("amazon.titan")
+    }
+
+    fn body(&self, request: &CompletionRequest) -> Value {
+        let params = &request.params;
+        if self.is_titan() {
+            let mut config = json!({
+                "maxTokenCount": params.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+            });
+            if let Some(temperature) = params.temperature {
+                config["temperature"] = json!(temperature);
+            }
+            if let Some(top_p) = params.top_p {
+                config["topP"] = json!(top_p);
+            }
+            if !params.stop.is_empty() {
+                config["stopSequences"] = json!(params.stop);
+            }
+            return json!({
+                "inputText": titan_prompt(&request.messages),
+                "textGenerationConfig": config,
+            });
+        }
+
+        // Claude takes the system prompt separately and only user/assistant turns;
+        // tool results are fed back as user turns
+        let system: Vec<&str> = request
+            .messages
+            .iter()
+            .filter(|message| message.role == Role::System)
+            .map(|message| message.content.as_str())
+            .collect();
+        let messages: Vec<Value> = request
+            .messages
+            .iter()
+            .filter(|message| message.role != Role::System)
+            .map(|message| {
+                let role = match message.role {
+                    Role::Assistant => "assistant",
+                    _ => "user",
+                };
+                json!({"role": role, "content": claude_content(message)})
+            })
+            .collect();
+        let mut body = json!({
+            "anthropic_version": ANTHROPIC_VERSION,
+            "max_tokens": params.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+            "messages": messages,
+        });
+        if !system.is_empty() {
+            body["system"] = json!(system.join("\n\n"));
+        }
+        if let Some(temperature) = params.temperature {
+            body["temperature"] = json!(temperature);
+        }
+        if let Some(top_p) = params.top_p {
+            body["top_p"] = json!(top_p);
+        }
+        if !params.stop.is_empty() {
+            body["stop_sequences"] = json!(params.stop);
+        }
+        body
+    }
+}
+
+#[async_trait]
+impl ModelClient for BedrockClient {
+    async fn complete(&self, request: &CompletionRequest) -> Result<ModelResponse> {
+        let host = format!("bedrock-runtime.{}.amazonaws.com", self.region);
+        let path = format!("/model/{}/invoke", uri_encode(&self.model_id, false));
+        let payload = serde_json::to_vec(&self.body(request))?;
+        let now = Utc::now();
+        let timestamp = now.format("%Y%m%dT%H%M%SZ").to_string();
+
+        let mut headers = vec![
+            ("content-type", "application/json"),
+            ("host", host.as_str()),
+            ("x-amz-date", timestamp.as_str()),
+        ];
+        if let Some(token) = &self.credentials.session_token {
+            headers.push(("x-amz-security-token", token.as_str()));
+        }
+        let authorization = sign_request(
+            &self.credentials,
+            &self.region,
+            SERVICE,
+            "POST",
+            &path,
+            "",
+            &headers,
+            &payload,
+            now,
+        );
+
+        let mut builder = reqwest::Client::new()
+            .post(format!("https://{}{}", host, path))
+            .header("authorization", authorization)
+            .body(payload);
+        for (name, value) in headers.iter().filter(|(name, _)| *name != "host") {
+            builder = builder.header(*name, *value);
+        }
+        let response = builder.send().await?;
+        let status = response.status();
+        let response: Value = response.json().await?;
+        if !status.is_success() {
+            return Err(AgentError::ModelError(format!(
+                "Bedrock returned {}: {}",
+                status, response
+            )));
+        }
+
+        let text = if self.is_titan() {
+            response["results"][0]["outputText"]
+                .as_str()
+                .map(str::to_string)
+        } else {
+            response["content"].as_array().map(|blocks| {
+                blocks
+                    .iter()
+                    .filter_map(|block| block["text"].as_str())
+                    .collect()
+            })
+        };
+        let text = text.ok_or_else(|| {
+            AgentError::ModelError(format!("Unexpected Bedrock response: {}", response))
+        })?;
+        Ok(ModelResponse {
+            response: text,
+            reasoning: None,
+        })
+    }
+
+    fn capabilities(&self) -> ModelCapabilities {
+        ModelCapabilities {
+            vision: !self.is_titan(),
+            stop_sequences: true,
+            ..ModelCapabilities::default()
+        }
+    }
+}
+
+fn claude_content(message: &ChatMessage) -> Value {
+    if message.images.is_empty() {
+        return json!(message.content);
+    }
+    let mut blocks: Vec<Value> = message
+        .images
+        .iter()
+        .map(|image| {
+            json!({
+                "type": "image",
+                "source": {"type": "base64", "media_type": image.media_type, "data": image.data},
+            })
+        })
+        .collect();
+    blocks.push(json!({"type": "text", "text": message.content}));
+    Value::Array(blocks)
+}
+
+fn titan_prompt(messages: &[ChatMessage]) -> String {
+    let mut prompt = String::new();
+    for message in messages {
+        let speaker = match message.role {
+            Role::Assistant => "Bot",
+            _ => "User",
+        };
+        prompt.push_str(&format!("{}: {}\n", speaker, message.content));
+    }
+    prompt.push_str("Bot:");
+    prompt
+}
+
+// Percent-encodes everything but RFC 3986 unreserved characters (and '/' in paths)
+fn uri_encode(raw: &str, keep_slash: bool) -> String {
+    raw.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            b'/' if keep_slash => "/".to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn signing_key(secret: &str, date: &str, region: &str, service: &str) -> Vec<u8> {
+    let key = hmac_sha256(format!("AWS4{}", secret).as_bytes(), date);
+    let key = hmac_sha256(&key, region);
+    let key = hmac_sha256(&key, service);
+    hmac_sha256(&key, "aws4_request")
+}
+
+// SigV4 Authorization header. `headers` must include host and x-amz-date, all lowercase;
+// `path` is the already-encoded request path, which non-S3 services encode once more
+#[allow(clippy::too_many_arguments)]
+fn sign_request(
+    credentials: &AwsCredentials,
+    region: &str,
+    service: &str,
+    method: &str,
+    path: &str,
+    query: &str,
+    headers: &[(&str, &str)],
+    payload: &[u8],
+    now: DateTime<Utc>,
+) -> String {
+    let timestamp = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let date = now.format("%Y%m%d").to_string();
+
+    let mut headers = headers.to_vec();
+    headers.sort_by_key(|(name, _)| *name);
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+        .collect();
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(";");
+    let canonical_request = format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        method,
+        uri_encode(path, true),
+        query,
+        canonical_headers,
+        signed_headers,
+        hex(&Sha256::digest(payload))
+    );
+
+    let scope = format!("{}/{}/{}/aws4_request", date, region, service);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        timestamp,
+        scope,
+        hex(&Sha256::digest(canonical_request.as_bytes()))
+    );
+    let key = signing_key(&credentials.secret_access_key, &date, region, service);
+    format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+        credentials.access_key_id,
+        scope,
+        signed_headers,
+        hex(&hmac_sha256(&key, &string_to_sign))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    // The IAM ListUsers example from the AWS Signature Version 4 documentation
+    #[test]
+    fn test_sign_request_matches_aws_documentation_example() {
+        let credentials = AwsCredentials {
+            access_key_id: "AKIDEXAMPLE".to_string(),
+            secret_access_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_string(),
+            session_token: None,
+        };
+        let now = Utc.with_ymd_and_hms(2015, 8, 30, 12, 36, 0).unwrap();
+
+        let authorization = sign_request(
+            &credentials,
+            "us-east-1",
+            "iam",
+            "GET",
+            "/",
+            "Action=ListUsers&Version=2010-05-08",
+            &[
+                (
+                    "content-type",
+                    "application/x-www-form-urlencoded; charset=utf-8",
+                ),
+                ("host", "iam.amazonaws.com"),
+                ("x-amz-date", "20150830T123600Z"),
+            ],
+            b"",
+            now,
+        );
+
+        assert_eq!(
+            hex(&signing_key(
+                &credentials.secret_access_key,
+                "20150830",
+                "us-east-1",
+                "iam"
+            )),
+            "c4afb1cc5771d871763a393e44b703571b55cc28424d1a5e86da6ed3c154a4b9"
+        );
+        assert_eq!(
+            authorization,
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/iam/aws4_request, SignedHeaders=content-type;host;x-amz-date, Signature=5d672d79c15b13162d9279b0855cfba6789a8edb4c82c400e06b5924a6f2b5d7"
+        );
+    }
+
+    #[test]
+    fn test_claude_body_separates_system_prompt() {
+        let client = BedrockClient::with_credentials(
+            "anthropic.claude-3-haiku-20240307-v1:0",
+            "us-east-1",
+            AwsCredentials {
+                access_key_id: "id".to_string(),
+                secret_access_key: "secret".to_string(),
+                session_token: None,
+            },
+        );
+        let request = CompletionRequest::new(vec![
+            ChatMessage::system("Be terse"),
+            ChatMessage::user("Hi"),
+            ChatMessage::tool("{}"),
+        ]);
+
+        assert_eq!(
+            client.body(&request),
+            json!({
+                "anthropic_version": ANTHROPIC_VERSION,
+                "max_tokens": DEFAULT_MAX_TOKENS,
+                "system": "Be terse",
+                "messages": [
+                    {"role": "user", "content": "Hi"},
+                    {"role": "user", "content": "{}"},
+                ],
+            })
+        );
+        assert_eq!(
+            uri_encode(
+                &format!("/model/{}/invoke", uri_encode(&client.model_id, false)),
+                true
+            ),
+            "/model/anthropic.claude-3-haiku-20240307-v1%253A0/invoke"
+        );
+    }
+}
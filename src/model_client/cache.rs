@@ -0,0 +1,202 @@
+use super::{CompletionRequest, ModelCapabilities, ModelClient, ModelResponse};
+use crate::error::Result;
+use async_trait::async_trait;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+// Serves repeated completions from memory (and optionally disk) instead of the backend
+pub struct CachedModelClient {
+    inner: Box<dyn ModelClient>,
+    model: String,
+    memory: Mutex<LruCache>,
+    disk_dir: Option<PathBuf>,
+}
+
+impl CachedModelClient {
+    pub fn new(inner: Box<dyn ModelClient>, model: &str, capacity: usize) -> Self {
+        Self {
+            inner,
+            model: model.to_string(),
+            memory: Mutex::new(LruCache::new(capacity)),
+            disk_dir: None,
+        }
+    }
+
+    pub fn with_disk_cache(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.disk_dir = Some(dir.into());
+        self
+    }
+
+    // SHA-256 rather than a short hash: two requests sharing a key would get each other's reply
+    fn key(&self, request: &CompletionRequest) -> Result<String> {
+        let mut hasher = Sha256::new();
+        hasher.update(self.model.as_bytes());
+        // Separator so ("ab", "c") and ("a", "bc") differ
+        hasher.update([0]);
+        hasher.update(serde_json::to_string(request)?.as_bytes());
+        Ok(format!("{:x}", hasher.finalize()))
+    }
+
+    fn disk_path(&self, key: &str) -> Option<PathBuf> {
+        self.disk_dir
+            .as_ref()
+            .map(|dir| dir.join(format!("{}.json", key)))
+    }
+
+    fn read_disk(&self, key: &str) -> Option<ModelResponse> {
+        let content = std::fs::read_to_string(self.disk_path(key)?).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    fn write_disk(&self, key: &str, response: &ModelResponse) -> Result<()> {
+        if let Some(path) = self.disk_path(key) {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            std::fs::write(path, serde_json::to_string(response)?)?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ModelClient for CachedModelClient {
+    async fn complete(&self, request: &CompletionRequest) -> Result<ModelResponse> {
+        let key = self.key(request)?;
+
+        if let Some(response) = self.memory.lock().unwrap().get(&key) {
+            return Ok(response);
+        }
+        if let Some(response) = self.read_disk(&key) {
+            self.memory.lock().unwrap().put(key, response.clone());
+            return Ok(response);
+        }
+
+        let response = self.inner.complete(request).await?;
+        self.write_disk(&key, &response)?;
+        self.memory.lock().unwrap().put(key, response.clone());
+        Ok(response)
+    }
+
+    fn capabilities(&self) -> ModelCapabilities {
+        self.inner.capabilities()
+    }
+}
+
+struct LruCache {
+    capacity: usize,
+    entries: HashMap<String, ModelResponse>,
+    order: VecDeque<String>,
+}
+
+impl LruCache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    fn get(&mut self, key: &str) -> Option<ModelResponse> {
+        let response = self.entries.get(key)?.clone();
+        self.touch(key);
+        Some(response)
+    }
+
+    fn put(&mut self, key: String, response: ModelResponse) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.insert(key.clone(), response).is_none()
+            && self.entries.len() > self.capacity
+        {
+            if let Some(oldest) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.touch(&key);
+    }
+
+    fn touch(&mut self, key: &str) {
+        self.order.retain(|existing| existing != key);
+        self.order.push_back(key.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model_client::ChatMessage;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    struct CountingModel(Arc<AtomicUsize>);
+
+    #[async_trait]
+    impl ModelClient for CountingModel {
+        async fn complete(&self, request: &CompletionRequest) -> Result<ModelResponse> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(ModelResponse {
+                response: format!("echo: {}", request.messages[0].content),
+                reasoning: None,
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_repeated_prompt_is_served_from_memory() -> Result<()> {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let client = CachedModelClient::new(Box::new(CountingModel(calls.clone())), "qwen3", 8);
+
+        let hello = CompletionRequest::new(vec![ChatMessage::user("hello")]);
+        let first = client.complete(&hello).await?;
+        let second = client.complete(&hello).await?;
+        let mut seeded = hello.clone();
+        seeded.params.seed = Some(42);
+        client.complete(&seeded).await?;
+
+        assert_eq!(first, second);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_disk_cache_survives_new_client() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        CachedModelClient::new(Box::new(CountingModel(calls.clone())), "qwen3", 8)
+            .with_disk_cache(dir.path())
+            .complete(&CompletionRequest::new(vec![ChatMessage::user("hello")]))
+            .await?;
+        let response = CachedModelClient::new(Box::new(CountingModel(calls.clone())), "qwen3", 8)
+            .with_disk_cache(dir.path())
+            .complete(&CompletionRequest::new(vec![ChatMessage::user("hello")]))
+            .await?;
+
+        assert_eq!(response.response, "echo: hello");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_lru_evicts_least_recently_used() {
+        let mut cache = LruCache::new(2);
+        let response = |text: &str| ModelResponse {
+            response: text.to_string(),
+            reasoning: None,
+        };
+
+        cache.put("one".to_string(), response("one"));
+        cache.put("two".to_string(), response("two"));
+        cache.get("one");
+        cache.put("three".to_string(), response("three"));
+
+        assert!(cache.get("one").is_some());
+        assert!(cache.get("two").is_none());
+        assert!(cache.get("three").is_some());
+    }
+}
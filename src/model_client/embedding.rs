@@ -0,0 +1,128 @@
+use crate::error::{AgentError, Result};
+use async_trait::async_trait;
+use serde_json::{json, Value};
+
+#[async_trait]
+pub trait EmbeddingClient: Send + Sync {
+    // One vector per input text, in input order
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>>;
+
+    async fn embed_one(&self, text: &str) -> Result<Vec<f32>> {
+        self.embed(&[text.to_string()])
+            .await?
+            .pop()
+            .ok_or_else(|| AgentError::ModelError("No embedding returned".to_string()))
+    }
+}
+
+pub struct OllamaEmbeddingClient {
+    model: String,
+    base_url: String,
+    bearer_token: Option<String>,
+}
+
+impl OllamaEmbeddingClient {
+    pub fn new(model: String, base_url: &str, bearer_token: Option<String>) -> Self {
+        Self {
+            model,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            bearer_token,
+        }
+    }
+}
+
+#[async_trait]
+impl EmbeddingClient for OllamaEmbeddingClient {
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let mut builder = reqwest::Client::new()
+            .post(format!("{}/api/embed", self.base_url))
+            .json(&json!({
+                "model": self.model,
+                "input": texts
+            }));
+        if let Some(token) = &self.bearer_token {
+            builder = builder.bearer_auth(token);
+        }
+        let response: Value = builder.send().await?.json().await?;
+        parse_embeddings(&response["embeddings"], texts.len())
+    }
+}
+
+pub struct OpenAiEmbeddingClient {
+    api_key: String,
+    model: String,
+}
+
+impl OpenAiEmbeddingClient {
+    pub fn new(api_key: String, model: String) -> Self {
+        Self { api_key, model }
+    }
+}
+
+#[async_trait]
+impl EmbeddingClient for OpenAiEmbeddingClient {
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let response: Value = reqwest::Client::new()
+            .post("https://api.openai.com/v1/embeddings")
+            .bearer_auth(&self.api_key)
+            .json(&json!({
+                "model": self.model,
+                "input": texts
+            }))
+            .send()
+            .await?
+            .json()
+            .await?;
+        let embeddings: Vec<Value> = response["data"]
+            .as_array()
+            .map(|data| data.iter().map(|item| item["embedding"].clone()).collect())
+            .unwrap_or_default();
+        parse_embeddings(&Value::Array(embeddings), texts.len())
+    }
+}
+
+fn parse_embeddings(value: &Value, expected: usize) -> Result<Vec<Vec<f32>>> {
+    let embeddings = value
+        .as_array()
+        .ok_or_else(|| AgentError::ModelError("Missing embeddings in response".to_string()))?;
+    if embeddings.len() != expected {
+        return Err(AgentError::ModelError(format!(
+            "Expected {} embeddings, got {}",
+            expected,
+            embeddings.len()
+        )));
+    }
+    embeddings.iter().map(parse_embedding).collect()
+}
+
+fn parse_embedding(value: &Value) -> Result<Vec<f32>> {
+    value
+        .as_array()
+        .ok_or_else(|| AgentError::ModelError("Missing embedding in response".to_string()))?
+        .iter()
+        .map(|number| {
+            number
+                .as_f64()
+                .map(|number| number as f32)
+                .ok_or_else(|| AgentError::ParseError("Non-numeric embedding value".to_string()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_embeddings_checks_count_and_values() {
+        assert_eq!(
+            parse_embeddings(&json!([[0.5, 1], [2.0, -1.5]]), 2).unwrap(),
+            vec![vec![0.5, 1.0], vec![2.0, -1.5]]
+        );
+        assert!(parse_embeddings(&json!([[0.5]]), 2).is_err());
+        assert!(matches!(
+            parse_embeddings(&json!([["x"]]), 1),
+            Err(AgentError::ParseError(_))
+        ));
+    }
+}
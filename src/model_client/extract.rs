@@ -0,0 +1,132 @@
+use regex::Regex;
+use rmcp::model::JsonRpcRequest;
+use serde_json::Value;
+use std::sync::OnceLock;
+
+// Pulls the JSON a model meant to send out of surrounding prose, code fences and
+// reasoning: the first candidate that is a JSON-RPC tool call, else the first that
+// parses at all
+pub fn extract_json(text: &str) -> Option<String> {
+    let (text, _) = split_reasoning(text);
+    let candidates: Vec<&str> = fenced_blocks(&text)
+        .into_iter()
+        .chain(balanced_objects(&text))
+        .collect();
+    let parsed: Vec<(&str, Value)> = candidates
+        .iter()
+        .filter_map(|candidate| {
+            serde_json::from_str(candidate)
+                .ok()
+                .map(|value| (*candidate, value))
+        })
+        .collect();
+    parsed
+        .iter()
+        .find(|(_, value)| serde_json::from_value::<JsonRpcRequest>(value.clone()).is_ok())
+        .or_else(|| parsed.first())
+        .map(|(candidate, _)| candidate.to_string())
+}
+
+// Separates <think> blocks from the answer; an unclosed block runs to the end of the text
+pub fn split_reasoning(text: &str) -> (String, Option<String>) {
+    static THINK: OnceLock<Regex> = OnceLock::new();
+    let think = THINK.get_or_init(|| Regex::new(r"(?s)<think>(.*?)(?:</think>|$)").unwrap());
+    let reasoning: Vec<&str> = think
+        .captures_iter(text)
+        .filter_map(|captures| captures.get(1))
+        .map(|block| block.as_str().trim())
+        .filter(|block| !block.is_empty())
+        .collect();
+    let answer = think.replace_all(text, "").trim().to_string();
+    let reasoning = (!reasoning.is_empty()).then(|| reasoning.join("\n\n"));
+    (answer, reasoning)
+}
+
+fn fenced_blocks(text: &str) -> Vec<&str> {
+    static FENCE: OnceLock<Regex> = OnceLock::new();
+    let fence = FENCE.get_or_init(|| Regex::new(r"(?s)```(?:json)?[ \t]*\n(.*?)```").unwrap());
+    fence
+        .captures_iter(text)
+        .filter_map(|captures| captures.get(1))
+        .map(|block| block.as_str().trim())
+        .collect()
+}
+
+// Top-level {...} spans, matching braces outside of JSON strings
+fn balanced_objects(text: &str) -> Vec<&str> {
+    let mut objects = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (index, c) in text.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' if depth > 0 => in_string = true,
+            '{' => {
+                if depth == 0 {
+                    start = index;
+                }
+                depth += 1;
+            }
+            '}' if depth > 0 => {
+                depth -= 1;
+                if depth == 0 {
+                    objects.push(&text[start..=index]);
+                }
+            }
+            _ => {}
+        }
+    }
+    objects
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_picks_tool_call_over_other_objects() {
+        let text = r#"<think>Maybe {"draft": true}?</think>
+Here is an example: {"note": "use } carefully"}
+```json
+{"jsonrpc": "2.0", "id": 1, "method": "tools/call", "params": {"name": "write_file"}}
+```
+Done."#;
+
+        let extracted = extract_json(text).unwrap();
+
+        assert!(extracted.starts_with(r#"{"jsonrpc": "2.0""#));
+        assert!(extracted.ends_with(r#""write_file"}}"#));
+    }
+
+    #[test]
+    fn test_split_reasoning_keeps_think_text_apart() {
+        assert_eq!(
+            split_reasoning("<think>\nThree lines.\n</think>\n\n{\"a\": 1}"),
+            ("{\"a\": 1}".to_string(), Some("Three lines.".to_string()))
+        );
+        assert_eq!(
+            split_reasoning("<think></think>plain"),
+            ("plain".to_string(), None)
+        );
+    }
+
+    #[test]
+    fn test_falls_back_to_first_parsable_object() {
+        assert_eq!(
+            extract_json(r#"Answer: {"final_answer": "a {b}"} and {"x": 1}"#).as_deref(),
+            Some(r#"{"final_answer": "a {b}"}"#)
+        );
+        assert_eq!(extract_json("{not json}"), None);
+        assert_eq!(extract_json("<think>{\"a\": 1}"), None);
+    }
+}
@@ -0,0 +1,208 @@
+use super::extract::split_reasoning;
+use super::{
+    openai_content, ChatMessage, CompletionRequest, ModelCapabilities, ModelClient, ModelResponse,
+    Role,
+};
+use crate::error::{AgentError, Result};
+use async_trait::async_trait;
+use serde_json::{json, Value};
+
+pub const DEFAULT_LLAMA_CPP_URL: &str = "http://localhost:8080";
+
+// llama.cpp's grammars/json.gbnf: any JSON value, used when a request asks for "json"
+pub const JSON_GRAMMAR: &str = r#"root   ::= object
+value  ::= object | array | string | number | ("true" | "false" | "null") ws
+
+object ::=
+  "{" ws (
+            string ":" ws value
+    ("," ws string ":" ws value)*
+  )? "}" ws
+
+array  ::=
+  "[" ws (
+            value
+    ("," ws value)*
+  )? "]" ws
+
+string ::=
+  "\"" (
+    [^"\\\x7F\x00-\x1F] |
+    "\\" (["\\bfnrt] | "u" [0-9a-fA-F]{4})
+  )* "\"" ws
+
+number ::= ("-"? ([0-9] | [1-9] [0-9]{0,15})) ("." [0-9]+)? ([eE] [-+]? [0-9] [1-9]{0,15})? ws
+
+ws ::= | " " | "\n" [ \t]{0,20}
+"#;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LlamaCppEndpoint {
+    // /v1/chat/completions, using the model's own chat template
+    Chat,
+    // /completion with a ChatML prompt rendered here, for models served without a template
+    Completion,
+}
+
+pub struct LlamaCppClient {
+    base_url: String,
+    endpoint: LlamaCppEndpoint,
+    grammar: Option<String>,
+}
+
+impl LlamaCppClient {
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            endpoint: LlamaCppEndpoint::Chat,
+            grammar: None,
+        }
+    }
+
+    pub fn with_endpoint(mut self, endpoint: LlamaCppEndpoint) -> Self {
+        self.endpoint = endpoint;
+        self
+    }
+
+    // Constrain every completion with this GBNF grammar
+    pub fn with_grammar(mut self, grammar: impl Into<String>) -> Self {
+        self.grammar = Some(grammar.into());
+        self
+    }
+
+    fn body(&self, request: &CompletionRequest) -> Value {
+        let params = &request.params;
+        let mut body = match self.endpoint {
+            LlamaCppEndpoint::Chat => json!({
+                "messages": request
+                    .messages
+                    .iter()
+                    .map(|message| json!({"role": message.role, "content": openai_content(message)}))
+                    .collect::<Vec<_>>(),
+                "stream": false,
+            }),
+            LlamaCppEndpoint::Completion => json!({
+                "prompt": chatml_prompt(&request.messages),
+                "stream": false,
+            }),
+        };
+        if let Some(temperature) = params.temperature {
+            body["temperature"] = json!(temperature);
+        }
+        if let Some(top_p) = params.top_p {
+            body["top_p"] = json!(top_p);
+        }
+        if let Some(max_tokens) = params.max_tokens {
+            let key = match self.endpoint {
+                LlamaCppEndpoint::Chat => "max_tokens",
+                LlamaCppEndpoint::Completion => "n_predict",
+            };
+            body[key] = json!(max_tokens);
+        }
+        if !params.stop.is_empty() {
+            body["stop"] = json!(params.stop);
+        }
+        if let Some(seed) = params.seed {
+            body["seed"] = json!(seed);
+        }
+        // An explicit grammar wins; otherwise "json" maps to the JSON grammar and
+        // a schema object is handed to llama.cpp to convert itself
+        match (&self.grammar, &request.format) {
+            (Some(grammar), _) => body["grammar"] = json!(grammar),
+            (None, Some(Value::String(format))) if format == "json" => {
+                body["grammar"] = json!(JSON_GRAMMAR)
+            }
+            (None, Some(schema @ Value::Object(_))) => body["json_schema"] = schema.clone(),
+            _ => {}
+        }
+        body
+    }
+}
+
+#[async_trait]
+impl ModelClient for LlamaCppClient {
+    async fn complete(&self, request: &CompletionRequest) -> Result<ModelResponse> {
+        let path = match self.endpoint {
+            LlamaCppEndpoint::Chat => "/v1/chat/completions",
+            LlamaCppEndpoint::Completion => "/completion",
+        };
+        let response: Value = reqwest::Client::new()
+            .post(format!("{}{}", self.base_url, path))
+            .json(&self.body(request))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let text = match self.endpoint {
+            LlamaCppEndpoint::Chat => response["choices"][0]["message"]["content"].as_str(),
+            LlamaCppEndpoint::Completion => response["content"].as_str(),
+        };
+        let text = text.ok_or_else(|| {
+            AgentError::ModelError(format!("Unexpected llama.cpp response: {}", response))
+        })?;
+        let (response, reasoning) = split_reasoning(text);
+        Ok(ModelResponse {
+            response,
+            reasoning,
+        })
+    }
+
+    fn capabilities(&self) -> ModelCapabilities {
+        ModelCapabilities {
+            streaming: true,
+            json_schema: true,
+            stop_sequences: true,
+            ..ModelCapabilities::default()
+        }
+    }
+}
+
+fn chatml_prompt(messages: &[ChatMessage]) -> String {
+    let mut prompt = String::new();
+    for message in messages {
+        let role = match message.role {
+            Role::System => "system",
+            Role::User => "user",
+            Role::Assistant => "assistant",
+            Role::Tool => "tool",
+        };
+        prompt.push_str(&format!(
+            "<|im_start|>{}\n{}<|im_end|>\n",
+            role, message.content
+        ));
+    }
+    prompt.push_str("<|im_start|>assistant\n");
+    prompt
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model_client::GenerationParams;
+
+    #[test]
+    fn test_json_format_constrains_completion_with_grammar() {
+        let client = LlamaCppClient::new("http://localhost:8080/")
+            .with_endpoint(LlamaCppEndpoint::Completion);
+        let request = CompletionRequest {
+            messages: vec![ChatMessage::system("Be terse"), ChatMessage::user("Hi")],
+            params: GenerationParams {
+                max_tokens: Some(16),
+                ..GenerationParams::default()
+            },
+            format: Some(json!("json")),
+        };
+
+        let body = client.body(&request);
+
+        assert_eq!(client.base_url, "http://localhost:8080");
+        assert_eq!(body["n_predict"], 16);
+        assert_eq!(body["grammar"], JSON_GRAMMAR);
+        assert_eq!(
+            body["prompt"],
+            "<|im_start|>system\nBe terse<|im_end|>\n<|im_start|>user\nHi<|im_end|>\n<|im_start|>assistant\n"
+        );
+    }
+}
@@ -1,74 +1,471 @@
-use anyhow::Result;
+pub mod azure_openai;
+pub mod bedrock;
+pub mod cache;
+pub mod embedding;
+pub mod extract;
+pub mod llama_cpp;
+pub mod probe;
+pub mod rate_limit;
+pub mod repair;
+pub mod routing;
+pub mod stop;
+pub mod streaming;
+pub mod transform;
+pub mod usage;
+
+use crate::error::{AgentError, Result};
+use crate::redaction::Redactor;
 use async_trait::async_trait;
-use serde::Deserialize;
-use serde_json::json;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::path::Path;
+use std::time::Duration;
+use streaming::ReplyStream;
+use transform::ResponsePipeline;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    System,
+    User,
+    Assistant,
+    Tool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ImagePart {
+    pub media_type: String,
+    // Base64-encoded image bytes
+    pub data: String,
+}
+
+impl ImagePart {
+    pub fn from_base64(media_type: impl Into<String>, data: impl Into<String>) -> Self {
+        Self {
+            media_type: media_type.into(),
+            data: data.into(),
+        }
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_ascii_lowercase);
+        let media_type = match extension.as_deref() {
+            Some("png") => "image/png",
+            Some("jpg" | "jpeg") => "image/jpeg",
+            Some("gif") => "image/gif",
+            Some("webp") => "image/webp",
+            _ => {
+                return Err(AgentError::ParseError(format!(
+                    "Unsupported image type: {}",
+                    path.display()
+                )))
+            }
+        };
+        Ok(Self::from_base64(
+            media_type,
+            STANDARD.encode(std::fs::read(path)?),
+        ))
+    }
+
+    pub fn data_url(&self) -> String {
+        format!("data:{};base64,{}", self.media_type, self.data)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ChatMessage {
+    pub role: Role,
+    pub content: String,
+    // Only vision-capable backends see these; the rest get the text alone
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<ImagePart>,
+}
+
+impl ChatMessage {
+    pub fn new(role: Role, content: impl Into<String>) -> Self {
+        Self {
+            role,
+            content: content.into(),
+            images: Vec::new(),
+        }
+    }
 
-#[derive(Debug, Deserialize, PartialEq)]
+    pub fn with_image(mut self, image: ImagePart) -> Self {
+        self.images.push(image);
+        self
+    }
+
+    pub fn system(content: impl Into<String>) -> Self {
+        Self::new(Role::System, content)
+    }
+
+    pub fn user(content: impl Into<String>) -> Self {
+        Self::new(Role::User, content)
+    }
+
+    pub fn assistant(content: impl Into<String>) -> Self {
+        Self::new(Role::Assistant, content)
+    }
+
+    pub fn tool(content: impl Into<String>) -> Self {
+        Self::new(Role::Tool, content)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct GenerationParams {
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub max_tokens: Option<u32>,
+    pub stop: Vec<String>,
+    pub seed: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct CompletionRequest {
+    pub messages: Vec<ChatMessage>,
+    pub params: GenerationParams,
+    // Constrain output to JSON ("json") or a JSON schema, if the backend supports it
+    pub format: Option<Value>,
+}
+
+impl CompletionRequest {
+    pub fn new(messages: Vec<ChatMessage>) -> Self {
+        Self {
+            messages,
+            ..Self::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct ModelResponse {
     pub response: String,
+    // Text the model reasoned in before answering (Qwen3's <think> blocks), kept for debugging
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ModelCapabilities {
+    pub streaming: bool,
+    pub tools: bool,
+    pub json_schema: bool,
+    pub vision: bool,
+    pub logprobs: bool,
+    // Honours GenerationParams::stop
+    pub stop_sequences: bool,
+    pub max_context: Option<usize>,
 }
 
 #[async_trait]
-pub trait ModelClient {
-    async fn complete(&self, prompt: &str) -> Result<ModelResponse>;
+pub trait ModelClient: Send + Sync {
+    async fn complete(&self, request: &CompletionRequest) -> Result<ModelResponse>;
+
+    // Backends that don't override this are assumed to support nothing beyond plain completion
+    fn capabilities(&self) -> ModelCapabilities {
+        ModelCapabilities::default()
+    }
+}
+
+// Lets several agents share one backend, e.g. an orchestrator and its sub-agents
+#[async_trait]
+impl<T: ModelClient + ?Sized> ModelClient for std::sync::Arc<T> {
+    async fn complete(&self, request: &CompletionRequest) -> Result<ModelResponse> {
+        (**self).complete(request).await
+    }
+
+    fn capabilities(&self) -> ModelCapabilities {
+        (**self).capabilities()
+    }
+}
+
+// Rough token estimate (about four characters per token) for context budgeting
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+pub fn estimate_message_tokens(messages: &[ChatMessage]) -> usize {
+    messages
+        .iter()
+        .map(|message| estimate_tokens(&message.content))
+        .sum()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpOptions {
+    pub connect_timeout: Duration,
+    // None waits as long as the model takes to answer
+    pub request_timeout: Option<Duration>,
+    // How long idle pooled connections are kept open for the next call
+    pub pool_idle_timeout: Duration,
+    pub tcp_keepalive: Option<Duration>,
+}
+
+impl Default for HttpOptions {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Duration::from_secs(5),
+            request_timeout: None,
+            pool_idle_timeout: Duration::from_secs(90),
+            tcp_keepalive: Some(Duration::from_secs(60)),
+        }
+    }
 }
 
+impl HttpOptions {
+    pub fn client(&self) -> Result<reqwest::Client> {
+        let mut builder = reqwest::Client::builder()
+            .connect_timeout(self.connect_timeout)
+            .pool_idle_timeout(self.pool_idle_timeout)
+            .tcp_keepalive(self.tcp_keepalive);
+        if let Some(timeout) = self.request_timeout {
+            builder = builder.timeout(timeout);
+        }
+        Ok(builder.build()?)
+    }
+}
+
+pub const DEFAULT_OLLAMA_URL: &str = "http://localhost:11434";
+
 pub struct LocalOllamaClient {
     model: String,
+    base_url: String,
+    // For Ollama behind an authenticating reverse proxy
+    bearer_token: Option<String>,
+    // Shared so consecutive turns reuse pooled connections
+    http: reqwest::Client,
+    keep_alive: Option<Duration>,
+    pipeline: ResponsePipeline,
+    streaming: bool,
+    // Masks secrets in the requests and responses it logs
+    redactor: Redactor,
+    // Whether the model takes images, as Ollama reports it
+    vision: bool,
 }
 
 impl LocalOllamaClient {
-    pub fn new(model: String) -> Self {
-        Self { model }
+    pub fn new(model: String, base_url: &str, bearer_token: Option<String>) -> Self {
+        Self {
+            model,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            bearer_token,
+            http: HttpOptions::default()
+                .client()
+                .expect("default HTTP options are valid"),
+            keep_alive: None,
+            pipeline: ResponsePipeline::standard(),
+            streaming: false,
+            redactor: Redactor::new(),
+            vision: false,
+        }
+    }
+
+    // What the model's text goes through before the agent parses it; the standard pipeline
+    // splits off reasoning and extracts the JSON
+    pub fn with_pipeline(mut self, pipeline: ResponsePipeline) -> Self {
+        self.pipeline = pipeline;
+        self
+    }
+
+    pub fn with_http_options(mut self, options: &HttpOptions) -> Result<Self> {
+        self.http = options.client()?;
+        Ok(self)
+    }
+
+    // How long Ollama keeps the model loaded after a call, so tight loops don't reload it
+    pub fn with_keep_alive(mut self, keep_alive: Duration) -> Self {
+        self.keep_alive = Some(keep_alive);
+        self
+    }
+
+    // Stream the reply and stop generating once it holds a complete tool call, instead of
+    // waiting for whatever the model writes after it
+    pub fn with_streaming(mut self, streaming: bool) -> Self {
+        self.streaming = streaming;
+        self
+    }
+
+    pub fn with_redactor(mut self, redactor: Redactor) -> Self {
+        self.redactor = redactor;
+        self
+    }
+
+    // Asks Ollama what the model can do. Until asked, or when Ollama can't say, the model is
+    // taken to be text-only
+    pub async fn with_detected_capabilities(mut self) -> Self {
+        match self.model_capabilities().await {
+            Ok(capabilities) => self.vision = capabilities.iter().any(|name| name == "vision"),
+            Err(err) => tracing::warn!("Could not ask Ollama about {}: {}", self.model, err),
+        }
+        self
+    }
+
+    async fn model_capabilities(&self) -> Result<Vec<String>> {
+        let mut builder = self
+            .http
+            .post(format!("{}/api/show", self.base_url))
+            .json(&json!({"model": self.model}));
+        if let Some(token) = &self.bearer_token {
+            builder = builder.bearer_auth(token);
+        }
+        let mut shown: Value = builder.send().await?.error_for_status()?.json().await?;
+        // Older Ollama versions don't list capabilities
+        Ok(serde_json::from_value(shown["capabilities"].take()).unwrap_or_default())
+    }
+
+    fn chat_body(&self, request: &CompletionRequest) -> Value {
+        let mut body = json!({
+            "model": self.model,
+            "messages": ollama_messages(&request.messages),
+            "stream": self.streaming,
+            "options": ollama_options(&request.params)
+        });
+        if let Some(format) = &request.format {
+            body["format"] = format.clone();
+        }
+        if let Some(keep_alive) = self.keep_alive {
+            body["keep_alive"] = json!(format!("{}s", keep_alive.as_secs()));
+        }
+        body
     }
 }
 
 #[async_trait]
 impl ModelClient for LocalOllamaClient {
-    async fn complete(&self, prompt: &str) -> Result<ModelResponse> {
-        let client = reqwest::Client::new();
+    async fn complete(&self, request: &CompletionRequest) -> Result<ModelResponse> {
+        tracing::debug!(
+            "Sending messages to model: {}",
+            self.redactor.redact(&format!("{:?}", request.messages))
+        );
 
-        eprintln!("Sending prompt to model: {}", prompt);
+        let mut builder = self
+            .http
+            .post(format!("{}/api/chat", self.base_url))
+            .json(&self.chat_body(request));
+        if let Some(token) = &self.bearer_token {
+            builder = builder.bearer_auth(token);
+        }
+        let response = checked(builder.send().await?).await?;
+        let raw_response = if self.streaming {
+            read_stream(response).await?
+        } else {
+            let response = response.text().await?;
 
-        let response = client
-            .post("http://localhost:11434/api/generate")
-            .json(&json!({
-                "model": self.model,
-                "prompt": prompt,
-                "stream": false  // Disable streaming for simplicity
-            }))
-            .send()
-            .await?
-            .text()
-            .await?;
-
-        eprintln!("Raw model response: {}", response);
-
-        // Parse the Ollama response
-        let ollama_response: serde_json::Value = serde_json::from_str(&response)?;
-        let raw_response = ollama_response["response"]
-            .as_str()
-            .ok_or_else(|| anyhow::anyhow!("Missing response field"))?;
-
-        eprintln!("Model text response: {}", raw_response);
-
-        // Extract JSON part from the response
-        if let Some(json_start) = raw_response.find('{') {
-            if let Some(json_end) = raw_response.rfind('}') {
-                let json_str = &raw_response[json_start..=json_end];
-                eprintln!("Extracted JSON: {}", json_str);
-                return Ok(ModelResponse {
-                    response: json_str.to_string(),
-                });
-            }
+            tracing::debug!("Raw model response: {}", self.redactor.redact(&response));
+
+            // Parse the Ollama response
+            let ollama_response: serde_json::Value = serde_json::from_str(&response)?;
+            ollama_response["message"]["content"]
+                .as_str()
+                .ok_or_else(|| AgentError::ModelError("Missing message content".to_string()))?
+                .to_string()
+        };
+
+        tracing::debug!(
+            "Model text response: {}",
+            self.redactor.redact(&raw_response)
+        );
+
+        self.pipeline.apply(ModelResponse {
+            response: raw_response,
+            reasoning: None,
+        })
+    }
+
+    fn capabilities(&self) -> ModelCapabilities {
+        ModelCapabilities {
+            streaming: true,
+            tools: true,
+            json_schema: true,
+            vision: self.vision,
+            logprobs: false,
+            stop_sequences: true,
+            max_context: None,
+        }
+    }
+}
+
+// Ollama explains a failed call (unknown model, bad request) in the body, so keep it in the error
+async fn checked(response: reqwest::Response) -> Result<reqwest::Response> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body = response.text().await.unwrap_or_default();
+    Err(AgentError::ModelError(format!(
+        "Ollama returned {}: {}",
+        status, body
+    )))
+}
+
+// Dropping the response closes the connection, which makes Ollama stop generating
+async fn read_stream(mut response: reqwest::Response) -> Result<String> {
+    let mut stream = ReplyStream::new();
+    while let Some(bytes) = response.chunk().await? {
+        if let Some(reply) = stream.push(&bytes)? {
+            return Ok(reply);
         }
+    }
+    stream.finish()
+}
 
-        // If no JSON found, return the raw response
-        eprintln!("No JSON found in response");
-        Ok(ModelResponse {
-            response: raw_response.to_string(),
+// Ollama takes images as a list of bare base64 strings next to the text
+fn ollama_messages(messages: &[ChatMessage]) -> Vec<Value> {
+    messages
+        .iter()
+        .map(|message| {
+            let mut value = json!({"role": message.role, "content": message.content});
+            if !message.images.is_empty() {
+                let images: Vec<&str> = message
+                    .images
+                    .iter()
+                    .map(|image| image.data.as_str())
+                    .collect();
+                value["images"] = json!(images);
+            }
+            value
         })
+        .collect()
+}
+
+// OpenAI-style content: a plain string, or text and image_url parts when images are attached
+pub(crate) fn openai_content(message: &ChatMessage) -> Value {
+    if message.images.is_empty() {
+        return json!(message.content);
+    }
+    let mut parts = vec![json!({"type": "text", "text": message.content})];
+    for image in &message.images {
+        parts.push(json!({"type": "image_url", "image_url": {"url": image.data_url()}}));
+    }
+    Value::Array(parts)
+}
+
+// Ollama takes generation parameters under "options", with its own names
+fn ollama_options(params: &GenerationParams) -> Value {
+    let mut options = serde_json::Map::new();
+    if let Some(temperature) = params.temperature {
+        options.insert("temperature".to_string(), json!(temperature));
     }
+    if let Some(top_p) = params.top_p {
+        options.insert("top_p".to_string(), json!(top_p));
+    }
+    if let Some(max_tokens) = params.max_tokens {
+        options.insert("num_predict".to_string(), json!(max_tokens));
+    }
+    if !params.stop.is_empty() {
+        options.insert("stop".to_string(), json!(params.stop));
+    }
+    if let Some(seed) = params.seed {
+        options.insert("seed".to_string(), json!(seed));
+    }
+    Value::Object(options)
 }
 
 #[cfg(test)]
@@ -77,9 +474,172 @@ mod tests {
 
     #[tokio::test]
     async fn test_local_model_responds_to_prompt() {
-        let client = LocalOllamaClient::new("qwen3".to_string());
-        let response = client.complete("Say hello").await.unwrap();
+        let client = LocalOllamaClient::new("qwen3".to_string(), DEFAULT_OLLAMA_URL, None);
+        let response = client
+            .complete(&CompletionRequest::new(vec![ChatMessage::user(
+                "Say hello",
+            )]))
+            .await
+            .unwrap();
         assert!(!response.response.is_empty());
     }
-}
 
+    #[tokio::test]
+    async fn test_failed_chat_call_reports_ollama_error() {
+        let app = axum::Router::new().route(
+            "/api/chat",
+            axum::routing::post(|| async {
+                (
+                    axum::http::StatusCode::NOT_FOUND,
+                    r#"{"error":"model 'missing' not found"}"#,
+                )
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        for streaming in [false, true] {
+            let client =
+                LocalOllamaClient::new("missing".to_string(), &url, None).with_streaming(streaming);
+            let err = client
+                .complete(&CompletionRequest::new(vec![ChatMessage::user("Hi")]))
+                .await
+                .unwrap_err();
+            assert!(err.to_string().contains("404"));
+            assert!(err.to_string().contains("model 'missing' not found"));
+        }
+    }
+
+    #[test]
+    fn test_ollama_options_only_include_set_params() {
+        let params = GenerationParams {
+            temperature: Some(0.5),
+            max_tokens: Some(128),
+            stop: vec!["}".to_string()],
+            ..GenerationParams::default()
+        };
+
+        assert_eq!(
+            ollama_options(&params),
+            json!({"temperature": 0.5, "num_predict": 128, "stop": ["}"]})
+        );
+    }
+
+    #[test]
+    fn test_images_are_attached_per_backend_format() {
+        let message = ChatMessage::user("What is this?")
+            .with_image(ImagePart::from_base64("image/png", "iVBO"));
+
+        assert_eq!(
+            ollama_messages(std::slice::from_ref(&message)),
+            vec![json!({"role": "user", "content": "What is this?", "images": ["iVBO"]})]
+        );
+        assert_eq!(
+            openai_content(&message),
+            json!([
+                {"type": "text", "text": "What is this?"},
+                {"type": "image_url", "image_url": {"url": "data:image/png;base64,iVBO"}},
+            ])
+        );
+        assert_eq!(openai_content(&ChatMessage::user("Hi")), json!("Hi"));
+    }
+
+    #[test]
+    fn test_chat_body_keeps_model_loaded_when_configured() {
+        let request = CompletionRequest::new(vec![ChatMessage::user("Hi")]);
+        let client = LocalOllamaClient::new("qwen3".to_string(), DEFAULT_OLLAMA_URL, None);
+        assert!(client.chat_body(&request).get("keep_alive").is_none());
+
+        let client = client
+            .with_http_options(&HttpOptions {
+                request_timeout: Some(Duration::from_secs(120)),
+                ..HttpOptions::default()
+            })
+            .unwrap()
+            .with_keep_alive(Duration::from_secs(600));
+        assert_eq!(client.chat_body(&request)["keep_alive"], "600s");
+    }
+
+    #[tokio::test]
+    async fn test_remote_ollama_gets_base_url_and_bearer_token() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = vec![0; 4096];
+            let read = socket.read(&mut request).await.unwrap();
+            let body = r#"{"message":{"content":"hello"}}"#;
+            socket
+                .write_all(
+                    format!(
+                        "HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n{}",
+                        body.len(),
+                        body
+                    )
+                    .as_bytes(),
+                )
+                .await
+                .unwrap();
+            String::from_utf8_lossy(&request[..read]).to_lowercase()
+        });
+
+        let client = LocalOllamaClient::new("qwen3".to_string(), &url, Some("secret".to_string()));
+        let response = client
+            .complete(&CompletionRequest::new(vec![ChatMessage::user("Hi")]))
+            .await
+            .unwrap();
+
+        let request = server.await.unwrap();
+        assert!(request.starts_with("post /api/chat "));
+        assert!(request.contains("authorization: bearer secret"));
+        assert_eq!(response.response, "hello");
+    }
+
+    #[tokio::test]
+    async fn test_vision_is_detected_from_the_models_capabilities() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            for body in [
+                r#"{"capabilities":["completion","vision"]}"#,
+                r#"{"capabilities":["completion"]}"#,
+            ] {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = vec![0; 4096];
+                assert!(socket.read(&mut request).await.unwrap() > 0);
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        let llava = LocalOllamaClient::new("llava".to_string(), &url, None)
+            .with_detected_capabilities()
+            .await;
+        let qwen = LocalOllamaClient::new("qwen3".to_string(), &url, None)
+            .with_detected_capabilities()
+            .await;
+        let unreachable = LocalOllamaClient::new("llava".to_string(), "http://127.0.0.1:1", None)
+            .with_detected_capabilities()
+            .await;
+
+        assert!(llava.capabilities().vision);
+        assert!(!qwen.capabilities().vision);
+        assert!(!unreachable.capabilities().vision);
+    }
+
+    #[test]
+    fn test_estimate_tokens_rounds_up() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("abc"), 1);
+        assert_eq!(estimate_tokens("abcdefgh"), 2);
+    }
+}
@@ -0,0 +1,90 @@
+use super::extract::extract_json;
+use super::usage::Usage;
+use super::{estimate_message_tokens, estimate_tokens, CompletionRequest, ModelClient};
+use crate::error::Result;
+use std::time::{Duration, Instant};
+
+// One completion outside the agent loop, for checking that a backend is set up right
+#[derive(Debug, Clone, PartialEq)]
+pub struct Probe {
+    pub response: String,
+    pub reasoning: Option<String>,
+    // The JSON the agent would take from the response, if any
+    pub extracted: Option<String>,
+    pub latency: Duration,
+    pub usage: Usage,
+}
+
+impl Probe {
+    pub async fn run(model: &dyn ModelClient, request: &CompletionRequest) -> Result<Self> {
+        let started = Instant::now();
+        let response = model.complete(request).await?;
+        let latency = started.elapsed();
+        Ok(Self {
+            extracted: extract_json(&response.response),
+            usage: Usage {
+                requests: 1,
+                prompt_tokens: estimate_message_tokens(&request.messages),
+                completion_tokens: estimate_tokens(&response.response),
+            },
+            response: response.response,
+            reasoning: response.reasoning,
+            latency,
+        })
+    }
+
+    pub fn report(&self) -> String {
+        let mut report = String::new();
+        if let Some(reasoning) = &self.reasoning {
+            report.push_str(&format!("Reasoning:\n{}\n\n", reasoning));
+        }
+        report.push_str(&format!("Response:\n{}\n\n", self.response));
+        report.push_str(&format!(
+            "Extracted JSON: {}\n",
+            self.extracted.as_deref().unwrap_or("none")
+        ));
+        report.push_str(&format!(
+            "Latency: {}ms\nTokens: ~{} prompt, ~{} completion",
+            self.latency.as_millis(),
+            self.usage.prompt_tokens,
+            self.usage.completion_tokens
+        ));
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model_client::{ChatMessage, ModelResponse};
+    use crate::replay::{Fixture, Interaction, ModelExchange, ReplayModelClient};
+
+    #[tokio::test]
+    async fn test_probe_reports_response_extracted_json_and_usage() -> Result<()> {
+        let fixture = Fixture {
+            interactions: vec![Interaction::Model(ModelExchange {
+                request: CompletionRequest::default(),
+                response: ModelResponse {
+                    response: "Sure:\n```json\n{\"season\": \"autumn\"}\n```".to_string(),
+                    reasoning: Some("Haiku need a season word".to_string()),
+                },
+            })],
+        };
+        let model = ReplayModelClient::new(&fixture);
+        let request = CompletionRequest::new(vec![ChatMessage::user("Name a season as JSON")]);
+
+        let probe = Probe::run(&model, &request).await?;
+
+        assert_eq!(probe.extracted.as_deref(), Some("{\"season\": \"autumn\"}"));
+        assert_eq!(probe.usage.requests, 1);
+        assert_eq!(
+            probe.usage.completion_tokens,
+            estimate_tokens(&probe.response)
+        );
+        let report = probe.report();
+        assert!(report.starts_with("Reasoning:\nHaiku need a season word\n\nResponse:\nSure:"));
+        assert!(report.contains("Extracted JSON: {\"season\": \"autumn\"}"));
+        assert!(report.contains("Latency: "));
+        Ok(())
+    }
+}
@@ -0,0 +1,148 @@
+use super::{
+    estimate_message_tokens, CompletionRequest, ModelCapabilities, ModelClient, ModelResponse,
+};
+use crate::clock::{system_clock, Clock};
+use crate::error::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RateLimits {
+    pub requests_per_minute: Option<u32>,
+    // Counted as estimated prompt tokens plus max_tokens, since usage isn't reported back
+    pub tokens_per_minute: Option<u32>,
+}
+
+// Refills continuously up to one minute's allowance; callers reserve ahead and
+// wait out any debt, so concurrent callers queue in the order they arrived
+#[derive(Debug)]
+pub struct TokenBucket {
+    capacity: f64,
+    available: f64,
+    per_second: f64,
+    // None until the first reservation; the bucket starts full
+    updated: Option<DateTime<Utc>>,
+}
+
+impl TokenBucket {
+    pub fn per_minute(limit: u32) -> Self {
+        let capacity = f64::from(limit.max(1));
+        Self {
+            capacity,
+            available: capacity,
+            per_second: capacity / 60.0,
+            updated: None,
+        }
+    }
+
+    // Takes `amount` at `now` and returns how long to wait before using it
+    pub fn reserve(&mut self, amount: f64, now: DateTime<Utc>) -> Duration {
+        if let Some(updated) = self.updated {
+            let elapsed = (now - updated).to_std().unwrap_or_default().as_secs_f64();
+            self.available = (self.available + elapsed * self.per_second).min(self.capacity);
+        }
+        self.updated = Some(now);
+        self.available -= amount;
+        if self.available >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.available / self.per_second)
+        }
+    }
+}
+
+pub struct RateLimitedModelClient {
+    inner: Box<dyn ModelClient>,
+    requests: Option<Mutex<TokenBucket>>,
+    tokens: Option<Mutex<TokenBucket>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl RateLimitedModelClient {
+    pub fn new(inner: Box<dyn ModelClient>, limits: RateLimits) -> Self {
+        Self {
+            inner,
+            requests: limits
+                .requests_per_minute
+                .map(|limit| Mutex::new(TokenBucket::per_minute(limit))),
+            tokens: limits
+                .tokens_per_minute
+                .map(|limit| Mutex::new(TokenBucket::per_minute(limit))),
+            clock: system_clock(),
+        }
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+}
+
+#[async_trait]
+impl ModelClient for RateLimitedModelClient {
+    async fn complete(&self, request: &CompletionRequest) -> Result<ModelResponse> {
+        let tokens = estimate_message_tokens(&request.messages)
+            + request.params.max_tokens.unwrap_or(0) as usize;
+        let now = self.clock.now();
+        let request_wait = self.requests.as_ref().map_or(Duration::ZERO, |bucket| {
+            bucket.lock().unwrap().reserve(1.0, now)
+        });
+        let token_wait = self.tokens.as_ref().map_or(Duration::ZERO, |bucket| {
+            bucket.lock().unwrap().reserve(tokens as f64, now)
+        });
+        let wait = request_wait.max(token_wait);
+        if !wait.is_zero() {
+            tracing::info!("Rate limited, waiting {:.1}s", wait.as_secs_f64());
+            self.clock.sleep(wait).await;
+        }
+        self.inner.complete(request).await
+    }
+
+    fn capabilities(&self) -> ModelCapabilities {
+        self.inner.capabilities()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::model_client::ChatMessage;
+
+    struct Echo;
+
+    #[async_trait]
+    impl ModelClient for Echo {
+        async fn complete(&self, request: &CompletionRequest) -> Result<ModelResponse> {
+            Ok(ModelResponse {
+                response: request.messages[0].content.clone(),
+                reasoning: None,
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_requests_beyond_the_limit_wait_for_refill() -> Result<()> {
+        let start = Utc::now();
+        let clock = ManualClock::new(start);
+        let client = RateLimitedModelClient::new(
+            Box::new(Echo),
+            RateLimits {
+                requests_per_minute: Some(2),
+                tokens_per_minute: None,
+            },
+        )
+        .with_clock(Arc::new(clock.clone()));
+        let request = CompletionRequest::new(vec![ChatMessage::user("hi")]);
+
+        client.complete(&request).await?;
+        client.complete(&request).await?;
+        assert_eq!(clock.now(), start);
+
+        client.complete(&request).await?;
+        assert_eq!((clock.now() - start).num_seconds(), 30);
+        Ok(())
+    }
+}
@@ -0,0 +1,144 @@
+use serde_json::Value;
+
+// Almost-valid JSON made valid, with what it took
+#[derive(Debug, Clone, PartialEq)]
+pub struct Repair {
+    pub json: String,
+    pub fixes: Vec<String>,
+}
+
+// Fixes the mistakes small models make when writing JSON by hand: trailing commas, single
+// quoted strings, raw newlines inside strings and closing braces left off the end. None when
+// the text is already valid or still isn't after these fixes
+pub fn repair_json(text: &str) -> Option<Repair> {
+    if serde_json::from_str::<Value>(text).is_ok() {
+        return None;
+    }
+    let start = text.find(['{', '['])?;
+    let mut json = String::with_capacity(text.len());
+    let mut fixes = Vec::new();
+    let mut open = Vec::new();
+    let mut quote = None;
+    let mut escaped = false;
+
+    for c in text[start..].chars() {
+        if let Some(delimiter) = quote {
+            match c {
+                _ if escaped => {
+                    escaped = false;
+                    // \' is no escape in JSON
+                    if c == '\'' {
+                        json.pop();
+                    }
+                    json.push(c);
+                }
+                '\\' => {
+                    escaped = true;
+                    json.push(c);
+                }
+                _ if c == delimiter => {
+                    quote = None;
+                    json.push('"');
+                }
+                '"' => json.push_str("\\\""),
+                '\n' | '\r' | '\t' => {
+                    note(&mut fixes, "escaped control characters in strings");
+                    json.push_str(match c {
+                        '\n' => "\\n",
+                        '\r' => "\\r",
+                        _ => "\\t",
+                    });
+                }
+                _ => json.push(c),
+            }
+            continue;
+        }
+        match c {
+            '"' | '\'' => {
+                if c == '\'' {
+                    note(&mut fixes, "replaced single quotes");
+                }
+                quote = Some(c);
+                json.push('"');
+            }
+            '{' | '[' => {
+                open.push(if c == '{' { '}' } else { ']' });
+                json.push(c);
+            }
+            '}' | ']' => {
+                drop_trailing_comma(&mut json, &mut fixes);
+                open.pop();
+                json.push(c);
+                if open.is_empty() {
+                    break;
+                }
+            }
+            _ => json.push(c),
+        }
+    }
+
+    if quote.is_some() {
+        note(&mut fixes, "closed an unterminated string");
+        json.push('"');
+    }
+    if !open.is_empty() {
+        drop_trailing_comma(&mut json, &mut fixes);
+        note(&mut fixes, "closed unbalanced braces");
+        json.extend(open.iter().rev());
+    }
+
+    serde_json::from_str::<Value>(&json).ok()?;
+    (!fixes.is_empty()).then_some(Repair { json, fixes })
+}
+
+fn drop_trailing_comma(json: &mut String, fixes: &mut Vec<String>) {
+    let end = json.trim_end().len();
+    if json[..end].ends_with(',') {
+        json.truncate(end - 1);
+        note(fixes, "removed trailing commas");
+    }
+}
+
+fn note(fixes: &mut Vec<String>, fix: &str) {
+    if !fixes.iter().any(|noted| noted == fix) {
+        fixes.push(fix.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_repairs_common_mistakes_and_gives_up_on_the_rest() {
+        let repair = repair_json(
+            "{'final_answer': 'An old silent pond\nA frog jumps into the pond', 'lines': [1, 2,],",
+        )
+        .unwrap();
+
+        assert_eq!(
+            serde_json::from_str::<Value>(&repair.json).unwrap(),
+            json!({
+                "final_answer": "An old silent pond\nA frog jumps into the pond",
+                "lines": [1, 2]
+            })
+        );
+        assert_eq!(
+            repair.fixes,
+            [
+                "replaced single quotes",
+                "escaped control characters in strings",
+                "removed trailing commas",
+                "closed unbalanced braces"
+            ]
+        );
+        assert_eq!(
+            repair_json(r#"{"said": "it's \"fine\""#).unwrap().json,
+            r#"{"said": "it's \"fine\""}"#
+        );
+        assert_eq!(repair_json(r#"{"valid": true}"#), None);
+        assert_eq!(repair_json("{final_answer: pond}"), None);
+        assert_eq!(repair_json("No JSON here"), None);
+    }
+}
@@ -0,0 +1,299 @@
+use super::{
+    estimate_message_tokens, ChatMessage, CompletionRequest, ModelCapabilities, ModelClient,
+    ModelResponse, Role,
+};
+use crate::error::Result;
+use async_trait::async_trait;
+use std::sync::Mutex;
+
+// What a request has to look like for a route to take it
+#[derive(Debug, Clone, PartialEq)]
+pub enum RouteRule {
+    // The classifier put the request's last user message under this task type
+    TaskType(String),
+    // Estimated prompt tokens
+    LongerThan(usize),
+    ShorterThan(usize),
+    HasImages,
+    // The request constrains the output to JSON or a schema
+    StructuredOutput,
+}
+
+struct Route {
+    name: String,
+    rules: Vec<RouteRule>,
+    model: Box<dyn ModelClient>,
+}
+
+// Asks a model, usually a small and fast one, which of the task types a request is
+struct Classifier {
+    model: Box<dyn ModelClient>,
+    task_types: Vec<String>,
+    // The last message classified, since every turn of a task repeats it
+    last: Mutex<Option<(String, Option<String>)>>,
+}
+
+impl Classifier {
+    async fn classify(&self, text: &str) -> Result<Option<String>> {
+        if let Some((classified, task_type)) = self.last.lock().unwrap().as_ref() {
+            if classified == text {
+                return Ok(task_type.clone());
+            }
+        }
+        let prompt = format!(
+            "Classify the request below as one of these task types: {}. Reply with the task type only.\n\nRequest:\n{}",
+            self.task_types.join(", "),
+            text
+        );
+        let reply = self
+            .model
+            .complete(&CompletionRequest::new(vec![ChatMessage::user(prompt)]))
+            .await?
+            .response
+            .to_lowercase();
+        let task_type = self
+            .task_types
+            .iter()
+            .find(|task_type| reply.contains(&task_type.to_lowercase()))
+            .cloned();
+        *self.last.lock().unwrap() = Some((text.to_string(), task_type.clone()));
+        Ok(task_type)
+    }
+}
+
+// Sends each request to the first route whose rules all match and whose model can serve it:
+// one that sees images for a request with images, takes a JSON schema for a schema-constrained
+// one, and has the context for its length. Anything else goes to the default model, e.g. a
+// small local model for formatting tool calls and a large hosted one for reasoning
+pub struct RoutingModelClient {
+    routes: Vec<Route>,
+    default: Box<dyn ModelClient>,
+    classifier: Option<Classifier>,
+}
+
+impl RoutingModelClient {
+    pub fn new(default: Box<dyn ModelClient>) -> Self {
+        Self {
+            routes: Vec::new(),
+            default,
+            classifier: None,
+        }
+    }
+
+    // Routes are tried in the order they are added
+    pub fn with_route(
+        mut self,
+        name: impl Into<String>,
+        rules: Vec<RouteRule>,
+        model: Box<dyn ModelClient>,
+    ) -> Self {
+        self.routes.push(Route {
+            name: name.into(),
+            rules,
+            model,
+        });
+        self
+    }
+
+    // Needed by TaskType rules; without it they never match
+    pub fn with_classifier<I, S>(mut self, model: Box<dyn ModelClient>, task_types: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.classifier = Some(Classifier {
+            model,
+            task_types: task_types.into_iter().map(Into::into).collect(),
+            last: Mutex::new(None),
+        });
+        self
+    }
+
+    // The name of the route the request goes to, or None for the default model
+    pub async fn route(&self, request: &CompletionRequest) -> Result<Option<&str>> {
+        Ok(self.pick(request).await?.map(|route| route.name.as_str()))
+    }
+
+    async fn pick(&self, request: &CompletionRequest) -> Result<Option<&Route>> {
+        let classify = self.routes.iter().any(|route| {
+            route
+                .rules
+                .iter()
+                .any(|rule| matches!(rule, RouteRule::TaskType(_)))
+        });
+        let task_type = match (&self.classifier, last_user_message(request)) {
+            (Some(classifier), Some(text)) if classify => classifier.classify(text).await?,
+            _ => None,
+        };
+        let tokens = estimate_message_tokens(&request.messages);
+        Ok(self.routes.iter().find(|route| {
+            can_serve(&route.model.capabilities(), request, tokens)
+                && route.rules.iter().all(|rule| match rule {
+                    RouteRule::TaskType(wanted) => task_type.as_ref() == Some(wanted),
+                    RouteRule::LongerThan(limit) => tokens > *limit,
+                    RouteRule::ShorterThan(limit) => tokens < *limit,
+                    RouteRule::HasImages => has_images(request),
+                    RouteRule::StructuredOutput => request.format.is_some(),
+                })
+        }))
+    }
+}
+
+fn last_user_message(request: &CompletionRequest) -> Option<&str> {
+    request
+        .messages
+        .iter()
+        .rev()
+        .find(|message| message.role == Role::User)
+        .map(|message| message.content.as_str())
+}
+
+fn has_images(request: &CompletionRequest) -> bool {
+    request
+        .messages
+        .iter()
+        .any(|message| !message.images.is_empty())
+}
+
+fn can_serve(capabilities: &ModelCapabilities, request: &CompletionRequest, tokens: usize) -> bool {
+    (capabilities.vision || !has_images(request))
+        && (capabilities.json_schema || request.format.is_none())
+        && capabilities.max_context.is_none_or(|max| tokens <= max)
+}
+
+#[async_trait]
+impl ModelClient for RoutingModelClient {
+    async fn complete(&self, request: &CompletionRequest) -> Result<ModelResponse> {
+        match self.pick(request).await? {
+            Some(route) => {
+                tracing::debug!(route = route.name, "Routing completion");
+                route.model.complete(request).await
+            }
+            None => self.default.complete(request).await,
+        }
+    }
+
+    // What any of the models can do, so the agent still sends images and schemas for the
+    // routes that take them
+    fn capabilities(&self) -> ModelCapabilities {
+        let default = self.default.capabilities();
+        self.routes
+            .iter()
+            .map(|route| route.model.capabilities())
+            .fold(default, |all, route| ModelCapabilities {
+                streaming: all.streaming || route.streaming,
+                tools: all.tools || route.tools,
+                json_schema: all.json_schema || route.json_schema,
+                vision: all.vision || route.vision,
+                logprobs: all.logprobs || route.logprobs,
+                stop_sequences: all.stop_sequences || route.stop_sequences,
+                // Unknown when any of them is, since that one may take anything
+                max_context: match (all.max_context, route.max_context) {
+                    (Some(all), Some(route)) => Some(all.max(route)),
+                    _ => None,
+                },
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model_client::ImagePart;
+    use serde_json::json;
+
+    // Answers with its own name
+    struct Named(&'static str, ModelCapabilities);
+
+    #[async_trait]
+    impl ModelClient for Named {
+        async fn complete(&self, _request: &CompletionRequest) -> Result<ModelResponse> {
+            Ok(ModelResponse {
+                response: self.0.to_string(),
+                reasoning: None,
+            })
+        }
+
+        fn capabilities(&self) -> ModelCapabilities {
+            self.1.clone()
+        }
+    }
+
+    // Calls anything mentioning a proof reasoning
+    struct Keywords;
+
+    #[async_trait]
+    impl ModelClient for Keywords {
+        async fn complete(&self, request: &CompletionRequest) -> Result<ModelResponse> {
+            let reasoning = request.messages[0].content.contains("prove");
+            Ok(ModelResponse {
+                response: if reasoning { "Reasoning" } else { "formatting" }.to_string(),
+                reasoning: None,
+            })
+        }
+    }
+
+    fn model(name: &'static str, capabilities: ModelCapabilities) -> Box<dyn ModelClient> {
+        Box::new(Named(name, capabilities))
+    }
+
+    #[tokio::test]
+    async fn test_requests_go_to_the_first_route_that_matches_and_can_serve_them() -> Result<()> {
+        let vision = ModelCapabilities {
+            vision: true,
+            ..ModelCapabilities::default()
+        };
+        let router = RoutingModelClient::new(model(
+            "local",
+            ModelCapabilities {
+                max_context: Some(100),
+                ..ModelCapabilities::default()
+            },
+        ))
+        .with_route(
+            "vision",
+            vec![RouteRule::HasImages],
+            model("vision", vision.clone()),
+        )
+        .with_route(
+            "reasoning",
+            vec![RouteRule::TaskType("reasoning".to_string())],
+            model("hosted", ModelCapabilities::default()),
+        )
+        .with_route(
+            "long",
+            vec![RouteRule::LongerThan(50)],
+            model("long", ModelCapabilities::default()),
+        )
+        .with_classifier(Box::new(Keywords), ["reasoning", "formatting"]);
+        let ask = |text: &str| CompletionRequest::new(vec![ChatMessage::user(text)]);
+        let with_image = CompletionRequest::new(vec![ChatMessage::user("What is this?")
+            .with_image(ImagePart::from_base64("image/png", "iVBORw0KGgo="))]);
+
+        assert_eq!(router.complete(&with_image).await?.response, "vision");
+        assert_eq!(
+            router
+                .complete(&ask("Please prove it halts"))
+                .await?
+                .response,
+            "hosted"
+        );
+        assert_eq!(
+            router.complete(&ask(&"haiku ".repeat(50))).await?.response,
+            "long"
+        );
+        assert_eq!(
+            router.complete(&ask("Write a haiku")).await?.response,
+            "local"
+        );
+        // The vision model takes no schema, so with one the image falls through
+        let schema = CompletionRequest {
+            format: Some(json!("json")),
+            ..with_image
+        };
+        assert_eq!(router.route(&schema).await?, None);
+        assert!(router.capabilities().vision);
+        assert_eq!(router.capabilities().max_context, None);
+        Ok(())
+    }
+}
@@ -0,0 +1,60 @@
+use super::extract::extract_json;
+
+// Where a reply that is one JSON object is over: its closing brace followed by a blank line or
+// a closing fence on a line of its own. Valid JSON has neither inside an object, since strings
+// can't hold raw newlines, so whatever the model writes after them is explanation nobody reads.
+// A fence right after the brace is left out: a string can hold one, as in "```{}```"
+pub const JSON_STOP_SEQUENCES: &[&str] = &["}\n\n", "}\n```"];
+
+// What a completion is asked to reply with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    JsonObject,
+}
+
+impl OutputFormat {
+    // Where a reply in this format can be cut off without losing any of it
+    pub fn stop_sequences(self) -> Vec<String> {
+        match self {
+            OutputFormat::Text => Vec::new(),
+            OutputFormat::JsonObject => JSON_STOP_SEQUENCES
+                .iter()
+                .map(|stop| stop.to_string())
+                .collect(),
+        }
+    }
+}
+
+// Backends leave the whole matched stop sequence out of the reply, including the brace that
+// closed the object. Puts it back when the reply holds no JSON without it but does with it
+pub fn restore_closing_brace(text: &str) -> Option<String> {
+    if extract_json(text).is_some() {
+        return None;
+    }
+    let restored = format!("{}}}", text.trim_end());
+    extract_json(&restored).map(|_| restored)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_closing_brace_is_restored_only_where_a_stop_cut_it() {
+        let stopped = "<think>Call it</think>\n{\"jsonrpc\": \"2.0\", \"method\": \"tools/call\", \"params\": {\"name\": \"math_eval\"}, \"id\": 1";
+
+        assert_eq!(
+            restore_closing_brace(stopped),
+            Some(format!("{}}}", stopped))
+        );
+        assert_eq!(restore_closing_brace(r#"{"final_answer": "pond"}"#), None);
+        assert_eq!(restore_closing_brace("An old silent pond"), None);
+        assert!(OutputFormat::Text.stop_sequences().is_empty());
+        let answer = r#"{"final_answer": "Fence it: ```{}```"}"#;
+        assert!(OutputFormat::JsonObject
+            .stop_sequences()
+            .iter()
+            .all(|stop| !answer.contains(stop.as_str())));
+    }
+}
@@ -0,0 +1,151 @@
+use crate::error::{AgentError, Result};
+use serde_json::Value;
+
+// Collects the text of Ollama's streamed chat reply, one JSON object per line, and notices when
+// the text already holds a whole tool call so the rest of the generation can be skipped
+#[derive(Debug, Default)]
+pub struct ReplyStream {
+    // Bytes of a line that hasn't fully arrived
+    pending: Vec<u8>,
+    text: String,
+}
+
+impl ReplyStream {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Some with the reply once it is done, or as soon as it holds a complete tool call
+    pub fn push(&mut self, bytes: &[u8]) -> Result<Option<String>> {
+        self.pending.extend_from_slice(bytes);
+        while let Some(end) = self.pending.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=end).collect();
+            if let Some(reply) = self.line(&line)? {
+                return Ok(Some(reply));
+            }
+        }
+        Ok(None)
+    }
+
+    // The stream ended without a done chunk
+    pub fn finish(mut self) -> Result<String> {
+        let line = std::mem::take(&mut self.pending);
+        Ok(self.line(&line)?.unwrap_or(self.text))
+    }
+
+    fn line(&mut self, line: &[u8]) -> Result<Option<String>> {
+        let line = String::from_utf8_lossy(line);
+        if line.trim().is_empty() {
+            return Ok(None);
+        }
+        let chunk: Value = serde_json::from_str(&line)?;
+        if let Some(error) = chunk["error"].as_str() {
+            return Err(AgentError::ModelError(error.to_string()));
+        }
+        self.text
+            .push_str(chunk["message"]["content"].as_str().unwrap_or_default());
+        if chunk["done"].as_bool().unwrap_or(false) {
+            return Ok(Some(std::mem::take(&mut self.text)));
+        }
+        Ok(complete_tool_call(&self.text).map(str::to_string))
+    }
+}
+
+// The text up to the end of a tool call, once its JSON object has closed. Braces in a leading
+// <think> block don't count, and neither does a final answer, which may still be followed by
+// more of the same reply
+pub fn complete_tool_call(text: &str) -> Option<&str> {
+    let body = match text.find("<think>") {
+        Some(open) if text[..open].trim().is_empty() => {
+            open + text[open..].find("</think>")? + "</think>".len()
+        }
+        _ => 0,
+    };
+    let start = body + text[body..].find('{')?;
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (index, c) in text[start..].char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' | '[' => depth += 1,
+            '}' | ']' => {
+                depth -= 1;
+                if depth == 0 {
+                    let end = start + index + 1;
+                    let value: Value = serde_json::from_str(&text[start..end]).ok()?;
+                    let call = value.get("jsonrpc").is_some() && value.get("method").is_some();
+                    return call.then_some(&text[..end]);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn chunk(content: &str, done: bool) -> String {
+        format!(
+            "{}\n",
+            json!({"message": {"role": "assistant", "content": content}, "done": done})
+        )
+    }
+
+    #[test]
+    fn test_stream_stops_at_a_complete_tool_call() -> Result<()> {
+        let call = r#"{"jsonrpc": "2.0", "method": "tools/call", "params": {"name": "write_file", "arguments": {"path": "haiku.txt", "content": "{pond}"}}, "id": 1}"#;
+        let (head, tail) = call.split_at(60);
+        let streamed = [
+            chunk("<think>Write {it} down</think>\n", false),
+            chunk(head, false),
+            chunk(tail, false),
+            chunk("\nI have written the haiku.", false),
+            chunk("", true),
+        ]
+        .concat();
+        let mut stream = ReplyStream::new();
+
+        let mut reply = None;
+        let mut consumed = 0;
+        for byte in streamed.as_bytes().chunks(7) {
+            consumed += byte.len();
+            if let Some(text) = stream.push(byte)? {
+                reply = Some(text);
+                break;
+            }
+        }
+
+        assert_eq!(
+            reply.unwrap(),
+            format!("<think>Write {{it}} down</think>\n{}", call)
+        );
+        assert!(consumed < streamed.len());
+        let mut answer = ReplyStream::new();
+        assert_eq!(
+            answer.push(chunk(r#"{"final_answer": "pond"}"#, false).as_bytes())?,
+            None
+        );
+        assert_eq!(
+            answer.push(chunk("", true).as_bytes())?,
+            Some(r#"{"final_answer": "pond"}"#.to_string())
+        );
+        assert!(ReplyStream::new()
+            .push(b"{\"error\": \"model not found\"}\n")
+            .is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,254 @@
+use super::extract::{extract_json, split_reasoning};
+use super::repair::repair_json;
+use super::{CompletionRequest, ModelCapabilities, ModelClient, ModelResponse};
+use crate::error::{AgentError, Result};
+use async_trait::async_trait;
+use regex::Regex;
+use std::sync::OnceLock;
+
+// One step between what a model said and what the agent parses
+pub trait ResponseTransform: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn apply(&self, response: ModelResponse) -> Result<ModelResponse>;
+}
+
+// Moves <think> blocks out of the response into its reasoning
+pub struct StripThinkTags;
+
+impl ResponseTransform for StripThinkTags {
+    fn name(&self) -> &str {
+        "strip_think_tags"
+    }
+
+    fn apply(&self, response: ModelResponse) -> Result<ModelResponse> {
+        let (answer, reasoning) = split_reasoning(&response.response);
+        let reasoning = match (response.reasoning, reasoning) {
+            (Some(earlier), Some(reasoning)) => Some(format!("{}\n\n{}", earlier, reasoning)),
+            (earlier, reasoning) => earlier.or(reasoning),
+        };
+        Ok(ModelResponse {
+            response: answer,
+            reasoning,
+        })
+    }
+}
+
+// Unwraps a response that is nothing but one fenced code block
+pub struct StripFences;
+
+impl ResponseTransform for StripFences {
+    fn name(&self) -> &str {
+        "strip_fences"
+    }
+
+    fn apply(&self, response: ModelResponse) -> Result<ModelResponse> {
+        static FENCED: OnceLock<Regex> = OnceLock::new();
+        let fenced = FENCED
+            .get_or_init(|| Regex::new(r"(?s)\A\s*```[\w+-]*[ \t]*\n(.*?)\n?```\s*\z").unwrap());
+        let Some(inner) = fenced
+            .captures(&response.response)
+            .and_then(|captures| captures.get(1))
+            .filter(|inner| !inner.as_str().contains("```"))
+        else {
+            return Ok(response);
+        };
+        Ok(ModelResponse {
+            response: inner.as_str().to_string(),
+            ..response
+        })
+    }
+}
+
+// Keeps only the JSON in a response that has any, e.g. a tool call in between prose
+pub struct ExtractJson;
+
+impl ResponseTransform for ExtractJson {
+    fn name(&self) -> &str {
+        "extract_json"
+    }
+
+    fn apply(&self, response: ModelResponse) -> Result<ModelResponse> {
+        match extract_json(&response.response) {
+            Some(json) => Ok(ModelResponse {
+                response: json,
+                ..response
+            }),
+            None => Ok(response),
+        }
+    }
+}
+
+// Fixes almost-valid JSON, e.g. with trailing commas or single quotes. The agent does this
+// itself before giving up on a reply, noting the fixes in its transcript
+pub struct RepairJson;
+
+impl ResponseTransform for RepairJson {
+    fn name(&self) -> &str {
+        "repair_json"
+    }
+
+    fn apply(&self, response: ModelResponse) -> Result<ModelResponse> {
+        match repair_json(&response.response) {
+            Some(repair) => Ok(ModelResponse {
+                response: repair.json,
+                ..response
+            }),
+            None => Ok(response),
+        }
+    }
+}
+
+// Masks listed words, whole and in any case, with asterisks
+pub struct ProfanityFilter {
+    words: Regex,
+}
+
+impl ProfanityFilter {
+    pub fn new<I, S>(words: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let words: Vec<String> = words
+            .into_iter()
+            .map(|word| regex::escape(word.as_ref().trim()))
+            .filter(|word| !word.is_empty())
+            .collect();
+        if words.is_empty() {
+            return Err(AgentError::ConfigError(
+                "The profanity filter has no words".to_string(),
+            ));
+        }
+        let words = Regex::new(&format!(r"(?i)\b(?:{})\b", words.join("|")))
+            .map_err(|err| AgentError::ConfigError(err.to_string()))?;
+        Ok(Self { words })
+    }
+}
+
+impl ResponseTransform for ProfanityFilter {
+    fn name(&self) -> &str {
+        "profanity_filter"
+    }
+
+    fn apply(&self, response: ModelResponse) -> Result<ModelResponse> {
+        let masked = self
+            .words
+            .replace_all(&response.response, |captures: &regex::Captures| {
+                "*".repeat(captures[0].chars().count())
+            })
+            .into_owned();
+        Ok(ModelResponse {
+            response: masked,
+            ..response
+        })
+    }
+}
+
+// Transforms applied in order; any of them can reject a response with an error
+#[derive(Default)]
+pub struct ResponsePipeline {
+    transforms: Vec<Box<dyn ResponseTransform>>,
+}
+
+impl ResponsePipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // What local models need before their output can be parsed: reasoning split off and the
+    // JSON taken out of the prose around it
+    pub fn standard() -> Self {
+        Self::new()
+            .with(Box::new(StripThinkTags))
+            .with(Box::new(ExtractJson))
+    }
+
+    pub fn with(mut self, transform: Box<dyn ResponseTransform>) -> Self {
+        self.transforms.push(transform);
+        self
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.transforms
+            .iter()
+            .map(|transform| transform.name())
+            .collect()
+    }
+
+    pub fn apply(&self, response: ModelResponse) -> Result<ModelResponse> {
+        self.transforms
+            .iter()
+            .try_fold(response, |response, transform| transform.apply(response))
+    }
+}
+
+// Runs any backend's responses through a pipeline
+pub struct TransformingModelClient {
+    inner: Box<dyn ModelClient>,
+    pipeline: ResponsePipeline,
+}
+
+impl TransformingModelClient {
+    pub fn new(inner: Box<dyn ModelClient>, pipeline: ResponsePipeline) -> Self {
+        Self { inner, pipeline }
+    }
+}
+
+#[async_trait]
+impl ModelClient for TransformingModelClient {
+    async fn complete(&self, request: &CompletionRequest) -> Result<ModelResponse> {
+        self.pipeline.apply(self.inner.complete(request).await?)
+    }
+
+    fn capabilities(&self) -> ModelCapabilities {
+        self.inner.capabilities()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(text: &str) -> ModelResponse {
+        ModelResponse {
+            response: text.to_string(),
+            reasoning: None,
+        }
+    }
+
+    #[test]
+    fn test_standard_pipeline_splits_reasoning_and_extracts_json() -> Result<()> {
+        let raw = "<think>A write_file call</think>Sure! {\"jsonrpc\": \"2.0\", \"method\": \"tools/call\"} Done.";
+
+        let processed = ResponsePipeline::standard().apply(response(raw))?;
+
+        assert_eq!(
+            processed.response,
+            "{\"jsonrpc\": \"2.0\", \"method\": \"tools/call\"}"
+        );
+        assert_eq!(processed.reasoning.as_deref(), Some("A write_file call"));
+        assert_eq!(
+            ResponsePipeline::standard().names(),
+            ["strip_think_tags", "extract_json"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_fences_are_stripped_and_listed_words_masked() -> Result<()> {
+        let pipeline = ResponsePipeline::new()
+            .with(Box::new(StripFences))
+            .with(Box::new(ProfanityFilter::new(["darn"])?));
+
+        let fenced = pipeline.apply(response(
+            "```text\nDarn frog,\nleaping in the darnedest pond\n```",
+        ))?;
+        let two_blocks = pipeline.apply(response("```\na\n```\n```\nb\n```"))?;
+
+        assert_eq!(fenced.response, "**** frog,\nleaping in the darnedest pond");
+        assert_eq!(two_blocks.response, "```\na\n```\n```\nb\n```");
+        assert!(ProfanityFilter::new([" "]).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,153 @@
+use super::{
+    estimate_message_tokens, estimate_tokens, CompletionRequest, ModelCapabilities, ModelClient,
+    ModelResponse,
+};
+use crate::error::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+
+// Estimated from text length, since the backends don't report usage back
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Usage {
+    pub requests: usize,
+    pub prompt_tokens: usize,
+    pub completion_tokens: usize,
+}
+
+impl Usage {
+    pub fn total_tokens(&self) -> usize {
+        self.prompt_tokens + self.completion_tokens
+    }
+
+    // What was used after `earlier` was taken from the same meter
+    pub fn since(&self, earlier: &Usage) -> Usage {
+        Usage {
+            requests: self.requests.saturating_sub(earlier.requests),
+            prompt_tokens: self.prompt_tokens.saturating_sub(earlier.prompt_tokens),
+            completion_tokens: self
+                .completion_tokens
+                .saturating_sub(earlier.completion_tokens),
+        }
+    }
+
+    pub fn add(&mut self, other: &Usage) {
+        self.requests += other.requests;
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+    }
+}
+
+// US dollars per million tokens
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Pricing {
+    pub prompt_per_million: f64,
+    pub completion_per_million: f64,
+}
+
+impl Pricing {
+    // Local backends cost nothing per token
+    pub fn free() -> Self {
+        Self::default()
+    }
+
+    pub fn cost(&self, usage: &Usage) -> f64 {
+        (usage.prompt_tokens as f64 * self.prompt_per_million
+            + usage.completion_tokens as f64 * self.completion_per_million)
+            / 1_000_000.0
+    }
+}
+
+// What a plan is expected to use before any of it runs; the cost is unknown without a price
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct CostEstimate {
+    pub usage: Usage,
+    pub cost: Option<f64>,
+}
+
+impl CostEstimate {
+    pub fn summary(&self) -> String {
+        let mut summary = format!(
+            "{} turns, ~{} prompt and ~{} completion tokens",
+            self.usage.requests, self.usage.prompt_tokens, self.usage.completion_tokens
+        );
+        if let Some(cost) = self.cost {
+            summary.push_str(&format!(", ~${:.4}", cost));
+        }
+        summary
+    }
+}
+
+// Read side of a MeteredModelClient, kept after the client is handed to an agent
+#[derive(Clone, Default)]
+pub struct UsageMeter {
+    usage: Arc<Mutex<Usage>>,
+}
+
+impl UsageMeter {
+    pub fn usage(&self) -> Usage {
+        *self.usage.lock().unwrap()
+    }
+}
+
+pub struct MeteredModelClient {
+    inner: Box<dyn ModelClient>,
+    meter: UsageMeter,
+}
+
+impl MeteredModelClient {
+    pub fn new(inner: Box<dyn ModelClient>) -> Self {
+        Self {
+            inner,
+            meter: UsageMeter::default(),
+        }
+    }
+
+    pub fn meter(&self) -> UsageMeter {
+        self.meter.clone()
+    }
+}
+
+#[async_trait]
+impl ModelClient for MeteredModelClient {
+    async fn complete(&self, request: &CompletionRequest) -> Result<ModelResponse> {
+        let response = self.inner.complete(request).await?;
+        let mut usage = self.meter.usage.lock().unwrap();
+        usage.requests += 1;
+        usage.prompt_tokens += estimate_message_tokens(&request.messages);
+        usage.completion_tokens += estimate_tokens(&response.response);
+        Ok(response)
+    }
+
+    fn capabilities(&self) -> ModelCapabilities {
+        self.inner.capabilities()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model_client::ChatMessage;
+    use crate::replay::{Fixture, ReplayModelClient};
+
+    #[tokio::test]
+    async fn test_meter_counts_requests_and_estimated_tokens() -> Result<()> {
+        let fixture = Fixture::from_replies(["12345678"]);
+        let client = MeteredModelClient::new(Box::new(ReplayModelClient::new(&fixture)));
+        let meter = client.meter();
+
+        client
+            .complete(&CompletionRequest::new(vec![ChatMessage::user("1234")]))
+            .await?;
+
+        assert_eq!(
+            meter.usage(),
+            Usage {
+                requests: 1,
+                prompt_tokens: 1,
+                completion_tokens: 2,
+            }
+        );
+        Ok(())
+    }
+}
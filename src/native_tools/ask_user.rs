@@ -0,0 +1,107 @@
+use super::{schema, string_argument, NativeTool};
+use crate::error::Result;
+use crate::terminal;
+use async_trait::async_trait;
+use rmcp::model::{CallToolResult, Content, JsonObject, Tool};
+use serde_json::json;
+use std::sync::Arc;
+
+pub const ASK_USER_TOOL: &str = "ask_user";
+
+// Gets the user's answer to a question the model asked; None when there is no answer
+#[async_trait]
+pub trait Clarifier: Send + Sync {
+    async fn ask(&self, question: &str) -> Result<Option<String>>;
+}
+
+// Asks on the terminal; an empty line leaves the question unanswered
+pub struct TerminalClarifier;
+
+#[async_trait]
+impl Clarifier for TerminalClarifier {
+    async fn ask(&self, question: &str) -> Result<Option<String>> {
+        eprintln!("The model asks: {}", question);
+        eprint!("> ");
+        let answer = terminal::read_line().await?;
+        let answer = answer.trim();
+        Ok((!answer.is_empty()).then(|| answer.to_string()))
+    }
+}
+
+// Tells the model to ask instead of guessing
+pub fn tool_instructions() -> String {
+    format!(
+        "When the task is ambiguous and a wrong guess would waste work, ask the user with the '{}' tool (argument: question) before acting on it. Ask one short, specific question at a time.",
+        ASK_USER_TOOL
+    )
+}
+
+pub struct AskUserTool {
+    clarifier: Arc<dyn Clarifier>,
+}
+
+impl AskUserTool {
+    pub fn new(clarifier: Arc<dyn Clarifier>) -> Self {
+        Self { clarifier }
+    }
+}
+
+#[async_trait]
+impl NativeTool for AskUserTool {
+    fn definition(&self) -> Tool {
+        Tool::new(
+            ASK_USER_TOOL,
+            "Asks the user a question about the task and returns their answer",
+            schema(json!({
+                "type": "object",
+                "properties": {"question": {"type": "string"}},
+                "required": ["question"]
+            })),
+        )
+    }
+
+    async fn call(&self, arguments: &JsonObject) -> Result<CallToolResult> {
+        let question = string_argument(arguments, "question")?;
+        let answer = self.clarifier.ask(question).await?.unwrap_or_else(|| {
+            "The user did not answer. Make the most reasonable assumption and state it".to_string()
+        });
+        Ok(CallToolResult::success(vec![Content::text(answer)]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct User(Option<&'static str>);
+
+    #[async_trait]
+    impl Clarifier for User {
+        async fn ask(&self, _question: &str) -> Result<Option<String>> {
+            Ok(self.0.map(str::to_string))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ask_user_returns_the_answer_or_says_there_was_none() -> Result<()> {
+        let arguments = json!({"question": "Which season?"})
+            .as_object()
+            .cloned()
+            .unwrap();
+
+        let answered = AskUserTool::new(Arc::new(User(Some("Winter"))))
+            .call(&arguments)
+            .await?;
+        let unanswered = AskUserTool::new(Arc::new(User(None)))
+            .call(&arguments)
+            .await?;
+
+        assert_eq!(answered.content[0].as_text().unwrap().text, "Winter");
+        assert!(unanswered.content[0]
+            .as_text()
+            .unwrap()
+            .text
+            .starts_with("The user did not answer"));
+        Ok(())
+    }
+}
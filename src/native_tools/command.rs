@@ -0,0 +1,621 @@
+use super::{schema, string_argument, NativeTool};
+use crate::error::{AgentError, Result};
+use crate::terminal;
+use crate::workspace;
+use async_trait::async_trait;
+use rmcp::model::{CallToolResult, Content, JsonObject, Tool};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+pub const RUN_COMMAND_TOOL: &str = "run_command";
+pub const DEFAULT_COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+pub const DEFAULT_MAX_OUTPUT_BYTES: usize = 16 * 1024;
+// Refused even when no allowlist is configured
+pub const DEFAULT_DENIED_COMMANDS: &[&str] = &[
+    "rm", "sudo", "su", "doas", "dd", "mkfs", "shutdown", "reboot", "chown", "chmod", "kill",
+    "killall",
+];
+// Programs that run the program named in their arguments, out of the lists' sight
+const COMMAND_RUNNERS: &[&str] = &[
+    "env",
+    "sh",
+    "bash",
+    "zsh",
+    "dash",
+    "ksh",
+    "fish",
+    "cmd",
+    "powershell",
+    "pwsh",
+    "xargs",
+    "exec",
+    "eval",
+    "source",
+    ".",
+    "command",
+    "builtin",
+    "nohup",
+    "nice",
+    "timeout",
+    "time",
+    "watch",
+    "setsid",
+    "stdbuf",
+    "busybox",
+];
+// Control flow and grouping would put programs where the check doesn't look for them
+const SHELL_KEYWORDS: &[&str] = &[
+    "{", "}", "!", "if", "then", "else", "elif", "fi", "for", "while", "until", "do", "done",
+    "case", "esac", "select", "function",
+];
+
+// Whether each command needs the user's go-ahead before it runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CommandApproval {
+    Always,
+    Ask,
+    Never,
+}
+
+// Runs shell command lines with a working directory inside a root directory. Every program in
+// the line is checked against the allow- and denylists, and whatever would hide a program from
+// that check or name a path outside the root is refused: command substitution, variable
+// expansion, redirections, shell keywords, programs that run other programs and the options that
+// make find, awk, git and interpreters do so. That is all the confinement there is: the
+// programs themselves can still reach anything the user can
+pub struct RunCommandTool {
+    root: PathBuf,
+    allowed: Option<HashSet<String>>,
+    denied: HashSet<String>,
+    timeout: Duration,
+    max_output_bytes: usize,
+    approval: CommandApproval,
+}
+
+impl RunCommandTool {
+    pub fn new(root: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self {
+            root: workspace::canonicalize(root.as_ref())?,
+            allowed: None,
+            denied: DEFAULT_DENIED_COMMANDS
+                .iter()
+                .map(|command| command.to_string())
+                .collect(),
+            timeout: DEFAULT_COMMAND_TIMEOUT,
+            max_output_bytes: DEFAULT_MAX_OUTPUT_BYTES,
+            approval: CommandApproval::Ask,
+        })
+    }
+
+    // Only these programs may run; without an allowlist anything not denied may
+    pub fn with_allowed<I, S>(mut self, commands: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.allowed = Some(commands.into_iter().map(Into::into).collect());
+        self
+    }
+
+    pub fn with_denied<I, S>(mut self, commands: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.denied.extend(commands.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn with_max_output_bytes(mut self, max_output_bytes: usize) -> Self {
+        self.max_output_bytes = max_output_bytes;
+        self
+    }
+
+    pub fn with_approval(mut self, approval: CommandApproval) -> Self {
+        self.approval = approval;
+        self
+    }
+
+    fn check_command(&self, command: &str) -> Result<()> {
+        if command.contains("$(") || command.contains('`') {
+            return Err(AgentError::ToolError(
+                "Command substitution is not allowed".to_string(),
+            ));
+        }
+        // Expansions like $HOME or ${PWD%/*} name paths the root check can't see
+        if let Some(word) = command.split_whitespace().find(|word| word.contains('$')) {
+            return Err(AgentError::ToolError(format!(
+                "Variable expansion in '{}' is not allowed",
+                word
+            )));
+        }
+        if command.contains(['<', '>']) {
+            return Err(AgentError::ToolError(
+                "Redirections are not allowed".to_string(),
+            ));
+        }
+        for words in stages(command) {
+            let program = program_name(&words[0], cfg!(windows));
+            if SHELL_KEYWORDS.contains(&program.as_str()) {
+                return Err(AgentError::ToolError(format!(
+                    "Shell keyword '{}' is not allowed",
+                    program
+                )));
+            }
+            if COMMAND_RUNNERS.contains(&program.as_str()) {
+                return Err(AgentError::ToolError(format!(
+                    "Command '{}' runs other programs and is not allowed",
+                    program
+                )));
+            }
+            if program.contains(['$', '*', '?', '[']) {
+                return Err(AgentError::ToolError(format!(
+                    "Program '{}' must be named literally",
+                    program
+                )));
+            }
+            if let Some(reason) = runs_hidden_code(&program, &words[1..], command) {
+                return Err(AgentError::ToolError(reason));
+            }
+            if self.denied.contains(&program) {
+                return Err(AgentError::ToolError(format!(
+                    "Command '{}' is denied",
+                    program
+                )));
+            }
+            if let Some(allowed) = &self.allowed {
+                if !allowed.contains(&program) {
+                    return Err(AgentError::ToolError(format!(
+                        "Command '{}' is not allowed",
+                        program
+                    )));
+                }
+            }
+        }
+        if let Some(path) = command.split_whitespace().find(|word| escapes_root(word)) {
+            return Err(AgentError::ToolError(format!(
+                "Path '{}' is outside the working directory",
+                path
+            )));
+        }
+        Ok(())
+    }
+
+    // The working directory, which must resolve to the root or somewhere below it
+    fn working_dir(&self, cwd: Option<&str>) -> Result<PathBuf> {
+        let Some(cwd) = cwd else {
+            return Ok(self.root.clone());
+        };
+        let dir = workspace::canonicalize(&self.root.join(cwd))?;
+        if !dir.starts_with(&self.root) {
+            return Err(AgentError::ToolError(format!(
+                "Working directory '{}' is outside {}",
+                cwd,
+                self.root.display()
+            )));
+        }
+        Ok(dir)
+    }
+
+    async fn approve(&self, command: &str, dir: &Path) -> Result<bool> {
+        match self.approval {
+            CommandApproval::Always => Ok(true),
+            CommandApproval::Never => Ok(false),
+            CommandApproval::Ask => {
+                eprintln!("The model wants to run in {}:", dir.display());
+                eprintln!("  {}", command);
+                terminal::confirm("Allow?").await
+            }
+        }
+    }
+
+    fn truncate(&self, output: &[u8]) -> String {
+        if output.len() <= self.max_output_bytes {
+            return String::from_utf8_lossy(output).into_owned();
+        }
+        format!(
+            "{}\n[truncated {} of {} bytes]",
+            String::from_utf8_lossy(&output[..self.max_output_bytes]),
+            output.len() - self.max_output_bytes,
+            output.len()
+        )
+    }
+}
+
+// The words of each pipeline stage or chained command, from the program on past any VAR=value
+// prefixes, with the quotes and escapes the shell would drop removed so r''m still reads as rm
+fn stages(command: &str) -> Vec<Vec<String>> {
+    command
+        .split(['|', ';', '&', '\n', '(', ')'])
+        .map(|stage| {
+            stage
+                .split_whitespace()
+                .skip_while(|word| word.contains('='))
+                .map(|word| {
+                    word.chars()
+                        .filter(|c| !matches!(c, '"' | '\'') && (cfg!(windows) || *c != '\\'))
+                        .collect()
+                })
+                .collect::<Vec<String>>()
+        })
+        .filter(|words| !words.is_empty())
+        .collect()
+}
+
+// Why the arguments of an otherwise harmless program would make it run code the lists never
+// see, if they do. awk programs are split up by the stage separators, so they are looked for in
+// the whole command
+fn runs_hidden_code(program: &str, args: &[String], command: &str) -> Option<String> {
+    let short_flag = |letters: &[char]| {
+        args.iter()
+            .any(|arg| arg.starts_with('-') && !arg.starts_with("--") && arg.contains(letters))
+    };
+    let hidden = match program {
+        "find" => args.iter().any(|arg| {
+            matches!(
+                arg.as_str(),
+                "-exec" | "-execdir" | "-ok" | "-okdir" | "-delete"
+            )
+        }),
+        "awk" | "gawk" | "mawk" | "nawk" => command
+            .split_whitespace()
+            .collect::<String>()
+            .contains("system("),
+        "git" => git_config_option(args),
+        "perl" | "ruby" => short_flag(&['e', 'E']),
+        "node" => {
+            short_flag(&['e', 'p'])
+                || args
+                    .iter()
+                    .any(|arg| matches!(arg.as_str(), "--eval" | "--print"))
+        }
+        "php" => short_flag(&['r']),
+        _ if program.starts_with("python") => short_flag(&['c']),
+        _ => false,
+    };
+    hidden.then(|| match program {
+        "find" => "find's -exec, -ok and -delete actions are not allowed".to_string(),
+        "awk" | "gawk" | "mawk" | "nawk" => format!("{}'s system() is not allowed", program),
+        "git" => "git -c and --config-env are not allowed: config can define aliases that run \
+                  programs"
+            .to_string(),
+        _ => format!(
+            "Code given to {} on the command line is not allowed",
+            program
+        ),
+    })
+}
+
+// Whether git's own options, before the subcommand, set config. -c after the subcommand means
+// something else, as in git grep -c
+fn git_config_option(args: &[String]) -> bool {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg.starts_with("-c") || arg.starts_with("--config-env") {
+            return true;
+        }
+        if matches!(
+            arg.as_str(),
+            "-C" | "--git-dir" | "--work-tree" | "--namespace"
+        ) {
+            args.next();
+        } else if !arg.starts_with('-') {
+            return false;
+        }
+    }
+    false
+}
+
+// Whether a word, or a value after = in it, is an absolute, home or parent path
+fn escapes_root(word: &str) -> bool {
+    word.split('=').any(|part| {
+        let part = part.trim_matches(['"', '\'']);
+        part.starts_with(['/', '\\', '~'])
+            || Path::new(part).is_absolute()
+            || Path::new(part)
+                .components()
+                .any(|component| component == std::path::Component::ParentDir)
+    })
+}
+
+// What a program is listed as: its file name, and on Windows, where C:\Tools\RM.EXE runs rm,
+// without an executable extension and in lower case
+fn program_name(program: &str, windows: bool) -> String {
+    if !windows {
+        return Path::new(program)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| program.to_string());
+    }
+    let name = program
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or(program)
+        .to_ascii_lowercase();
+    [".exe", ".cmd", ".bat", ".com"]
+        .iter()
+        .find_map(|extension| name.strip_suffix(extension))
+        .map_or(name.clone(), str::to_string)
+}
+
+#[async_trait]
+impl NativeTool for RunCommandTool {
+    fn definition(&self) -> Tool {
+        let mut description = format!(
+            "Runs a shell command line in {} (or the relative cwd below it) and returns its exit code and output",
+            self.root.display()
+        );
+        if let Some(allowed) = &self.allowed {
+            let mut allowed: Vec<&str> = allowed.iter().map(String::as_str).collect();
+            allowed.sort();
+            description.push_str(&format!(". Allowed programs: {}", allowed.join(", ")));
+        }
+        Tool::new(
+            RUN_COMMAND_TOOL,
+            description,
+            schema(json!({
+                "type": "object",
+                "properties": {
+                    "command": {"type": "string"},
+                    "cwd": {"type": "string"}
+                },
+                "required": ["command"]
+            })),
+        )
+    }
+
+    async fn call(&self, arguments: &JsonObject) -> Result<CallToolResult> {
+        let command = string_argument(arguments, "command")?;
+        self.check_command(command)?;
+        let dir = self.working_dir(arguments.get("cwd").and_then(Value::as_str))?;
+        if !self.approve(command, &dir).await? {
+            return Err(AgentError::ToolError(
+                "User rejected the command".to_string(),
+            ));
+        }
+
+        let (shell, flag) = if cfg!(windows) {
+            ("cmd", "/C")
+        } else {
+            ("sh", "-c")
+        };
+        let output = tokio::process::Command::new(shell)
+            .arg(flag)
+            .arg(command)
+            .current_dir(&dir)
+            .kill_on_drop(true)
+            .output();
+        let output = tokio::time::timeout(self.timeout, output)
+            .await
+            .map_err(|_| {
+                AgentError::Timeout(format!("Command did not finish within {:?}", self.timeout))
+            })??;
+
+        let mut content = vec![Content::text(format!(
+            "exit code: {}",
+            output
+                .status
+                .code()
+                .map_or("none".to_string(), |code| code.to_string())
+        ))];
+        content.push(Content::text(self.truncate(&output.stdout)));
+        if !output.stderr.is_empty() {
+            content.push(Content::text(self.truncate(&output.stderr)));
+        }
+        Ok(if output.status.success() {
+            CallToolResult::success(content)
+        } else {
+            CallToolResult::error(content)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn arguments(value: Value) -> JsonObject {
+        value.as_object().cloned().unwrap_or_default()
+    }
+
+    #[test]
+    fn test_windows_program_paths_match_their_listed_names() {
+        assert_eq!(program_name(r"C:\Tools\RM.EXE", true), "rm");
+        assert_eq!(program_name("npx.cmd", true), "npx");
+        assert_eq!(program_name("/usr/bin/rm", false), "rm");
+        assert_eq!(program_name("RM", false), "RM");
+    }
+
+    #[tokio::test]
+    async fn test_run_command_checks_every_program_and_truncates_output() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let tool = RunCommandTool::new(dir.path())?
+            .with_allowed(["echo", "wc"])
+            .with_max_output_bytes(4)
+            .with_approval(CommandApproval::Always);
+
+        let result = tool
+            .call(&arguments(json!({"command": "echo haiku | wc -c"})))
+            .await?;
+        let long = tool
+            .call(&arguments(json!({"command": "echo an old silent pond"})))
+            .await?;
+        let piped = tool
+            .call(&arguments(json!({"command": "echo x | rm -rf /"})))
+            .await;
+        let unlisted = tool.call(&arguments(json!({"command": "ls"}))).await;
+        let hidden = tool
+            .call(&arguments(json!({"command": "echo $(cat /etc/passwd)"})))
+            .await;
+
+        assert_eq!(json!(result.content)[0]["text"], "exit code: 0");
+        assert_eq!(
+            json!(result.content)[1]["text"].as_str().map(str::trim),
+            Some("6")
+        );
+        assert!(json!(long.content)[1]["text"]
+            .as_str()
+            .is_some_and(|text| text.starts_with("an o\n[truncated")));
+        assert!(
+            matches!(piped, Err(AgentError::ToolError(message)) if message.contains("'rm' is denied"))
+        );
+        assert!(matches!(unlisted, Err(AgentError::ToolError(_))));
+        assert!(matches!(hidden, Err(AgentError::ToolError(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_programs_cannot_hide_from_the_check_or_name_paths_outside_the_root() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let tool = RunCommandTool::new(dir.path())?;
+
+        for command in [
+            "{ rm -rf x; }",
+            "if true; then rm x; fi",
+            "env rm x",
+            "sh -c 'rm x'",
+            "ls | xargs rm",
+            "r''m x",
+            "\\rm x",
+            "echo haiku > ../poem.txt",
+            "cat < /etc/passwd",
+            "cat /etc/passwd",
+            "cat ../../secret",
+            "ls --directory=~/.ssh",
+        ] {
+            assert!(
+                matches!(tool.check_command(command), Err(AgentError::ToolError(_))),
+                "{} was let through",
+                command
+            );
+        }
+        assert!(tool.check_command("grep -r pond poems | wc -l").is_ok());
+        Ok(())
+    }
+
+    fn refusal(tool: &RunCommandTool, command: &str) -> String {
+        match tool.check_command(command) {
+            Err(AgentError::ToolError(message)) => message,
+            other => panic!("{} was let through: {:?}", command, other),
+        }
+    }
+
+    #[test]
+    fn test_find_may_not_run_or_delete() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let tool = RunCommandTool::new(dir.path())?;
+
+        assert!(refusal(&tool, "find . -name '*.md' -exec rm {} \\;").contains("-exec"));
+        assert!(refusal(&tool, "find . -execdir rm {} +").contains("-exec"));
+        assert!(refusal(&tool, "find . -name draft -delete").contains("-delete"));
+        assert!(tool.check_command("find . -name '*.md' -print").is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_awk_may_not_call_system() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let tool = RunCommandTool::new(dir.path())?;
+
+        assert!(refusal(&tool, "awk 'BEGIN { system(\"rm x\") }'").contains("system()"));
+        assert!(refusal(&tool, "gawk 'BEGIN { system (\"ls\") }'").contains("system()"));
+        assert!(tool.check_command("awk '{ print }' poems.txt").is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_variables_may_not_name_paths() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let tool = RunCommandTool::new(dir.path())?;
+
+        for command in [
+            "cat $HOME/.ssh/id_rsa",
+            "ls ${PWD%/*}",
+            "cp x $OLDPWD/",
+            "cat \"$HOME\"/.netrc",
+        ] {
+            assert!(refusal(&tool, command).contains("Variable expansion"));
+        }
+        assert!(tool.check_command("cat poems/pond.md").is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_xargs_may_not_run_programs() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let tool = RunCommandTool::new(dir.path())?.with_allowed(["ls", "xargs", "echo"]);
+
+        assert!(refusal(&tool, "ls | xargs -0 echo").contains("'xargs' runs other programs"));
+        assert!(refusal(&tool, "ls | x'a'rgs rm").contains("'xargs' runs other programs"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_git_may_not_set_config() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let tool = RunCommandTool::new(dir.path())?;
+
+        assert!(refusal(&tool, "git -c alias.pond='!rm -rf .' pond").contains("git -c"));
+        assert!(refusal(&tool, "git -C poems -c core.pager=rm log").contains("git -c"));
+        assert!(refusal(&tool, "git --config-env=alias.pond=POND pond").contains("git -c"));
+        assert!(tool.check_command("git -C poems grep -c pond").is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_interpreters_may_not_run_code_from_the_command_line() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let tool = RunCommandTool::new(dir.path())?;
+
+        for command in [
+            "python -c 'import shutil; shutil.rmtree(\"poems\")'",
+            "python3 -Sc 'print(1)'",
+            "perl -e 'unlink \"poem\"'",
+            "perl -ne 'print' poems.txt",
+            "ruby -e 'File.delete(\"poem\")'",
+            "node --eval 'require(\"fs\").rmSync(\"poems\")'",
+            "php -r 'unlink(\"poem\");'",
+        ] {
+            assert!(refusal(&tool, command).contains("on the command line"));
+        }
+        assert!(refusal(&tool, "sh -c 'rm x'").contains("'sh' runs other programs"));
+        assert!(tool.check_command("python3 haiku.py").is_ok());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_run_command_stays_inside_its_root_and_needs_approval() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        std::fs::create_dir(dir.path().join("poems"))?;
+        let tool = RunCommandTool::new(dir.path())?.with_approval(CommandApproval::Always);
+
+        let inside = tool
+            .call(&arguments(json!({"command": "pwd", "cwd": "poems"})))
+            .await?;
+        let outside = tool
+            .call(&arguments(json!({"command": "pwd", "cwd": "../.."})))
+            .await;
+        let rejected = RunCommandTool::new(dir.path())?
+            .with_approval(CommandApproval::Never)
+            .call(&arguments(json!({"command": "pwd"})))
+            .await;
+
+        assert!(json!(inside.content)[1]["text"]
+            .as_str()
+            .is_some_and(|text| text.trim().ends_with("poems")));
+        assert!(matches!(outside, Err(AgentError::ToolError(_))));
+        assert!(
+            matches!(rejected, Err(AgentError::ToolError(message)) if message.contains("rejected"))
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,299 @@
+use super::{schema, string_argument, NativeTool};
+use crate::error::{AgentError, Result};
+use async_trait::async_trait;
+use regex::Regex;
+use reqwest::Url;
+use rmcp::model::{CallToolResult, Content, JsonObject, Tool};
+use serde_json::json;
+use std::collections::HashSet;
+use std::net::IpAddr;
+use std::sync::{Arc, OnceLock};
+
+pub const FETCH_URL_TOOL: &str = "fetch_url";
+pub const DEFAULT_MAX_FETCH_BYTES: usize = 512 * 1024;
+
+// Fetches pages from allowed domains only, redirects included, and returns them as text.
+// Without an allowlist any public host may be fetched
+pub struct FetchUrlTool {
+    allowed_domains: Option<Arc<HashSet<String>>>,
+    max_bytes: usize,
+    http: reqwest::Client,
+}
+
+impl FetchUrlTool {
+    // A domain also allows its subdomains
+    pub fn new<I, S>(allowed_domains: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self::restricted_to(Some(Arc::new(
+            allowed_domains
+                .into_iter()
+                .map(|domain| domain.into().trim_start_matches('.').to_lowercase())
+                .collect(),
+        )))
+    }
+
+    // For research across the web. Hosts on this machine or the local network are still
+    // refused when named directly, by name or address
+    pub fn public_web() -> Result<Self> {
+        Self::restricted_to(None)
+    }
+
+    fn restricted_to(allowed_domains: Option<Arc<HashSet<String>>>) -> Result<Self> {
+        let redirect_domains = allowed_domains.clone();
+        let http = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::custom(move |attempt| {
+                if attempt.previous().len() >= 5 {
+                    attempt.error("too many redirects")
+                } else if domain_allowed(redirect_domains.as_deref(), attempt.url()) {
+                    attempt.follow()
+                } else {
+                    attempt.stop()
+                }
+            }))
+            .build()?;
+        Ok(Self {
+            allowed_domains,
+            max_bytes: DEFAULT_MAX_FETCH_BYTES,
+            http,
+        })
+    }
+
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    fn check_url(&self, raw: &str) -> Result<Url> {
+        let url = Url::parse(raw)
+            .map_err(|err| AgentError::ToolError(format!("Invalid URL '{}': {}", raw, err)))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(AgentError::ToolError(format!(
+                "Only http(s) URLs can be fetched, got '{}'",
+                raw
+            )));
+        }
+        if !domain_allowed(self.allowed_domains.as_deref(), &url) {
+            return Err(AgentError::ToolError(format!(
+                "Domain of '{}' is not in the allowlist",
+                raw
+            )));
+        }
+        Ok(url)
+    }
+}
+
+fn domain_allowed(allowed: Option<&HashSet<String>>, url: &Url) -> bool {
+    let Some(host) = url.host_str().map(str::to_lowercase) else {
+        return false;
+    };
+    let Some(allowed) = allowed else {
+        return public_host(&host);
+    };
+    allowed.iter().any(|domain| {
+        host == *domain
+            || host
+                .strip_suffix(domain.as_str())
+                .is_some_and(|prefix| prefix.ends_with('.'))
+    })
+}
+
+fn public_host(host: &str) -> bool {
+    let ip = match host.trim_matches(['[', ']']).parse::<IpAddr>() {
+        // ::ffff:127.0.0.1 reaches 127.0.0.1
+        Ok(IpAddr::V6(ip)) => Ok(ip.to_ipv4_mapped().map_or(IpAddr::V6(ip), IpAddr::V4)),
+        ip => ip,
+    };
+    match ip {
+        // Also 0.0.0.0/8, which reaches this host, and carrier-grade NAT (100.64.0.0/10)
+        Ok(IpAddr::V4(ip)) => {
+            let [first, second, ..] = ip.octets();
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || first == 0
+                || (first == 100 && (second & 0xc0) == 64))
+        }
+        // Also unique local (fc00::/7) and link-local (fe80::/10) addresses
+        Ok(IpAddr::V6(ip)) => {
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || (ip.segments()[0] & 0xfe00) == 0xfc00
+                || (ip.segments()[0] & 0xffc0) == 0xfe80)
+        }
+        Err(_) => {
+            !(host == "localhost" || host.ends_with(".localhost") || host.ends_with(".local"))
+        }
+    }
+}
+
+#[async_trait]
+impl NativeTool for FetchUrlTool {
+    fn definition(&self) -> Tool {
+        let mut description =
+            "Fetches a web page with GET and returns its text, with HTML markup removed"
+                .to_string();
+        if let Some(allowed) = &self.allowed_domains {
+            let mut domains: Vec<&str> = allowed.iter().map(String::as_str).collect();
+            domains.sort();
+            description.push_str(&format!(". Allowed domains: {}", domains.join(", ")));
+        }
+        Tool::new(
+            FETCH_URL_TOOL,
+            description,
+            schema(json!({
+                "type": "object",
+                "properties": {"url": {"type": "string"}},
+                "required": ["url"]
+            })),
+        )
+    }
+
+    async fn call(&self, arguments: &JsonObject) -> Result<CallToolResult> {
+        let url = self.check_url(string_argument(arguments, "url")?)?;
+        let mut response = self.http.get(url).send().await?;
+        let status = response.status();
+        let html = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.contains("html"));
+
+        // Read no more than the limit, whatever the server claims the length is
+        let mut body = Vec::new();
+        let mut truncated = false;
+        while let Some(chunk) = response.chunk().await? {
+            let room = self.max_bytes - body.len();
+            if chunk.len() > room {
+                body.extend_from_slice(&chunk[..room]);
+                truncated = true;
+                break;
+            }
+            body.extend_from_slice(&chunk);
+        }
+        let body = String::from_utf8_lossy(&body);
+        let mut text = if html {
+            html_to_text(&body)
+        } else {
+            body.into_owned()
+        };
+        if truncated {
+            text.push_str(&format!("\n[truncated after {} bytes]", self.max_bytes));
+        }
+
+        let content = vec![Content::text(text)];
+        Ok(if status.is_success() {
+            CallToolResult::success(content)
+        } else {
+            CallToolResult::error(content)
+        })
+    }
+}
+
+// Readable text from a page: no scripts, styles or tags, one block per line
+pub fn html_to_text(html: &str) -> String {
+    static HIDDEN: OnceLock<Regex> = OnceLock::new();
+    static BREAKS: OnceLock<Regex> = OnceLock::new();
+    static TAGS: OnceLock<Regex> = OnceLock::new();
+    let hidden = HIDDEN.get_or_init(|| {
+        Regex::new(
+            r"(?is)<(script|style|noscript|head)\b.*?</(script|style|noscript|head)\s*>|<!--.*?-->",
+        )
+        .unwrap()
+    });
+    let breaks = BREAKS.get_or_init(|| {
+        Regex::new(r"(?i)<br\s*/?>|</?(p|div|li|tr|h[1-6]|section|article|blockquote|pre)\b[^>]*>")
+            .unwrap()
+    });
+    let tags = TAGS.get_or_init(|| Regex::new(r"(?s)<[^>]*>").unwrap());
+
+    let text = hidden.replace_all(html, "");
+    let text = breaks.replace_all(&text, "\n");
+    let text = tags.replace_all(&text, "");
+    let text = text
+        .replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&");
+    text.lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_html_to_text_keeps_only_visible_text() {
+        let html = "<html><head><title>Pond</title></head><body><script>track()</script>\
+            <h1>Basho</h1><p>An old  silent pond&nbsp;&amp; a frog</p><ul><li>one</li><li>two</li></ul></body></html>";
+
+        assert_eq!(
+            html_to_text(html),
+            "Basho\nAn old silent pond & a frog\none\ntwo"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fetch_url_rejects_domains_outside_the_allowlist() -> Result<()> {
+        let tool = FetchUrlTool::new(["example.com"])?;
+
+        assert!(tool.check_url("https://docs.example.com/page").is_ok());
+        assert!(tool.check_url("https://example.com.evil.net/").is_err());
+        assert!(tool.check_url("file:///etc/passwd").is_err());
+        let denied = tool
+            .call(
+                &json!({"url": "https://evil.net/"})
+                    .as_object()
+                    .cloned()
+                    .unwrap_or_default(),
+            )
+            .await;
+        assert!(matches!(denied, Err(AgentError::ToolError(_))));
+        let web = FetchUrlTool::public_web()?;
+        assert!(web.check_url("https://evil.net/").is_ok());
+        for local in [
+            "http://localhost:8080/",
+            "http://127.0.0.1/",
+            "http://192.168.1.1/admin",
+            "http://[::1]/",
+            "http://printer.local/",
+        ] {
+            assert!(web.check_url(local).is_err(), "{}", local);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_public_web_rejects_mapped_shared_and_this_network_addresses() -> Result<()> {
+        let web = FetchUrlTool::public_web()?;
+
+        for local in [
+            "http://[::ffff:127.0.0.1]/",
+            "http://[::ffff:169.254.169.254]/latest/meta-data/",
+            "http://[::ffff:10.0.0.1]/",
+            "http://100.64.0.1/",
+            "http://100.127.255.254/",
+            "http://0.0.0.0/",
+            "http://0.1.2.3/",
+        ] {
+            assert!(web.check_url(local).is_err(), "{}", local);
+        }
+        for public in [
+            "http://100.63.255.255/",
+            "http://100.128.0.1/",
+            "http://[::ffff:93.184.216.34]/",
+        ] {
+            assert!(web.check_url(public).is_ok(), "{}", public);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,231 @@
+use super::{schema, string_argument, NativeTool};
+use crate::error::{AgentError, Result};
+use async_trait::async_trait;
+use rmcp::model::{CallToolResult, Content, JsonObject, Tool};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+pub const SEND_MESSAGE_TOOL: &str = "send_message";
+pub const READ_MESSAGES_TOOL: &str = "read_messages";
+// Addressing a message to this name delivers it to every other member
+pub const BROADCAST: &str = "all";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MailMessage {
+    pub from: String,
+    pub to: String,
+    pub text: String,
+}
+
+// Inboxes for agents working side by side; each member reads only its own
+#[derive(Clone, Default)]
+pub struct Mailbox {
+    inboxes: Arc<Mutex<BTreeMap<String, VecDeque<MailMessage>>>>,
+}
+
+impl Mailbox {
+    pub fn new<I, S>(members: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let inboxes = members
+            .into_iter()
+            .map(|member| (member.into(), VecDeque::new()))
+            .collect();
+        Self {
+            inboxes: Arc::new(Mutex::new(inboxes)),
+        }
+    }
+
+    pub fn members(&self) -> Vec<String> {
+        self.lock().keys().cloned().collect()
+    }
+
+    pub fn send(&self, from: &str, to: &str, text: &str) -> Result<()> {
+        let mut inboxes = self.lock();
+        let recipients: Vec<String> = if to == BROADCAST {
+            inboxes
+                .keys()
+                .filter(|member| member.as_str() != from)
+                .cloned()
+                .collect()
+        } else if inboxes.contains_key(to) {
+            vec![to.to_string()]
+        } else {
+            return Err(AgentError::ToolError(format!(
+                "No agent named '{}'; members are {}",
+                to,
+                inboxes.keys().cloned().collect::<Vec<_>>().join(", ")
+            )));
+        };
+        for recipient in recipients {
+            if let Some(inbox) = inboxes.get_mut(&recipient) {
+                inbox.push_back(MailMessage {
+                    from: from.to_string(),
+                    to: to.to_string(),
+                    text: text.to_string(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    // Empties the member's inbox
+    pub fn take(&self, member: &str) -> Vec<MailMessage> {
+        self.lock()
+            .get_mut(member)
+            .map(|inbox| inbox.drain(..).collect())
+            .unwrap_or_default()
+    }
+
+    // send_message and read_messages on behalf of `member`
+    pub fn tools(&self, member: &str) -> Vec<Box<dyn NativeTool>> {
+        vec![
+            Box::new(SendMessageTool {
+                mailbox: self.clone(),
+                member: member.to_string(),
+            }),
+            Box::new(ReadMessagesTool {
+                mailbox: self.clone(),
+                member: member.to_string(),
+            }),
+        ]
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<String, VecDeque<MailMessage>>> {
+        self.inboxes
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+// Tells a member who else is working and how to reach them
+pub fn tool_instructions(member: &str, members: &[String]) -> String {
+    let others: Vec<&str> = members
+        .iter()
+        .map(String::as_str)
+        .filter(|other| *other != member)
+        .collect();
+    format!(
+        "You are '{}', working alongside {}. Send them notes with the '{}' tool (arguments: to, text; to '{}' reaches everyone) and check for theirs with '{}'.",
+        member,
+        others.join(", "),
+        SEND_MESSAGE_TOOL,
+        BROADCAST,
+        READ_MESSAGES_TOOL
+    )
+}
+
+pub struct SendMessageTool {
+    mailbox: Mailbox,
+    member: String,
+}
+
+#[async_trait]
+impl NativeTool for SendMessageTool {
+    fn definition(&self) -> Tool {
+        Tool::new(
+            SEND_MESSAGE_TOOL,
+            "Sends a message to another agent by name, or to all of them",
+            schema(json!({
+                "type": "object",
+                "properties": {"to": {"type": "string"}, "text": {"type": "string"}},
+                "required": ["to", "text"]
+            })),
+        )
+    }
+
+    async fn call(&self, arguments: &JsonObject) -> Result<CallToolResult> {
+        let to = string_argument(arguments, "to")?;
+        self.mailbox
+            .send(&self.member, to, string_argument(arguments, "text")?)?;
+        Ok(CallToolResult::success(vec![Content::text(format!(
+            "Sent to {}",
+            to
+        ))]))
+    }
+}
+
+pub struct ReadMessagesTool {
+    mailbox: Mailbox,
+    member: String,
+}
+
+#[async_trait]
+impl NativeTool for ReadMessagesTool {
+    fn definition(&self) -> Tool {
+        Tool::new(
+            READ_MESSAGES_TOOL,
+            "Returns the messages other agents sent you since you last checked",
+            schema(json!({"type": "object", "properties": {}})),
+        )
+    }
+
+    async fn call(&self, _arguments: &JsonObject) -> Result<CallToolResult> {
+        let messages = self.mailbox.take(&self.member);
+        let text = if messages.is_empty() {
+            "No new messages.".to_string()
+        } else {
+            messages
+                .iter()
+                .map(|message| format!("From {}: {}", message.from, message.text))
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        Ok(CallToolResult::success(vec![Content::text(text)]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::native_tools::NativeTools;
+    use serde_json::Value;
+
+    fn arguments(value: Value) -> JsonObject {
+        value.as_object().cloned().unwrap_or_default()
+    }
+
+    #[tokio::test]
+    async fn test_messages_reach_the_addressed_inboxes_once() -> Result<()> {
+        let mailbox = Mailbox::new(["poet", "critic", "editor"]);
+        let mut poet = NativeTools::new();
+        for tool in mailbox.tools("poet") {
+            poet.register(tool);
+        }
+        let send = poet
+            .get(SEND_MESSAGE_TOOL)
+            .expect("send_message is registered");
+        let read = poet
+            .get(READ_MESSAGES_TOOL)
+            .expect("read_messages is registered");
+
+        send.call(&arguments(
+            json!({"to": "critic", "text": "Draft is in pond.txt"}),
+        ))
+        .await?;
+        send.call(&arguments(
+            json!({"to": BROADCAST, "text": "Done for today"}),
+        ))
+        .await?;
+        let inbox = read.call(&JsonObject::new()).await?;
+
+        assert_eq!(json!(inbox.content)[0]["text"], "No new messages.");
+        assert_eq!(mailbox.take("critic").len(), 2);
+        assert!(mailbox.take("critic").is_empty());
+        assert_eq!(
+            mailbox.take("editor"),
+            vec![MailMessage {
+                from: "poet".to_string(),
+                to: BROADCAST.to_string(),
+                text: "Done for today".to_string(),
+            }]
+        );
+        assert!(mailbox.take("poet").is_empty());
+        assert!(mailbox.send("poet", "nobody", "hello").is_err());
+        Ok(())
+    }
+}
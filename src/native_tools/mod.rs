@@ -0,0 +1,165 @@
+mod ask_user;
+mod command;
+mod fetch;
+mod mailbox;
+mod math;
+mod scratchpad;
+mod test_runner;
+
+pub use ask_user::{
+    tool_instructions as ask_user_instructions, AskUserTool, Clarifier, TerminalClarifier,
+    ASK_USER_TOOL,
+};
+pub use command::{
+    CommandApproval, RunCommandTool, DEFAULT_COMMAND_TIMEOUT, DEFAULT_DENIED_COMMANDS,
+    DEFAULT_MAX_OUTPUT_BYTES, RUN_COMMAND_TOOL,
+};
+pub use fetch::{html_to_text, FetchUrlTool, DEFAULT_MAX_FETCH_BYTES, FETCH_URL_TOOL};
+pub use mailbox::{
+    tool_instructions as mailbox_instructions, MailMessage, Mailbox, ReadMessagesTool,
+    SendMessageTool, BROADCAST, READ_MESSAGES_TOOL, SEND_MESSAGE_TOOL,
+};
+pub use math::evaluate;
+pub use scratchpad::{
+    tool_instructions as scratchpad_instructions, RecallTool, RememberTool, Scratchpad,
+    RECALL_TOOL, REMEMBER_TOOL,
+};
+pub use test_runner::{RunTestsTool, DEFAULT_TEST_TIMEOUT, RUN_TESTS_TOOL};
+
+use crate::clock::{system_clock, Clock};
+use crate::error::{AgentError, Result};
+use async_trait::async_trait;
+use rmcp::model::{CallToolResult, Content, JsonObject, Tool};
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+// Tools implemented in-process, listed and called exactly like MCP server tools
+#[async_trait]
+pub trait NativeTool: Send + Sync {
+    fn definition(&self) -> Tool;
+
+    async fn call(&self, arguments: &JsonObject) -> Result<CallToolResult>;
+}
+
+#[derive(Default)]
+pub struct NativeTools {
+    tools: Vec<Box<dyn NativeTool>>,
+}
+
+impl NativeTools {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Time and math; the shell and fetch tools need an explicit allowlist
+    pub fn with_defaults() -> Self {
+        let mut tools = Self::new();
+        tools.register(Box::new(CurrentTimeTool::new()));
+        tools.register(Box::new(MathEvalTool));
+        tools
+    }
+
+    pub fn register(&mut self, tool: Box<dyn NativeTool>) {
+        self.tools.push(tool);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn NativeTool> {
+        self.tools
+            .iter()
+            .find(|tool| tool.definition().name == name)
+            .map(|tool| tool.as_ref())
+    }
+
+    pub fn definitions(&self) -> Vec<Tool> {
+        self.tools.iter().map(|tool| tool.definition()).collect()
+    }
+}
+
+fn schema(value: Value) -> Arc<JsonObject> {
+    match value {
+        Value::Object(object) => Arc::new(object),
+        _ => Arc::new(JsonObject::new()),
+    }
+}
+
+fn string_argument<'a>(arguments: &'a JsonObject, name: &str) -> Result<&'a str> {
+    arguments
+        .get(name)
+        .and_then(Value::as_str)
+        .ok_or_else(|| AgentError::ToolError(format!("Missing string argument '{}'", name)))
+}
+
+pub struct CurrentTimeTool {
+    clock: Arc<dyn Clock>,
+}
+
+impl CurrentTimeTool {
+    pub fn new() -> Self {
+        Self::with_clock(system_clock())
+    }
+
+    pub fn with_clock(clock: Arc<dyn Clock>) -> Self {
+        Self { clock }
+    }
+}
+
+impl Default for CurrentTimeTool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl NativeTool for CurrentTimeTool {
+    fn definition(&self) -> Tool {
+        Tool::new(
+            "current_time",
+            "Returns the current date and time in RFC 3339 format (UTC)",
+            schema(json!({"type": "object", "properties": {}})),
+        )
+    }
+
+    async fn call(&self, _arguments: &JsonObject) -> Result<CallToolResult> {
+        Ok(CallToolResult::success(vec![Content::text(
+            self.clock.now().to_rfc3339(),
+        )]))
+    }
+}
+
+pub struct MathEvalTool;
+
+#[async_trait]
+impl NativeTool for MathEvalTool {
+    fn definition(&self) -> Tool {
+        Tool::new(
+            "math_eval",
+            "Evaluates an arithmetic expression with + - * / % ^ and parentheses",
+            schema(json!({
+                "type": "object",
+                "properties": {"expression": {"type": "string"}},
+                "required": ["expression"]
+            })),
+        )
+    }
+
+    async fn call(&self, arguments: &JsonObject) -> Result<CallToolResult> {
+        let value = evaluate(string_argument(arguments, "expression")?)?;
+        Ok(CallToolResult::success(vec![Content::text(
+            value.to_string(),
+        )]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry_finds_tools_by_name() {
+        let tools = NativeTools::with_defaults();
+
+        assert!(tools.get("math_eval").is_some());
+        assert!(tools.get("http_fetch").is_none());
+        assert_eq!(tools.definitions().len(), 2);
+    }
+}
@@ -0,0 +1,171 @@
+use super::{schema, string_argument, NativeTool};
+use crate::error::Result;
+use async_trait::async_trait;
+use rmcp::model::{CallToolResult, Content, JsonObject, Tool};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+pub const REMEMBER_TOOL: &str = "remember";
+pub const RECALL_TOOL: &str = "recall";
+
+// Working memory for intermediate results; values only enter the prompt when recalled
+#[derive(Clone, Default)]
+pub struct Scratchpad {
+    entries: Arc<Mutex<BTreeMap<String, String>>>,
+}
+
+impl Scratchpad {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(&self, key: &str, value: &str) {
+        self.lock().insert(key.to_string(), value.to_string());
+    }
+
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.lock().get(key).cloned()
+    }
+
+    pub fn keys(&self) -> Vec<String> {
+        self.lock().keys().cloned().collect()
+    }
+
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    pub fn entries(&self) -> BTreeMap<String, String> {
+        self.lock().clone()
+    }
+
+    pub fn replace(&self, entries: BTreeMap<String, String>) {
+        *self.lock() = entries;
+    }
+
+    // The remember and recall tools, both backed by this scratchpad
+    pub fn tools(&self) -> Vec<Box<dyn NativeTool>> {
+        vec![
+            Box::new(RememberTool {
+                scratchpad: self.clone(),
+            }),
+            Box::new(RecallTool {
+                scratchpad: self.clone(),
+            }),
+        ]
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<String, String>> {
+        self.entries
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+// Tells the model the scratchpad exists and when to use it
+pub fn tool_instructions() -> String {
+    format!(
+        "You have a scratchpad for intermediate results. Save one with the '{}' tool (arguments: key, value) and read it back with '{}' (argument: key; omit it to list the saved keys) instead of repeating it in the conversation.",
+        REMEMBER_TOOL, RECALL_TOOL
+    )
+}
+
+pub struct RememberTool {
+    scratchpad: Scratchpad,
+}
+
+#[async_trait]
+impl NativeTool for RememberTool {
+    fn definition(&self) -> Tool {
+        Tool::new(
+            REMEMBER_TOOL,
+            "Saves a value in the scratchpad under a key, replacing any earlier value",
+            schema(json!({
+                "type": "object",
+                "properties": {"key": {"type": "string"}, "value": {"type": "string"}},
+                "required": ["key", "value"]
+            })),
+        )
+    }
+
+    async fn call(&self, arguments: &JsonObject) -> Result<CallToolResult> {
+        let key = string_argument(arguments, "key")?;
+        self.scratchpad
+            .set(key, string_argument(arguments, "value")?);
+        Ok(CallToolResult::success(vec![Content::text(format!(
+            "Saved '{}'",
+            key
+        ))]))
+    }
+}
+
+pub struct RecallTool {
+    scratchpad: Scratchpad,
+}
+
+#[async_trait]
+impl NativeTool for RecallTool {
+    fn definition(&self) -> Tool {
+        Tool::new(
+            RECALL_TOOL,
+            "Returns the scratchpad value saved under a key, or lists the saved keys when no key is given",
+            schema(json!({
+                "type": "object",
+                "properties": {"key": {"type": "string"}}
+            })),
+        )
+    }
+
+    async fn call(&self, arguments: &JsonObject) -> Result<CallToolResult> {
+        let Some(key) = arguments.get("key").and_then(Value::as_str) else {
+            let keys = self.scratchpad.keys();
+            let listing = if keys.is_empty() {
+                "The scratchpad is empty.".to_string()
+            } else {
+                format!("Saved keys: {}", keys.join(", "))
+            };
+            return Ok(CallToolResult::success(vec![Content::text(listing)]));
+        };
+        Ok(match self.scratchpad.get(key) {
+            Some(value) => CallToolResult::success(vec![Content::text(value)]),
+            None => CallToolResult::error(vec![Content::text(format!(
+                "Nothing saved under '{}'",
+                key
+            ))]),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::native_tools::NativeTools;
+
+    fn arguments(value: Value) -> JsonObject {
+        value.as_object().cloned().unwrap_or_default()
+    }
+
+    #[tokio::test]
+    async fn test_recall_returns_what_was_remembered() -> Result<()> {
+        let scratchpad = Scratchpad::new();
+        let mut tools = NativeTools::new();
+        for tool in scratchpad.tools() {
+            tools.register(tool);
+        }
+        let remember = tools.get(REMEMBER_TOOL).expect("remember is registered");
+        let recall = tools.get(RECALL_TOOL).expect("recall is registered");
+
+        remember
+            .call(&arguments(json!({"key": "total", "value": "42"})))
+            .await?;
+        let value = recall.call(&arguments(json!({"key": "total"}))).await?;
+        let keys = recall.call(&JsonObject::new()).await?;
+        let missing = recall.call(&arguments(json!({"key": "other"}))).await?;
+
+        assert_eq!(json!(value.content)[0]["text"], "42");
+        assert_eq!(json!(keys.content)[0]["text"], "Saved keys: total");
+        assert_eq!(missing.is_error, Some(true));
+        Ok(())
+    }
+}
@@ -0,0 +1,153 @@
+use super::{schema, NativeTool, DEFAULT_MAX_OUTPUT_BYTES};
+use crate::error::{AgentError, Result};
+use crate::workspace;
+use async_trait::async_trait;
+use rmcp::model::{CallToolResult, Content, JsonObject, Tool};
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+pub const RUN_TESTS_TOOL: &str = "run_tests";
+pub const DEFAULT_TEST_TIMEOUT: Duration = Duration::from_secs(600);
+
+// Runs the project's one test command in its root, optionally narrowed by a filter argument.
+// The command is fixed up front, so the model can check its work without a shell
+pub struct RunTestsTool {
+    root: PathBuf,
+    command: Vec<String>,
+    timeout: Duration,
+    max_output_bytes: usize,
+}
+
+impl RunTestsTool {
+    pub fn new(root: impl AsRef<Path>, command: &str) -> Result<Self> {
+        let command: Vec<String> = command.split_whitespace().map(str::to_string).collect();
+        if command.is_empty() {
+            return Err(AgentError::ConfigError(
+                "The test command is empty".to_string(),
+            ));
+        }
+        Ok(Self {
+            root: workspace::canonicalize(root.as_ref())?,
+            command,
+            timeout: DEFAULT_TEST_TIMEOUT,
+            max_output_bytes: DEFAULT_MAX_OUTPUT_BYTES,
+        })
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn with_max_output_bytes(mut self, max_output_bytes: usize) -> Self {
+        self.max_output_bytes = max_output_bytes;
+        self
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    // Failures are reported at the end of a test run, so it's the start that gets cut
+    fn truncate(&self, output: &[u8]) -> String {
+        if output.len() <= self.max_output_bytes {
+            return String::from_utf8_lossy(output).into_owned();
+        }
+        let cut = output.len() - self.max_output_bytes;
+        format!(
+            "[truncated {} of {} bytes]\n{}",
+            cut,
+            output.len(),
+            String::from_utf8_lossy(&output[cut..])
+        )
+    }
+}
+
+#[async_trait]
+impl NativeTool for RunTestsTool {
+    fn definition(&self) -> Tool {
+        Tool::new(
+            RUN_TESTS_TOOL,
+            format!(
+                "Runs the tests ({}) in {} and returns whether they passed with their output",
+                self.command.join(" "),
+                self.root.display()
+            ),
+            schema(json!({
+                "type": "object",
+                "properties": {
+                    "filter": {
+                        "type": "string",
+                        "description": "Only run the tests this argument selects"
+                    }
+                }
+            })),
+        )
+    }
+
+    // Failing tests are a result for the model to act on, not a failed call
+    async fn call(&self, arguments: &JsonObject) -> Result<CallToolResult> {
+        let filter = arguments
+            .get("filter")
+            .and_then(Value::as_str)
+            .filter(|filter| !filter.trim().is_empty());
+        let output = tokio::process::Command::new(&self.command[0])
+            .args(&self.command[1..])
+            .args(filter)
+            .current_dir(&self.root)
+            .kill_on_drop(true)
+            .output();
+        let output = tokio::time::timeout(self.timeout, output)
+            .await
+            .map_err(|_| {
+                AgentError::Timeout(format!("Tests did not finish within {:?}", self.timeout))
+            })??;
+
+        let verdict = match output.status.code() {
+            Some(0) => "tests passed".to_string(),
+            Some(code) => format!("tests failed with exit code {}", code),
+            None => "tests were killed".to_string(),
+        };
+        let mut content = vec![Content::text(verdict)];
+        content.push(Content::text(self.truncate(&output.stdout)));
+        if !output.stderr.is_empty() {
+            content.push(Content::text(self.truncate(&output.stderr)));
+        }
+        Ok(CallToolResult::success(content))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_run_tests_reports_failures_with_the_end_of_the_output() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        std::fs::write(
+            dir.path().join("check.sh"),
+            "echo running ${1:-all}\necho 'frog: FAILED'\n[ \"$1\" = pond ]\n",
+        )?;
+        let tool = RunTestsTool::new(dir.path(), "sh check.sh")?.with_max_output_bytes(14);
+
+        let all = tool.call(&JsonObject::new()).await?;
+        let filtered = tool
+            .call(json!({"filter": "pond"}).as_object().unwrap())
+            .await?;
+
+        assert_eq!(all.is_error, Some(false));
+        assert_eq!(
+            json!(all.content)[0]["text"],
+            "tests failed with exit code 1"
+        );
+        assert!(json!(all.content)[1]["text"]
+            .as_str()
+            .is_some_and(
+                |text| text.starts_with("[truncated") && text.ends_with("\nfrog: FAILED\n")
+            ));
+        assert_eq!(json!(filtered.content)[0]["text"], "tests passed");
+        assert!(RunTestsTool::new(dir.path(), " ").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,451 @@
+use crate::agent::Agent;
+use crate::error::{AgentError, Result};
+use crate::mcp_client::MCPClient;
+use crate::model_client::{ChatMessage, ModelClient};
+use crate::native_tools::{mailbox_instructions, Mailbox, READ_MESSAGES_TOOL, SEND_MESSAGE_TOOL};
+use crate::redaction::Redactor;
+use crate::task::{Artifact, Task, TaskResult};
+use crate::workspace::Workspaces;
+use futures_util::future::join_all;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+// Builds an MCP client whose server is rooted at the given directory
+pub type McpClientFactory = Box<dyn Fn(&Path) -> MCPClient + Send + Sync>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubAgentSpec {
+    pub name: String,
+    pub system_prompt: String,
+    pub allowed_tools: Vec<String>,
+}
+
+impl SubAgentSpec {
+    pub fn new(name: &str, system_prompt: &str, allowed_tools: &[&str]) -> Self {
+        Self {
+            name: name.to_string(),
+            system_prompt: system_prompt.to_string(),
+            allowed_tools: allowed_tools.iter().map(|tool| tool.to_string()).collect(),
+        }
+    }
+
+    fn system_message(&self) -> ChatMessage {
+        let tools = if self.allowed_tools.is_empty() {
+            "none".to_string()
+        } else {
+            self.allowed_tools.join(", ")
+        };
+        ChatMessage::system(format!(
+            "{}\nYou may only use these tools: {}",
+            self.system_prompt, tools
+        ))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Delegation {
+    pub sub_agent: String,
+    pub result: TaskResult,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrchestrationResult {
+    pub delegations: Vec<Delegation>,
+}
+
+impl OrchestrationResult {
+    pub fn success(&self) -> bool {
+        self.delegations
+            .iter()
+            .all(|delegation| delegation.result.success)
+    }
+
+    pub fn artifacts(&self) -> Vec<Artifact> {
+        self.delegations
+            .iter()
+            .flat_map(|delegation| delegation.result.artifacts.clone())
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CollaborationResult {
+    pub delegations: Vec<Delegation>,
+    // The coordinator's merge of what the delegations produced
+    pub merged: TaskResult,
+}
+
+impl CollaborationResult {
+    pub fn success(&self) -> bool {
+        self.merged.success
+    }
+}
+
+// Runs tasks on short-lived child agents, each with its own prompt, tools and MCP session
+pub struct Orchestrator {
+    model: Arc<dyn ModelClient>,
+    mcp_factory: McpClientFactory,
+    sub_agents: Vec<SubAgentSpec>,
+    workspaces: Option<Workspaces>,
+    redactor: Option<Redactor>,
+}
+
+impl Orchestrator {
+    pub fn new(model: Arc<dyn ModelClient>, mcp_factory: McpClientFactory) -> Self {
+        Self {
+            model,
+            mcp_factory,
+            sub_agents: Vec::new(),
+            workspaces: None,
+            redactor: None,
+        }
+    }
+
+    pub fn with_sub_agent(mut self, spec: SubAgentSpec) -> Self {
+        self.sub_agents.push(spec);
+        self
+    }
+
+    // Run in a fresh workspace instead of the current directory: one per delegate call,
+    // shared by the steps of a run so they can hand files to each other
+    pub fn with_workspaces(mut self, workspaces: Workspaces) -> Self {
+        self.workspaces = Some(workspaces);
+        self
+    }
+
+    // Masks secrets in what every sub-agent sends to the model and keeps in transcripts
+    pub fn with_redactor(mut self, redactor: Redactor) -> Self {
+        self.redactor = Some(redactor);
+        self
+    }
+
+    fn workspace(&self, name: &str) -> Result<Option<PathBuf>> {
+        self.workspaces
+            .as_ref()
+            .map(|workspaces| workspaces.create(name))
+            .transpose()
+    }
+
+    pub async fn delegate(&self, sub_agent: &str, task: &Task) -> Result<TaskResult> {
+        let workspace = self.workspace(sub_agent)?;
+        self.delegate_in(sub_agent, task, workspace, None).await
+    }
+
+    // With a mailbox the child can also message the others working on it
+    async fn delegate_in(
+        &self,
+        sub_agent: &str,
+        task: &Task,
+        workspace: Option<PathBuf>,
+        mailbox: Option<&Mailbox>,
+    ) -> Result<TaskResult> {
+        let spec = self
+            .sub_agents
+            .iter()
+            .find(|spec| spec.name == sub_agent)
+            .ok_or_else(|| AgentError::ToolError(format!("Unknown sub-agent '{}'", sub_agent)))?;
+
+        let root = workspace.clone().unwrap_or_else(|| PathBuf::from("."));
+        let mut mcp_client = (self.mcp_factory)(&root);
+        mcp_client.init().await?;
+        let mut builder = Agent::builder()
+            .model(Box::new(self.model.clone()))
+            .mcp_client(mcp_client)
+            .message(spec.system_message());
+        let mut allowed_tools = spec.allowed_tools.clone();
+        if let Some(mailbox) = mailbox {
+            for tool in mailbox.tools(sub_agent) {
+                builder = builder.native_tool(tool);
+            }
+            builder = builder.message(ChatMessage::system(mailbox_instructions(
+                sub_agent,
+                &mailbox.members(),
+            )));
+            allowed_tools.extend([
+                SEND_MESSAGE_TOOL.to_string(),
+                READ_MESSAGES_TOOL.to_string(),
+            ]);
+        }
+        builder = builder.allow_tools(allowed_tools);
+        if let Some(redactor) = &self.redactor {
+            builder = builder.redactor(redactor.clone());
+        }
+        if let Some(workspace) = workspace {
+            builder = builder.workspace(workspace);
+        }
+        let mut child = builder.build().await?;
+
+        let result = child.run_task(task).await;
+        child.shutdown().await?;
+        result
+    }
+
+    // Run the steps in order, handing each sub-agent the artifacts produced so far
+    pub async fn run(&self, steps: &[(String, Task)]) -> Result<OrchestrationResult> {
+        let workspace = self.workspace("run")?;
+        let mut delegations: Vec<Delegation> = Vec::new();
+        for (sub_agent, task) in steps {
+            let mut task = task.clone();
+            let previous: Vec<Artifact> = delegations
+                .iter()
+                .flat_map(|delegation| delegation.result.artifacts.clone())
+                .collect();
+            if !previous.is_empty() {
+                task.description.push_str(&format!(
+                    "\n\nResults from earlier steps:\n{}",
+                    serde_json::to_string(&previous)?
+                ));
+            }
+
+            let result = self
+                .delegate_in(sub_agent, &task, workspace.clone(), None)
+                .await?;
+            let failed = !result.success;
+            delegations.push(Delegation {
+                sub_agent: sub_agent.clone(),
+                result,
+            });
+            if failed {
+                break;
+            }
+        }
+        Ok(OrchestrationResult { delegations })
+    }
+
+    // Run the assignments at the same time in one workspace, letting the sub-agents message
+    // each other, then have the coordinator merge their outputs into `goal`'s result
+    pub async fn collaborate(
+        &self,
+        assignments: &[(String, Task)],
+        coordinator: &str,
+        goal: &Task,
+    ) -> Result<CollaborationResult> {
+        let workspace = self.workspace("collaboration")?;
+        let mailbox = Mailbox::new(assignments.iter().map(|(sub_agent, _)| sub_agent.clone()));
+        let results = join_all(assignments.iter().map(|(sub_agent, task)| {
+            self.delegate_in(sub_agent, task, workspace.clone(), Some(&mailbox))
+        }))
+        .await;
+        let mut delegations = Vec::new();
+        for ((sub_agent, _), result) in assignments.iter().zip(results) {
+            delegations.push(Delegation {
+                sub_agent: sub_agent.clone(),
+                result: result?,
+            });
+        }
+
+        let mut outputs = Vec::new();
+        for delegation in &delegations {
+            let mut output = format!(
+                "- {} ({}): {}",
+                delegation.sub_agent,
+                if delegation.result.success {
+                    "succeeded"
+                } else {
+                    "failed"
+                },
+                serde_json::to_string(&delegation.result.artifacts)?
+            );
+            if let Some(error) = &delegation.result.error {
+                output.push_str(&format!(" error: {}", error));
+            }
+            outputs.push(output);
+        }
+        let mut merge = goal.clone();
+        merge.description.push_str(&format!(
+            "\n\nMerge what these agents produced into the final result:\n{}",
+            outputs.join("\n")
+        ));
+        let merged = self
+            .delegate_in(coordinator, &merge, workspace, None)
+            .await?;
+        Ok(CollaborationResult {
+            delegations,
+            merged,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model_client::{CompletionRequest, ModelResponse};
+    use crate::replay::{Fixture, Interaction, McpExchange, ReplayModelClient};
+    use serde_json::json;
+
+    fn write_call(path: &str) -> serde_json::Value {
+        json!({
+            "jsonrpc": "2.0",
+            "method": "tools/call",
+            "params": {"name": "write_file", "arguments": {"path": path, "content": "..."}},
+            "id": 1
+        })
+    }
+
+    #[tokio::test]
+    async fn test_sub_agent_cannot_call_tools_outside_its_set() -> Result<()> {
+        let fixture = Fixture {
+            interactions: vec![Interaction::model_reply(write_call("haiku.txt")); 3],
+        };
+        let mcp_fixture = Fixture::initialized_mcp();
+        let orchestrator = Orchestrator::new(
+            Arc::new(ReplayModelClient::new(&fixture)),
+            Box::new(move |_: &Path| MCPClient::replaying(&mcp_fixture)),
+        )
+        .with_sub_agent(SubAgentSpec::new(
+            "reader",
+            "You read files",
+            &["read_file"],
+        ));
+
+        let result = orchestrator
+            .delegate("reader", &Task::new("Write a haiku"))
+            .await?;
+
+        assert!(!result.success);
+        assert!(result.error.unwrap().contains("not allowed"));
+        assert!(result.artifacts.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_run_aggregates_artifacts_across_sub_agents() -> Result<()> {
+        let fixture = Fixture::from_replies([
+            write_call("draft.txt"),
+            json!({"final_answer": "Drafted"}),
+            write_call("final.txt"),
+            json!({"final_answer": "Polished"}),
+        ]);
+        let mcp_fixture = Fixture {
+            interactions: vec![
+                Interaction::Mcp(McpExchange::new(&json!({}), &Ok(json!({})))),
+                Interaction::Mcp(McpExchange::new(&json!({}), &Ok(json!({})))),
+            ],
+        };
+        let orchestrator = Orchestrator::new(
+            Arc::new(ReplayModelClient::new(&fixture)),
+            Box::new(move |_: &Path| MCPClient::replaying(&mcp_fixture)),
+        )
+        .with_sub_agent(SubAgentSpec::new("writer", "You write", &["write_file"]))
+        .with_sub_agent(SubAgentSpec::new("editor", "You edit", &["write_file"]));
+
+        let result = orchestrator
+            .run(&[
+                ("writer".to_string(), Task::new("Draft a haiku")),
+                ("editor".to_string(), Task::new("Polish the haiku")),
+            ])
+            .await?;
+
+        assert!(result.success());
+        assert_eq!(
+            result.artifacts(),
+            vec![
+                Artifact::File {
+                    path: "draft.txt".to_string(),
+                    stored: None,
+                },
+                Artifact::Text {
+                    text: "Drafted".to_string()
+                },
+                Artifact::File {
+                    path: "final.txt".to_string(),
+                    stored: None,
+                },
+                Artifact::Text {
+                    text: "Polished".to_string()
+                },
+            ]
+        );
+        Ok(())
+    }
+
+    // Answers each sub-agent from its own script, picked by its system prompt, since
+    // collaborators call the model in no fixed order
+    struct ScriptedRoles {
+        scripts: std::sync::Mutex<Vec<(&'static str, Vec<serde_json::Value>)>>,
+    }
+
+    #[async_trait::async_trait]
+    impl ModelClient for ScriptedRoles {
+        async fn complete(&self, request: &CompletionRequest) -> Result<ModelResponse> {
+            let mut scripts = self.scripts.lock().unwrap();
+            let (_, script) = scripts
+                .iter_mut()
+                .find(|(role, _)| request.messages[0].content.starts_with(role))
+                .expect("a script for every role");
+            Ok(ModelResponse {
+                response: script.remove(0).to_string(),
+                reasoning: None,
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_collaborators_message_each_other_and_the_coordinator_merges() -> Result<()> {
+        let call = |name: &str, arguments: serde_json::Value| {
+            json!({
+                "jsonrpc": "2.0",
+                "method": "tools/call",
+                "params": {"name": name, "arguments": arguments},
+                "id": 1
+            })
+        };
+        let model = ScriptedRoles {
+            scripts: std::sync::Mutex::new(vec![
+                (
+                    "You write",
+                    vec![
+                        call(
+                            "send_message",
+                            json!({"to": "critic", "text": "Draft is ready"}),
+                        ),
+                        json!({"final_answer": "An old silent pond"}),
+                    ],
+                ),
+                (
+                    "You review",
+                    vec![
+                        call("read_messages", json!({})),
+                        json!({"final_answer": "Keep it"}),
+                    ],
+                ),
+                (
+                    "You merge",
+                    vec![json!({"final_answer": "An old silent pond (approved)"})],
+                ),
+            ]),
+        };
+        let mcp_fixture = Fixture::initialized_mcp();
+        let orchestrator = Orchestrator::new(
+            Arc::new(model),
+            Box::new(move |_: &Path| MCPClient::replaying(&mcp_fixture)),
+        )
+        .with_sub_agent(SubAgentSpec::new("poet", "You write", &[]))
+        .with_sub_agent(SubAgentSpec::new("critic", "You review", &[]))
+        .with_sub_agent(SubAgentSpec::new("coordinator", "You merge", &[]));
+
+        let result = orchestrator
+            .collaborate(
+                &[
+                    ("poet".to_string(), Task::new("Write a haiku")),
+                    ("critic".to_string(), Task::new("Review the poet's haiku")),
+                ],
+                "coordinator",
+                &Task::new("Deliver a reviewed haiku"),
+            )
+            .await?;
+
+        assert!(result.success());
+        assert!(result
+            .delegations
+            .iter()
+            .all(|delegation| delegation.result.success));
+        assert_eq!(
+            result.merged.artifacts,
+            vec![Artifact::Text {
+                text: "An old silent pond (approved)".to_string()
+            }]
+        );
+        Ok(())
+    }
+}
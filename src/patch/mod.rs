@@ -0,0 +1,226 @@
+use crate::error::{AgentError, Result};
+
+const SEARCH_MARKER: &str = "<<<<<<< SEARCH";
+const DIVIDER: &str = "=======";
+const REPLACE_MARKER: &str = ">>>>>>> REPLACE";
+
+// Lines to find in a file and what to put in their place. A diff hunk knows where it starts,
+// which settles which match is meant when the lines occur more than once
+#[derive(Debug, Clone, PartialEq)]
+pub struct Replacement {
+    pub search: Vec<String>,
+    pub replace: Vec<String>,
+    pub line: Option<usize>,
+}
+
+// An edit as a model writes it: search/replace blocks, or else a unified diff
+pub fn parse(edit: &str) -> Result<Vec<Replacement>> {
+    let replacements = if edit.contains(SEARCH_MARKER) {
+        parse_blocks(edit)?
+    } else {
+        parse_unified_diff(edit)?
+    };
+    if replacements.is_empty() {
+        return Err(invalid(
+            "expected search/replace blocks or a unified diff with @@ hunks",
+        ));
+    }
+    Ok(replacements)
+}
+
+fn parse_blocks(edit: &str) -> Result<Vec<Replacement>> {
+    let mut replacements = Vec::new();
+    let mut lines = edit.lines();
+    while let Some(line) = lines.next() {
+        if line.trim() != SEARCH_MARKER {
+            continue;
+        }
+        let search = take_until(&mut lines, DIVIDER)
+            .ok_or_else(|| invalid("a SEARCH block has no ======= line"))?;
+        let replace = take_until(&mut lines, REPLACE_MARKER)
+            .ok_or_else(|| invalid("a SEARCH block has no >>>>>>> REPLACE line"))?;
+        replacements.push(Replacement {
+            search,
+            replace,
+            line: None,
+        });
+    }
+    Ok(replacements)
+}
+
+fn take_until<'a>(lines: &mut impl Iterator<Item = &'a str>, end: &str) -> Option<Vec<String>> {
+    let mut taken = Vec::new();
+    for line in lines.by_ref() {
+        if line.trim() == end {
+            return Some(taken);
+        }
+        taken.push(line.to_string());
+    }
+    None
+}
+
+fn parse_unified_diff(edit: &str) -> Result<Vec<Replacement>> {
+    let mut replacements: Vec<Replacement> = Vec::new();
+    for line in edit.lines() {
+        if let Some(header) = line.strip_prefix("@@") {
+            replacements.push(Replacement {
+                search: Vec::new(),
+                replace: Vec::new(),
+                line: Some(hunk_start(header)?),
+            });
+            continue;
+        }
+        // File headers and anything else before the first hunk
+        let Some(hunk) = replacements.last_mut() else {
+            continue;
+        };
+        if line.starts_with("--- ") || line.starts_with("+++ ") || line.starts_with('\\') {
+            continue;
+        }
+        let mut chars = line.chars();
+        match chars.next() {
+            Some('-') => hunk.search.push(chars.as_str().to_string()),
+            Some('+') => hunk.replace.push(chars.as_str().to_string()),
+            Some(' ') => {
+                hunk.search.push(chars.as_str().to_string());
+                hunk.replace.push(chars.as_str().to_string());
+            }
+            // Models often drop the space in front of an empty context line
+            None => {
+                hunk.search.push(String::new());
+                hunk.replace.push(String::new());
+            }
+            Some(_) => return Err(invalid(&format!("unexpected diff line '{}'", line))),
+        }
+    }
+    Ok(replacements)
+}
+
+// The old start line in "@@ -12,5 +12,6 @@"
+fn hunk_start(header: &str) -> Result<usize> {
+    header
+        .split_whitespace()
+        .find_map(|range| range.strip_prefix('-'))
+        .and_then(|range| range.split(',').next())
+        .and_then(|start| start.parse().ok())
+        .ok_or_else(|| invalid(&format!("malformed hunk header '@@{}'", header)))
+}
+
+// Applies the replacements in order. Lines are compared without trailing whitespace; each
+// search has to match exactly once, or once nearest its hunk's start line. A file with CRLF
+// line endings keeps them
+pub fn apply(content: &str, replacements: &[Replacement]) -> Result<String> {
+    let newline = if content.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+    let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+    for (number, replacement) in replacements.iter().enumerate() {
+        let at = find(&lines, replacement)
+            .map_err(|problem| AgentError::ToolError(format!("Edit {} {}", number + 1, problem)))?;
+        lines.splice(
+            at..at + replacement.search.len(),
+            replacement.replace.iter().cloned(),
+        );
+    }
+    let mut updated = lines.join(newline);
+    if content.ends_with('\n') || (content.is_empty() && !updated.is_empty()) {
+        updated.push_str(newline);
+    }
+    Ok(updated)
+}
+
+fn find(lines: &[String], replacement: &Replacement) -> std::result::Result<usize, String> {
+    let search = &replacement.search;
+    if search.is_empty() {
+        // Only an empty file can be written by a hunk without context
+        if lines.is_empty() {
+            return Ok(0);
+        }
+        return Err("has no lines to find; include some of the existing lines".to_string());
+    }
+    let matches: Vec<usize> = (0..=lines.len().saturating_sub(search.len()))
+        .filter(|&start| {
+            lines.len() >= search.len()
+                && search
+                    .iter()
+                    .zip(&lines[start..])
+                    .all(|(wanted, line)| wanted.trim_end() == line.trim_end())
+        })
+        .collect();
+    match (matches.as_slice(), replacement.line) {
+        ([], _) => Err(format!(
+            "does not apply: these lines are not in the file:\n{}",
+            search.join("\n")
+        )),
+        ([only], _) => Ok(*only),
+        (_, Some(line)) => Ok(*matches
+            .iter()
+            .min_by_key(|start| start.abs_diff(line.saturating_sub(1)))
+            .unwrap()),
+        (_, None) => Err(format!(
+            "is ambiguous: its lines occur {} times; include more of the lines around them",
+            matches.len()
+        )),
+    }
+}
+
+fn invalid(problem: &str) -> AgentError {
+    AgentError::ToolError(format!("Invalid edit: {}", problem))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POEM: &str = "An old silent pond\nA frog jumps into the pond\nsplash! Silence again.\n";
+
+    #[test]
+    fn test_blocks_and_diffs_apply_to_the_lines_they_name() -> Result<()> {
+        let blocks = "Here you go:\n<<<<<<< SEARCH\nA frog jumps into the pond\n=======\nA frog leaps in\n>>>>>>> REPLACE\n";
+        let diff = "--- a/haiku.txt\n+++ b/haiku.txt\n@@ -2,2 +2,2 @@\n A frog jumps into the pond\n-splash! Silence again.\n+Splash! Silence again.\n";
+
+        assert_eq!(
+            apply(POEM, &parse(blocks)?)?,
+            "An old silent pond\nA frog leaps in\nsplash! Silence again.\n"
+        );
+        assert_eq!(
+            apply(POEM, &parse(diff)?)?,
+            "An old silent pond\nA frog jumps into the pond\nSplash! Silence again.\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_non_ascii_diffs_and_crlf_files_keep_their_text() -> Result<()> {
+        let poem = "Un vieil étang\r\nUne grenouille plonge\r\nété\r\n";
+        let diff = "@@ -2,2 +2,2 @@\n Une grenouille plonge\n-été\n+Été\n";
+
+        assert_eq!(
+            apply(poem, &parse(diff)?)?,
+            "Un vieil étang\r\nUne grenouille plonge\r\nÉté\r\n"
+        );
+        assert!(parse("@@ -3 +3 @@\nété\n")
+            .is_err_and(|err| err.to_string().contains("unexpected diff line 'été'")));
+        Ok(())
+    }
+
+    #[test]
+    fn test_edits_that_dont_match_once_are_refused() -> Result<()> {
+        let repeated = "pond\nfrog\npond\n";
+        let missing = parse("<<<<<<< SEARCH\nA crow\n=======\nA heron\n>>>>>>> REPLACE")?;
+        let ambiguous = parse("<<<<<<< SEARCH\npond\n=======\nlake\n>>>>>>> REPLACE")?;
+        let hinted = parse("@@ -3 +3 @@\n-pond\n+lake\n")?;
+
+        assert!(apply(POEM, &missing)
+            .is_err_and(|err| err.to_string().contains("Edit 1 does not apply")));
+        assert!(
+            apply(repeated, &ambiguous).is_err_and(|err| err.to_string().contains("occur 2 times"))
+        );
+        assert_eq!(apply(repeated, &hinted)?, "pond\nfrog\nlake\n");
+        assert!(parse("rewrite the second line").is_err());
+        assert!(parse("<<<<<<< SEARCH\npond\n").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,138 @@
+use crate::agent::{Agent, AgentBuilder, RunOptions, SystemPrompt, EDIT_FILE_TOOL};
+use crate::error::Result;
+use crate::mcp_client::ServerConfig;
+use crate::native_tools::{
+    CommandApproval, NativeTools, RunCommandTool, RunTestsTool, DEFAULT_COMMAND_TIMEOUT,
+    DEFAULT_TEST_TIMEOUT, RUN_COMMAND_TOOL, RUN_TESTS_TOOL,
+};
+use std::path::PathBuf;
+use std::time::Duration;
+
+pub const DEFAULT_TEST_COMMAND: &str = "cargo test";
+// Attempts per plan step: reading, editing and testing often take a few tries
+pub const CODE_MAX_TURNS: usize = 5;
+// Programs the model may run besides the tests, to see what it has changed
+const READ_ONLY_COMMANDS: &[&str] = &["git", "grep", "find", "ls"];
+
+pub const CODE_ASSISTANT_PROMPT: &str = "You are a software engineer making a change to a code repository. Read the code involved before changing it and keep to the style of the surrounding code. Make the smallest change that does the job, one file at a time. Use git through run_command to see what you have changed so far. After changing code, run the tests with run_tests; when they fail, fix the cause rather than the tests. Finish with a short summary of what you changed.";
+
+// A coding agent on the core loop: the filesystem server rooted at the repository, diff-based
+// edits, the test command, and read-only shell commands such as git log and git diff
+pub struct CodePreset {
+    repo: PathBuf,
+    test_command: String,
+    test_timeout: Duration,
+    command_approval: CommandApproval,
+}
+
+impl CodePreset {
+    pub fn new(repo: impl Into<PathBuf>) -> Self {
+        Self {
+            repo: repo.into(),
+            test_command: DEFAULT_TEST_COMMAND.to_string(),
+            test_timeout: DEFAULT_TEST_TIMEOUT,
+            command_approval: CommandApproval::Ask,
+        }
+    }
+
+    pub fn with_test_command(mut self, command: impl Into<String>) -> Self {
+        self.test_command = command.into();
+        self
+    }
+
+    pub fn with_test_timeout(mut self, timeout: Duration) -> Self {
+        self.test_timeout = timeout;
+        self
+    }
+
+    // Whether git and the other shell commands need the user's go-ahead; the tests never do
+    pub fn with_command_approval(mut self, approval: CommandApproval) -> Self {
+        self.command_approval = approval;
+        self
+    }
+
+    pub fn server(&self) -> ServerConfig {
+        ServerConfig::filesystem(&self.repo.to_string_lossy())
+    }
+
+    pub fn native_tools(&self) -> Result<NativeTools> {
+        let mut tools = NativeTools::new();
+        tools.register(Box::new(
+            RunTestsTool::new(&self.repo, &self.test_command)?.with_timeout(self.test_timeout),
+        ));
+        tools.register(Box::new(
+            RunCommandTool::new(&self.repo)?
+                .with_allowed(READ_ONLY_COMMANDS.iter().copied())
+                .with_approval(self.command_approval),
+        ));
+        Ok(tools)
+    }
+
+    // Plans the change first, so reading, editing and testing each get a step of their own
+    pub fn options(&self) -> RunOptions {
+        self.options_over(RunOptions::default())
+    }
+
+    // The preset's planning, turns and timeouts on top of options of your own
+    pub fn options_over(&self, base: RunOptions) -> RunOptions {
+        RunOptions {
+            plan_first: true,
+            max_turns: CODE_MAX_TURNS,
+            ..base
+        }
+        .with_tool_timeout(RUN_TESTS_TOOL, self.test_timeout)
+        .with_tool_timeout(RUN_COMMAND_TOOL, DEFAULT_COMMAND_TIMEOUT)
+    }
+
+    pub fn system_prompt(&self) -> SystemPrompt {
+        SystemPrompt::new(CODE_ASSISTANT_PROMPT)
+            .with_root(&self.repo)
+            .with_rule(format!(
+                "Change existing files with {} and create new ones with write_file.",
+                EDIT_FILE_TOOL
+            ))
+    }
+
+    // Everything but the model and the connection: add .server(preset.server()), or an MCP
+    // client of your own for e.g. replay. Options and the rest can still be overridden
+    pub fn builder(&self) -> Result<AgentBuilder> {
+        Ok(Agent::builder()
+            .generated_system_prompt(self.system_prompt())
+            .native_tools(self.native_tools()?)
+            .file_editing()
+            .options(self.options()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_code_preset_offers_tests_and_git_and_plans_first() -> Result<()> {
+        let repo = tempfile::tempdir()?;
+        let preset = CodePreset::new(repo.path()).with_test_command("make check");
+
+        let tools = preset.native_tools()?.definitions();
+        let options = preset.options();
+        let prompt = preset.system_prompt().render();
+
+        let names: Vec<&str> = tools.iter().map(|tool| tool.name.as_ref()).collect();
+        assert_eq!(names, [RUN_TESTS_TOOL, RUN_COMMAND_TOOL]);
+        assert!(tools[0].description.contains("make check"));
+        assert!(tools[1].description.contains("git"));
+        assert!(options.plan_first);
+        assert_eq!(options.tool_timeout(RUN_TESTS_TOOL), DEFAULT_TEST_TIMEOUT);
+        assert!(
+            preset
+                .options_over(RunOptions {
+                    dry_run: true,
+                    ..RunOptions::default()
+                })
+                .dry_run
+        );
+        assert!(prompt.starts_with(CODE_ASSISTANT_PROMPT));
+        assert!(prompt.contains(EDIT_FILE_TOOL));
+        Ok(())
+    }
+}
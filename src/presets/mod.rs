@@ -0,0 +1,8 @@
+mod code;
+mod research;
+
+pub use code::{CodePreset, CODE_ASSISTANT_PROMPT, CODE_MAX_TURNS, DEFAULT_TEST_COMMAND};
+pub use research::{
+    Report, ResearchPreset, Source, RESEARCH_ASSISTANT_PROMPT, RESEARCH_MAX_TURNS,
+    RESEARCH_TOOL_TIMEOUT,
+};
@@ -0,0 +1,193 @@
+use crate::agent::{Agent, AgentBuilder, RunOptions, SystemPrompt};
+use crate::error::Result;
+use crate::mcp_client::search::SearchEngine;
+use crate::mcp_client::ServerConfig;
+use crate::native_tools::{FetchUrlTool, NativeTools, DEFAULT_MAX_FETCH_BYTES, FETCH_URL_TOOL};
+use crate::task::Task;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::time::Duration;
+
+// Attempts per plan step
+pub const RESEARCH_MAX_TURNS: usize = 4;
+// Searches and page fetches go over the network, unlike most tools
+pub const RESEARCH_TOOL_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub const RESEARCH_ASSISTANT_PROMPT: &str = "You are a research assistant. Answer questions from what you find on the web, not from memory: search for the question, read the most relevant pages, and only state what those pages say. Mark each claim with the number of the source it comes from, e.g. [1], numbering the sources in the order you list them. Say so when the sources disagree or don't answer the question.";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Source {
+    pub title: String,
+    pub url: String,
+}
+
+// The answer to a research question, citing its sources as [1], [2], ...
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Report {
+    pub answer: String,
+    pub sources: Vec<Source>,
+}
+
+impl Report {
+    pub fn schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "answer": {"type": "string"},
+                "sources": {
+                    "type": "array",
+                    "minItems": 1,
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "title": {"type": "string"},
+                            "url": {"type": "string", "pattern": "^https?://"}
+                        },
+                        "required": ["title", "url"]
+                    }
+                }
+            },
+            "required": ["answer", "sources"]
+        })
+    }
+
+    // The answer followed by its numbered sources
+    pub fn render(&self) -> String {
+        let mut rendered = format!("{}\n\nSources:", self.answer.trim());
+        for (number, source) in self.sources.iter().enumerate() {
+            rendered.push_str(&format!(
+                "\n[{}] {} - {}",
+                number + 1,
+                source.title,
+                source.url
+            ));
+        }
+        rendered
+    }
+}
+
+// A research agent on the core loop: a web search server, fetch_url for reading the pages it
+// finds, and a final answer that cites them
+pub struct ResearchPreset {
+    engine: SearchEngine,
+    max_fetch_bytes: usize,
+}
+
+impl ResearchPreset {
+    pub fn new(engine: SearchEngine) -> Self {
+        Self {
+            engine,
+            max_fetch_bytes: DEFAULT_MAX_FETCH_BYTES,
+        }
+    }
+
+    pub fn with_max_fetch_bytes(mut self, max_fetch_bytes: usize) -> Self {
+        self.max_fetch_bytes = max_fetch_bytes;
+        self
+    }
+
+    pub fn server(&self) -> ServerConfig {
+        self.engine.server()
+    }
+
+    pub fn native_tools(&self) -> Result<NativeTools> {
+        let mut tools = NativeTools::new();
+        tools.register(Box::new(
+            FetchUrlTool::public_web()?.with_max_bytes(self.max_fetch_bytes),
+        ));
+        Ok(tools)
+    }
+
+    // Plans first, so searching, reading and answering each get a step of their own
+    pub fn options(&self) -> RunOptions {
+        self.options_over(RunOptions::default())
+    }
+
+    // The preset's planning, turns and timeout on top of options of your own
+    pub fn options_over(&self, base: RunOptions) -> RunOptions {
+        RunOptions {
+            plan_first: true,
+            max_turns: RESEARCH_MAX_TURNS,
+            default_tool_timeout: RESEARCH_TOOL_TIMEOUT,
+            ..base
+        }
+    }
+
+    pub fn system_prompt(&self) -> SystemPrompt {
+        SystemPrompt::new(RESEARCH_ASSISTANT_PROMPT).with_rule(format!(
+            "Read pages with {} before citing them; search results alone are not sources.",
+            FETCH_URL_TOOL
+        ))
+    }
+
+    // The question, with the answer due as a Report
+    pub fn task(&self, question: &str) -> Task {
+        Task::new(question)
+            .with_success_criterion("Every claim in the answer cites a source it came from")
+            .with_output_schema(Report::schema())
+    }
+
+    // Everything but the model and the connection: add .server(preset.server()), or an MCP
+    // client of your own for e.g. replay
+    pub fn builder(&self) -> Result<AgentBuilder> {
+        Ok(Agent::builder()
+            .generated_system_prompt(self.system_prompt())
+            .native_tools(self.native_tools()?)
+            .options(self.options()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mcp_client::MCPClient;
+    use crate::replay::{Fixture, Interaction, McpExchange, ReplayModelClient};
+
+    #[tokio::test]
+    async fn test_research_answers_must_cite_sources() -> Result<()> {
+        let cited = json!({
+            "answer": "Basho wrote it in 1686 [1].",
+            "sources": [{"title": "Old pond", "url": "https://en.wikipedia.org/wiki/Old_pond"}]
+        });
+        let fixture = Fixture {
+            interactions: vec![
+                Interaction::Mcp(McpExchange::new(
+                    &json!({"method": "tools/list"}),
+                    &Ok(json!({"tools": [{
+                        "name": "search",
+                        "description": "Searches DuckDuckGo",
+                        "inputSchema": {"type": "object"}
+                    }]})),
+                )),
+                Interaction::model_reply(
+                    json!({"steps": [{"description": "Answer with sources"}]}),
+                ),
+                Interaction::model_reply(
+                    json!({"final_answer": {"answer": "Basho wrote it in 1686.", "sources": []}}),
+                ),
+                Interaction::model_reply(json!({ "final_answer": cited })),
+            ],
+        };
+        let preset = ResearchPreset::new(SearchEngine::DuckDuckGo);
+        let mut agent = preset
+            .builder()?
+            .model(Box::new(ReplayModelClient::new(&fixture)))
+            .mcp_client(MCPClient::replaying(&fixture))
+            .build()
+            .await?;
+
+        let result = agent
+            .run_task(&preset.task("Who wrote the old pond haiku?"))
+            .await?;
+
+        assert!(result.success, "{:?}", result.error);
+        // The uncited answer was sent back
+        assert_eq!(result.turns, 3);
+        let report: Report = result.output_as()?;
+        assert_eq!(
+            report.render(),
+            "Basho wrote it in 1686 [1].\n\nSources:\n[1] Old pond - https://en.wikipedia.org/wiki/Old_pond"
+        );
+        Ok(())
+    }
+}
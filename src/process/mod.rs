@@ -0,0 +1,95 @@
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use tokio::process::{Child, Command};
+
+// Extensions Windows tries when PATHEXT is not set
+const DEFAULT_PATHEXT: &str = ".COM;.EXE;.BAT;.CMD";
+
+// A command for a program named as on a command line. On Windows that means finding e.g.
+// npx.cmd on PATH, and running batch files through cmd /C since they can't be spawned directly
+pub fn command(program: &str) -> Command {
+    if !cfg!(windows) {
+        return Command::new(program);
+    }
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    let extensions = std::env::var("PATHEXT").unwrap_or_else(|_| DEFAULT_PATHEXT.to_string());
+    match find_program(program, &path, &extensions) {
+        Some(found) if is_batch_file(&found) => {
+            let mut command = Command::new("cmd");
+            command.arg("/C").arg(found);
+            command
+        }
+        Some(found) => Command::new(found),
+        None => Command::new(program),
+    }
+}
+
+// The first file on the search path named program, with one of the extensions unless it has
+// its own. npm installs a shell script called npx next to npx.cmd, and only the latter runs
+pub fn find_program(program: &str, path: &OsStr, extensions: &str) -> Option<PathBuf> {
+    let candidates = |dir: &Path| -> Vec<PathBuf> {
+        if Path::new(program).extension().is_some() {
+            return vec![dir.join(program)];
+        }
+        extensions
+            .split(';')
+            .filter(|extension| !extension.is_empty())
+            .map(|extension| dir.join(format!("{}{}", program, extension)))
+            .collect()
+    };
+    // A path, rather than a bare name, is not looked up
+    if Path::new(program).components().count() > 1 {
+        return candidates(Path::new(""))
+            .into_iter()
+            .find(|name| name.is_file());
+    }
+    std::env::split_paths(path)
+        .flat_map(|dir| candidates(&dir))
+        .find(|name| name.is_file())
+}
+
+fn is_batch_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(OsStr::to_str)
+        .is_some_and(|extension| {
+            extension.eq_ignore_ascii_case("cmd") || extension.eq_ignore_ascii_case("bat")
+        })
+}
+
+// Killing cmd /C on Windows leaves the program it started running, so the whole tree goes.
+// Elsewhere the server is the process we spawned
+pub fn kill_tree(child: &mut Child) {
+    #[cfg(windows)]
+    if let Some(pid) = child.id() {
+        let _ = std::process::Command::new("taskkill")
+            .args(["/PID", &pid.to_string(), "/T", "/F"])
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .status();
+    }
+    let _ = child.start_kill();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_programs_are_found_with_path_extensions() -> std::io::Result<()> {
+        let bin = tempfile::tempdir()?;
+        let other = tempfile::tempdir()?;
+        std::fs::write(bin.path().join("npx"), "")?;
+        std::fs::write(bin.path().join("npx.cmd"), "")?;
+        std::fs::write(bin.path().join("node.exe"), "")?;
+        let path = std::env::join_paths([other.path(), bin.path()]).unwrap();
+
+        let npx = find_program("npx", &path, ".exe;.cmd");
+        let node = find_program("node.exe", &path, ".exe;.cmd");
+
+        assert_eq!(npx, Some(bin.path().join("npx.cmd")));
+        assert!(npx.as_deref().is_some_and(is_batch_file));
+        assert_eq!(node, Some(bin.path().join("node.exe")));
+        assert_eq!(find_program("uvx", &path, ".exe;.cmd"), None);
+        Ok(())
+    }
+}
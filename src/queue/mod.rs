@@ -0,0 +1,383 @@
+#[cfg(feature = "redis")]
+mod redis;
+
+#[cfg(feature = "redis")]
+pub use self::redis::RedisQueue;
+
+use crate::agent::{Agent, PermissionProfile, RunOptions, SystemPrompt};
+use crate::error::Result;
+use crate::generation::tasks::TaskListSpec;
+use crate::generation::ContentGenerator;
+use crate::ids::{random_ids, IdGenerator};
+use crate::model_client::{ChatMessage, ModelClient};
+use crate::orchestrator::McpClientFactory;
+use crate::redaction::Redactor;
+use crate::task::{Task, TaskResult};
+use crate::workspace::Workspaces;
+use async_trait::async_trait;
+use futures_util::future::join_all;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+pub const DEFAULT_WORKERS: usize = 2;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct QueuedTask {
+    pub id: String,
+    pub task: Task,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TaskReport {
+    pub id: String,
+    pub worker: String,
+    pub result: TaskResult,
+}
+
+// Work waiting to be picked up and what the workers made of it; tasks are handed out first in,
+// first out and each to one worker only
+#[async_trait]
+pub trait TaskQueue: Send + Sync {
+    async fn push(&self, task: QueuedTask) -> Result<()>;
+
+    async fn pop(&self) -> Result<Option<QueuedTask>>;
+
+    async fn report(&self, report: TaskReport) -> Result<()>;
+
+    // Every report so far, in the order they came in
+    async fn reports(&self) -> Result<Vec<TaskReport>>;
+}
+
+#[derive(Default)]
+pub struct InMemoryQueue {
+    tasks: Mutex<VecDeque<QueuedTask>>,
+    reports: Mutex<Vec<TaskReport>>,
+}
+
+impl InMemoryQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl TaskQueue for InMemoryQueue {
+    async fn push(&self, task: QueuedTask) -> Result<()> {
+        self.tasks.lock().unwrap().push_back(task);
+        Ok(())
+    }
+
+    async fn pop(&self) -> Result<Option<QueuedTask>> {
+        Ok(self.tasks.lock().unwrap().pop_front())
+    }
+
+    async fn report(&self, report: TaskReport) -> Result<()> {
+        self.reports.lock().unwrap().push(report);
+        Ok(())
+    }
+
+    async fn reports(&self) -> Result<Vec<TaskReport>> {
+        Ok(self.reports.lock().unwrap().clone())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SupervisionResult {
+    pub tasks: Vec<QueuedTask>,
+    // In the order the workers finished, which need not be the order of `tasks`
+    pub reports: Vec<TaskReport>,
+}
+
+impl SupervisionResult {
+    pub fn success(&self) -> bool {
+        self.tasks.iter().all(|task| {
+            self.reports
+                .iter()
+                .any(|report| report.id == task.id && report.result.success)
+        })
+    }
+
+    pub fn summary(&self) -> String {
+        let mut summary = String::new();
+        for task in &self.tasks {
+            let status = match self.reports.iter().find(|report| report.id == task.id) {
+                Some(report) if report.result.success => format!("done by {}", report.worker),
+                Some(report) => format!(
+                    "failed on {}: {}",
+                    report.worker,
+                    report.result.error.as_deref().unwrap_or("unknown error")
+                ),
+                None => "not run".to_string(),
+            };
+            summary.push_str(&format!(
+                "{} {} ({})\n",
+                task.id, task.task.description, status
+            ));
+        }
+        summary
+    }
+}
+
+// A supervisor model splits a goal into tasks on the queue; workers, each a fresh agent per
+// task, pull from it until it is empty and report back
+pub struct Supervisor {
+    model: Arc<dyn ModelClient>,
+    mcp_factory: McpClientFactory,
+    queue: Arc<dyn TaskQueue>,
+    base_messages: Vec<ChatMessage>,
+    generated_prompt: Option<SystemPrompt>,
+    options: RunOptions,
+    workers: usize,
+    max_tasks: usize,
+    workspaces: Option<Workspaces>,
+    ids: Arc<dyn IdGenerator>,
+    permission_profile: Option<PermissionProfile>,
+    redactor: Option<Redactor>,
+}
+
+impl Supervisor {
+    pub fn new(
+        model: Arc<dyn ModelClient>,
+        mcp_factory: McpClientFactory,
+        queue: Arc<dyn TaskQueue>,
+        base_messages: Vec<ChatMessage>,
+    ) -> Self {
+        Self {
+            model,
+            mcp_factory,
+            queue,
+            base_messages,
+            generated_prompt: None,
+            options: RunOptions::default(),
+            workers: DEFAULT_WORKERS,
+            max_tasks: crate::generation::tasks::DEFAULT_MAX_SUBTASKS,
+            workspaces: None,
+            ids: random_ids(),
+            permission_profile: None,
+            redactor: None,
+        }
+    }
+
+    pub fn with_options(mut self, options: RunOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn with_generated_prompt(mut self, prompt: SystemPrompt) -> Self {
+        self.generated_prompt = Some(prompt);
+        self
+    }
+
+    pub fn with_workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+
+    pub fn with_max_tasks(mut self, max_tasks: usize) -> Self {
+        self.max_tasks = max_tasks.max(1);
+        self
+    }
+
+    // Give every task a directory of its own and root its MCP server there
+    pub fn with_workspaces(mut self, workspaces: Workspaces) -> Self {
+        self.workspaces = Some(workspaces);
+        self
+    }
+
+    pub fn with_ids(mut self, ids: Arc<dyn IdGenerator>) -> Self {
+        self.ids = ids;
+        self
+    }
+
+    // Every worker's agent runs under this profile
+    pub fn with_permission_profile(mut self, profile: PermissionProfile) -> Self {
+        self.permission_profile = Some(profile);
+        self
+    }
+
+    // Masks secrets in what every worker sends to the model and keeps in transcripts
+    pub fn with_redactor(mut self, redactor: Redactor) -> Self {
+        self.redactor = Some(redactor);
+        self
+    }
+
+    pub async fn decompose(&self, goal: &str) -> Result<Vec<Task>> {
+        ContentGenerator::new(
+            Box::new(self.model.clone()),
+            TaskListSpec::new(self.max_tasks),
+        )
+        .generate(goal)
+        .await
+    }
+
+    pub async fn enqueue(&self, goal: &str) -> Result<Vec<QueuedTask>> {
+        let mut queued = Vec::new();
+        for task in self.decompose(goal).await? {
+            let task = QueuedTask {
+                id: self.ids.new_id().to_string(),
+                task,
+            };
+            self.queue.push(task.clone()).await?;
+            queued.push(task);
+        }
+        Ok(queued)
+    }
+
+    // Drains the queue with the configured number of workers; returns the reports they made
+    pub async fn work(&self) -> Result<Vec<TaskReport>> {
+        let workers = (1..=self.workers).map(|n| self.worker(format!("worker-{}", n)));
+        let mut reports = Vec::new();
+        for result in join_all(workers).await {
+            reports.extend(result?);
+        }
+        Ok(reports)
+    }
+
+    pub async fn run(&self, goal: &str) -> Result<SupervisionResult> {
+        let tasks = self.enqueue(goal).await?;
+        self.work().await?;
+        let reports = self
+            .queue
+            .reports()
+            .await?
+            .into_iter()
+            .filter(|report| tasks.iter().any(|task| task.id == report.id))
+            .collect();
+        Ok(SupervisionResult { tasks, reports })
+    }
+
+    async fn worker(&self, name: String) -> Result<Vec<TaskReport>> {
+        let mut reports = Vec::new();
+        while let Some(queued) = self.queue.pop().await? {
+            let result = match self.execute(&queued).await {
+                Ok(result) => result,
+                Err(err) => TaskResult {
+                    success: false,
+                    artifacts: Vec::new(),
+                    turns: 0,
+                    error: Some(err.to_string()),
+                    workspace: None,
+                    output: None,
+                    timings: Vec::new(),
+                },
+            };
+            let report = TaskReport {
+                id: queued.id,
+                worker: name.clone(),
+                result,
+            };
+            self.queue.report(report.clone()).await?;
+            reports.push(report);
+        }
+        Ok(reports)
+    }
+
+    async fn execute(&self, queued: &QueuedTask) -> Result<TaskResult> {
+        let workspace = self
+            .workspaces
+            .as_ref()
+            .map(|workspaces| workspaces.create(&queued.id))
+            .transpose()?;
+        let root = workspace.clone().unwrap_or_else(|| PathBuf::from("."));
+        let mut mcp_client = (self.mcp_factory)(&root);
+        mcp_client.init().await?;
+        let mut builder = Agent::builder()
+            .model(Box::new(self.model.clone()))
+            .mcp_client(mcp_client)
+            .options(self.options.clone());
+        for message in &self.base_messages {
+            builder = builder.message(message.clone());
+        }
+        if let Some(prompt) = &self.generated_prompt {
+            builder = builder.generated_system_prompt(prompt.clone());
+        }
+        if let Some(workspace) = workspace {
+            builder = builder.workspace(workspace);
+        }
+        if let Some(profile) = &self.permission_profile {
+            builder = builder.permission_profile(profile.clone());
+        }
+        if let Some(redactor) = &self.redactor {
+            builder = builder.redactor(redactor.clone());
+        }
+        let mut agent = builder.build().await?;
+        let result = agent.run_task(&queued.task).await;
+        agent.shutdown().await?;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ids::SequentialIds;
+    use crate::mcp_client::MCPClient;
+    use crate::replay::{Fixture, ReplayModelClient};
+    use std::path::Path;
+
+    #[tokio::test]
+    async fn test_in_memory_queue_hands_each_task_out_once() -> Result<()> {
+        let queue = InMemoryQueue::new();
+        for id in ["a", "b"] {
+            queue
+                .push(QueuedTask {
+                    id: id.to_string(),
+                    task: Task::new(id),
+                })
+                .await?;
+        }
+
+        assert_eq!(
+            queue.pop().await?.map(|task| task.id),
+            Some("a".to_string())
+        );
+        assert_eq!(
+            queue.pop().await?.map(|task| task.id),
+            Some("b".to_string())
+        );
+        assert_eq!(queue.pop().await?, None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_workers_drain_the_tasks_the_supervisor_enqueued() -> Result<()> {
+        let fixture = Fixture::from_replies([
+            r#"{"tasks": [{"description": "Write a haiku about rain"}, {"description": "Write a haiku about snow"}]}"#,
+            r#"{"final_answer": "done"}"#,
+            r#"{"final_answer": "done"}"#,
+        ]);
+        let mcp_fixture = Fixture::initialized_mcp();
+        let queue = Arc::new(InMemoryQueue::new());
+        // One worker keeps the replayed model responses in order
+        let supervisor = Supervisor::new(
+            Arc::new(ReplayModelClient::new(&fixture)),
+            Box::new(move |_: &Path| MCPClient::replaying(&mcp_fixture)),
+            queue.clone(),
+            vec![ChatMessage::system("You are a poet")],
+        )
+        .with_workers(1)
+        .with_ids(Arc::new(SequentialIds::new()));
+
+        let result = supervisor.run("Write two haiku").await?;
+
+        assert!(result.success());
+        assert_eq!(
+            result
+                .reports
+                .iter()
+                .map(|report| (report.id.as_str(), report.worker.as_str()))
+                .collect::<Vec<_>>(),
+            [
+                ("00000000-0000-0000-0000-000000000001", "worker-1"),
+                ("00000000-0000-0000-0000-000000000002", "worker-1")
+            ]
+        );
+        assert_eq!(queue.pop().await?, None);
+        assert!(result
+            .summary()
+            .contains("Write a haiku about snow (done by worker-1)"));
+        Ok(())
+    }
+}
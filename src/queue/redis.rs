@@ -0,0 +1,59 @@
+use super::{QueuedTask, TaskQueue, TaskReport};
+use crate::error::Result;
+use async_trait::async_trait;
+use redis::aio::MultiplexedConnection;
+use redis::AsyncCommands;
+
+// Lists in Redis, so supervisors and workers in separate processes can share one queue
+pub struct RedisQueue {
+    connection: MultiplexedConnection,
+    tasks_key: String,
+    reports_key: String,
+}
+
+impl RedisQueue {
+    // Keys are `<prefix>:tasks` and `<prefix>:reports`
+    pub async fn connect(url: &str, prefix: &str) -> Result<Self> {
+        let client = redis::Client::open(url)?;
+        Ok(Self {
+            connection: client.get_multiplexed_async_connection().await?,
+            tasks_key: format!("{}:tasks", prefix),
+            reports_key: format!("{}:reports", prefix),
+        })
+    }
+}
+
+#[async_trait]
+impl TaskQueue for RedisQueue {
+    async fn push(&self, task: QueuedTask) -> Result<()> {
+        let mut connection = self.connection.clone();
+        connection
+            .rpush::<_, _, ()>(&self.tasks_key, serde_json::to_string(&task)?)
+            .await?;
+        Ok(())
+    }
+
+    async fn pop(&self) -> Result<Option<QueuedTask>> {
+        let mut connection = self.connection.clone();
+        let task: Option<String> = connection.lpop(&self.tasks_key, None).await?;
+        task.map(|task| serde_json::from_str(&task).map_err(Into::into))
+            .transpose()
+    }
+
+    async fn report(&self, report: TaskReport) -> Result<()> {
+        let mut connection = self.connection.clone();
+        connection
+            .rpush::<_, _, ()>(&self.reports_key, serde_json::to_string(&report)?)
+            .await?;
+        Ok(())
+    }
+
+    async fn reports(&self) -> Result<Vec<TaskReport>> {
+        let mut connection = self.connection.clone();
+        let reports: Vec<String> = connection.lrange(&self.reports_key, 0, -1).await?;
+        reports
+            .iter()
+            .map(|report| serde_json::from_str(report).map_err(Into::into))
+            .collect()
+    }
+}
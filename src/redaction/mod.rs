@@ -0,0 +1,148 @@
+use crate::error::{AgentError, Result};
+use regex::Regex;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+
+pub const REDACTED: &str = "[REDACTED]";
+
+// Shapes of well-known credentials: OpenAI/Anthropic keys, GitHub tokens, AWS key ids, bearer tokens
+const DEFAULT_PATTERNS: &[&str] = &[
+    r"sk-[A-Za-z0-9_-]{20,}",
+    r"gh[pousr]_[A-Za-z0-9]{36,}",
+    r"AKIA[0-9A-Z]{16}",
+    r"(?i)bearer\s+[A-Za-z0-9._~+/-]{20,}=*",
+];
+
+// Environment variables whose values are treated as secrets
+const SECRET_ENV_MARKERS: &[&str] = &["KEY", "TOKEN", "SECRET", "PASSWORD"];
+// Shorter values would mask ordinary words
+const MIN_SECRET_LENGTH: usize = 8;
+
+#[derive(Debug, Clone, Default)]
+pub struct Redactor {
+    secrets: Vec<String>,
+    patterns: Vec<Regex>,
+}
+
+impl Redactor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Default credential patterns plus the values of secret-looking environment variables
+    pub fn from_env() -> Self {
+        let mut redactor = Self::new();
+        for pattern in DEFAULT_PATTERNS {
+            redactor = redactor
+                .with_pattern(pattern)
+                .expect("default patterns are valid");
+        }
+        for (name, value) in std::env::vars() {
+            let name = name.to_uppercase();
+            if SECRET_ENV_MARKERS
+                .iter()
+                .any(|marker| name.contains(marker))
+            {
+                redactor = redactor.with_secret(value);
+            }
+        }
+        redactor
+    }
+
+    pub fn with_secret(mut self, secret: impl Into<String>) -> Self {
+        let secret = secret.into();
+        if secret.len() >= MIN_SECRET_LENGTH && !self.secrets.contains(&secret) {
+            self.secrets.push(secret);
+        }
+        self
+    }
+
+    pub fn with_pattern(mut self, pattern: &str) -> Result<Self> {
+        let regex = Regex::new(pattern).map_err(|err| {
+            AgentError::ParseError(format!("Invalid redaction pattern '{}': {}", pattern, err))
+        })?;
+        self.patterns.push(regex);
+        Ok(self)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.secrets.is_empty() && self.patterns.is_empty()
+    }
+
+    pub fn redact(&self, text: &str) -> String {
+        let mut redacted = text.to_string();
+        for secret in &self.secrets {
+            if redacted.contains(secret.as_str()) {
+                redacted = redacted.replace(secret.as_str(), REDACTED);
+            }
+        }
+        for pattern in &self.patterns {
+            if pattern.is_match(&redacted) {
+                redacted = pattern.replace_all(&redacted, REDACTED).into_owned();
+            }
+        }
+        redacted
+    }
+
+    pub fn redact_value(&self, value: &Value) -> Value {
+        match value {
+            Value::String(text) => Value::String(self.redact(text)),
+            Value::Array(items) => {
+                Value::Array(items.iter().map(|item| self.redact_value(item)).collect())
+            }
+            Value::Object(object) => Value::Object(
+                object
+                    .iter()
+                    .map(|(key, value)| (key.clone(), self.redact_value(value)))
+                    .collect(),
+            ),
+            other => other.clone(),
+        }
+    }
+
+    // Redact every string inside a serializable record, e.g. a transcript entry or event. Fails
+    // rather than hand back a record it could not redact
+    pub fn redact_record<T: Serialize + DeserializeOwned + Clone>(&self, record: &T) -> Result<T> {
+        if self.is_empty() {
+            return Ok(record.clone());
+        }
+        let value = serde_json::to_value(record)?;
+        Ok(serde_json::from_value(self.redact_value(&value))?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_redact_masks_secrets_and_patterns() -> Result<()> {
+        let redactor = Redactor::new()
+            .with_secret("hunter2-password")
+            .with_secret("short")
+            .with_pattern(DEFAULT_PATTERNS[0])?;
+
+        assert_eq!(
+            redactor.redact("login hunter2-password with sk-abcdefghijklmnopqrstuvwx"),
+            "login [REDACTED] with [REDACTED]"
+        );
+        assert_eq!(redactor.redact("short"), "short");
+        assert_eq!(
+            redactor.redact_value(&json!({"args": ["hunter2-password", 1]})),
+            json!({"args": ["[REDACTED]", 1]})
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_records_that_cannot_be_redacted_are_not_returned() {
+        let redactor = Redactor::new().with_secret("hunter2-password");
+        // JSON object keys must be strings
+        let record = std::collections::HashMap::from([((1, 2), "hunter2-password".to_string())]);
+
+        assert!(redactor.redact_record(&record).is_err());
+        assert!(Redactor::new().redact_record(&record).is_ok());
+    }
+}
@@ -0,0 +1,271 @@
+use crate::error::{AgentError, Result};
+use crate::model_client::{CompletionRequest, ModelCapabilities, ModelClient, ModelResponse};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ModelExchange {
+    pub request: CompletionRequest,
+    pub response: ModelResponse,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct McpExchange {
+    pub request: Value,
+    pub result: Option<Value>,
+    pub error: Option<String>,
+}
+
+impl McpExchange {
+    pub fn new(request: &Value, outcome: &Result<Value>) -> Self {
+        Self {
+            request: request.clone(),
+            result: outcome.as_ref().ok().cloned(),
+            error: outcome.as_ref().err().map(|err| err.to_string()),
+        }
+    }
+
+    pub fn into_result(self) -> Result<Value> {
+        match self.error {
+            Some(error) => Err(AgentError::ToolError(error)),
+            None => Ok(self.result.unwrap_or(Value::Null)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Interaction {
+    Model(ModelExchange),
+    Mcp(McpExchange),
+}
+
+impl Interaction {
+    // A model reply to whatever it was asked, as a scripted conversation has it
+    pub fn model_reply(response: impl ToString) -> Self {
+        Self::Model(ModelExchange {
+            request: CompletionRequest::default(),
+            response: ModelResponse {
+                response: response.to_string(),
+                reasoning: None,
+            },
+        })
+    }
+}
+
+// Shared between the model and MCP sides so the fixture keeps their relative order
+#[derive(Debug, Clone, Default)]
+pub struct Recorder {
+    interactions: Arc<Mutex<Vec<Interaction>>>,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&self, interaction: Interaction) {
+        self.interactions.lock().unwrap().push(interaction);
+    }
+
+    pub fn fixture(&self) -> Fixture {
+        Fixture {
+            interactions: self.interactions.lock().unwrap().clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Fixture {
+    pub interactions: Vec<Interaction>,
+}
+
+impl Fixture {
+    // The model replies these in order, with no MCP server traffic
+    pub fn from_replies<T: ToString>(replies: impl IntoIterator<Item = T>) -> Self {
+        Self {
+            interactions: replies.into_iter().map(Interaction::model_reply).collect(),
+        }
+    }
+
+    // An MCP server that answers the initialize handshake and nothing else
+    pub fn initialized_mcp() -> Self {
+        Self {
+            interactions: vec![Interaction::Mcp(McpExchange::new(
+                &json!({}),
+                &Ok(json!({})),
+            ))],
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        let interactions = content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(serde_json::from_str)
+            .collect::<std::result::Result<_, _>>()?;
+        Ok(Self { interactions })
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let mut output = String::new();
+        for interaction in &self.interactions {
+            output.push_str(&serde_json::to_string(interaction)?);
+            output.push('\n');
+        }
+        std::fs::write(path, output)?;
+        Ok(())
+    }
+
+    pub fn model_responses(&self) -> VecDeque<ModelResponse> {
+        self.interactions
+            .iter()
+            .filter_map(|interaction| match interaction {
+                Interaction::Model(exchange) => Some(exchange.response.clone()),
+                Interaction::Mcp(_) => None,
+            })
+            .collect()
+    }
+
+    pub fn mcp_exchanges(&self) -> VecDeque<McpExchange> {
+        self.interactions
+            .iter()
+            .filter_map(|interaction| match interaction {
+                Interaction::Mcp(exchange) => Some(exchange.clone()),
+                Interaction::Model(_) => None,
+            })
+            .collect()
+    }
+}
+
+pub struct RecordingModelClient {
+    inner: Box<dyn ModelClient>,
+    recorder: Recorder,
+}
+
+impl RecordingModelClient {
+    pub fn new(inner: Box<dyn ModelClient>, recorder: Recorder) -> Self {
+        Self { inner, recorder }
+    }
+}
+
+#[async_trait]
+impl ModelClient for RecordingModelClient {
+    async fn complete(&self, request: &CompletionRequest) -> Result<ModelResponse> {
+        let response = self.inner.complete(request).await?;
+        self.recorder.record(Interaction::Model(ModelExchange {
+            request: request.clone(),
+            response: response.clone(),
+        }));
+        Ok(response)
+    }
+
+    fn capabilities(&self) -> ModelCapabilities {
+        self.inner.capabilities()
+    }
+}
+
+// Answers with recorded responses in order, ignoring the actual request
+pub struct ReplayModelClient {
+    responses: Mutex<VecDeque<ModelResponse>>,
+}
+
+impl ReplayModelClient {
+    pub fn new(fixture: &Fixture) -> Self {
+        Self {
+            responses: Mutex::new(fixture.model_responses()),
+        }
+    }
+}
+
+#[async_trait]
+impl ModelClient for ReplayModelClient {
+    async fn complete(&self, _request: &CompletionRequest) -> Result<ModelResponse> {
+        self.responses.lock().unwrap().pop_front().ok_or_else(|| {
+            AgentError::ModelError("Replay fixture has no more model responses".to_string())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::Agent;
+    use crate::mcp_client::MCPClient;
+    use crate::model_client::ChatMessage;
+    use crate::transcript::TranscriptEntry;
+    use serde_json::json;
+
+    fn haiku_fixture() -> Fixture {
+        let tool_call = json!({
+            "jsonrpc": "2.0",
+            "method": "tools/call",
+            "params": {"name": "write_file", "arguments": {"path": "haiku.txt", "content": "..."}},
+            "id": 1
+        });
+        Fixture {
+            interactions: vec![
+                Interaction::Mcp(McpExchange::new(
+                    &json!({"method": "initialize"}),
+                    &Ok(json!({})),
+                )),
+                Interaction::Model(ModelExchange {
+                    request: CompletionRequest::new(vec![ChatMessage::user("Write a haiku")]),
+                    response: ModelResponse {
+                        response: tool_call.to_string(),
+                        reasoning: None,
+                    },
+                }),
+                Interaction::Mcp(McpExchange::new(
+                    &tool_call,
+                    &Ok(json!({"content": [{"type": "text", "text": "ok"}]})),
+                )),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_fixture_round_trips_through_file() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("fixture.jsonl");
+
+        haiku_fixture().save(&path)?;
+
+        assert_eq!(Fixture::load(&path)?, haiku_fixture());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_agent_runs_offline_from_fixture() -> Result<()> {
+        let fixture = haiku_fixture();
+        let mut mcp_client = MCPClient::replaying(&fixture);
+        mcp_client.init().await?;
+        let mut agent = Agent::new(
+            Box::new(ReplayModelClient::new(&fixture)),
+            mcp_client,
+            vec![ChatMessage::user("Write a haiku")],
+        );
+
+        agent.run_once().await?;
+
+        assert!(matches!(
+            agent.transcript().entries().last(),
+            Some(TranscriptEntry::ToolResult { .. })
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_replay_model_runs_out_of_responses() {
+        let client = ReplayModelClient::new(&Fixture::default());
+
+        let result = client.complete(&CompletionRequest::default()).await;
+
+        assert!(matches!(result, Err(AgentError::ModelError(_))));
+    }
+}
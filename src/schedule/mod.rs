@@ -0,0 +1,209 @@
+use crate::batch::{BatchOutcome, BatchRunner, BatchTask};
+use crate::clock::{system_clock, Clock};
+use crate::error::{AgentError, Result};
+use crate::task::Task;
+use chrono::{DateTime, Utc};
+use croner::Cron;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::Arc;
+
+// One line of a schedules file: a task and the cron expression saying when it runs
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ScheduledTask {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub cron: String,
+    #[serde(flatten)]
+    pub task: Task,
+}
+
+// Tasks without an id are named after their line number
+pub fn load_schedules(path: &Path) -> Result<Vec<ScheduledTask>> {
+    let contents = std::fs::read_to_string(path)?;
+    let mut schedules = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let mut schedule: ScheduledTask = serde_json::from_str(line).map_err(|err| {
+            AgentError::ParseError(format!("{} line {}: {}", path.display(), index + 1, err))
+        })?;
+        schedule.id.get_or_insert_with(|| (index + 1).to_string());
+        schedules.push(schedule);
+    }
+    Ok(schedules)
+}
+
+// Standard five-field crontab syntax (minute hour day month weekday), evaluated in UTC
+pub fn parse_cron(expression: &str) -> Result<Cron> {
+    Cron::new(expression).parse().map_err(|err| {
+        AgentError::ConfigError(format!("Invalid cron expression '{}': {}", expression, err))
+    })
+}
+
+// Runs tasks on their cron cadence, each run a fresh session through the batch runner, so
+// runs share no conversation or workspace and land in its run store
+pub struct Scheduler {
+    runner: BatchRunner,
+    schedules: Vec<(ScheduledTask, Cron)>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Scheduler {
+    pub fn new(runner: BatchRunner, schedules: Vec<ScheduledTask>) -> Result<Self> {
+        let schedules = schedules
+            .into_iter()
+            .map(|schedule| {
+                let cron = parse_cron(&schedule.cron)?;
+                Ok((schedule, cron))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            runner,
+            schedules,
+            clock: system_clock(),
+        })
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    // Waits for each task to come due and runs it, until `limit` runs have finished or forever
+    // when None. Ticks missed while earlier runs were busy are skipped, not caught up on
+    pub async fn run(
+        &self,
+        limit: Option<usize>,
+        mut on_run: impl FnMut(&BatchOutcome),
+    ) -> Result<()> {
+        let mut after = self.clock.now();
+        let mut runs = 0;
+        while limit.is_none_or(|limit| runs < limit) {
+            let Some((due, indices)) = self.next_due(after) else {
+                return Ok(());
+            };
+            let now = self.clock.now();
+            if due > now {
+                self.clock
+                    .sleep((due - now).to_std().unwrap_or_default())
+                    .await;
+            }
+            let tasks: Vec<BatchTask> = indices
+                .into_iter()
+                .take(limit.map_or(usize::MAX, |limit| limit - runs))
+                .map(|index| {
+                    let (schedule, _) = &self.schedules[index];
+                    BatchTask {
+                        id: Some(format!(
+                            "{}-{}",
+                            schedule.id.as_deref().unwrap_or_default(),
+                            due.format("%Y%m%dT%H%M%S")
+                        )),
+                        task: schedule.task.clone(),
+                    }
+                })
+                .collect();
+            let report = self.runner.run(&tasks).await;
+            for outcome in &report.outcomes {
+                on_run(outcome);
+            }
+            runs += report.outcomes.len();
+            after = self.clock.now().max(due);
+        }
+        Ok(())
+    }
+
+    // The next time after `after` that any task is due, with every task due then
+    fn next_due(&self, after: DateTime<Utc>) -> Option<(DateTime<Utc>, Vec<usize>)> {
+        let times: Vec<Option<DateTime<Utc>>> = self
+            .schedules
+            .iter()
+            .map(|(_, cron)| cron.find_next_occurrence(&after, false).ok())
+            .collect();
+        let due = times.iter().flatten().min().copied()?;
+        let indices = times
+            .iter()
+            .enumerate()
+            .filter(|(_, time)| **time == Some(due))
+            .map(|(index, _)| index)
+            .collect();
+        Some((due, indices))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::RunOptions;
+    use crate::clock::ManualClock;
+    use crate::mcp_client::MCPClient;
+    use crate::replay::{Fixture, ReplayModelClient};
+    use chrono::TimeZone;
+
+    #[tokio::test]
+    async fn test_tasks_run_when_due_each_in_a_fresh_session() -> Result<()> {
+        let fixture = Fixture::from_replies([
+            r#"{"final_answer": "Morning digest"}"#,
+            r#"{"final_answer": "Synced"}"#,
+            "not json",
+        ]);
+        let runner = BatchRunner::new(
+            Arc::new(ReplayModelClient::new(&fixture)),
+            Box::new(|_: &Path| MCPClient::replaying(&Fixture::initialized_mcp())),
+            Vec::new(),
+        )
+        .with_concurrency(1)
+        .with_options(RunOptions {
+            max_turns: 1,
+            ..RunOptions::default()
+        });
+        let schedule = |id: &str, cron: &str| ScheduledTask {
+            id: Some(id.to_string()),
+            cron: cron.to_string(),
+            task: Task::new("Summarize the notes"),
+        };
+        let start = Utc.with_ymd_and_hms(2026, 10, 16, 8, 59, 30).unwrap();
+        let clock = ManualClock::new(start);
+        let scheduler = Scheduler::new(
+            runner,
+            vec![
+                schedule("digest", "0 9 * * *"),
+                schedule("sync", "*/30 * * * *"),
+            ],
+        )?
+        .with_clock(Arc::new(clock.clone()));
+        let mut outcomes = Vec::new();
+
+        scheduler
+            .run(Some(3), |outcome| outcomes.push(outcome.clone()))
+            .await?;
+
+        let ids: Vec<&str> = outcomes.iter().map(|outcome| outcome.id.as_str()).collect();
+        assert_eq!(
+            ids,
+            vec![
+                "digest-20261016T090000",
+                "sync-20261016T090000",
+                "sync-20261016T093000"
+            ]
+        );
+        assert!(outcomes[0].success && outcomes[1].success);
+        assert!(!outcomes[2].success);
+        assert_eq!(
+            clock.now(),
+            Utc.with_ymd_and_hms(2026, 10, 16, 9, 30, 0).unwrap()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_cron_expressions_are_rejected() {
+        assert!(parse_cron("0 9 * * 1-5").is_ok());
+        assert!(matches!(
+            parse_cron("every morning"),
+            Err(AgentError::ConfigError(_))
+        ));
+    }
+}
@@ -0,0 +1,198 @@
+use serde_json::Value;
+
+// Validates the subset of JSON Schema that tool input schemas use in practice:
+// type, enum, const, required, properties, additionalProperties, items, anyOf,
+// and the numeric, string and array bounds. Unknown keywords are ignored.
+pub fn validate(schema: &Value, instance: &Value) -> Vec<String> {
+    validate_named(schema, instance, "arguments")
+}
+
+// As validate, calling the instance `root` in the messages
+pub fn validate_named(schema: &Value, instance: &Value, root: &str) -> Vec<String> {
+    let mut errors = Vec::new();
+    validate_at(schema, instance, root, "", &mut errors);
+    errors
+}
+
+fn validate_at(schema: &Value, instance: &Value, root: &str, path: &str, errors: &mut Vec<String>) {
+    let Some(schema) = schema.as_object() else {
+        return;
+    };
+    let location = if path.is_empty() { root } else { path };
+
+    if let Some(expected) = schema.get("type") {
+        let allowed: Vec<&str> = match expected {
+            Value::String(name) => vec![name.as_str()],
+            Value::Array(names) => names.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !allowed.is_empty() && !allowed.iter().any(|name| has_type(instance, name)) {
+            errors.push(format!(
+                "{} should be {} but is {}",
+                location,
+                allowed.join(" or "),
+                type_name(instance)
+            ));
+            return;
+        }
+    }
+    if let Some(Value::Array(options)) = schema.get("enum") {
+        if !options.contains(instance) {
+            errors.push(format!(
+                "{} should be one of {}",
+                location,
+                Value::Array(options.clone())
+            ));
+        }
+    }
+    if let Some(expected) = schema.get("const") {
+        if expected != instance {
+            errors.push(format!("{} should be {}", location, expected));
+        }
+    }
+    if let Some(Value::Array(alternatives)) = schema.get("anyOf") {
+        let matches_one = alternatives
+            .iter()
+            .any(|alternative| validate(alternative, instance).is_empty());
+        if !matches_one {
+            errors.push(format!("{} matches none of the allowed shapes", location));
+        }
+    }
+
+    match instance {
+        Value::Object(object) => {
+            if let Some(Value::Array(required)) = schema.get("required") {
+                for name in required.iter().filter_map(Value::as_str) {
+                    if !object.contains_key(name) {
+                        errors.push(format!("{} is missing required field '{}'", location, name));
+                    }
+                }
+            }
+            let properties = schema.get("properties").and_then(Value::as_object);
+            for (name, value) in object {
+                let child = format!("{}/{}", path, name);
+                match properties.and_then(|properties| properties.get(name)) {
+                    Some(property) => validate_at(property, value, root, &child, errors),
+                    None if schema.get("additionalProperties") == Some(&Value::Bool(false)) => {
+                        errors.push(format!("{} has unexpected field '{}'", location, name));
+                    }
+                    None => {}
+                }
+            }
+        }
+        Value::Array(items) => {
+            if let Some(item_schema) = schema.get("items") {
+                for (index, item) in items.iter().enumerate() {
+                    validate_at(
+                        item_schema,
+                        item,
+                        root,
+                        &format!("{}/{}", path, index),
+                        errors,
+                    );
+                }
+            }
+            check_bound(
+                schema,
+                "minItems",
+                location,
+                items.len() as f64,
+                false,
+                errors,
+            );
+            check_bound(
+                schema,
+                "maxItems",
+                location,
+                items.len() as f64,
+                true,
+                errors,
+            );
+        }
+        Value::String(text) => {
+            let length = text.chars().count() as f64;
+            check_bound(schema, "minLength", location, length, false, errors);
+            check_bound(schema, "maxLength", location, length, true, errors);
+        }
+        Value::Number(number) => {
+            if let Some(number) = number.as_f64() {
+                check_bound(schema, "minimum", location, number, false, errors);
+                check_bound(schema, "maximum", location, number, true, errors);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn check_bound(
+    schema: &serde_json::Map<String, Value>,
+    keyword: &str,
+    location: &str,
+    actual: f64,
+    upper: bool,
+    errors: &mut Vec<String>,
+) {
+    let Some(bound) = schema.get(keyword).and_then(Value::as_f64) else {
+        return;
+    };
+    if (upper && actual > bound) || (!upper && actual < bound) {
+        errors.push(format!("{} violates {} of {}", location, keyword, bound));
+    }
+}
+
+fn has_type(instance: &Value, name: &str) -> bool {
+    match name {
+        "integer" => instance.as_i64().is_some() || instance.as_u64().is_some(),
+        "number" => instance.is_number(),
+        other => type_name(instance) == other,
+    }
+}
+
+fn type_name(instance: &Value) -> &'static str {
+    match instance {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_validate_reports_each_problem_with_its_location() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "path": {"type": "string", "minLength": 1},
+                "content": {"type": "string"},
+                "lines": {"type": "array", "items": {"type": "integer"}},
+                "mode": {"enum": ["overwrite", "append"]}
+            },
+            "required": ["path", "content"],
+            "additionalProperties": false
+        });
+
+        let errors = validate(
+            &schema,
+            &json!({"path": "", "lines": [1, "two"], "mode": "replace", "extra": true}),
+        );
+
+        assert_eq!(
+            errors,
+            vec![
+                "arguments is missing required field 'content'",
+                "arguments has unexpected field 'extra'",
+                "/lines/1 should be integer but is string",
+                "/mode should be one of [\"overwrite\",\"append\"]",
+                "/path violates minLength of 1",
+            ]
+        );
+        assert!(validate(&schema, &json!({"path": "a", "content": "b"})).is_empty());
+    }
+}
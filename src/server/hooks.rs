@@ -0,0 +1,449 @@
+use crate::batch::{BatchOutcome, BatchRunner, BatchTask};
+use crate::clock::{system_clock, Clock};
+use crate::error::{AgentError, Result};
+use crate::ids::{random_ids, IdGenerator};
+use crate::task::Task;
+use axum::body::Bytes;
+use axum::extract::{Path as UrlPath, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use axum::{Json, Router};
+use hmac::{Hmac, Mac};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+use tokio::sync::{broadcast, Semaphore};
+
+// Slack rejects requests signed longer ago than this, against replays
+const MAX_SIGNATURE_AGE_SECS: i64 = 300;
+const OUTCOME_BACKLOG: usize = 64;
+
+// A webhook accepted at /hooks/<name>, and the task each of its payloads becomes
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Hook {
+    pub name: String,
+    // {a.b} takes field b of field a from the JSON payload, {payload} is all of it
+    pub task: String,
+    // Environment variable holding the secret GitHub or Slack signs the payloads with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret_env: Option<String>,
+    // Accept payloads without a signature; a hook needs either this or secret_env
+    #[serde(default)]
+    pub unsigned: bool,
+}
+
+// A JSON array of hooks
+pub fn load_hooks(path: impl AsRef<Path>) -> Result<Vec<Hook>> {
+    Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+}
+
+// Fills {field.path} placeholders from the payload; missing fields become empty. Other braces,
+// such as JSON in the template, are left alone
+pub fn render_task(template: &str, payload: &Value) -> String {
+    static PLACEHOLDER: OnceLock<Regex> = OnceLock::new();
+    let placeholder = PLACEHOLDER
+        .get_or_init(|| Regex::new(r"\{([A-Za-z0-9_\-]+(?:\.[A-Za-z0-9_\-]+)*)\}").unwrap());
+    placeholder
+        .replace_all(template, |captures: &regex::Captures| {
+            let path = &captures[1];
+            if path == "payload" {
+                return payload.to_string();
+            }
+            let mut value = payload;
+            for key in path.split('.') {
+                value = match value {
+                    Value::Array(items) => key
+                        .parse::<usize>()
+                        .ok()
+                        .and_then(|index| items.get(index))
+                        .unwrap_or(&Value::Null),
+                    _ => value.get(key).unwrap_or(&Value::Null),
+                };
+            }
+            match value {
+                Value::String(text) => text.clone(),
+                Value::Null => String::new(),
+                other => other.to_string(),
+            }
+        })
+        .into_owned()
+}
+
+// Whether the body carries a valid GitHub (X-Hub-Signature-256) or Slack (X-Slack-Signature)
+// signature made with the secret
+pub fn verify_signature(secret: &str, headers: &HeaderMap, body: &[u8], now: i64) -> bool {
+    let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+    if let Some(signature) = header("x-hub-signature-256") {
+        return signature
+            .strip_prefix("sha256=")
+            .is_some_and(|signature| signed(secret, &[body], signature));
+    }
+    if let (Some(signature), Some(timestamp)) = (
+        header("x-slack-signature"),
+        header("x-slack-request-timestamp"),
+    ) {
+        let fresh = timestamp
+            .parse::<i64>()
+            .is_ok_and(|timestamp| (now - timestamp).abs() <= MAX_SIGNATURE_AGE_SECS);
+        let base = format!("v0:{}:", timestamp);
+        return fresh
+            && signature
+                .strip_prefix("v0=")
+                .is_some_and(|signature| signed(secret, &[base.as_bytes(), body], signature));
+    }
+    false
+}
+
+fn signed(secret: &str, parts: &[&[u8]], signature: &str) -> bool {
+    let Some(signature) = decode_hex(signature) else {
+        return false;
+    };
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    for part in parts {
+        mac.update(part);
+    }
+    mac.verify_slice(&signature).is_ok()
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(text.get(index..index + 2)?, 16).ok())
+        .collect()
+}
+
+// Turns webhook payloads into runs, each a fresh session through the batch runner. Senders get
+// 202 with the run's id at once, since runs outlast their delivery timeouts; outcomes go to
+// subscribers. At most the runner's concurrency runs at once; payloads beyond it get 429 to send
+// again later
+pub struct Webhooks {
+    runner: Arc<BatchRunner>,
+    hooks: HashMap<String, (Hook, Option<String>)>,
+    ids: Arc<dyn IdGenerator>,
+    clock: Arc<dyn Clock>,
+    outcomes: broadcast::Sender<BatchOutcome>,
+    runs: Arc<Semaphore>,
+}
+
+impl Webhooks {
+    // Fails when a hook has no secret, or its secret_env is not set, unless the hook is marked
+    // unsigned
+    pub fn new(runner: BatchRunner, hooks: Vec<Hook>) -> Result<Self> {
+        let hooks = hooks
+            .into_iter()
+            .map(|hook| {
+                let secret = match (&hook.secret_env, hook.unsigned) {
+                    (Some(variable), false) => Some(std::env::var(variable).map_err(|_| {
+                        AgentError::ConfigError(format!(
+                            "Hook '{}' needs its secret in {}",
+                            hook.name, variable
+                        ))
+                    })?),
+                    (None, true) => None,
+                    (Some(_), true) => {
+                        return Err(AgentError::ConfigError(format!(
+                            "Hook '{}' has a secret_env and is marked unsigned",
+                            hook.name
+                        )))
+                    }
+                    (None, false) => {
+                        return Err(AgentError::ConfigError(format!(
+                            "Hook '{}' needs a secret_env, or \"unsigned\": true to accept unsigned payloads",
+                            hook.name
+                        )))
+                    }
+                };
+                Ok((hook.name.clone(), (hook, secret)))
+            })
+            .collect::<Result<_>>()?;
+        let (outcomes, _) = broadcast::channel(OUTCOME_BACKLOG);
+        Ok(Self {
+            runs: Arc::new(Semaphore::new(runner.concurrency())),
+            runner: Arc::new(runner),
+            hooks,
+            ids: random_ids(),
+            clock: system_clock(),
+            outcomes,
+        })
+    }
+
+    pub fn with_ids(mut self, ids: Arc<dyn IdGenerator>) -> Self {
+        self.ids = ids;
+        self
+    }
+
+    // Slack signatures are checked for freshness against this clock
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<BatchOutcome> {
+        self.outcomes.subscribe()
+    }
+
+    fn receive(&self, name: &str, headers: &HeaderMap, body: &[u8]) -> Response {
+        let Some((hook, secret)) = self.hooks.get(name) else {
+            return error(StatusCode::NOT_FOUND, format!("No hook named '{}'", name));
+        };
+        if let Some(secret) = secret {
+            if !verify_signature(secret, headers, body, self.clock.now().timestamp()) {
+                return error(StatusCode::UNAUTHORIZED, "Invalid signature".to_string());
+            }
+        }
+        let payload: Value = match serde_json::from_slice(body) {
+            Ok(payload) => payload,
+            Err(err) => return error(StatusCode::BAD_REQUEST, format!("Invalid JSON: {}", err)),
+        };
+        // Slack checks the endpoint once with a challenge to echo
+        if payload["type"] == "url_verification" {
+            return Json(json!({"challenge": payload["challenge"]})).into_response();
+        }
+        // Refused rather than queued, so a burst of deliveries can't pile up without bound
+        let Ok(permit) = self.runs.clone().try_acquire_owned() else {
+            return error(
+                StatusCode::TOO_MANY_REQUESTS,
+                "Every run slot is busy; try again later".to_string(),
+            );
+        };
+        let id = format!("{}-{}", name, self.ids.new_id());
+        let task = BatchTask {
+            id: Some(id.clone()),
+            task: Task::new(render_task(&hook.task, &payload)),
+        };
+        let runner = self.runner.clone();
+        let outcomes = self.outcomes.clone();
+        tokio::spawn(async move {
+            let outcome = runner.run_one(&task).await;
+            // The slot is free by the time anyone hears the outcome
+            drop(permit);
+            // Nobody listening is fine; the run store has it too
+            let _ = outcomes.send(outcome);
+        });
+        (StatusCode::ACCEPTED, Json(json!({"run": id}))).into_response()
+    }
+}
+
+fn error(status: StatusCode, message: String) -> Response {
+    (status, Json(json!({"error": message}))).into_response()
+}
+
+pub fn router(hooks: Arc<Webhooks>) -> Router {
+    Router::new()
+        .route("/hooks/:name", post(receive))
+        .with_state(hooks)
+}
+
+async fn receive(
+    State(hooks): State<Arc<Webhooks>>,
+    UrlPath(name): UrlPath<String>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    hooks.receive(&name, &headers, &body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::RunOptions;
+    use crate::clock::ManualClock;
+    use crate::ids::SequentialIds;
+    use crate::mcp_client::MCPClient;
+    use crate::model_client::{CompletionRequest, ModelClient, ModelResponse};
+    use crate::replay::{Fixture, ReplayModelClient};
+    use async_trait::async_trait;
+    use chrono::{TimeZone, Utc};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn runner(model: Arc<dyn ModelClient>) -> BatchRunner {
+        BatchRunner::new(
+            model,
+            Box::new(|_: &Path| MCPClient::replaying(&Fixture::initialized_mcp())),
+            Vec::new(),
+        )
+        .with_options(RunOptions {
+            max_turns: 1,
+            ..RunOptions::default()
+        })
+    }
+
+    fn hook(unsigned: bool) -> Hook {
+        Hook {
+            name: "push".to_string(),
+            task: "Review the commits pushed to {repository.full_name}".to_string(),
+            secret_env: None,
+            unsigned,
+        }
+    }
+
+    async fn serve(hooks: Arc<Webhooks>) -> Result<std::net::SocketAddr> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        tokio::spawn(async move { axum::serve(listener, router(hooks)).await });
+        Ok(addr)
+    }
+
+    // Counts how many completions are in flight at once
+    #[derive(Default)]
+    struct Overlap {
+        active: AtomicUsize,
+        most: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl ModelClient for Overlap {
+        async fn complete(&self, _request: &CompletionRequest) -> Result<ModelResponse> {
+            let active = self.active.fetch_add(1, Ordering::SeqCst) + 1;
+            self.most.fetch_max(active, Ordering::SeqCst);
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            self.active.fetch_sub(1, Ordering::SeqCst);
+            Ok(ModelResponse {
+                response: r#"{"final_answer": "Reviewed"}"#.to_string(),
+                reasoning: None,
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_payloads_become_runs_of_the_hooks_task() -> Result<()> {
+        let fixture = Fixture::from_replies([r#"{"final_answer": "Reviewed"}"#]);
+        let runner = runner(Arc::new(ReplayModelClient::new(&fixture)));
+        let hook = hook(true);
+        let hooks =
+            Arc::new(Webhooks::new(runner, vec![hook])?.with_ids(Arc::new(SequentialIds::new())));
+        let mut outcomes = hooks.subscribe();
+        let addr = serve(hooks).await?;
+        let client = reqwest::Client::new();
+
+        let accepted = client
+            .post(format!("http://{}/hooks/push", addr))
+            .json(&json!({"repository": {"full_name": "kasbuunk/agent"}}))
+            .send()
+            .await?;
+        let unknown = client
+            .post(format!("http://{}/hooks/deploy", addr))
+            .json(&json!({}))
+            .send()
+            .await?;
+
+        assert_eq!(accepted.status().as_u16(), 202);
+        assert_eq!(
+            accepted.json::<Value>().await?["run"],
+            "push-00000000-0000-0000-0000-000000000001"
+        );
+        assert_eq!(unknown.status().as_u16(), 404);
+        let outcome = outcomes.recv().await.unwrap();
+        assert_eq!(outcome.id, "push-00000000-0000-0000-0000-000000000001");
+        assert!(outcome.success);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_hooks_need_a_secret_and_refuse_payloads_beyond_the_concurrency() -> Result<()> {
+        let unsigned = Webhooks::new(runner(Arc::new(Overlap::default())), vec![hook(false)]);
+        let model = Arc::new(Overlap::default());
+        let hooks = Arc::new(Webhooks::new(
+            runner(model.clone()).with_concurrency(1),
+            vec![hook(true)],
+        )?);
+        let mut outcomes = hooks.subscribe();
+        let addr = serve(hooks).await?;
+        let client = reqwest::Client::new();
+
+        let post = || {
+            client
+                .post(format!("http://{}/hooks/push", addr))
+                .json(&json!({}))
+                .send()
+        };
+
+        let first = post().await?.status().as_u16();
+        let excess = post().await?.status().as_u16();
+        outcomes.recv().await.unwrap();
+        let after = post().await?.status().as_u16();
+        outcomes.recv().await.unwrap();
+
+        assert!(matches!(unsigned, Err(AgentError::ConfigError(_))));
+        assert_eq!((first, excess, after), (202, 429, 202));
+        assert_eq!(model.most.load(Ordering::SeqCst), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_slack_signatures_are_fresh_by_the_hooks_clock() -> Result<()> {
+        std::env::set_var("AGENT_TEST_SLACK_SECRET", "secret");
+        let hook = Hook {
+            secret_env: Some("AGENT_TEST_SLACK_SECRET".to_string()),
+            ..hook(false)
+        };
+        let now = Utc.with_ymd_and_hms(2026, 10, 16, 9, 0, 0).unwrap();
+        let hooks = Webhooks::new(runner(Arc::new(Overlap::default())), vec![hook])?
+            .with_clock(Arc::new(ManualClock::new(now)));
+        let addr = serve(Arc::new(hooks)).await?;
+        let body = r#"{"type": "url_verification", "challenge": "pond"}"#;
+        let post = |timestamp: i64| {
+            let mut mac = Hmac::<Sha256>::new_from_slice(b"secret").unwrap();
+            mac.update(format!("v0:{}:{}", timestamp, body).as_bytes());
+            let signature: String = mac
+                .finalize()
+                .into_bytes()
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect();
+            reqwest::Client::new()
+                .post(format!("http://{}/hooks/push", addr))
+                .header("x-slack-request-timestamp", timestamp.to_string())
+                .header("x-slack-signature", format!("v0={}", signature))
+                .body(body)
+                .send()
+        };
+
+        let fresh = post(now.timestamp() - 60).await?;
+        let stale = post(now.timestamp() - 600).await?;
+
+        assert_eq!(fresh.status().as_u16(), 200);
+        assert_eq!(fresh.json::<Value>().await?["challenge"], "pond");
+        assert_eq!(stale.status().as_u16(), 401);
+        Ok(())
+    }
+
+    #[test]
+    fn test_templates_and_signatures() {
+        let payload = json!({"event": {"text": "deploy please", "files": [{"name": "a.txt"}]}});
+        assert_eq!(
+            render_task(
+                "Handle \"{event.text}\" with {event.files.0.name}{missing}, not {\"json\": 1}",
+                &payload
+            ),
+            "Handle \"deploy please\" with a.txt, not {\"json\": 1}"
+        );
+
+        let body = br#"{"zen": "Keep it logically awesome."}"#;
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"secret").unwrap();
+        mac.update(body);
+        let signature: String = mac
+            .finalize()
+            .into_bytes()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "x-hub-signature-256",
+            format!("sha256={}", signature).parse().unwrap(),
+        );
+        assert!(verify_signature("secret", &headers, body, 0));
+        assert!(!verify_signature("other", &headers, body, 0));
+        assert!(!verify_signature("secret", &HeaderMap::new(), body, 0));
+    }
+}
@@ -0,0 +1,126 @@
+pub mod hooks;
+pub mod openai;
+
+use crate::error::Result;
+use crate::events::{AgentEvent, AgentObserver};
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::State;
+use axum::response::Response;
+use axum::routing::get;
+use axum::Router;
+use hooks::Webhooks;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+
+// Slow consumers skip events beyond this backlog rather than stalling the agent
+const EVENT_BACKLOG: usize = 256;
+
+// Fans agent events out to every connected WebSocket client
+pub struct EventBroadcaster {
+    sender: broadcast::Sender<AgentEvent>,
+}
+
+impl Default for EventBroadcaster {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventBroadcaster {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_BACKLOG);
+        Self { sender }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<AgentEvent> {
+        self.sender.subscribe()
+    }
+}
+
+impl AgentObserver for EventBroadcaster {
+    fn on_event(&self, event: &AgentEvent) {
+        // No subscribers is fine; events are only for whoever is watching
+        let _ = self.sender.send(event.clone());
+    }
+}
+
+pub fn router(events: Arc<EventBroadcaster>) -> Router {
+    Router::new()
+        .route("/events", get(stream_events))
+        .with_state(events)
+}
+
+// With webhooks, also starts a run for every payload posted to /hooks/<name>
+pub async fn serve(
+    addr: &str,
+    events: Arc<EventBroadcaster>,
+    hooks: Option<Arc<Webhooks>>,
+) -> Result<()> {
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    let addr = listener.local_addr()?;
+    eprintln!("Streaming agent events on ws://{}/events", addr);
+    let mut app = router(events);
+    if let Some(hooks) = hooks {
+        eprintln!("Accepting webhooks on http://{}/hooks/<name>", addr);
+        app = app.merge(hooks::router(hooks));
+    }
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+async fn stream_events(
+    upgrade: WebSocketUpgrade,
+    State(events): State<Arc<EventBroadcaster>>,
+) -> Response {
+    // Subscribe before the handshake completes so no event is missed in between
+    let receiver = events.subscribe();
+    upgrade.on_upgrade(move |socket| forward_events(socket, receiver))
+}
+
+async fn forward_events(mut socket: WebSocket, mut receiver: broadcast::Receiver<AgentEvent>) {
+    loop {
+        let event = match receiver.recv().await {
+            Ok(event) => event,
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                tracing::warn!("Event stream client lagged, skipped {} events", skipped);
+                continue;
+            }
+            Err(broadcast::error::RecvError::Closed) => break,
+        };
+        let Ok(text) = serde_json::to_string(&event) else {
+            continue;
+        };
+        if socket.send(Message::Text(text)).await.is_err() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::StreamExt;
+
+    #[tokio::test]
+    async fn test_events_are_streamed_to_websocket_clients() -> Result<()> {
+        let events = Arc::new(EventBroadcaster::new());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let app = router(events.clone());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let (mut client, _) = tokio_tungstenite::connect_async(format!("ws://{}/events", addr))
+            .await
+            .expect("websocket handshake");
+        events.on_event(&AgentEvent::ModelResponse {
+            text: "{}".to_string(),
+        });
+        let message = client.next().await.expect("an event").expect("a message");
+
+        assert_eq!(
+            message.into_text().expect("text"),
+            r#"{"type":"model_response","text":"{}"}"#
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,427 @@
+use crate::error::Result;
+use crate::model_client::usage::Usage;
+use crate::task::{Task, TaskResult};
+use crate::transcript::{Transcript, TranscriptEntry};
+use rusqlite::types::ToSql;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    started_at TEXT NOT NULL,
+    task TEXT NOT NULL,
+    success INTEGER NOT NULL,
+    turns INTEGER NOT NULL,
+    error TEXT,
+    artifacts TEXT NOT NULL,
+    requests INTEGER NOT NULL,
+    prompt_tokens INTEGER NOT NULL,
+    completion_tokens INTEGER NOT NULL,
+    duration_ms INTEGER NOT NULL,
+    transcript TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS tool_calls (
+    run_id INTEGER NOT NULL REFERENCES runs(id),
+    position INTEGER NOT NULL,
+    tool TEXT NOT NULL,
+    arguments TEXT NOT NULL,
+    result TEXT,
+    error TEXT
+);
+CREATE INDEX IF NOT EXISTS tool_calls_by_run ON tool_calls(run_id);
+";
+// Changes to SCHEMA since the first release, in order; a database's user_version counts those
+// it has had. Append to the end, never edit or reorder
+const MIGRATIONS: &[&str] = &["ALTER TABLE runs ADD COLUMN workspace TEXT"];
+
+// Everything worth keeping about one run_task call
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RunRecord {
+    pub started_at: String,
+    pub task: Task,
+    pub result: TaskResult,
+    pub usage: Usage,
+    pub duration_ms: u64,
+    pub transcript: Vec<TranscriptEntry>,
+}
+
+impl RunRecord {
+    pub fn transcript(&self) -> Transcript {
+        Transcript::from(self.transcript.clone())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct StoredRun {
+    pub id: i64,
+    #[serde(flatten)]
+    pub run: RunRecord,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ToolCallRecord {
+    pub tool: String,
+    pub arguments: Value,
+    pub result: Option<Value>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RunQuery {
+    pub success: Option<bool>,
+    // Runs that called this tool at least once
+    pub tool: Option<String>,
+    // RFC 3339 timestamps compare correctly as strings when both are UTC
+    pub since: Option<String>,
+    pub limit: Option<usize>,
+}
+
+// Run history in SQLite; cloning shares the connection
+#[derive(Clone)]
+pub struct RunStore {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl RunStore {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::with_connection(Connection::open(path)?)
+    }
+
+    pub fn in_memory() -> Result<Self> {
+        Self::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(mut connection: Connection) -> Result<Self> {
+        connection.execute_batch(SCHEMA)?;
+        migrate(&mut connection)?;
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    pub fn record(&self, run: &RunRecord) -> Result<i64> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        transaction.execute(
+            "INSERT INTO runs (started_at, task, success, turns, error, artifacts, requests,
+                prompt_tokens, completion_tokens, duration_ms, transcript, workspace)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+            params![
+                run.started_at,
+                serde_json::to_string(&run.task)?,
+                run.result.success,
+                run.result.turns as i64,
+                run.result.error,
+                serde_json::to_string(&run.result.artifacts)?,
+                run.usage.requests as i64,
+                run.usage.prompt_tokens as i64,
+                run.usage.completion_tokens as i64,
+                run.duration_ms as i64,
+                serde_json::to_string(&run.transcript)?,
+                run.result
+                    .workspace
+                    .as_ref()
+                    .map(|workspace| workspace.to_string_lossy().into_owned()),
+            ],
+        )?;
+        let id = transaction.last_insert_rowid();
+        for (position, call) in tool_calls_from(&run.transcript).iter().enumerate() {
+            transaction.execute(
+                "INSERT INTO tool_calls (run_id, position, tool, arguments, result, error)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    id,
+                    position as i64,
+                    call.tool,
+                    call.arguments.to_string(),
+                    call.result.as_ref().map(Value::to_string),
+                    call.error,
+                ],
+            )?;
+        }
+        transaction.commit()?;
+        Ok(id)
+    }
+
+    // Newest first
+    pub fn runs(&self, query: &RunQuery) -> Result<Vec<StoredRun>> {
+        let mut sql = String::from("SELECT * FROM runs WHERE 1 = 1");
+        let mut values: Vec<Box<dyn ToSql>> = Vec::new();
+        if let Some(success) = query.success {
+            sql.push_str(" AND success = ?");
+            values.push(Box::new(success));
+        }
+        if let Some(tool) = &query.tool {
+            sql.push_str(
+                " AND EXISTS (SELECT 1 FROM tool_calls WHERE run_id = runs.id AND tool = ?)",
+            );
+            values.push(Box::new(tool.clone()));
+        }
+        if let Some(since) = &query.since {
+            sql.push_str(" AND started_at >= ?");
+            values.push(Box::new(since.clone()));
+        }
+        sql.push_str(" ORDER BY id DESC");
+        if let Some(limit) = query.limit {
+            sql.push_str(" LIMIT ?");
+            values.push(Box::new(limit as i64));
+        }
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(&sql)?;
+        let rows = statement.query_map(
+            rusqlite::params_from_iter(values.iter().map(|value| value.as_ref())),
+            stored_run_columns,
+        )?;
+        rows.map(|row| stored_run(row?)).collect()
+    }
+
+    pub fn run(&self, id: i64) -> Result<Option<StoredRun>> {
+        let connection = self.connection.lock().unwrap();
+        let columns = connection
+            .query_row("SELECT * FROM runs WHERE id = ?1", [id], stored_run_columns)
+            .optional()?;
+        columns.map(stored_run).transpose()
+    }
+
+    pub fn tool_calls(&self, run_id: i64) -> Result<Vec<ToolCallRecord>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(
+            "SELECT tool, arguments, result, error FROM tool_calls
+             WHERE run_id = ?1 ORDER BY position",
+        )?;
+        let rows = statement.query_map([run_id], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, Option<String>>(2)?,
+                row.get::<_, Option<String>>(3)?,
+            ))
+        })?;
+        rows.map(|row| {
+            let (tool, arguments, result, error) = row?;
+            Ok(ToolCallRecord {
+                tool,
+                arguments: serde_json::from_str(&arguments)?,
+                result: result
+                    .map(|result| serde_json::from_str(&result))
+                    .transpose()?,
+                error,
+            })
+        })
+        .collect()
+    }
+}
+
+// Raw columns of a runs row; the JSON ones are parsed outside the rusqlite callback
+type RunColumns = (
+    i64,
+    String,
+    String,
+    bool,
+    i64,
+    Option<String>,
+    String,
+    [i64; 4],
+    String,
+    Option<String>,
+);
+
+fn migrate(connection: &mut Connection) -> Result<()> {
+    let transaction = connection.transaction()?;
+    let version: usize = transaction.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    for migration in MIGRATIONS.iter().skip(version) {
+        transaction.execute_batch(migration)?;
+    }
+    transaction.pragma_update(None, "user_version", MIGRATIONS.len())?;
+    transaction.commit()?;
+    Ok(())
+}
+
+fn stored_run_columns(row: &Row) -> rusqlite::Result<RunColumns> {
+    Ok((
+        row.get("id")?,
+        row.get("started_at")?,
+        row.get("task")?,
+        row.get("success")?,
+        row.get("turns")?,
+        row.get("error")?,
+        row.get("artifacts")?,
+        [
+            row.get("requests")?,
+            row.get("prompt_tokens")?,
+            row.get("completion_tokens")?,
+            row.get("duration_ms")?,
+        ],
+        row.get("transcript")?,
+        row.get("workspace")?,
+    ))
+}
+
+fn stored_run(columns: RunColumns) -> Result<StoredRun> {
+    let (id, started_at, task, success, turns, error, artifacts, counts, transcript, workspace) =
+        columns;
+    let [requests, prompt_tokens, completion_tokens, duration_ms] = counts;
+    Ok(StoredRun {
+        id,
+        run: RunRecord {
+            started_at,
+            task: serde_json::from_str(&task)?,
+            result: TaskResult {
+                success,
+                artifacts: serde_json::from_str(&artifacts)?,
+                turns: turns as usize,
+                error,
+                workspace: workspace.map(PathBuf::from),
+                output: None,
+                timings: Vec::new(),
+            },
+            usage: Usage {
+                requests: requests as usize,
+                prompt_tokens: prompt_tokens as usize,
+                completion_tokens: completion_tokens as usize,
+            },
+            duration_ms: duration_ms as u64,
+            transcript: serde_json::from_str(&transcript)?,
+        },
+    })
+}
+
+// Each tool call in a transcript paired with the result or error that followed it
+pub fn tool_calls_from(entries: &[TranscriptEntry]) -> Vec<ToolCallRecord> {
+    let mut calls: Vec<ToolCallRecord> = Vec::new();
+    let mut pending = false;
+    for entry in entries {
+        match entry {
+            TranscriptEntry::ToolCall { method, params } => {
+                calls.push(ToolCallRecord {
+                    tool: params
+                        .get("name")
+                        .and_then(Value::as_str)
+                        .unwrap_or(method)
+                        .to_string(),
+                    arguments: params.get("arguments").cloned().unwrap_or(Value::Null),
+                    result: None,
+                    error: None,
+                });
+                pending = true;
+            }
+            TranscriptEntry::ToolResult { result } if pending => {
+                if let Some(call) = calls.last_mut() {
+                    call.result = Some(result.clone());
+                }
+                pending = false;
+            }
+            TranscriptEntry::ToolError { error } if pending => {
+                if let Some(call) = calls.last_mut() {
+                    call.error = Some(error.clone());
+                }
+                pending = false;
+            }
+            _ => {}
+        }
+    }
+    calls
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task::Artifact;
+    use serde_json::json;
+
+    fn run(success: bool, tool: &str) -> RunRecord {
+        RunRecord {
+            started_at: "2026-10-16T09:00:00+00:00".to_string(),
+            task: Task::new("Write a haiku"),
+            result: TaskResult {
+                success,
+                artifacts: vec![Artifact::File {
+                    path: "haiku.txt".to_string(),
+                    stored: None,
+                }],
+                turns: 1,
+                error: (!success).then(|| "tool error".to_string()),
+                workspace: Some(PathBuf::from("/tmp/agent-workspaces/haiku-1a2b3c4d")),
+                output: None,
+                timings: Vec::new(),
+            },
+            usage: Usage {
+                requests: 1,
+                prompt_tokens: 40,
+                completion_tokens: 10,
+            },
+            duration_ms: 120,
+            transcript: vec![
+                TranscriptEntry::ToolCall {
+                    method: "tools/call".to_string(),
+                    params: json!({"name": tool, "arguments": {"path": "haiku.txt"}}),
+                },
+                TranscriptEntry::ToolResult {
+                    result: json!({"content": []}),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_databases_from_before_workspaces_are_migrated() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("runs.db");
+        Connection::open(&path)?.execute_batch(SCHEMA)?;
+
+        let store = RunStore::open(&path)?;
+        let id = store.record(&run(true, "write_file"))?;
+        let reopened = RunStore::open(&path)?;
+
+        assert_eq!(
+            reopened.run(id)?.map(|stored| stored.run),
+            Some(run(true, "write_file"))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_recorded_runs_round_trip_and_filter() -> Result<()> {
+        let store = RunStore::in_memory()?;
+        let written = store.record(&run(true, "write_file"))?;
+        store.record(&run(false, "read_file"))?;
+
+        let stored = store.run(written)?.expect("run was recorded");
+        let failed = store.runs(&RunQuery {
+            success: Some(false),
+            ..RunQuery::default()
+        })?;
+        let writes = store.runs(&RunQuery {
+            tool: Some("write_file".to_string()),
+            ..RunQuery::default()
+        })?;
+
+        assert_eq!(stored.run, run(true, "write_file"));
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].run.result.error.as_deref(), Some("tool error"));
+        assert_eq!(
+            writes.iter().map(|run| run.id).collect::<Vec<_>>(),
+            vec![written]
+        );
+        assert_eq!(store.runs(&RunQuery::default())?.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_tool_calls_are_queryable_per_run() -> Result<()> {
+        let store = RunStore::in_memory()?;
+        let id = store.record(&run(true, "write_file"))?;
+
+        let calls = store.tool_calls(id)?;
+
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].tool, "write_file");
+        assert_eq!(calls[0].arguments, json!({"path": "haiku.txt"}));
+        assert_eq!(calls[0].result, Some(json!({"content": []})));
+        Ok(())
+    }
+}
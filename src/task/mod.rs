@@ -0,0 +1,237 @@
+use crate::error::{AgentError, Result};
+use crate::model_client::ChatMessage;
+use crate::transcript::TranscriptEntry;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct Task {
+    pub description: String,
+    #[serde(default)]
+    pub success_criteria: Vec<String>,
+    #[serde(default)]
+    pub output_schema: Option<Value>,
+}
+
+impl Task {
+    pub fn new(description: impl Into<String>) -> Self {
+        Self {
+            description: description.into(),
+            ..Self::default()
+        }
+    }
+
+    pub fn with_success_criterion(mut self, criterion: impl Into<String>) -> Self {
+        self.success_criteria.push(criterion.into());
+        self
+    }
+
+    pub fn with_output_schema(mut self, schema: Value) -> Self {
+        self.output_schema = Some(schema);
+        self
+    }
+
+    pub fn to_message(&self) -> ChatMessage {
+        let mut content = self.description.clone();
+        if !self.success_criteria.is_empty() {
+            content.push_str("\n\nSuccess criteria:");
+            for criterion in &self.success_criteria {
+                content.push_str(&format!("\n- {}", criterion));
+            }
+        }
+        if let Some(schema) = &self.output_schema {
+            content.push_str(&format!(
+                "\n\nThe final output must match this JSON schema:\n{}",
+                schema
+            ));
+        }
+        ChatMessage::user(content)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Artifact {
+    File {
+        path: String,
+        // The copy kept in the run's artifacts directory, when there is one
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        stored: Option<PathBuf>,
+    },
+    Text {
+        text: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TaskResult {
+    pub success: bool,
+    pub artifacts: Vec<Artifact>,
+    pub turns: usize,
+    pub error: Option<String>,
+    // Directory the run worked in, when it had one of its own
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workspace: Option<PathBuf>,
+    // The final answer as JSON, when the task declared an output schema it matches
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<Value>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub timings: Vec<TurnTiming>,
+}
+
+// Where one turn's time went, in milliseconds
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct TurnTiming {
+    pub turn: usize,
+    pub model_ms: f64,
+    // Making sense of the reply: a final answer, or the tool call to make
+    pub parse_ms: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool: Option<String>,
+    pub tool_ms: f64,
+}
+
+// One row per turn plus the totals, for finding where a slow run spent its time
+pub fn timing_table(timings: &[TurnTiming]) -> String {
+    let mut table = format!(
+        "{:>4}  {:>10}  {:>10}  {:>10}  {}\n",
+        "turn", "model ms", "parse ms", "tool ms", "tool"
+    );
+    let mut total = TurnTiming::default();
+    for timing in timings {
+        table.push_str(&format!(
+            "{:>4}  {:>10.1}  {:>10.1}  {:>10.1}  {}\n",
+            timing.turn,
+            timing.model_ms,
+            timing.parse_ms,
+            timing.tool_ms,
+            timing.tool.as_deref().unwrap_or("-")
+        ));
+        total.model_ms += timing.model_ms;
+        total.parse_ms += timing.parse_ms;
+        total.tool_ms += timing.tool_ms;
+    }
+    table.push_str(&format!(
+        "{:>4}  {:>10.1}  {:>10.1}  {:>10.1}\n",
+        "all", total.model_ms, total.parse_ms, total.tool_ms
+    ));
+    table
+}
+
+impl TaskResult {
+    pub fn workspace(&self) -> Option<&Path> {
+        self.workspace.as_deref()
+    }
+
+    pub fn output_as<T: DeserializeOwned>(&self) -> Result<T> {
+        let output = self.output.clone().ok_or_else(|| {
+            AgentError::ParseError("The task produced no structured output".to_string())
+        })?;
+        Ok(serde_json::from_value(output)?)
+    }
+}
+
+// Files written by successful write_file and edit_file calls, in the order they happened
+pub fn artifacts_from(entries: &[TranscriptEntry]) -> Vec<Artifact> {
+    let mut artifacts = Vec::new();
+    let mut pending = None;
+    for entry in entries {
+        match entry {
+            TranscriptEntry::ToolCall { params, .. } => {
+                let arguments = &params["arguments"];
+                pending = match params.get("name").and_then(Value::as_str) {
+                    Some("write_file") => arguments["path"].as_str().map(str::to_string),
+                    // edit_file without an edit only shows the file
+                    Some("edit_file") if arguments["edit"].is_string() => {
+                        arguments["path"].as_str().map(str::to_string)
+                    }
+                    _ => None,
+                };
+            }
+            TranscriptEntry::ToolResult { .. } => {
+                if let Some(path) = pending.take() {
+                    artifacts.push(Artifact::File { path, stored: None });
+                }
+            }
+            TranscriptEntry::ToolError { .. } => pending = None,
+            _ => {}
+        }
+    }
+    artifacts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_task_message_lists_criteria_and_schema() {
+        let task = Task::new("Write a haiku")
+            .with_success_criterion("Three lines")
+            .with_output_schema(json!({"type": "string"}));
+
+        let message = task.to_message();
+
+        assert!(message.content.starts_with("Write a haiku"));
+        assert!(message.content.contains("- Three lines"));
+        assert!(message.content.contains(r#"{"type":"string"}"#));
+    }
+
+    #[test]
+    fn test_only_successful_writes_become_artifacts() {
+        let write = |path: &str| TranscriptEntry::ToolCall {
+            method: "tools/call".to_string(),
+            params: json!({"name": "write_file", "arguments": {"path": path}}),
+        };
+        let entries = vec![
+            write("ok.txt"),
+            TranscriptEntry::ToolResult { result: json!({}) },
+            write("failed.txt"),
+            TranscriptEntry::ToolError {
+                error: "denied".to_string(),
+            },
+        ];
+
+        assert_eq!(
+            artifacts_from(&entries),
+            vec![Artifact::File {
+                path: "ok.txt".to_string(),
+                stored: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_timing_table_lists_turns_and_totals() {
+        let timings = [
+            TurnTiming {
+                turn: 1,
+                model_ms: 1200.0,
+                parse_ms: 0.5,
+                tool: Some("write_file".to_string()),
+                tool_ms: 30.0,
+            },
+            TurnTiming {
+                turn: 2,
+                model_ms: 800.0,
+                parse_ms: 0.25,
+                tool: None,
+                tool_ms: 0.0,
+            },
+        ];
+
+        let table = timing_table(&timings);
+        let lines: Vec<&str> = table.lines().collect();
+
+        assert_eq!(lines.len(), 4);
+        assert!(lines[1].ends_with("write_file"));
+        assert!(lines[2].ends_with(" -"));
+        assert_eq!(
+            lines[3].split_whitespace().collect::<Vec<_>>(),
+            ["all", "2000.0", "0.8", "30.0"]
+        );
+    }
+}
@@ -0,0 +1,33 @@
+use crate::error::{AgentError, Result};
+
+// A line typed on the terminal, read off the async runtime's threads
+pub async fn read_line() -> Result<String> {
+    tokio::task::spawn_blocking(|| {
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer).map(|_| answer)
+    })
+    .await
+    .map_err(|err| AgentError::ProtocolError(err.to_string()))?
+    .map_err(AgentError::from)
+}
+
+// Asks a yes/no question on the terminal; anything but y is a no
+pub async fn confirm(prompt: &str) -> Result<bool> {
+    eprint!("{} [y/N] ", prompt);
+    Ok(yes(&read_line().await?))
+}
+
+fn yes(answer: &str) -> bool {
+    answer.trim().eq_ignore_ascii_case("y")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_y_confirms() {
+        assert!(yes("y\n") && yes(" Y "));
+        assert!(!yes("yes\n") && !yes("\n") && !yes("n"));
+    }
+}
@@ -0,0 +1,220 @@
+use crate::error::Result;
+use crate::guardrails::{pattern_names, Finding, InjectionAction};
+use crate::model_client::usage::CostEstimate;
+use crate::model_client::{ChatMessage, Role};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TranscriptEntry {
+    Message {
+        message: ChatMessage,
+    },
+    Reasoning {
+        text: String,
+    },
+    ModelResponse {
+        response: String,
+    },
+    ToolCall {
+        method: String,
+        params: Value,
+    },
+    ToolResult {
+        result: Value,
+    },
+    ToolError {
+        error: String,
+    },
+    Plan {
+        steps: Vec<String>,
+    },
+    // Planned versus actual for one step of a plan-then-execute run
+    PlanStep {
+        step: usize,
+        planned: String,
+        actual: String,
+        verified: bool,
+    },
+    // An almost-valid JSON reply the agent fixed before parsing it
+    RepairedJson {
+        original: String,
+        repaired: String,
+        fixes: Vec<String>,
+    },
+    // Tool output the injection guard found suspicious, and what it did about it
+    InjectionSuspected {
+        findings: Vec<Finding>,
+        action: InjectionAction,
+    },
+    // A stop condition ended the task
+    Stopped {
+        condition: String,
+    },
+    // The plan's expected usage, made before its first step runs
+    CostEstimate {
+        estimate: CostEstimate,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TranscriptFormat {
+    Markdown,
+    Jsonl,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Transcript {
+    entries: Vec<TranscriptEntry>,
+}
+
+impl From<Vec<TranscriptEntry>> for Transcript {
+    fn from(entries: Vec<TranscriptEntry>) -> Self {
+        Self { entries }
+    }
+}
+
+impl Transcript {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, entry: TranscriptEntry) {
+        self.entries.push(entry);
+    }
+
+    pub fn entries(&self) -> &[TranscriptEntry] {
+        &self.entries
+    }
+
+    pub fn export(&self, format: TranscriptFormat) -> Result<String> {
+        match format {
+            TranscriptFormat::Jsonl => {
+                let mut output = String::new();
+                for entry in &self.entries {
+                    output.push_str(&serde_json::to_string(entry)?);
+                    output.push('\n');
+                }
+                Ok(output)
+            }
+            TranscriptFormat::Markdown => Ok(self.to_markdown()),
+        }
+    }
+
+    fn to_markdown(&self) -> String {
+        let mut output = String::from("# Agent transcript\n");
+        for entry in &self.entries {
+            let section = match entry {
+                TranscriptEntry::Message { message } => {
+                    let role = match message.role {
+                        Role::System => "System",
+                        Role::User => "User",
+                        Role::Assistant => "Assistant",
+                        Role::Tool => "Tool",
+                    };
+                    format!("## {}\n\n{}\n", role, message.content)
+                }
+                TranscriptEntry::Reasoning { text } => format!("## Reasoning\n\n{}\n", text),
+                TranscriptEntry::ModelResponse { response } => {
+                    format!("## Model response\n\n```\n{}\n```\n", response)
+                }
+                TranscriptEntry::ToolCall { method, params } => format!(
+                    "## Tool call `{}`\n\n```json\n{}\n```\n",
+                    method,
+                    serde_json::to_string_pretty(params).unwrap_or_default()
+                ),
+                TranscriptEntry::ToolResult { result } => format!(
+                    "## Tool result\n\n```json\n{}\n```\n",
+                    serde_json::to_string_pretty(result).unwrap_or_default()
+                ),
+                TranscriptEntry::ToolError { error } => format!("## Tool error\n\n{}\n", error),
+                TranscriptEntry::Plan { steps } => {
+                    let steps: Vec<String> = steps
+                        .iter()
+                        .enumerate()
+                        .map(|(index, step)| format!("{}. {}", index + 1, step))
+                        .collect();
+                    format!("## Plan\n\n{}\n", steps.join("\n"))
+                }
+                TranscriptEntry::PlanStep {
+                    step,
+                    planned,
+                    actual,
+                    verified,
+                } => format!(
+                    "## Step {} {}\n\n- Planned: {}\n- Actual: {}\n",
+                    step,
+                    if *verified { "done" } else { "failed" },
+                    planned,
+                    actual
+                ),
+                TranscriptEntry::RepairedJson {
+                    original,
+                    repaired,
+                    fixes,
+                } => format!(
+                    "## Repaired JSON\n\n{}\n\n```\n{}\n```\n\n```json\n{}\n```\n",
+                    fixes.join(", "),
+                    original,
+                    repaired
+                ),
+                TranscriptEntry::InjectionSuspected { findings, action } => format!(
+                    "## Possible prompt injection\n\n{} tool output with {}\n",
+                    match action {
+                        InjectionAction::Sanitized => "Sanitized",
+                        InjectionAction::Allowed => "Allowed",
+                        InjectionAction::Withheld => "Withheld",
+                    },
+                    pattern_names(findings)
+                ),
+                TranscriptEntry::Stopped { condition } => format!("## Stopped\n\n{}\n", condition),
+                TranscriptEntry::CostEstimate { estimate } => {
+                    format!("## Cost estimate\n\n{}\n", estimate.summary())
+                }
+            };
+            output.push('\n');
+            output.push_str(&section);
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn sample() -> Transcript {
+        let mut transcript = Transcript::new();
+        transcript.record(TranscriptEntry::Message {
+            message: ChatMessage::user("Write a haiku"),
+        });
+        transcript.record(TranscriptEntry::ToolCall {
+            method: "tools/call".to_string(),
+            params: json!({"name": "write_file"}),
+        });
+        transcript
+    }
+
+    #[test]
+    fn test_jsonl_export_has_one_entry_per_line() -> Result<()> {
+        let exported = sample().export(TranscriptFormat::Jsonl)?;
+
+        let entries = exported
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<std::result::Result<Vec<TranscriptEntry>, _>>()?;
+        assert_eq!(entries, sample().entries());
+        Ok(())
+    }
+
+    #[test]
+    fn test_markdown_export_has_section_per_entry() -> Result<()> {
+        let exported = sample().export(TranscriptFormat::Markdown)?;
+
+        assert!(exported.contains("## User\n\nWrite a haiku"));
+        assert!(exported.contains("## Tool call `tools/call`"));
+        Ok(())
+    }
+}
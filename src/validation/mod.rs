@@ -0,0 +1,177 @@
+use crate::error::{AgentError, Result};
+use crate::schema;
+use regex::Regex;
+use serde_json::Value;
+
+// Checks a final answer; the problems found are fed back to the model for another try
+pub trait Validator: Send + Sync {
+    // Empty when the output passes
+    fn validate(&self, output: &str) -> Vec<String>;
+}
+
+pub struct JsonSchemaValidator {
+    schema: Value,
+}
+
+impl JsonSchemaValidator {
+    pub fn new(schema: Value) -> Self {
+        Self { schema }
+    }
+}
+
+impl Validator for JsonSchemaValidator {
+    fn validate(&self, output: &str) -> Vec<String> {
+        match serde_json::from_str(output) {
+            Ok(instance) => schema::validate(&self.schema, &instance)
+                .into_iter()
+                // The schema module words errors for tool arguments
+                .map(|error| match error.strip_prefix("arguments") {
+                    Some(rest) => format!("output{}", rest),
+                    None => error,
+                })
+                .collect(),
+            Err(err) => vec![format!("output is not valid JSON: {}", err)],
+        }
+    }
+}
+
+pub struct RegexValidator {
+    pattern: Regex,
+}
+
+impl RegexValidator {
+    pub fn new(pattern: &str) -> Result<Self> {
+        let pattern = Regex::new(pattern)
+            .map_err(|err| AgentError::ParseError(format!("Invalid pattern: {}", err)))?;
+        Ok(Self { pattern })
+    }
+}
+
+impl Validator for RegexValidator {
+    fn validate(&self, output: &str) -> Vec<String> {
+        if self.pattern.is_match(output) {
+            Vec::new()
+        } else {
+            vec![format!("output does not match /{}/", self.pattern)]
+        }
+    }
+}
+
+// One line per entry with that many syllables, e.g. 5-7-5 for a haiku
+pub struct SyllableCountValidator {
+    pattern: Vec<usize>,
+}
+
+impl SyllableCountValidator {
+    pub fn new(pattern: Vec<usize>) -> Self {
+        Self { pattern }
+    }
+
+    pub fn haiku() -> Self {
+        Self::new(vec![5, 7, 5])
+    }
+}
+
+impl Validator for SyllableCountValidator {
+    fn validate(&self, output: &str) -> Vec<String> {
+        let lines: Vec<&str> = output
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect();
+        if lines.len() != self.pattern.len() {
+            return vec![format!(
+                "expected {} lines, got {}",
+                self.pattern.len(),
+                lines.len()
+            )];
+        }
+        lines
+            .iter()
+            .zip(&self.pattern)
+            .enumerate()
+            .filter_map(|(index, (line, expected))| {
+                let count = count_line_syllables(line);
+                (count != *expected).then(|| {
+                    format!(
+                        "line {} has {} syllables, expected {}",
+                        index + 1,
+                        count,
+                        expected
+                    )
+                })
+            })
+            .collect()
+    }
+}
+
+pub fn count_line_syllables(line: &str) -> usize {
+    line.split(|c: char| !c.is_alphabetic() && c != '\'')
+        .filter(|word| word.chars().any(char::is_alphabetic))
+        .map(count_syllables)
+        .sum()
+}
+
+// English heuristic: vowel groups, less a silent final "e" (but not "-le" as in "table")
+pub fn count_syllables(word: &str) -> usize {
+    let word: Vec<char> = word
+        .to_lowercase()
+        .chars()
+        .filter(|c| c.is_ascii_alphabetic())
+        .collect();
+    let is_vowel = |c: char| "aeiouy".contains(c);
+    let mut count = 0;
+    let mut previous_vowel = false;
+    for &c in &word {
+        let vowel = is_vowel(c);
+        if vowel && !previous_vowel {
+            count += 1;
+        }
+        previous_vowel = vowel;
+    }
+    let len = word.len();
+    let consonant_le = |len: usize| word[len - 2] == 'l' && !is_vowel(word[len - 3]);
+    if len > 2 && word[len - 1] == 'e' && !is_vowel(word[len - 2]) && !consonant_le(len) {
+        count -= 1;
+    }
+    count.max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_syllable_validator_checks_each_line() {
+        let validator = SyllableCountValidator::haiku();
+
+        assert!(validator
+            .validate("An old silent pond\nA frog jumps into the pond\nSplash! Silence again.")
+            .is_empty());
+        assert_eq!(
+            validator.validate("An old silent pond\nA frog jumps in\nSplash! Silence again."),
+            vec!["line 2 has 4 syllables, expected 7"]
+        );
+        assert_eq!(
+            validator.validate("Too short"),
+            vec!["expected 3 lines, got 1"]
+        );
+        assert_eq!(count_syllables("table"), 2);
+    }
+
+    #[test]
+    fn test_schema_and_regex_validators_report_problems() {
+        let validator = JsonSchemaValidator::new(json!({"type": "object", "required": ["title"]}));
+
+        assert_eq!(
+            validator.validate(r#"{"body": "x"}"#),
+            vec!["output is missing required field 'title'"]
+        );
+        assert!(validator.validate("not json")[0].starts_with("output is not valid JSON"));
+        assert!(RegexValidator::new(r"^\d+$")
+            .unwrap()
+            .validate("17")
+            .is_empty());
+    }
+}
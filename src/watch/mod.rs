@@ -0,0 +1,343 @@
+use crate::batch::{BatchOutcome, BatchReport, BatchRunner, BatchTask};
+use crate::error::{AgentError, Result};
+use crate::task::Task;
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use globset::{GlobBuilder, GlobMatcher};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+use tokio::time::{sleep_until, Duration, Instant};
+
+pub const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(500);
+
+// Runs a task whenever a file under a directory that matches a glob changes. Editors write a
+// file in several steps, so a path has to stay quiet for the debounce period first. {path} in
+// the task's description is replaced by the changed file's path
+pub struct FileWatch {
+    runner: BatchRunner,
+    root: PathBuf,
+    matcher: GlobMatcher,
+    task: Task,
+    debounce: Duration,
+}
+
+impl FileWatch {
+    // The glob is matched against paths relative to root; * stays within a directory, ** does not
+    pub fn new(
+        runner: BatchRunner,
+        root: impl Into<PathBuf>,
+        glob: &str,
+        task: Task,
+    ) -> Result<Self> {
+        let matcher = GlobBuilder::new(glob)
+            .literal_separator(true)
+            .build()
+            .map_err(|err| AgentError::ConfigError(format!("Invalid glob '{}': {}", glob, err)))?
+            .compile_matcher();
+        Ok(Self {
+            runner,
+            root: root.into(),
+            matcher,
+            task,
+            debounce: DEFAULT_DEBOUNCE,
+        })
+    }
+
+    pub fn with_debounce(mut self, debounce: Duration) -> Self {
+        self.debounce = debounce;
+        self
+    }
+
+    // Watches the root recursively until interrupted, or until `limit` runs have finished
+    pub async fn watch(
+        &self,
+        limit: Option<usize>,
+        on_run: impl FnMut(&BatchOutcome),
+    ) -> Result<()> {
+        let (sender, changes) = mpsc::unbounded_channel();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+            if let Ok(event) = event {
+                if matches!(
+                    event.kind,
+                    EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+                ) {
+                    for path in event.paths {
+                        let _ = sender.send(path);
+                    }
+                }
+            }
+        })
+        .map_err(|err| AgentError::ConfigError(format!("Cannot watch files: {}", err)))?;
+        let root = self.root.canonicalize()?;
+        watcher
+            .watch(&root, RecursiveMode::Recursive)
+            .map_err(|err| {
+                AgentError::ConfigError(format!("Cannot watch {}: {}", self.root.display(), err))
+            })?;
+        self.run(&root, changes, limit, on_run).await
+    }
+
+    // Runs the task for each matching path once it has stopped changing. A path that changes
+    // while its run is in flight runs once more after it, unless the run wrote the path itself:
+    // running again for its own writes would never end. Different paths run side by side.
+    // Returns when the changes end and the last run is done
+    pub async fn run(
+        &self,
+        root: &Path,
+        mut changes: UnboundedReceiver<PathBuf>,
+        limit: Option<usize>,
+        mut on_run: impl FnMut(&BatchOutcome),
+    ) -> Result<()> {
+        // When each changed path will have been quiet long enough
+        let mut quiet_at: HashMap<PathBuf, Instant> = HashMap::new();
+        let mut running: HashSet<PathBuf> = HashSet::new();
+        // Running paths that changed during their run
+        let mut dirty: HashSet<PathBuf> = HashSet::new();
+        let mut runs = FuturesUnordered::new();
+        let mut started = 0;
+        let mut finished = 0;
+        let mut open = true;
+        while open || !quiet_at.is_empty() || !runs.is_empty() {
+            let next_quiet = quiet_at.values().min().copied();
+            tokio::select! {
+                change = changes.recv(), if open => match change {
+                    Some(path) => {
+                        self.note_change(root, &path, &running, &mut dirty, &mut quiet_at)
+                    }
+                    None => open = false,
+                },
+                _ = sleep_until(next_quiet.unwrap_or_else(Instant::now)), if next_quiet.is_some() => {
+                    let now = Instant::now();
+                    let mut quiet: Vec<PathBuf> = quiet_at
+                        .iter()
+                        .filter(|(_, at)| **at <= now)
+                        .map(|(path, _)| path.clone())
+                        .collect();
+                    quiet.sort();
+                    for path in quiet {
+                        quiet_at.remove(&path);
+                        running.insert(path.clone());
+                        started += 1;
+                        runs.push(self.run_for(path, started));
+                    }
+                },
+                Some((path, report)) = runs.next(), if !runs.is_empty() => {
+                    for outcome in &report.outcomes {
+                        on_run(outcome);
+                    }
+                    finished += 1;
+                    if limit.is_some_and(|limit| finished >= limit) {
+                        return Ok(());
+                    }
+                    // The run's own writes may still be queued, and count as made during it
+                    while let Ok(changed) = changes.try_recv() {
+                        self.note_change(root, &changed, &running, &mut dirty, &mut quiet_at);
+                    }
+                    running.remove(&path);
+                    let wrote = report
+                        .outcomes
+                        .iter()
+                        .any(|outcome| self.wrote(root, outcome, &path));
+                    if dirty.remove(&path) && !wrote {
+                        quiet_at.insert(path, Instant::now() + self.debounce);
+                    }
+                },
+            }
+        }
+        Ok(())
+    }
+
+    // Starts or restarts the quiet period of a matching path, or marks a running one as changed
+    fn note_change(
+        &self,
+        root: &Path,
+        path: &Path,
+        running: &HashSet<PathBuf>,
+        dirty: &mut HashSet<PathBuf>,
+        quiet_at: &mut HashMap<PathBuf, Instant>,
+    ) {
+        if let Some(relative) = self.relative_match(root, path) {
+            if running.contains(&relative) {
+                dirty.insert(relative);
+            } else {
+                quiet_at.insert(relative, Instant::now() + self.debounce);
+            }
+        }
+    }
+
+    // Whether the run called write_file or edit_file on the path. Relative paths are the
+    // filesystem server's, which runs in the current directory
+    fn wrote(&self, root: &Path, outcome: &BatchOutcome, relative: &Path) -> bool {
+        outcome.files_written.iter().any(|written| {
+            let written = Path::new(written);
+            let written = match written.is_absolute() {
+                true => written.to_path_buf(),
+                false => std::env::current_dir().unwrap_or_default().join(written),
+            };
+            self.relative_match(root, &written).as_deref() == Some(relative)
+        })
+    }
+
+    // The path relative to root, when the glob matches it
+    fn relative_match(&self, root: &Path, path: &Path) -> Option<PathBuf> {
+        let relative = path.strip_prefix(root).ok()?;
+        self.matcher
+            .is_match(relative)
+            .then(|| relative.to_path_buf())
+    }
+
+    async fn run_for(&self, relative: PathBuf, number: usize) -> (PathBuf, BatchReport) {
+        let path = self.root.join(&relative);
+        let mut task = self.task.clone();
+        task.description = task
+            .description
+            .replace("{path}", &path.display().to_string());
+        let name = relative.display().to_string().replace(['/', '\\'], "_");
+        let report = self
+            .runner
+            .run(&[BatchTask {
+                id: Some(format!("{}-{}", name, number)),
+                task,
+            }])
+            .await;
+        (relative, report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::RunOptions;
+    use crate::mcp_client::MCPClient;
+    use crate::model_client::{CompletionRequest, ModelClient, ModelResponse};
+    use crate::replay::{Fixture, ReplayModelClient};
+    use async_trait::async_trait;
+    use serde_json::json;
+    use std::sync::{Arc, Mutex};
+    use tokio::sync::mpsc::UnboundedSender;
+
+    fn runner(model: Arc<dyn ModelClient>) -> BatchRunner {
+        BatchRunner::new(
+            model,
+            Box::new(|_: &Path| MCPClient::replaying(&Fixture::initialized_mcp())),
+            Vec::new(),
+        )
+        .with_options(RunOptions {
+            max_turns: 1,
+            ..RunOptions::default()
+        })
+    }
+
+    // Changes /notes/pond.md the first time it is asked, then gives its reply
+    struct ChangesPond {
+        changes: Mutex<Option<UnboundedSender<PathBuf>>>,
+        reply: String,
+    }
+
+    #[async_trait]
+    impl ModelClient for ChangesPond {
+        async fn complete(&self, _request: &CompletionRequest) -> Result<ModelResponse> {
+            if let Some(changes) = self.changes.lock().unwrap().take() {
+                changes.send(PathBuf::from("/notes/pond.md")).unwrap();
+            }
+            Ok(ModelResponse {
+                response: self.reply.clone(),
+                reasoning: None,
+            })
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_changes_are_debounced_per_matching_path() -> Result<()> {
+        let fixture = Fixture::from_replies([
+            r#"{"final_answer": "Summarized"}"#,
+            r#"{"final_answer": "Summarized"}"#,
+        ]);
+        let runner = runner(Arc::new(ReplayModelClient::new(&fixture)));
+        let root = Path::new("/notes");
+        let watch = FileWatch::new(runner, "notes", "**/*.md", Task::new("Summarize {path}"))?;
+        let (sender, changes) = mpsc::unbounded_channel();
+        for path in [
+            "/notes/pond.md",
+            "/notes/pond.md",
+            "/notes/pond.txt",
+            "/notes/rain/today.md",
+        ] {
+            sender.send(PathBuf::from(path)).unwrap();
+        }
+        drop(sender);
+        let mut outcomes = Vec::new();
+
+        watch
+            .run(root, changes, None, |outcome| {
+                outcomes.push(outcome.clone())
+            })
+            .await?;
+
+        let mut ids: Vec<&str> = outcomes.iter().map(|outcome| outcome.id.as_str()).collect();
+        ids.sort();
+        assert_eq!(ids, vec!["pond.md-1", "rain_today.md-2"]);
+        assert!(outcomes.iter().all(|outcome| outcome.success));
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_a_runs_own_writes_do_not_trigger_it_again() -> Result<()> {
+        let (sender, changes) = mpsc::unbounded_channel();
+        sender.send(PathBuf::from("/notes/pond.md")).unwrap();
+        let model = ChangesPond {
+            changes: Mutex::new(Some(sender)),
+            reply: json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "tools/call",
+                "params": {
+                    "name": "write_file",
+                    "arguments": {"path": "/notes/pond.md", "content": "Rewritten"}
+                }
+            })
+            .to_string(),
+        };
+        let watch = FileWatch::new(
+            runner(Arc::new(model)),
+            "notes",
+            "*.md",
+            Task::new("Rewrite {path}"),
+        )?;
+        let mut runs = 0;
+
+        watch
+            .run(Path::new("/notes"), changes, None, |_| runs += 1)
+            .await?;
+
+        assert_eq!(runs, 1);
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_a_change_by_someone_else_during_a_run_runs_it_again() -> Result<()> {
+        let (sender, changes) = mpsc::unbounded_channel();
+        sender.send(PathBuf::from("/notes/pond.md")).unwrap();
+        let model = ChangesPond {
+            changes: Mutex::new(Some(sender)),
+            reply: r#"{"final_answer": "Summarized"}"#.to_string(),
+        };
+        let watch = FileWatch::new(
+            runner(Arc::new(model)),
+            "notes",
+            "*.md",
+            Task::new("Summarize {path}"),
+        )?;
+        let mut ids = Vec::new();
+
+        watch
+            .run(Path::new("/notes"), changes, None, |outcome| {
+                ids.push(outcome.id.clone())
+            })
+            .await?;
+
+        assert_eq!(ids, vec!["pond.md-1", "pond.md-2"]);
+        Ok(())
+    }
+}
@@ -0,0 +1,131 @@
+use crate::error::Result;
+use crate::ids::{random_ids, IdGenerator};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+// Hands out a fresh directory per run, so concurrent runs don't share files
+#[derive(Clone)]
+pub struct Workspaces {
+    base: PathBuf,
+    ids: Arc<dyn IdGenerator>,
+}
+
+impl Workspaces {
+    // Under the system temp directory; runs are kept for inspection, not cleaned up
+    pub fn temp() -> Self {
+        Self::under(std::env::temp_dir().join("agent-workspaces"))
+    }
+
+    pub fn under(base: impl Into<PathBuf>) -> Self {
+        Self {
+            base: base.into(),
+            ids: random_ids(),
+        }
+    }
+
+    pub fn with_ids(mut self, ids: Arc<dyn IdGenerator>) -> Self {
+        self.ids = ids;
+        self
+    }
+
+    pub fn base(&self) -> &Path {
+        &self.base
+    }
+
+    // An empty directory named after the run, made unique with a random suffix
+    pub fn create(&self, name: &str) -> Result<PathBuf> {
+        let name: String = name
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        let suffix = self.ids.new_id().simple().to_string();
+        let path = self
+            .base
+            .join(format!("{}-{}", name, &suffix[suffix.len() - 8..]));
+        std::fs::create_dir_all(&path)?;
+        canonicalize(&path)
+    }
+}
+
+// Like Path::canonicalize, but without the \\?\ prefix Windows puts on canonical paths. Servers,
+// shells and models given such a path tend to mangle it
+pub fn canonicalize(path: &Path) -> Result<PathBuf> {
+    Ok(strip_verbatim(path.canonicalize()?))
+}
+
+fn strip_verbatim(path: PathBuf) -> PathBuf {
+    let Some(text) = path.to_str() else {
+        return path;
+    };
+    if let Some(share) = text.strip_prefix(r"\\?\UNC\") {
+        return PathBuf::from(format!(r"\\{}", share));
+    }
+    match text.strip_prefix(r"\\?\") {
+        Some(rest) if rest.as_bytes().get(1) == Some(&b':') => PathBuf::from(rest),
+        _ => path,
+    }
+}
+
+// Tells the model where its files belong
+pub fn instructions(workspace: &Path) -> String {
+    format!(
+        "Your workspace is {}. Create and edit files only inside it, using absolute paths.",
+        workspace.display()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_each_run_gets_its_own_directory() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let workspaces = Workspaces::under(dir.path());
+
+        let first = workspaces.create("haiku/1")?;
+        let second = workspaces.create("haiku/1")?;
+
+        assert_ne!(first, second);
+        assert!(first.is_dir() && second.is_dir());
+        assert!(first.starts_with(canonicalize(dir.path())?));
+        assert!(first
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with("haiku_1-")));
+        Ok(())
+    }
+
+    #[test]
+    fn test_windows_verbatim_prefixes_are_stripped() {
+        let strip = |path: &str| strip_verbatim(PathBuf::from(path));
+
+        assert_eq!(strip(r"\\?\C:\agent\pond"), PathBuf::from(r"C:\agent\pond"));
+        assert_eq!(
+            strip(r"\\?\UNC\server\share\pond"),
+            PathBuf::from(r"\\server\share\pond")
+        );
+        assert_eq!(
+            strip(r"\\?\GLOBALROOT\Device"),
+            PathBuf::from(r"\\?\GLOBALROOT\Device")
+        );
+        assert_eq!(strip("/tmp/pond"), PathBuf::from("/tmp/pond"));
+    }
+
+    #[test]
+    fn test_injected_ids_make_directory_names_predictable() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let workspaces =
+            Workspaces::under(dir.path()).with_ids(Arc::new(crate::ids::SequentialIds::new()));
+
+        let path = workspaces.create("pond")?;
+
+        assert_eq!(path, canonicalize(dir.path())?.join("pond-00000001"));
+        Ok(())
+    }
+}